@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug)]
 #[derive(Serialize, Deserialize)]
@@ -32,6 +32,22 @@ impl Dictionary {
         self.words.values().sum()
     }
 
+    // NOTE: Number of distinct words for every occurrence count, in increasing count order
+    pub fn frequency_spectrum(&self) -> BTreeMap<usize, usize> {
+        let mut spectrum = BTreeMap::new();
+        for &count in self.words.values() {
+            *spectrum.entry(count).or_insert(0) += 1;
+        }
+
+        spectrum
+    }
+
+    pub fn hapax_count(&self) -> usize {
+        self.words.values()
+            .filter(|&&count| count == 1)
+            .count()
+    }
+
     pub fn add_word(&mut self, word: String) {
         self.add_word_with_count(word, 1);
     }
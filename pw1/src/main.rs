@@ -4,13 +4,15 @@ mod storage;
 mod dictionary;
 mod document;
 mod common;
+mod spectrum;
 
 use std::env;
 use anyhow::Result;
 use threadpool::ThreadPool;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::channel;
+use std::sync::mpsc::sync_channel;
 use crate::common::add_file_to_dict;
+use crate::spectrum::{print_spectrum_summary, write_spectrum_csv};
 use crate::storage::{DictionaryStorage, JsonDictionaryStorage, KeyValDictionaryStorage};
 
 fn get_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
@@ -22,8 +24,30 @@ fn get_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
         .collect())
 }
 
+// NOTE: Reads a dictionary written by a previous run, key-value files are recognized by the ".txt" extension
+fn spectrum(args: &[String]) -> Result<()> {
+    let dictionary_path = Path::new(args.first().map(AsRef::as_ref).unwrap_or("data/dictionary.json"));
+    let output_path = Path::new(args.get(1).map(AsRef::as_ref).unwrap_or("data/spectrum.csv"));
+
+    let dictionary = match dictionary_path.extension().and_then(|ext| ext.to_str()) {
+        Some("txt") => KeyValDictionaryStorage::read(dictionary_path)?,
+        _ => JsonDictionaryStorage::read(dictionary_path)?
+    };
+    println!("Unique word count: {}. Total word count: {}", dictionary.unique_word_count(), dictionary.total_word_count());
+    print_spectrum_summary(&dictionary);
+
+    println!("Writing frequency spectrum to \"{}\"", output_path.display());
+    write_spectrum_csv(output_path, &dictionary)?;
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
+    if args.get(1).map(AsRef::as_ref) == Some("spectrum") {
+        return spectrum(&args[2..]);
+    }
+
     let base_path = args.get(1).map(AsRef::as_ref).unwrap_or("data/shakespeare");
 
     let paths = match get_files(base_path) {
@@ -47,8 +71,11 @@ fn main() -> Result<()> {
         .enumerate()
         .for_each(|(i, path)| println!("\t{i}. {path}"));
 
-    let pool = ThreadPool::new(num_cpus::get());
-    let (tx, rx) = channel();
+    let worker_count = num_cpus::get();
+    let pool = ThreadPool::new(worker_count);
+    // NOTE: Workers wait once every one of them has a partial index queued,
+    //  so fast lexing can't run arbitrarily far ahead of merging
+    let (tx, rx) = sync_channel(worker_count);
     for path in paths {
         let tx = tx.clone();
         pool.execute(move || {
@@ -0,0 +1,33 @@
+use anyhow::Result;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::dictionary::Dictionary;
+
+// NOTE: Cumulative columns tell how much of the vocabulary a pruning threshold of `frequency` would drop
+pub fn write_spectrum_csv(path: &Path, dictionary: &Dictionary) -> Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = BufWriter::new(file);
+
+    let unique_words = dictionary.unique_word_count().max(1) as f64;
+    let mut cumulative_terms = 0;
+    writeln!(writer, "frequency,terms,cumulative_terms,cumulative_share")?;
+    for (frequency, terms) in dictionary.frequency_spectrum() {
+        cumulative_terms += terms;
+        writeln!(writer, "{},{},{},{:.6}", frequency, terms, cumulative_terms, cumulative_terms as f64 / unique_words)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn print_spectrum_summary(dictionary: &Dictionary) {
+    let spectrum = dictionary.frequency_spectrum();
+    let unique_words = dictionary.unique_word_count().max(1) as f64;
+    let count_of = |frequency| spectrum.get(&frequency).cloned().unwrap_or(0);
+
+    let hapax = dictionary.hapax_count();
+    let dis = count_of(2);
+    println!("Hapax legomena: {} ({:.2}% of unique words)", hapax, hapax as f64 / unique_words * 100.0);
+    println!("Dis legomena: {} ({:.2}% of unique words)", dis, dis as f64 / unique_words * 100.0);
+    println!("Distinct frequencies: {}", spectrum.len());
+}
@@ -2,6 +2,7 @@
 mod tests {
     use anyhow::Result;
     use crate::common::add_file_to_dict;
+    use crate::dictionary::Dictionary;
 
     #[test]
     fn case() -> Result<()> {
@@ -101,4 +102,16 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn frequency_spectrum() {
+        let mut dict = Dictionary::new();
+        for (word, count) in [("a", 1), ("b", 1), ("c", 2), ("d", 5), ("e", 1)] {
+            dict.add_word_with_count(word.to_owned(), count);
+        }
+
+        let spectrum = dict.frequency_spectrum();
+        assert_eq!(spectrum.into_iter().collect::<Vec<_>>(), vec![(1, 3), (2, 1), (5, 1)]);
+        assert_eq!(dict.hapax_count(), 3);
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use std::iter::Map;
 use std::str::Chars;
 
 #[derive(Clone, Debug)]
@@ -22,13 +23,27 @@ impl Token {
     }
 }
 
+// NOTE: Queries pasted from documents and chat apps come with typographic apostrophes,
+//  fullwidth punctuation and logic symbols, they mean the same as the ASCII operators
+fn normalize_char(ch: char) -> char {
+    match ch {
+        '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{02BC}' | '\u{FF07}' => '\'',
+        '\u{FF06}' | '\u{2227}' => '&',
+        '\u{FF5C}' | '\u{2228}' => '|',
+        '\u{FF01}' | '\u{00AC}' => '!',
+        '\u{FF08}' => '(',
+        '\u{FF09}' => ')',
+        _ => ch
+    }
+}
+
 struct Lexer<'a> {
-    iter: Chars<'a>
+    iter: Map<Chars<'a>, fn(char) -> char>
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Lexer { iter: input.chars() }
+        Lexer { iter: input.chars().map(normalize_char as fn(char) -> char) }
     }
 
     pub fn lex(mut self) -> Result<Vec<Token>> {
@@ -5,6 +5,7 @@ mod common;
 mod position;
 mod document;
 mod logic_op;
+mod metrics;
 
 use std::collections::HashSet;
 use std::{env, io};
@@ -13,10 +14,11 @@ use std::io::BufWriter;
 use std::ops::{BitAnd, BitOr, Not, Sub};
 use anyhow::{Context, Result};
 use threadpool::ThreadPool;
-use std::sync::mpsc::channel;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::mpsc::sync_channel;
 use bitvec::vec::BitVec;
 use itertools::Itertools;
+use crate::metrics::{metrics, Metrics, QueueDepth};
 use crate::common::add_file_to_index;
 use crate::document::DocumentRegistry;
 use crate::logic_op::LogicNode;
@@ -61,21 +63,11 @@ fn query_index(index: &InvertedIndex, query_ast: &LogicNode) -> HashSet<Document
     }
 }
 
-fn time_call<FnT, ResT>(func: FnT) -> (ResT, Duration)
-where FnT: FnOnce() -> ResT
-{
-    let start = Instant::now();
-    let result = func();
-    let time = start.elapsed();
-
-    (result, time)
-}
-
 fn query(document_registry: &DocumentRegistry, index: &InvertedIndex, matrix: &TermMatrix, query_text: &str) -> Result<()> {
     let ast = logic_op::parse_logic_expr(query_text).context("Invalid query")?;
 
-    let (index_result, index_time) = time_call(|| query_index(index, &ast));
-    let (matrix_result, matrix_time) = time_call(|| query_matrix(matrix, &ast));
+    let (index_result, index_time) = metrics().time("index_query", || query_index(index, &ast));
+    let (matrix_result, matrix_time) = metrics().time("matrix_query", || query_matrix(matrix, &ast));
 
     println!("Results match: {}", index_result == matrix_result);
     println!("Inverted index time {:?}. Matrix index time: {:?}", index_time, matrix_time);
@@ -95,7 +87,7 @@ fn query(document_registry: &DocumentRegistry, index: &InvertedIndex, matrix: &T
     Ok(())
 }
 
-fn main() -> Result<()> {
+fn run() -> Result<()> {
     let args: Vec<String> = env::args().collect();
     let base_path = args.get(1).map(AsRef::as_ref).unwrap_or("data/shakespeare");
 
@@ -104,21 +96,30 @@ fn main() -> Result<()> {
     println!("Processing {job_count} documents in folder \"{base_path}\"");
     println!("Files: ");
 
-    let pool = ThreadPool::new(num_cpus::get());
-    let (tx, rx) = channel();
+    let worker_count = num_cpus::get();
+    let pool = ThreadPool::new(worker_count);
+    // NOTE: Workers wait once every one of them has a partial index queued,
+    //  so fast lexing can't run arbitrarily far ahead of merging
+    let (tx, rx) = sync_channel(worker_count);
+    let queue_depth = Arc::new(QueueDepth::new());
     for i in 0..job_count {
         let tx = tx.clone();
+        let queue_depth1 = queue_depth.clone();
         let registry = document_registry.clone();
 
         println!("\t{}. {}", i, document_registry.get_document(DocumentId(i)).unwrap().name());
 
         pool.execute(move || {
-            tx.send(add_file_to_index(registry, DocumentId(i)).unwrap()).unwrap()
+            let partial = add_file_to_index(registry, DocumentId(i)).unwrap();
+            queue_depth1.push();
+            // NOTE: Send only fails once the receiver is gone, then nobody needs the partial
+            let _ = tx.send(partial);
         });
     }
 
     let result = rx.iter()
         .take(job_count)
+        .inspect(|_| queue_depth.pop())
         .flatten()
         .reduce(|mut a, b| {
             a.0.merge(b.0);
@@ -128,9 +129,13 @@ fn main() -> Result<()> {
             a
         });
 
+    metrics().set("max_queue_depth", queue_depth.max() as f64);
     if let Some((index, matrix, stats)) = result {
         println!("Unique word count: {}. Total word count: {}", index.unique_word_count(), index.total_word_count());
         println!("Lines read: {}. Characters read: {}. Characters ignored: {}", stats.lines, stats.characters_read, stats.characters_ignored);
+        metrics().add("documents_indexed", job_count as u64);
+        metrics().add("lines_read", stats.lines as u64);
+        metrics().add("characters_read", stats.characters_read as u64);
 
         println!("Writing index to a file...");
         serde_json::to_writer_pretty(BufWriter::new(File::create("data/index.json")?), &index)?;
@@ -156,3 +161,10 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn main() -> Result<()> {
+    let result = run();
+    metrics().save_to_file(Metrics::PATH)?;
+
+    result
+}
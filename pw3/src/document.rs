@@ -8,6 +8,16 @@ use crate::file::FileId;
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
 pub struct DocumentId(usize);
 
+impl DocumentId {
+    pub fn new(id: usize) -> Self {
+        DocumentId(id)
+    }
+
+    pub fn id(&self) -> usize {
+        self.0
+    }
+}
+
 impl Display for DocumentId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "Document({})", self.0)
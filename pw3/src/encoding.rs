@@ -0,0 +1,64 @@
+use anyhow::{anyhow, Result};
+
+const CONT_MASK: u8 = 0b10000000;
+
+pub fn vb_encode(value: usize) -> Vec<u8> {
+    if value == 0 {
+        return vec![CONT_MASK];
+    }
+
+    let mut result = Vec::new();
+
+    let mut acc = value;
+    while acc != 0 {
+        result.push((acc % 128) as u8);
+        acc /= 128;
+    }
+
+    result.reverse();
+    if let Some(last) = result.last_mut() {
+        *last |= CONT_MASK;
+    }
+
+    result
+}
+
+// NOTE: Decodes straight from a slice, going through an iterator of bytes costs a branch and a result per byte
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        ByteReader {
+            bytes,
+            position: 0
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position + length)
+            .ok_or_else(|| anyhow!("Unexpected end of encoded data"))?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    pub fn read_vb(&mut self) -> Result<usize> {
+        let mut result = 0;
+        for (i, &byte) in self.bytes[self.position..].iter().enumerate() {
+            result = (result << 7) | ((byte & 127) as usize);
+            if byte & CONT_MASK == CONT_MASK {
+                self.position += i + 1;
+                return Ok(result);
+            }
+        }
+
+        Err(anyhow!("Unexpected end of encoded data"))
+    }
+}
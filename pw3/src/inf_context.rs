@@ -4,14 +4,16 @@ use std::sync::Arc;
 use crate::document::{Document, DocumentRegistry};
 use crate::file::FilePool;
 use crate::document::DocumentId;
+use crate::synonyms::Synonyms;
 
 pub struct InfContext {
     documents: DocumentRegistry,
-    files: FilePool
+    files: FilePool,
+    synonyms: Synonyms
 }
 
 impl InfContext {
-    pub fn new(base_path: &str) -> Result<Arc<Self>> {
+    pub fn new(base_path: &str, synonyms: Synonyms) -> Result<Arc<Self>> {
         let mut file_names = get_files(base_path)?;
         let mut files = FilePool::new();
         let mut documents = DocumentRegistry::new();
@@ -23,7 +25,8 @@ impl InfContext {
 
         Ok(Arc::new(InfContext {
             documents,
-            files
+            files,
+            synonyms
         }))
     }
 
@@ -39,6 +42,10 @@ impl InfContext {
         self.documents.document(document_id)
     }
 
+    pub fn synonyms(&self) -> &Synonyms {
+        &self.synonyms
+    }
+
     pub fn document_data(&self, document_id: DocumentId) -> Result<&str> {
         let document = self.documents.document(document_id)
             .context(anyhow!("Document with id {document_id} doesn't exist"))?;
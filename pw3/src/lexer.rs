@@ -3,11 +3,13 @@ use std::str::CharIndices;
 use crate::document::DocumentId;
 use crate::inf_context::InfContext;
 use crate::position::TermDocumentPosition;
+use crate::synonyms::Synonyms;
 use crate::term_index::TermIndex;
 
 pub struct Lexer<'a> {
     document_id: DocumentId,
-    iter: CharIndices<'a>
+    iter: CharIndices<'a>,
+    synonyms: Option<&'a Synonyms>
 }
 
 impl<'a> Lexer<'a> {
@@ -16,10 +18,17 @@ impl<'a> Lexer<'a> {
 
         Ok(Lexer {
             document_id,
-            iter
+            iter,
+            synonyms: None
         })
     }
 
+    pub fn with_synonyms(mut self, synonyms: &'a Synonyms) -> Self {
+        self.synonyms = Some(synonyms);
+
+        self
+    }
+
     pub fn lex(mut self, term_index: &mut dyn TermIndex) -> LexerStats {
         let mut word_count = 0;
         let mut word = String::new();
@@ -39,23 +48,31 @@ impl<'a> Lexer<'a> {
                 stats.lines += 1;
             }
             if !word.is_empty() {
-                Self::add_term(&mut word, &mut word_count, self.document_id, term_index);
+                self.add_term(&mut word, &mut word_count, term_index, &mut stats);
             }
         }
 
         if !word.is_empty() {
-            Self::add_term(&mut word, &mut word_count, self.document_id, term_index);
+            self.add_term(&mut word, &mut word_count, term_index, &mut stats);
         }
 
         stats
     }
 
-    fn add_term(word: &mut String, pos: &mut usize, document_id: DocumentId, term_index: &mut dyn TermIndex) {
+    fn add_term(&self, word: &mut String, pos: &mut usize, term_index: &mut dyn TermIndex, stats: &mut LexerStats) {
         let mut new_word = String::new();
         std::mem::swap(word, &mut new_word);
 
+        let position = TermDocumentPosition::new(*pos);
+        if let Some(synonyms) = self.synonyms {
+            for synonym in synonyms.get(&new_word) {
+                term_index.add_term(synonym.clone(), self.document_id, position);
+                stats.synonyms_injected += 1;
+            }
+        }
+
         new_word.shrink_to_fit();
-        term_index.add_term(new_word, document_id, TermDocumentPosition::new(*pos));
+        term_index.add_term(new_word, self.document_id, position);
         *pos += 1;
     }
 }
@@ -64,7 +81,8 @@ impl<'a> Lexer<'a> {
 pub struct LexerStats {
     pub characters_read: usize,
     pub characters_ignored: usize,
-    pub lines: usize
+    pub lines: usize,
+    pub synonyms_injected: usize
 }
 
 impl LexerStats {
@@ -72,6 +90,7 @@ impl LexerStats {
         self.characters_read += other.characters_read;
         self.characters_ignored += other.characters_ignored;
         self.lines += other.lines;
+        self.synonyms_injected += other.synonyms_injected;
     }
 }
 
@@ -80,7 +99,8 @@ impl Default for LexerStats {
         LexerStats {
             characters_read: 0,
             characters_ignored: 0,
-            lines: 0
+            lines: 0,
+            synonyms_injected: 0
         }
     }
 }
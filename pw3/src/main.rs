@@ -7,34 +7,29 @@ mod document;
 mod query_lang;
 mod inf_context;
 mod two_word_index;
+mod synonyms;
+mod metrics;
+mod encoding;
 
 use std::{env, io};
-use std::fs::File;
-use std::io::BufWriter;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
 use anyhow::{Context, Result};
 use threadpool::ThreadPool;
-use std::sync::mpsc::channel;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::mpsc::sync_channel;
 use itertools::Itertools;
+use crate::metrics::{metrics, Metrics, QueueDepth};
 use crate::common::add_file_to_index;
 use crate::inf_context::InfContext;
-use crate::term_index::TermIndex;
-
-fn time_call<FnT, ResT>(func: FnT) -> (ResT, Duration)
-where FnT: FnOnce() -> ResT
-{
-    let start = Instant::now();
-    let result = func();
-    let time = start.elapsed();
-
-    (result, time)
-}
+use crate::term_index::{InvertedIndex, TermIndex};
+use crate::synonyms::Synonyms;
 
 fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()> {
     let ast = query_lang::parse_logic_expr(query_text).context("Invalid query")?;
     // println!("Ast: {ast:?}");
 
-    let (result, time) = time_call(|| index.query(&ast));
+    let (result, time) = metrics().time("query", || index.query(&ast));
     let result = result?;
 
     println!("Query time: {:?}.", time);
@@ -53,31 +48,48 @@ fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()
     Ok(())
 }
 
-fn main() -> Result<()> {
+fn run() -> Result<()> {
     let args: Vec<String> = env::args().collect();
     let base_path = args.get(1).map(AsRef::as_ref).unwrap_or("data/shakespeare");
 
-    let ctx = InfContext::new(base_path)?;
+    let synonyms = match File::open("data/synonyms.txt") {
+        Ok(file) => Synonyms::load(BufReader::new(file))?,
+        Err(_) => Synonyms::new()
+    };
+    if !synonyms.is_empty() {
+        println!("Using index-time synonyms from \"data/synonyms.txt\"");
+    }
+
+    let ctx = InfContext::new(base_path, synonyms)?;
     let mut document_ids = ctx.document_ids().collect::<Vec<_>>();
     let document_count = document_ids.len();
     println!("Processing {document_count} documents in folder \"{base_path}\"");
     println!("Files: ");
 
-    let pool = ThreadPool::new(num_cpus::get());
-    let (tx, rx) = channel();
+    let worker_count = num_cpus::get();
+    let pool = ThreadPool::new(worker_count);
+    // NOTE: Workers wait once every one of them has a partial index queued,
+    //  so fast lexing can't run arbitrarily far ahead of merging
+    let (tx, rx) = sync_channel(worker_count);
+    let queue_depth = Arc::new(QueueDepth::new());
     for (i, document_id) in document_ids.drain(..).enumerate() {
         let tx = tx.clone();
+        let queue_depth1 = queue_depth.clone();
         let ctx1 = ctx.clone();
 
         println!("\t{}. {}", i, ctx1.document(document_id).unwrap().name());
 
         pool.execute(move || {
-            tx.send(add_file_to_index(document_id, ctx1).unwrap()).unwrap()
+            let partial = add_file_to_index(document_id, ctx1).unwrap();
+            queue_depth1.push();
+            // NOTE: Send only fails once the receiver is gone, then nobody needs the partial
+            let _ = tx.send(partial);
         });
     }
 
     let result = rx.iter()
         .take(document_count)
+        .inspect(|_| queue_depth.pop())
         .flatten()
         .reduce(|mut a, b| {
             a.0.merge(b.0);
@@ -87,14 +99,37 @@ fn main() -> Result<()> {
             a
         });
 
+    metrics().set("max_queue_depth", queue_depth.max() as f64);
     if let Some((inverted_index, two_word_index, stats)) = result {
         println!("Unique word count: {}. Total word count: {}", inverted_index.unique_word_count(), inverted_index.total_word_count());
         println!("Lines read: {}. Characters read: {}. Characters ignored: {}", stats.lines, stats.characters_read, stats.characters_ignored);
+        metrics().add("documents_indexed", document_count as u64);
+        metrics().add("lines_read", stats.lines as u64);
+        metrics().add("characters_read", stats.characters_read as u64);
+        println!("Synonyms injected: {}", stats.synonyms_injected);
 
         println!("Writing index to a file...");
         serde_json::to_writer_pretty(BufWriter::new(File::create("data/index.json")?), &inverted_index)?;
         serde_json::to_writer_pretty(BufWriter::new(File::create("data/two_word_index.json")?), &two_word_index)?;
 
+        println!("Writing compressed index to a file...");
+        let (compression_result, compression_time) = metrics().time("compression", || -> Result<()> {
+            let mut writer = BufWriter::new(File::create("data/index.bin")?);
+            inverted_index.save_binary(&mut writer)?;
+            writer.flush()?;
+
+            Ok(())
+        });
+        compression_result?;
+        let index_size = fs::metadata("data/index.json")?.len();
+        let compressed_index_size = fs::metadata("data/index.bin")?.len();
+        println!("Index size: {index_size} bytes. Compressed index size: {compressed_index_size} bytes");
+
+        let compressed_data = fs::read("data/index.bin")?;
+        let (index_read, decompression_time) = metrics().time("decompression", || InvertedIndex::load_binary(&compressed_data));
+        println!("Compressed in: {:?}. Decompressed in: {:?}", compression_time, decompression_time);
+        println!("Are index equal: {}", index_read? == inverted_index);
+
         let mut buffer = String::new();
         let mut use_inverted_index = true;
         loop {
@@ -126,3 +161,10 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn main() -> Result<()> {
+    let result = run();
+    metrics().save_to_file(Metrics::PATH)?;
+
+    result
+}
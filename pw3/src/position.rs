@@ -1,11 +1,15 @@
+use anyhow::{anyhow, Result};
 use std::collections::{BTreeSet, HashMap};
 use std::ops::{BitAnd, BitOr, Sub};
 use std::ops::Bound::Included;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use crate::document::DocumentId;
+use crate::encoding::{vb_encode, ByteReader};
 
 #[derive(Serialize, Deserialize)]
 #[derive(Clone, Debug)]
+#[derive(Eq, PartialEq)]
 pub struct TermPositions {
     #[serde(flatten)]
     positions: HashMap<DocumentId, BTreeSet<TermDocumentPosition>>
@@ -90,6 +94,52 @@ impl TermPositions {
             .extend(positions);
     }
 
+    // NOTE: Documents go in id order, each with its number of positions. Document ids are delta coded against
+    //  the previous document and positions against the previous position in the same document
+    pub fn encode(&self, data: &mut Vec<u8>) {
+        data.extend(vb_encode(self.positions.len()));
+
+        let mut prev_document_id = 0;
+        for (document_id, positions) in self.positions.iter().sorted_by_key(|(document_id, _)| **document_id) {
+            data.extend(vb_encode(document_id.id() - prev_document_id));
+            prev_document_id = document_id.id();
+
+            data.extend(vb_encode(positions.len()));
+            let mut prev_offset = 0;
+            for position in positions {
+                data.extend(vb_encode(position.offset() - prev_offset));
+                prev_offset = position.offset();
+            }
+        }
+    }
+
+    pub fn decode(reader: &mut ByteReader) -> Result<Self> {
+        let document_count = reader.read_vb()?;
+        let mut positions = HashMap::with_capacity(document_count);
+
+        let mut document_id = 0;
+        for i in 0..document_count {
+            let delta = reader.read_vb()?;
+            if i != 0 && delta == 0 {
+                return Err(anyhow!("Document {document_id} is stored twice"));
+            }
+            document_id += delta;
+
+            let position_count = reader.read_vb()?;
+            let mut offset = 0;
+            let document_positions = (0..position_count)
+                .map(|_| {
+                    offset += reader.read_vb()?;
+
+                    Ok(TermDocumentPosition(offset))
+                })
+                .collect::<Result<BTreeSet<_>>>()?;
+            positions.insert(DocumentId::new(document_id), document_positions);
+        }
+
+        Ok(TermPositions { positions })
+    }
+
     pub fn document_sub(&self, rhs: &TermPositions) -> TermPositions {
         let result = self.positions.iter()
             .filter(|(document_id, _)| !rhs.positions.contains_key(document_id))
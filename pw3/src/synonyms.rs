@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::BufRead;
+
+pub struct Synonyms {
+    synonyms: HashMap<String, Vec<String>>
+}
+
+impl Synonyms {
+    const ARROW: &'static str = "=>";
+    const SEPARATOR: char = ',';
+    const COMMENT: &'static str = "#";
+
+    pub fn new() -> Self {
+        Synonyms { synonyms: HashMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.synonyms.is_empty()
+    }
+
+    pub fn get(&self, word: &str) -> &[String] {
+        self.synonyms.get(word)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    pub fn add_mapping(&mut self, word: String, synonyms: impl Iterator<Item = String>) {
+        let entry = self.synonyms.entry(word.clone())
+            .or_default();
+        for synonym in synonyms {
+            if synonym != word && !entry.contains(&synonym) {
+                entry.push(synonym);
+            }
+        }
+    }
+
+    pub fn add_group(&mut self, words: &[String]) {
+        for word in words {
+            self.add_mapping(word.clone(), words.iter().cloned());
+        }
+    }
+
+    // NOTE: "a, b, c" makes all words equivalent, "a => b, c" only expands 'a'
+    pub fn load(reader: impl BufRead) -> Result<Self> {
+        let mut synonyms = Synonyms::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(Self::COMMENT) {
+                continue;
+            }
+
+            if let Some((word, expansions)) = line.split_once(Self::ARROW) {
+                let word = Self::normalize(word);
+                if word.is_empty() {
+                    return Err(anyhow!("Expected word before '{}' on line {}", Self::ARROW, i + 1));
+                }
+
+                synonyms.add_mapping(word, Self::split(expansions).into_iter());
+            } else {
+                synonyms.add_group(&Self::split(line));
+            }
+        }
+
+        Ok(synonyms)
+    }
+
+    fn split(words: &str) -> Vec<String> {
+        words.split(Self::SEPARATOR)
+            .map(Self::normalize)
+            .filter(|word| !word.is_empty())
+            .collect()
+    }
+
+    fn normalize(word: &str) -> String {
+        word.trim().to_lowercase()
+    }
+}
+
+impl Default for Synonyms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,7 +1,10 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use itertools::Itertools;
 use crate::document::DocumentId;
+use crate::encoding::{vb_encode, ByteReader};
 use crate::query_lang::LogicNode;
 use crate::position::{TermDocumentPosition, TermPositions};
 
@@ -12,6 +15,7 @@ pub trait TermIndex {
 
 #[derive(Debug)]
 #[derive(Serialize, Deserialize)]
+#[derive(Eq, PartialEq)]
 pub struct InvertedIndex {
     documents: TermPositions,
     index: HashMap<String, TermPositions>
@@ -59,6 +63,44 @@ impl InvertedIndex {
             .merge(positions);
     }
 
+    // NOTE: Documents of the index come first, then the number of terms and every term in order,
+    //  its length and bytes followed by its positions. Terms are sorted, so the same corpus always produces the same file
+    pub fn save_binary(&self, mut writer: impl Write) -> Result<()> {
+        let mut data = Vec::new();
+        self.documents.encode(&mut data);
+
+        data.extend(vb_encode(self.index.len()));
+        for (term, positions) in self.index.iter().sorted_by_key(|(term, _)| *term) {
+            data.extend(vb_encode(term.len()));
+            data.extend(term.as_bytes());
+            positions.encode(&mut data);
+        }
+        writer.write_all(&data)?;
+
+        Ok(())
+    }
+
+    pub fn load_binary(data: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader::new(data);
+        let documents = TermPositions::decode(&mut reader)?;
+
+        let term_count = reader.read_vb()?;
+        let mut index = HashMap::with_capacity(term_count);
+        for _ in 0..term_count {
+            let length = reader.read_vb()?;
+            let term = String::from_utf8(reader.read_bytes(length)?.to_vec())?;
+            index.insert(term, TermPositions::decode(&mut reader)?);
+        }
+        if !reader.is_empty() {
+            return Err(anyhow!("Unexpected data after the last term"));
+        }
+
+        Ok(InvertedIndex {
+            documents,
+            index
+        })
+    }
+
     fn query_rec(&self, query_ast: &LogicNode) -> TermPositions {
         match query_ast {
             LogicNode::False => TermPositions::new(),
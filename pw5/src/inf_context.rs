@@ -1,8 +1,9 @@
 use anyhow::{anyhow, Result, Context};
+use rayon::prelude::*;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use crate::document::{Document, DocumentRegistry};
-use crate::file::FilePool;
+use crate::file::{File, FilePool};
 use crate::document::DocumentId;
 
 pub struct InfContext {
@@ -13,25 +14,27 @@ pub struct InfContext {
 impl InfContext {
     pub fn new(base_path: &str, file_limit: Option<usize>) -> Result<Arc<Self>> {
         let mut file_names = get_files(base_path)?;
-        let mut files = FilePool::new();
-        let mut documents = DocumentRegistry::new();
+        if let Some(file_limit) = file_limit {
+            file_names.truncate(file_limit);
+        }
 
-        let mut i = 0;
-        for path in file_names.drain(..) {
-            if let Some(file_limit) = file_limit {
-                if i >= file_limit {
-                    break;
-                }
-            }
-            i += 1;
+        // NOTE: Files are opened in parallel, but registered in their original order,
+        //  so document ids don't depend on which file finished opening first
+        let opened = file_names.into_par_iter()
+            .map(|path| (File::new(&path), path))
+            .collect::<Vec<_>>();
 
-            let file_id = match files.add_file(&path) {
-                Ok(file_id) => file_id,
+        let mut files = FilePool::new();
+        let mut documents = DocumentRegistry::new();
+        for (file, path) in opened {
+            let file = match file {
+                Ok(file) => file,
                 Err(err) => {
                     println!("Ignoring file {:?}. Error: {}. Caused by: {}", path, err, err.root_cause());
                     continue;
                 }
             };
+            let file_id = files.add(file);
             documents.add_document(Document::File { path, file_id });
         }
 
@@ -5,38 +5,31 @@ mod common;
 mod document;
 mod query_lang;
 mod inf_context;
+mod metrics;
+mod persist;
+mod merge;
 
 use std::{env, io};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
 use std::str::FromStr;
 use anyhow::{Context, Result};
 use threadpool::ThreadPool;
-use std::sync::mpsc::channel;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::mpsc::sync_channel;
 use human_bytes::human_bytes;
 use itertools::Itertools;
+use crate::metrics::{metrics, Metrics, QueueDepth};
 use crate::common::add_file_to_index;
 use crate::inf_context::InfContext;
 use crate::term_index::{InvertedIndex, TermIndex};
-use rayon::prelude::*;
 use crate::lexer::LexerStats;
+use crate::merge::{merge_bounded, DEFAULT_MERGE_BUFFER};
 
-fn time_call<FnT, ResT>(func: FnT) -> (ResT, Duration)
-where FnT: FnOnce() -> ResT
-{
-    let start = Instant::now();
-    let result = func();
-    let time = start.elapsed();
-
-    (result, time)
-}
-
-fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()> {
+fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext, max_results: Option<usize>) -> Result<()> {
     let ast = query_lang::parse_logic_expr(query_text).context("Invalid query")?;
     // println!("Ast: {ast:?}");
 
-    let (result, time) = time_call(|| index.query(&ast));
+    let (result, time) = metrics().time("query", || index.query(&ast, max_results));
     let result = result?;
 
     println!("Query time: {time:?}.");
@@ -48,6 +41,9 @@ fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()
             .map(|(i, (id, doc))| format!("\t{}. [{}] {}", i, id, doc.name()))
             .join("\n");
         println!("Result:\n{result_str}");
+        if Some(result.len()) == max_results {
+            println!("Stopped after the first {} matches.", result.len());
+        }
     } else {
         println!("No matches found.");
     }
@@ -55,55 +51,92 @@ fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()
     Ok(())
 }
 
-fn main() -> Result<()> {
+fn merge_buffer(flags: &[&str]) -> Result<usize> {
+    flags.iter()
+        .find_map(|flag| flag.strip_prefix("--merge-buffer="))
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid merge buffer")
+        .map(|merge_buffer| merge_buffer.unwrap_or(DEFAULT_MERGE_BUFFER))
+}
+
+fn max_results(flags: &[&str]) -> Result<Option<usize>> {
+    flags.iter()
+        .find_map(|flag| flag.strip_prefix("--max-results="))
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid max results")
+}
+
+fn run() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    let base_path = args.get(1).map(AsRef::as_ref).unwrap_or("data/shakespeare");
-    let file_limit = args.get(2).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+    let (flags, positional): (Vec<&str>, Vec<&str>) = args.iter()
+        .skip(1)
+        .map(String::as_str)
+        .partition(|arg| arg.starts_with("--"));
+    let base_path = positional.first().cloned().unwrap_or("data/shakespeare");
+    let file_limit = positional.get(1).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+    let merge_buffer = merge_buffer(&flags)?;
+    let max_results = max_results(&flags)?;
 
     println!("Processing...");
-    let (ctx, opening_files_time) = time_call(|| InfContext::new(base_path, file_limit).unwrap());
+    let (ctx, opening_files_time) = metrics().time("opening_files", || InfContext::new(base_path, file_limit).unwrap());
     println!("Opening files took: {opening_files_time:?}");
     let mut document_ids = ctx.document_ids().collect::<Vec<_>>();
     let document_count = document_ids.len();
     println!("Processing {document_count} documents in folder \"{base_path}\"");
 
-    let pool = ThreadPool::new((num_cpus::get() - 1).max(1));
-    let (tx, rx) = channel();
+    let worker_count = (num_cpus::get() - 1).max(1);
+    let pool = ThreadPool::new(worker_count);
+    // NOTE: Workers wait once every one of them has a partial index queued,
+    //  so fast lexing can't run arbitrarily far ahead of merging
+    let (tx, rx) = sync_channel(worker_count);
+    let queue_depth = Arc::new(QueueDepth::new());
     for document_id in document_ids.drain(..) {
         let tx = tx.clone();
+        let queue_depth1 = queue_depth.clone();
         let ctx1 = ctx.clone();
 
         pool.execute(move || {
-            tx.send(add_file_to_index(document_id, ctx1).unwrap()).unwrap()
+            let partial = add_file_to_index(document_id, ctx1).unwrap();
+            queue_depth1.push();
+            // NOTE: Send only fails once the receiver is gone, then nobody needs the partial
+            let _ = tx.send(partial);
         });
     }
 
-    let (result, index_time) = time_call(|| {
-        rx.into_iter()
+    let (result, index_time) = metrics().time("indexing", || {
+        let partials = rx.into_iter()
             .take(document_count)
-            .flatten()
-            .par_bridge()
-            .reduce(|| (InvertedIndex::new(), LexerStats::default()), |mut a, b| {
-                a.0.merge(b.0);
-                a.1.merge(b.1);
-
-                a
-            })
+            .inspect(|_| queue_depth.pop())
+            .flatten();
+
+        merge_bounded(partials, merge_buffer, |a, b| {
+            a.0.merge(b.0);
+            a.1.merge(b.1);
+        }).unwrap_or_else(|| (InvertedIndex::new(), LexerStats::default()))
     });
 
+    metrics().set("max_queue_depth", queue_depth.max() as f64);
     println!("Indexing took: {index_time:?}");
     let data_size: usize = ctx.files().files()
         .map(|file| file.bytes().len())
         .sum();
     println!("Amount of data indexed: {}", human_bytes(data_size as f64));
     println!("Speed is: {}/s", human_bytes(data_size as f64 / index_time.as_secs_f64()));
+    metrics().add("documents_indexed", document_count as u64);
+    metrics().add("bytes_indexed", data_size as u64);
+    metrics().set("documents_per_second", document_count as f64 / index_time.as_secs_f64());
+    metrics().set("bytes_per_second", data_size as f64 / index_time.as_secs_f64());
 
     if let (index, stats) = result {
         println!("Unique word count: {}.", index.unique_word_count());
         println!("Lines read: {}. Characters read: {}. Characters ignored: {}", stats.lines, stats.characters_read, stats.characters_ignored);
+        metrics().add("lines_read", stats.lines as u64);
+        metrics().add("characters_read", stats.characters_read as u64);
 
         println!("Writing index to a file...");
-        index.save(BufWriter::new(File::create("data/index.txt")?))?;
+        persist::save_checked("data/index.txt", |writer| index.save(writer))?;
         let index_size = File::open("data/index.txt")?.metadata()?.len();
         println!("Index size: {}", human_bytes(index_size as f64));
 
@@ -115,7 +148,7 @@ fn main() -> Result<()> {
                 break;
             }
 
-            if let Err(err) = query(&buffer, &index, &ctx) {
+            if let Err(err) = query(&buffer, &index, &ctx, max_results) {
                 println!("Error: {}. Caused by: {}", err, err.root_cause());
             }
             println!();
@@ -128,3 +161,10 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn main() -> Result<()> {
+    let result = run();
+    metrics().save_to_file(Metrics::PATH)?;
+
+    result
+}
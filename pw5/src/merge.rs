@@ -0,0 +1,31 @@
+pub const DEFAULT_MERGE_BUFFER: usize = 8;
+
+// NOTE: Partials are merged pairwise like a binary counter, so both sides of
+//  a merge are about the same size and at most `merge_buffer` partials are held
+//  at once, when the buffer is full the two most recent ones are merged early
+pub fn merge_bounded<T>(partials: impl Iterator<Item = T>, merge_buffer: usize, merge: impl Fn(&mut T, T)) -> Option<T> {
+    let merge_buffer = merge_buffer.max(2);
+    let mut levels: Vec<(usize, T)> = Vec::with_capacity(merge_buffer);
+    for partial in partials {
+        levels.push((0, partial));
+
+        while let [.., (a_level, _), (b_level, _)] = levels.as_slice() {
+            if a_level != b_level && levels.len() <= merge_buffer {
+                break;
+            }
+
+            let (b_level, b) = levels.pop().unwrap();
+            let (a_level, a) = levels.last_mut().unwrap();
+            *a_level = (*a_level).max(b_level) + 1;
+            merge(a, b);
+        }
+    }
+
+    let (_, mut result) = levels.pop()?;
+    while let Some((_, mut partial)) = levels.pop() {
+        merge(&mut partial, result);
+        result = partial;
+    }
+
+    Some(result)
+}
@@ -1,4 +1,4 @@
-use std::iter::Peekable;
+use std::iter::{Map, Peekable};
 use anyhow::{anyhow, Context, Result};
 use std::str::{Chars, FromStr};
 
@@ -18,13 +18,35 @@ enum Token {
     Backslash
 }
 
+// NOTE: Queries pasted from documents and chat apps come with typographic quotes,
+//  fullwidth punctuation and logic symbols, they mean the same as the ASCII operators
+fn normalize_char(ch: char) -> char {
+    match ch {
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{00AB}' | '\u{00BB}' | '\u{300C}' | '\u{300D}' | '\u{300E}' | '\u{300F}' | '\u{FF02}' => '"',
+        '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{02BC}' | '\u{FF07}' => '\'',
+        '\u{FF06}' | '\u{2227}' => '&',
+        '\u{FF5C}' | '\u{2228}' => '|',
+        '\u{FF01}' | '\u{00AC}' => '!',
+        '\u{FF08}' => '(',
+        '\u{FF09}' => ')',
+        '\u{FF5B}' => '{',
+        '\u{FF5D}' => '}',
+        '\u{FF1E}' => '>',
+        '\u{FF3C}' | '\u{2216}' => '\\',
+        '\u{FF10}'..='\u{FF19}' => char::from_u32(ch as u32 - 0xFF10 + '0' as u32).unwrap_or(ch),
+        _ => ch
+    }
+}
+
+type NormalizedChars<'a> = Map<Chars<'a>, fn(char) -> char>;
+
 struct Lexer<'a> {
-    iter: Peekable<Chars<'a>>
+    iter: Peekable<NormalizedChars<'a>>
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Lexer { iter: input.chars().peekable() }
+        Lexer { iter: input.chars().map(normalize_char as fn(char) -> char).peekable() }
     }
 
     pub fn lex(mut self) -> Result<Vec<Token>> {
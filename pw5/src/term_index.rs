@@ -8,7 +8,8 @@ use crate::query_lang::LogicNode;
 
 pub trait TermIndex {
     fn add_term(&mut self, term: String, document_id: DocumentId);
-    fn query(&self, query_ast: &LogicNode) -> Result<AHashSet<DocumentId>>;
+    // NOTE: With a limit, evaluation stops at the first `limit` matching documents in id order
+    fn query(&self, query_ast: &LogicNode, limit: Option<usize>) -> Result<AHashSet<DocumentId>>;
 }
 
 #[derive(Debug)]
@@ -79,6 +80,64 @@ impl InvertedIndex {
             }
         })
     }
+    // NOTE: Every matching document is in one of the returned posting lists.
+    //  Conjunctions only keep the smaller side, so they are never intersected up front
+    fn candidates(&self, query_ast: &LogicNode) -> Vec<&AHashSet<DocumentId>> {
+        match query_ast {
+            LogicNode::False => Vec::new(),
+            LogicNode::Term(term) => self.index.get(term).into_iter().collect(),
+            LogicNode::And(lhs, rhs) => {
+                let lhs = self.candidates(lhs);
+                let rhs = self.candidates(rhs);
+                let size = |sets: &Vec<&AHashSet<DocumentId>>| sets.iter().map(|set| set.len()).sum::<usize>();
+
+                if size(&lhs) <= size(&rhs) { lhs } else { rhs }
+            },
+            LogicNode::Or(lhs, rhs) => {
+                let mut candidates = self.candidates(lhs);
+                candidates.extend(self.candidates(rhs));
+
+                candidates
+            },
+            LogicNode::Not(_) | LogicNode::Near(_, _, _, _) => vec![self.documents()],
+            LogicNode::Subtract(lhs, _) => self.candidates(lhs)
+        }
+    }
+
+    fn matches(&self, query_ast: &LogicNode, document_id: DocumentId) -> Result<bool> {
+        Ok(match query_ast {
+            LogicNode::False => false,
+            LogicNode::Term(term) => self.index.get(term)
+                .is_some_and(|documents| documents.contains(&document_id)),
+            LogicNode::And(lhs, rhs) => self.matches(lhs, document_id)? && self.matches(rhs, document_id)?,
+            LogicNode::Or(lhs, rhs) => self.matches(lhs, document_id)? || self.matches(rhs, document_id)?,
+            LogicNode::Not(operand) => !self.matches(operand, document_id)?,
+            LogicNode::Near(_, _, _, _) => {
+                return Err(anyhow!("Operation not supported."));
+            },
+            LogicNode::Subtract(lhs, rhs) => self.matches(lhs, document_id)? && !self.matches(rhs, document_id)?
+        })
+    }
+
+    fn query_limited(&self, query_ast: &LogicNode, limit: usize) -> Result<AHashSet<DocumentId>> {
+        let candidates = self.candidates(query_ast).into_iter()
+            .flatten()
+            .copied()
+            .sorted()
+            .dedup();
+
+        let mut result = AHashSet::new();
+        for document_id in candidates {
+            if result.len() == limit {
+                break;
+            }
+            if self.matches(query_ast, document_id)? {
+                result.insert(document_id);
+            }
+        }
+
+        Ok(result)
+    }
 }
 
 impl TermIndex for InvertedIndex {
@@ -90,8 +149,11 @@ impl TermIndex for InvertedIndex {
         self.documents.insert(document_id);
     }
 
-    fn query(&self, query_ast: &LogicNode) -> Result<AHashSet<DocumentId>> {
-        self.query_rec(query_ast)
+    fn query(&self, query_ast: &LogicNode, limit: Option<usize>) -> Result<AHashSet<DocumentId>> {
+        match limit {
+            Some(limit) => self.query_limited(query_ast, limit),
+            None => self.query_rec(query_ast)
+        }
     }
 }
 
@@ -99,11 +161,12 @@ impl InvertedIndex {
     const TERM_POSITIONS_SEPARATOR: &'static str = ":";
     const POSITIONS_SEPARATOR: &'static str = ",";
 
+    // NOTE: Terms and documents are sorted, so the same corpus always produces the same file
     pub fn save(&self, mut writer: impl Write) -> Result<()> {
-        for (term, documents) in &self.index {
+        for (term, documents) in self.index.iter().sorted_by_key(|(term, _)| *term) {
             writer.write_all(term.as_bytes())?;
             writer.write_all(Self::TERM_POSITIONS_SEPARATOR.as_bytes())?;
-            for (i, document) in documents.iter().enumerate() {
+            for (i, document) in documents.iter().sorted().enumerate() {
                 writer.write_all(format!("{}", document.id()).as_bytes())?;
                 if i + 1 != documents.len() {
                     writer.write_all(Self::POSITIONS_SEPARATOR.as_bytes())?;
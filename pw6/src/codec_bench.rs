@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+use human_bytes::human_bytes;
+use crate::encoding::{block_decode, block_encode, delta_decode, delta_encode, gamma_decode, gamma_encode, golomb_decode, golomb_encode,
+                      golomb_parameter, vb_encode, BitReader, BitWriter, ByteReader};
+use crate::persist;
+use crate::term_index::{InvertedIndex, TermIndex};
+use crate::metrics::metrics;
+
+#[derive(Clone, Copy, Debug)]
+enum BenchCodec {
+    VariableByte,
+    Gamma,
+    Delta,
+    Golomb,
+    Block
+}
+
+impl BenchCodec {
+    const ALL: [BenchCodec; 5] = [BenchCodec::VariableByte, BenchCodec::Gamma, BenchCodec::Delta, BenchCodec::Golomb, BenchCodec::Block];
+
+    fn name(&self) -> &'static str {
+        match self {
+            BenchCodec::VariableByte => "vb",
+            BenchCodec::Gamma => "gamma",
+            BenchCodec::Delta => "delta",
+            BenchCodec::Golomb => "golomb",
+            BenchCodec::Block => "block"
+        }
+    }
+
+    // NOTE: Gamma and delta can't encode zero, and the first gap is zero for the first document
+    fn encode(&self, gaps: &[usize], document_count: usize) -> Vec<u8> {
+        match self {
+            BenchCodec::VariableByte => gaps.iter().flat_map(|&gap| vb_encode(gap)).collect(),
+            BenchCodec::Gamma => {
+                let mut writer = BitWriter::new();
+                gaps.iter().for_each(|&gap| gamma_encode(&mut writer, gap + 1));
+
+                writer.into_bytes()
+            },
+            BenchCodec::Delta => {
+                let mut writer = BitWriter::new();
+                gaps.iter().for_each(|&gap| delta_encode(&mut writer, gap + 1));
+
+                writer.into_bytes()
+            },
+            BenchCodec::Golomb => {
+                let parameter = golomb_parameter(document_count, gaps.len());
+                let mut writer = BitWriter::new();
+                gaps.iter().for_each(|&gap| golomb_encode(&mut writer, gap, parameter));
+
+                writer.into_bytes()
+            },
+            BenchCodec::Block => block_encode(gaps)
+        }
+    }
+
+    fn decode(&self, data: &[u8], count: usize, document_count: usize) -> Result<Vec<usize>> {
+        match self {
+            BenchCodec::VariableByte => {
+                let mut reader = ByteReader::new(data);
+
+                (0..count).map(|_| reader.read_vb()).collect()
+            },
+            BenchCodec::Gamma => {
+                let mut reader = BitReader::new(data);
+
+                (0..count).map(|_| gamma_decode(&mut reader).map(|gap| gap - 1)).collect()
+            },
+            BenchCodec::Delta => {
+                let mut reader = BitReader::new(data);
+
+                (0..count).map(|_| delta_decode(&mut reader).map(|gap| gap - 1)).collect()
+            },
+            BenchCodec::Golomb => {
+                let parameter = golomb_parameter(document_count, count);
+                let mut reader = BitReader::new(data);
+
+                (0..count).map(|_| golomb_decode(&mut reader, parameter)).collect()
+            },
+            BenchCodec::Block => block_decode(data, count)
+        }
+    }
+}
+
+struct BenchResult {
+    codec: BenchCodec,
+    size: usize,
+    encode_time: Duration,
+    decode_time: Duration
+}
+
+fn bench(codec: BenchCodec, gap_lists: &[Vec<usize>], document_count: usize) -> Result<BenchResult> {
+    let (encoded, encode_time) = metrics().time(&format!("{}_encode", codec.name()), || {
+        gap_lists.iter()
+            .map(|gaps| codec.encode(gaps, document_count))
+            .collect::<Vec<_>>()
+    });
+    let (decoded, decode_time) = metrics().time(&format!("{}_decode", codec.name()), || {
+        encoded.iter()
+            .zip(gap_lists)
+            .map(|(data, gaps)| codec.decode(data, gaps.len(), document_count))
+            .collect::<Result<Vec<_>>>()
+    });
+    if decoded? != gap_lists {
+        return Err(anyhow!("Codec {} didn't decode postings back", codec.name()));
+    }
+
+    Ok(BenchResult {
+        codec,
+        size: encoded.iter().map(Vec::len).sum(),
+        encode_time,
+        decode_time
+    })
+}
+
+pub fn bench_codecs(index_path: &str, csv_path: &str) -> Result<()> {
+    let index = InvertedIndex::load(&persist::load_checked(index_path)?[..])?;
+    let document_count = index.document_count();
+    let gap_lists = index.posting_lists()
+        .into_iter()
+        .map(|documents| {
+            let mut previous = 0;
+            documents.into_iter()
+                .map(|document| {
+                    let gap = document - previous;
+                    previous = document;
+
+                    gap
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let posting_count = gap_lists.iter().map(Vec::len).sum::<usize>();
+    println!("Benchmarking {} posting lists with {} postings from \"{index_path}\"", gap_lists.len(), posting_count);
+
+    let results = BenchCodec::ALL.iter()
+        .map(|&codec| bench(codec, &gap_lists, document_count))
+        .collect::<Result<Vec<_>>>()?;
+
+    println!("{:<8} | {:>12} | {:>12} | {:>14} | {:>14}", "Codec", "Size", "Bits/posting", "Encode", "Decode");
+    for result in &results {
+        println!("{:<8} | {:>12} | {:>12.3} | {:>14} | {:>14}",
+                 result.codec.name(),
+                 human_bytes(result.size as f64),
+                 result.size as f64 * 8.0 / posting_count.max(1) as f64,
+                 format!("{:?}", result.encode_time),
+                 format!("{:?}", result.decode_time));
+    }
+
+    let mut writer = BufWriter::new(File::create(csv_path)?);
+    writeln!(writer, "codec,bytes,bits_per_posting,encode_us,decode_us")?;
+    for result in &results {
+        writeln!(writer, "{},{},{},{},{}",
+                 result.codec.name(),
+                 result.size,
+                 result.size as f64 * 8.0 / posting_count.max(1) as f64,
+                 result.encode_time.as_micros(),
+                 result.decode_time.as_micros())?;
+    }
+    println!("Report written to \"{csv_path}\"");
+
+    Ok(())
+}
@@ -3,13 +3,22 @@ use std::sync::Arc;
 use crate::inf_context::InfContext;
 use crate::term_index::InvertedIndex;
 use crate::lexer::{Lexer, LexerStats};
-use crate::document::DocumentId;
+use crate::document::{Document, DocumentId};
+use crate::language::detect_language;
 
 pub fn add_file_to_index(document_id: DocumentId, ctx: Arc<InfContext>) -> Result<Option<(InvertedIndex, LexerStats)>> {
+    Ok(Some(index_document(document_id, &ctx)?))
+}
+
+pub fn index_document(document_id: DocumentId, ctx: &InfContext) -> Result<(InvertedIndex, LexerStats)> {
     let mut inverted_index = InvertedIndex::new();
-    let lexer = Lexer::new(document_id, &ctx)?;
+    let lexer = Lexer::new(document_id, ctx)?;
     let stats = lexer.lex(&mut inverted_index);
+    inverted_index.add_field("lang", detect_language(ctx.document_text(document_id)?), document_id);
+    if let Some(collection) = ctx.document(document_id).and_then(Document::collection) {
+        inverted_index.add_field("collection", collection, document_id);
+    }
     inverted_index.shrink_to_fit();
 
-    Ok(Some((inverted_index, stats)))
+    Ok((inverted_index, stats))
 }
@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use crate::query_lang::LogicNode;
+use crate::term_index::{InvertedIndex, TermIndex};
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum CostGuard {
+    Off,
+    Warn,
+    Refuse
+}
+
+impl FromStr for CostGuard {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => Ok(CostGuard::Off),
+            "warn" => Ok(CostGuard::Warn),
+            "refuse" => Ok(CostGuard::Refuse),
+            _ => Err(anyhow!("Unknown cost guard '{s}', expected 'off', 'warn' or 'refuse'"))
+        }
+    }
+}
+
+// NOTE: Sizes are estimated from document frequencies only, assuming terms are independent
+#[derive(Clone, Copy, Debug)]
+pub struct QueryCost {
+    pub estimated_results: usize,
+    // NOTE: Largest set a negation has to build by complementing against every document
+    pub largest_complement: usize
+}
+
+impl QueryCost {
+    pub const DEFAULT_GUARD_RATIO: f64 = 0.9;
+
+    pub fn estimate(query_ast: &LogicNode, index: &dyn TermIndex) -> Self {
+        let document_count = index.document_count();
+        let term = |df: usize| QueryCost { estimated_results: df, largest_complement: 0 };
+
+        match query_ast {
+            LogicNode::False => term(0),
+            LogicNode::Term(value) => term(index.document_frequency(value)),
+            LogicNode::Field(name, value) => term(index.document_frequency(&InvertedIndex::field_term(name, value))),
+            LogicNode::And(lhs, rhs) | LogicNode::Near(lhs, rhs, _, _) => {
+                Self::estimate_binary(lhs, rhs, index, |lhs, rhs| lhs.min(rhs))
+            },
+            LogicNode::Or(lhs, rhs) => {
+                Self::estimate_binary(lhs, rhs, index, |lhs, rhs| (lhs + rhs).min(document_count))
+            },
+            LogicNode::Subtract(lhs, rhs) => Self::estimate_binary(lhs, rhs, index, |lhs, _| lhs),
+            LogicNode::Not(operand) => {
+                let operand = Self::estimate(operand, index);
+                let complement = document_count.saturating_sub(operand.estimated_results);
+
+                QueryCost {
+                    estimated_results: complement,
+                    largest_complement: operand.largest_complement.max(complement)
+                }
+            }
+        }
+    }
+
+    fn estimate_binary(lhs: &LogicNode, rhs: &LogicNode, index: &dyn TermIndex, combine: impl FnOnce(usize, usize) -> usize) -> Self {
+        let lhs = Self::estimate(lhs, index);
+        let rhs = Self::estimate(rhs, index);
+
+        QueryCost {
+            estimated_results: combine(lhs.estimated_results, rhs.estimated_results),
+            largest_complement: lhs.largest_complement.max(rhs.largest_complement)
+        }
+    }
+
+    pub fn exceeds(&self, document_count: usize, ratio: f64) -> bool {
+        document_count != 0 && self.largest_complement as f64 >= document_count as f64 * ratio
+    }
+}
+
+// NOTE: Conjunctions with a negation are turned into subtractions, which never build the complement
+pub fn suggest_rewrite(query_ast: &LogicNode) -> LogicNode {
+    let boxed = |node: &LogicNode| Box::new(suggest_rewrite(node));
+
+    match query_ast {
+        LogicNode::And(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+            (LogicNode::Not(negated), other) | (other, LogicNode::Not(negated)) if !matches!(other, LogicNode::Not(_)) => {
+                LogicNode::Subtract(boxed(other), boxed(negated))
+            },
+            _ => LogicNode::And(boxed(lhs), boxed(rhs))
+        },
+        LogicNode::Or(lhs, rhs) => LogicNode::Or(boxed(lhs), boxed(rhs)),
+        LogicNode::Subtract(lhs, rhs) => LogicNode::Subtract(boxed(lhs), boxed(rhs)),
+        LogicNode::Near(lhs, rhs, left, right) => LogicNode::Near(boxed(lhs), boxed(rhs), *left, *right),
+        LogicNode::Not(operand) => LogicNode::Not(boxed(operand)),
+        LogicNode::False | LogicNode::Term(_) | LogicNode::Field(_, _) => query_ast.clone()
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::{anyhow, Result};
+use ahash::{AHashMap, AHashSet};
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use itertools::Itertools;
+use memmap::Mmap;
+use crate::document::{DocumentId, DocumentRegistry};
+use crate::persist;
+use crate::query_lang::LogicNode;
+use crate::term_index::{InvertedIndex, TermIndex};
+
+pub const DISK_INDEX_PATH: &str = "data/index.disk";
+const MAGIC: &[u8] = b"PW6DISK1";
+const WORD: usize = size_of::<u64>();
+
+// NOTE: Layout is the magic, the document registry as json, then the document and term counts,
+//  sorted ids of all documents, an offset table with a term offset and a postings offset per term
+//  plus one past the last term, the terms themselves, and the postings as fixed width document ids.
+//  Only the registry is read up front, the rest is mapped and paged in by the terms a query touches
+pub struct DiskIndex {
+    mmap: Mmap,
+    registry: DocumentRegistry,
+    document_count: usize,
+    term_count: usize,
+    documents_start: usize,
+    table_start: usize,
+    terms_start: usize,
+    postings_start: usize,
+    // NOTE: Terms added after the index was written, they're kept in memory and queried along with the file
+    pending: InvertedIndex
+}
+
+impl DiskIndex {
+    pub fn save(index: &InvertedIndex, registry: &DocumentRegistry, path: impl AsRef<Path>) -> Result<()> {
+        let terms = index.sorted_terms().collect::<Vec<_>>();
+        let documents = terms.iter()
+            .flat_map(|(_, documents)| documents.iter().copied())
+            .sorted()
+            .dedup()
+            .collect::<Vec<_>>();
+
+        persist::save_checked(path, |writer| {
+            let registry = serde_json::to_vec(registry)?;
+            writer.write_all(MAGIC)?;
+            write_word(writer, registry.len())?;
+            writer.write_all(&registry)?;
+
+            write_word(writer, documents.len())?;
+            write_word(writer, terms.len())?;
+            documents.iter().try_for_each(|document_id| write_word(writer, document_id.id()))?;
+
+            let (mut term_offset, mut posting_offset) = (0, 0);
+            for (term, documents) in &terms {
+                write_word(writer, term_offset)?;
+                write_word(writer, posting_offset)?;
+                term_offset += term.len();
+                posting_offset += documents.len();
+            }
+            write_word(writer, term_offset)?;
+            write_word(writer, posting_offset)?;
+
+            terms.iter().try_for_each(|(term, _)| writer.write_all(term.as_bytes()))?;
+            terms.iter()
+                .flat_map(|(_, documents)| documents.iter())
+                .try_for_each(|document_id| write_word(writer, document_id.id()))?;
+
+            Ok(())
+        })
+    }
+
+    // NOTE: The checksum trailer isn't verified, that would read every page of the file,
+    //  the sizes of all regions are checked against the file size instead. Offsets of the table
+    //  must not decrease, so every term and posting list lies within its region
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        let malformed = || anyhow!("Disk index {path:?} is truncated or malformed");
+
+        if mmap.get(..MAGIC.len()) != Some(MAGIC) {
+            return Err(anyhow!("{path:?} isn't a disk index"));
+        }
+        let registry_length = read_word(&mmap, MAGIC.len()).ok_or_else(malformed)?;
+        let registry_start = MAGIC.len() + WORD;
+        let registry_end = registry_start.checked_add(registry_length).ok_or_else(malformed)?;
+        let registry = serde_json::from_slice(mmap.get(registry_start..registry_end).ok_or_else(malformed)?)?;
+
+        let document_count = read_word(&mmap, registry_end).ok_or_else(malformed)?;
+        let term_count = read_word(&mmap, registry_end + WORD).ok_or_else(malformed)?;
+        let documents_start = registry_end + 2 * WORD;
+        let table_start = document_count.checked_mul(WORD)
+            .and_then(|length| documents_start.checked_add(length))
+            .ok_or_else(malformed)?;
+        let terms_start = term_count.checked_add(1)
+            .and_then(|entries| entries.checked_mul(2 * WORD))
+            .and_then(|length| table_start.checked_add(length))
+            .ok_or_else(malformed)?;
+
+        let mut index = DiskIndex {
+            mmap,
+            registry,
+            document_count,
+            term_count,
+            documents_start,
+            table_start,
+            terms_start,
+            postings_start: terms_start,
+            pending: InvertedIndex::new()
+        };
+        let mut previous = (0, 0);
+        for position in 0..=term_count {
+            let entry = index.table_entry(position).ok_or_else(malformed)?;
+            if entry.0 < previous.0 || entry.1 < previous.1 {
+                return Err(malformed());
+            }
+            previous = entry;
+        }
+        let (terms_length, postings_length) = previous;
+        index.postings_start = terms_start.checked_add(terms_length).ok_or_else(malformed)?;
+        let postings_end = postings_length.checked_mul(WORD)
+            .and_then(|length| index.postings_start.checked_add(length))
+            .ok_or_else(malformed)?;
+        if postings_end > index.mmap.len() {
+            return Err(malformed());
+        }
+
+        Ok(index)
+    }
+
+    pub fn registry(&self) -> &DocumentRegistry {
+        &self.registry
+    }
+
+    pub fn unique_word_count(&self) -> usize {
+        self.term_count
+    }
+
+    // NOTE: Same as for the in-memory index, a disk index never has positions
+    pub fn positions_unavailable(&self) -> Option<&'static str> {
+        Some("the disk index keeps only postings")
+    }
+
+    fn table_entry(&self, position: usize) -> Option<(usize, usize)> {
+        let offset = self.table_start + position * 2 * WORD;
+
+        Some((read_word(&self.mmap, offset)?, read_word(&self.mmap, offset + WORD)?))
+    }
+
+    fn term(&self, position: usize) -> Option<&[u8]> {
+        let (start, _) = self.table_entry(position)?;
+        let (end, _) = self.table_entry(position + 1)?;
+
+        self.mmap.get(self.terms_start + start..self.terms_start + end)
+    }
+
+    // NOTE: Terms are sorted, so the dictionary is binary searched through the offset table
+    fn postings(&self, term: &str) -> &[u8] {
+        let (mut low, mut high) = (0, self.term_count);
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.term(middle).map(|candidate| candidate.cmp(term.as_bytes())) {
+                Some(Ordering::Less) => low = middle + 1,
+                Some(Ordering::Greater) => high = middle,
+                Some(Ordering::Equal) => {
+                    let (_, start) = self.table_entry(middle).unwrap_or_default();
+                    let (_, end) = self.table_entry(middle + 1).unwrap_or_default();
+
+                    return self.mmap.get(self.postings_start + start * WORD..self.postings_start + end * WORD).unwrap_or_default();
+                },
+                None => break
+            }
+        }
+
+        &[]
+    }
+
+    fn term_positions(&self, term: &str) -> AHashSet<DocumentId> {
+        let mut documents = decode_documents(self.postings(term)).collect::<AHashSet<_>>();
+        documents.extend(self.pending.term_positions(term));
+
+        documents
+    }
+
+    fn all_documents(&self) -> AHashSet<DocumentId> {
+        let length = self.document_count * WORD;
+        let documents = self.mmap.get(self.documents_start..self.documents_start + length).unwrap_or_default();
+
+        decode_documents(documents)
+            .chain(self.pending.documents().iter().copied())
+            .collect()
+    }
+
+    fn query_rec(&self, query_ast: &LogicNode) -> Result<AHashSet<DocumentId>> {
+        Ok(match query_ast {
+            LogicNode::False => AHashSet::new(),
+            LogicNode::Term(term) => self.term_positions(term),
+            LogicNode::And(lhs, rhs) => &self.query_rec(lhs)? & &self.query_rec(rhs)?,
+            LogicNode::Or(lhs, rhs) => &self.query_rec(lhs)? | &self.query_rec(rhs)?,
+            LogicNode::Not(operand) => &self.all_documents() - &self.query_rec(operand)?,
+            LogicNode::Near(_, _, _, _) => {
+                let reason = self.positions_unavailable().unwrap_or_default();
+                return Err(anyhow!("Phrase and proximity queries aren't available, {reason}"));
+            },
+            LogicNode::Subtract(lhs, rhs) => &self.query_rec(lhs)? - &self.query_rec(rhs)?,
+            LogicNode::Field(name, value) => self.term_positions(&InvertedIndex::field_term(name, value))
+        })
+    }
+}
+
+impl TermIndex for DiskIndex {
+    fn add_term(&mut self, term: String, document_id: DocumentId) {
+        self.pending.add_term(term, document_id);
+    }
+
+    // NOTE: Posting lists are read whole, so a limit only trims the result to the lowest document ids
+    fn query(&self, query_ast: &LogicNode, limit: Option<usize>) -> Result<AHashSet<DocumentId>> {
+        let result = self.query_rec(query_ast)?;
+
+        Ok(match limit {
+            Some(limit) => result.into_iter().sorted().take(limit).collect(),
+            None => result
+        })
+    }
+
+    fn document_frequency(&self, term: &str) -> usize {
+        match self.pending.document_frequency(term) {
+            0 => self.postings(term).len() / WORD,
+            _ => self.term_positions(term).len()
+        }
+    }
+
+    fn document_count(&self) -> usize {
+        if self.pending.documents().is_empty() {
+            return self.document_count;
+        }
+
+        self.all_documents().len()
+    }
+
+    // NOTE: Posting lists are sorted, so the document is binary searched without decoding the list
+    fn contains(&self, term: &str, document_id: DocumentId) -> bool {
+        let postings = self.postings(term);
+        let (mut low, mut high) = (0, postings.len() / WORD);
+        while low < high {
+            let middle = (low + high) / 2;
+            match read_word(postings, middle * WORD).map(|id| id.cmp(&document_id.id())) {
+                Some(Ordering::Less) => low = middle + 1,
+                Some(Ordering::Greater) => high = middle,
+                Some(Ordering::Equal) => return true,
+                None => break
+            }
+        }
+
+        self.pending.contains(term, document_id)
+    }
+
+    // NOTE: Reads every posting list once
+    fn document_term_counts(&self) -> AHashMap<DocumentId, usize> {
+        let mut counts = AHashMap::new();
+        let postings_end = self.table_entry(self.term_count).map_or(0, |(_, end)| end);
+        let postings = self.mmap.get(self.postings_start..self.postings_start + postings_end * WORD).unwrap_or_default();
+        decode_documents(postings)
+            .for_each(|document_id| *counts.entry(document_id).or_default() += 1);
+        for (term, documents) in self.pending.sorted_terms() {
+            documents.into_iter()
+                .filter(|&document_id| !decode_documents(self.postings(term)).contains(&document_id))
+                .for_each(|document_id| *counts.entry(document_id).or_default() += 1);
+        }
+
+        counts
+    }
+}
+
+fn write_word(writer: &mut impl Write, value: usize) -> Result<()> {
+    writer.write_all(&(value as u64).to_le_bytes())?;
+
+    Ok(())
+}
+
+fn decode_documents(postings: &[u8]) -> impl Iterator<Item = DocumentId> + '_ {
+    postings.chunks_exact(WORD)
+        .map(|word| DocumentId(u64::from_le_bytes(word.try_into().unwrap()) as usize))
+}
+
+fn read_word(data: &[u8], offset: usize) -> Option<usize> {
+    let word = data.get(offset..offset.checked_add(WORD)?)?;
+
+    usize::try_from(u64::from_le_bytes(word.try_into().ok()?)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::query_lang::parse_logic_expr;
+
+    const TERMS: [&str; 10] = ["a", "ab", "abc", "abd", "b", "bar", "bark", "zebra", "ёж", "ёжик"];
+    const QUERIES: [&str; 8] = ["ab", "a & b", "a | zebra", "!a", "bar \\ bark", "!(ab | ёж) & b", "missing", "ёж | ёжик"];
+
+    fn build_index() -> InvertedIndex {
+        let mut index = InvertedIndex::new();
+        for (i, term) in TERMS.iter().enumerate() {
+            for document_id in (0..40).filter(|document_id| document_id % (i + 2) == 0) {
+                index.add_term(term.to_string(), DocumentId(document_id));
+            }
+        }
+
+        index
+    }
+
+    // NOTE: Unique per test, tests run in parallel
+    fn saved(index: &InvertedIndex, name: &str) -> Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!("pw6-{name}-{}.disk", std::process::id()));
+        DiskIndex::save(index, &DocumentRegistry::new(), &path)?;
+
+        Ok(path)
+    }
+
+    #[test]
+    fn queries_match_in_memory_index() -> Result<()> {
+        let index = build_index();
+        let path = saved(&index, "round-trip")?;
+        let disk_index = DiskIndex::open(&path);
+        fs::remove_file(&path)?;
+        let disk_index = disk_index?;
+
+        assert_eq!(disk_index.unique_word_count(), TERMS.len());
+        assert_eq!(TermIndex::document_count(&disk_index), TermIndex::document_count(&index));
+        assert_eq!(disk_index.document_term_counts(), index.document_term_counts());
+        for term in TERMS.iter().chain(&["missing"]) {
+            assert_eq!(disk_index.document_frequency(term), index.document_frequency(term), "{term}");
+            for document_id in (0..41).map(DocumentId) {
+                assert_eq!(disk_index.contains(term, document_id), index.contains(term, document_id), "{term} in {document_id}");
+            }
+        }
+        for query in QUERIES {
+            let query_ast = parse_logic_expr(query)?;
+            assert_eq!(disk_index.query(&query_ast, None)?, index.query(&query_ast, None)?, "{query}");
+            assert_eq!(disk_index.query(&query_ast, Some(3))?.len(), index.query(&query_ast, Some(3))?.len(), "{query} with a limit");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() -> Result<()> {
+        let path = saved(&build_index(), "truncated")?;
+        let data = fs::read(&path)?;
+        let disk_index = DiskIndex::open(&path)?;
+        let postings_end = disk_index.postings_start + disk_index.table_entry(disk_index.term_count).unwrap().1 * WORD;
+
+        // NOTE: The checksum trailer after the postings isn't needed to query
+        for length in 0..postings_end {
+            fs::write(&path, &data[..length])?;
+            assert!(DiskIndex::open(&path).is_err(), "truncated to {length} bytes");
+        }
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn corrupted_table_is_an_error() -> Result<()> {
+        let path = saved(&build_index(), "corrupted")?;
+        let data = fs::read(&path)?;
+        let table_start = DiskIndex::open(&path)?.table_start;
+
+        // NOTE: Term and posting offsets of the second entry, past the end and before the first entry
+        for (offset, value) in [(2 * WORD, u64::MAX), (2 * WORD, 1 << 40), (3 * WORD, u64::MAX), (4 * WORD, 0)] {
+            let mut corrupted = data.clone();
+            corrupted[table_start + offset..table_start + offset + WORD].copy_from_slice(&value.to_le_bytes());
+            fs::write(&path, &corrupted)?;
+            assert!(DiskIndex::open(&path).is_err(), "{value} at {offset}");
+        }
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn phrases_explain_why_they_fail() -> Result<()> {
+        let path = saved(&build_index(), "phrases")?;
+        let disk_index = DiskIndex::open(&path);
+        fs::remove_file(&path)?;
+
+        let error = disk_index?.query(&parse_logic_expr("\"a b\"")?, None).unwrap_err();
+        assert!(error.to_string().contains("keeps only postings"), "{error}");
+
+        Ok(())
+    }
+}
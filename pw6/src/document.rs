@@ -61,7 +61,7 @@ impl DocumentRegistry {
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 pub enum Document {
-    File { path: PathBuf, file_id: FileId }
+    File { path: PathBuf, file_id: FileId, collection: Option<String> }
 }
 
 impl Document {
@@ -70,4 +70,10 @@ impl Document {
             Document::File { path, .. } => path.to_string_lossy().to_string()
         }
     }
+
+    pub fn collection(&self) -> Option<&str> {
+        match self {
+            Document::File { collection, .. } => collection.as_deref()
+        }
+    }
 }
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use ahash::{AHashMap, AHashSet};
+use itertools::Itertools;
+use crate::common::index_document;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::lexer::LexerStats;
+use crate::query_lang::LogicNode;
+use crate::term_index::{InvertedIndex, TermIndex};
+
+pub const DEFAULT_MERGE_THRESHOLD: usize = 64;
+
+// NOTE: Documents added after the index was built go to a small auxiliary index, removed ones are
+//  only remembered. Both are queried along with the main index, and once enough documents were added
+//  or removed, the auxiliary index is merged into the main one and removed documents are purged from it.
+//  Every document lives in exactly one of the two indexes, so results of both can simply be joined
+pub struct DynamicIndex {
+    main: InvertedIndex,
+    auxiliary: InvertedIndex,
+    removed: AHashSet<DocumentId>,
+    merge_threshold: usize
+}
+
+impl DynamicIndex {
+    pub fn new(main: InvertedIndex, merge_threshold: usize) -> Self {
+        DynamicIndex {
+            main,
+            auxiliary: InvertedIndex::new(),
+            removed: AHashSet::new(),
+            merge_threshold
+        }
+    }
+
+    pub fn add_document(&mut self, document_id: DocumentId, ctx: &InfContext) -> Result<LexerStats> {
+        let (partial, stats) = index_document(document_id, ctx)?;
+        self.auxiliary.merge(partial);
+        self.merge_if_full();
+
+        Ok(stats)
+    }
+
+    pub fn remove_document(&mut self, document_id: DocumentId) {
+        self.removed.insert(document_id);
+        self.merge_if_full();
+    }
+
+    // NOTE: Number of documents added or removed since the last merge
+    pub fn pending_changes(&self) -> usize {
+        self.auxiliary.documents().len() + self.removed.len()
+    }
+
+    fn merge_if_full(&mut self) {
+        if self.pending_changes() >= self.merge_threshold {
+            self.merge();
+        }
+    }
+
+    pub fn merge(&mut self) {
+        let auxiliary = std::mem::replace(&mut self.auxiliary, InvertedIndex::new());
+        self.main.merge(auxiliary);
+        self.main.remove_documents(&self.removed);
+        self.main.shrink_to_fit();
+        self.removed.clear();
+    }
+
+    // NOTE: Documents of both indexes answer every query, so the main index decides
+    pub fn positions_unavailable(&self) -> Option<&'static str> {
+        self.main.positions_unavailable()
+    }
+
+    pub fn unique_word_count(&self) -> usize {
+        self.main.unique_word_count() + self.auxiliary.sorted_terms()
+            .filter(|(term, _)| self.main.document_frequency(term) == 0)
+            .count()
+    }
+
+    fn term_positions(&self, term: &str) -> AHashSet<DocumentId> {
+        let mut documents = self.main.term_positions(term);
+        documents.extend(self.auxiliary.term_positions(term));
+        documents.retain(|document_id| !self.removed.contains(document_id));
+
+        documents
+    }
+}
+
+impl TermIndex for DynamicIndex {
+    fn add_term(&mut self, term: String, document_id: DocumentId) {
+        self.auxiliary.add_term(term, document_id);
+    }
+
+    fn add_term_at(&mut self, term: String, document_id: DocumentId, offset: usize) {
+        self.auxiliary.add_term_at(term, document_id, offset);
+    }
+
+    // NOTE: The limit is applied to each index, so the joined result is trimmed once more
+    fn query(&self, query_ast: &LogicNode, limit: Option<usize>) -> Result<AHashSet<DocumentId>> {
+        let mut result = self.main.query(query_ast, limit)?;
+        result.extend(self.auxiliary.query(query_ast, limit)?);
+        result.retain(|document_id| !self.removed.contains(document_id));
+
+        Ok(match limit {
+            Some(limit) if result.len() > limit => result.into_iter().sorted().take(limit).collect(),
+            _ => result
+        })
+    }
+
+    fn document_frequency(&self, term: &str) -> usize {
+        if self.removed.is_empty() {
+            return self.main.document_frequency(term) + self.auxiliary.document_frequency(term);
+        }
+
+        self.term_positions(term).len()
+    }
+
+    fn document_count(&self) -> usize {
+        self.main.documents().iter()
+            .chain(self.auxiliary.documents())
+            .filter(|document_id| !self.removed.contains(document_id))
+            .count()
+    }
+
+    fn contains(&self, term: &str, document_id: DocumentId) -> bool {
+        !self.removed.contains(&document_id) && (self.main.contains(term, document_id) || self.auxiliary.contains(term, document_id))
+    }
+
+    fn document_term_counts(&self) -> AHashMap<DocumentId, usize> {
+        let mut counts = self.main.document_term_counts();
+        counts.extend(self.auxiliary.document_term_counts());
+        counts.retain(|document_id, _| !self.removed.contains(document_id));
+
+        counts
+    }
+}
@@ -1,4 +1,5 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
 
 const CONT_MASK: u8 = 0b10000000;
 
@@ -23,15 +24,390 @@ pub fn vb_encode(value: usize) -> Vec<u8> {
     result
 }
 
-pub fn vb_decode(data: &mut impl Iterator<Item = Result<u8, std::io::Error>>) -> Result<usize> {
-    let mut result = 0;
-    while let Some(byte) = data.next() {
-        let byte = byte?;
-        result = (result << 7) | ((byte & 127) as usize);
-        if byte & CONT_MASK == CONT_MASK {
-            break;
+// NOTE: Decodes straight from a slice, going through an iterator of bytes costs a branch and a result per byte
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        ByteReader {
+            bytes,
+            position: 0
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position + length)
+            .ok_or_else(|| anyhow!("Unexpected end of encoded data"))?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    pub fn read_vb(&mut self) -> Result<usize> {
+        let mut result = 0;
+        for (i, &byte) in self.bytes[self.position..].iter().enumerate() {
+            result = (result << 7) | ((byte & 127) as usize);
+            if byte & CONT_MASK == CONT_MASK {
+                self.position += i + 1;
+                return Ok(result);
+            }
+        }
+
+        Err(anyhow!("Unexpected end of encoded data"))
+    }
+}
+
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    length: usize
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            length: 0
+        }
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        if self.length.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> (self.length % 8);
+        }
+
+        self.length += 1;
+    }
+
+    pub fn write_bits(&mut self, value: usize, count: u32) {
+        for i in (0..count).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Default for BitWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        BitReader {
+            bytes,
+            position: 0
         }
     }
 
-    Ok(result)
+    pub fn read_bit(&mut self) -> Result<bool> {
+        let byte = self.bytes.get(self.position / 8)
+            .ok_or_else(|| anyhow!("Unexpected end of encoded data"))?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+
+        Ok(bit)
+    }
+
+    pub fn read_bits(&mut self, count: u32) -> Result<usize> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | usize::from(self.read_bit()?);
+        }
+
+        Ok(value)
+    }
+
+    // NOTE: Bits padding the last byte don't count as data
+    pub fn is_empty(&self) -> bool {
+        self.position.div_ceil(8) == self.bytes.len()
+    }
+}
+
+fn bit_length(value: usize) -> u32 {
+    usize::BITS - value.leading_zeros()
+}
+
+// NOTE: Gamma code can't represent zero, callers shift values by one
+pub fn gamma_encode(writer: &mut BitWriter, value: usize) {
+    let length = bit_length(value);
+    for _ in 1..length {
+        writer.write_bit(false);
+    }
+
+    writer.write_bits(value, length);
+}
+
+pub fn gamma_decode(reader: &mut BitReader) -> Result<usize> {
+    let mut zeros = 0;
+    while !reader.read_bit()? {
+        zeros += 1;
+        if zeros == usize::BITS {
+            return Err(anyhow!("Gamma coded value is longer than {} bits", usize::BITS));
+        }
+    }
+
+    Ok((1 << zeros) | reader.read_bits(zeros)?)
+}
+
+// NOTE: Length of the value is gamma coded, then the value follows without its leading one.
+//  Shorter than gamma for large values, like gaps in posting lists of rare terms
+pub fn delta_encode(writer: &mut BitWriter, value: usize) {
+    let length = bit_length(value);
+    gamma_encode(writer, length as usize);
+
+    writer.write_bits(value, length - 1);
+}
+
+pub fn delta_decode(reader: &mut BitReader) -> Result<usize> {
+    let length = gamma_decode(reader)? as u32;
+    if length > usize::BITS {
+        return Err(anyhow!("Delta coded value is {length} bits long"));
+    }
+
+    Ok((1 << (length - 1)) | reader.read_bits(length - 1)?)
+}
+
+// NOTE: Close to optimal parameter for gaps of a term that occurs in `frequency` of `document_count` documents
+pub fn golomb_parameter(document_count: usize, frequency: usize) -> usize {
+    ((0.69 * document_count as f64 / frequency.max(1) as f64).ceil() as usize).max(1)
+}
+
+pub fn golomb_encode(writer: &mut BitWriter, value: usize, parameter: usize) {
+    for _ in 0..value / parameter {
+        writer.write_bit(true);
+    }
+    writer.write_bit(false);
+    if parameter == 1 {
+        return;
+    }
+
+    let remainder = value % parameter;
+    let width = bit_length(parameter - 1);
+    let cutoff = (1 << width) - parameter;
+    if remainder < cutoff {
+        writer.write_bits(remainder, width - 1);
+    } else {
+        writer.write_bits(remainder + cutoff, width);
+    }
+}
+
+pub fn golomb_decode(reader: &mut BitReader, parameter: usize) -> Result<usize> {
+    let mut quotient = 0;
+    while reader.read_bit()? {
+        quotient += 1;
+    }
+    if parameter == 1 {
+        return Ok(quotient);
+    }
+
+    let width = bit_length(parameter - 1);
+    let cutoff = (1 << width) - parameter;
+    let mut remainder = reader.read_bits(width - 1)?;
+    if remainder >= cutoff {
+        remainder = ((remainder << 1) | usize::from(reader.read_bit()?)) - cutoff;
+    }
+
+    Ok(quotient * parameter + remainder)
+}
+
+pub const BLOCK_SIZE: usize = 128;
+
+// NOTE: Every block is bit packed with the width of its largest value, the width takes a leading byte
+pub fn block_encode(values: &[usize]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    for block in values.chunks(BLOCK_SIZE) {
+        let width = block.iter().map(|&value| bit_length(value)).max().unwrap_or(0);
+        writer.write_bits(width as usize, 8);
+        block.iter().for_each(|&value| writer.write_bits(value, width));
+    }
+
+    writer.into_bytes()
+}
+
+pub fn block_decode(data: &[u8], count: usize) -> Result<Vec<usize>> {
+    let mut reader = BitReader::new(data);
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        let width = reader.read_bits(8)? as u32;
+        let block_length = BLOCK_SIZE.min(count - values.len());
+        for _ in 0..block_length {
+            values.push(reader.read_bits(width)?);
+        }
+    }
+
+    Ok(values)
+}
+
+// NOTE: Codec of the postings of a compressed index
+#[derive(Clone, Copy, Eq, PartialEq, Default, Debug)]
+pub enum Codec {
+    #[default]
+    VariableByte,
+    Gamma,
+    Delta
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::VariableByte, Codec::Gamma, Codec::Delta];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::VariableByte => "vb",
+            Codec::Gamma => "gamma",
+            Codec::Delta => "delta"
+        }
+    }
+
+    pub fn tag(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_tag(tag: u8) -> Result<Self> {
+        Self::ALL.get(usize::from(tag))
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown codec tag {tag}"))
+    }
+}
+
+impl FromStr for Codec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "vb" => Ok(Codec::VariableByte),
+            "gamma" => Ok(Codec::Gamma),
+            "delta" => Ok(Codec::Delta),
+            _ => Err(anyhow!("Unknown codec '{s}', expected 'vb', 'gamma' or 'delta'"))
+        }
+    }
+}
+
+// NOTE: Gamma and delta codes can't represent zero, so they store every value shifted by one
+pub enum CodecWriter {
+    Bytes(Vec<u8>),
+    Bits(BitWriter, Codec)
+}
+
+impl CodecWriter {
+    pub fn new(codec: Codec) -> Self {
+        match codec {
+            Codec::VariableByte => CodecWriter::Bytes(Vec::new()),
+            codec => CodecWriter::Bits(BitWriter::new(), codec)
+        }
+    }
+
+    pub fn write(&mut self, value: usize) {
+        match self {
+            CodecWriter::Bytes(bytes) => bytes.extend(vb_encode(value)),
+            CodecWriter::Bits(writer, Codec::Delta) => delta_encode(writer, value + 1),
+            CodecWriter::Bits(writer, _) => gamma_encode(writer, value + 1)
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            CodecWriter::Bytes(bytes) => bytes,
+            CodecWriter::Bits(writer, _) => writer.into_bytes()
+        }
+    }
+}
+
+pub enum CodecReader<'a> {
+    Bytes(ByteReader<'a>),
+    Bits(BitReader<'a>, Codec)
+}
+
+impl<'a> CodecReader<'a> {
+    pub fn new(codec: Codec, data: &'a [u8]) -> Self {
+        match codec {
+            Codec::VariableByte => CodecReader::Bytes(ByteReader::new(data)),
+            codec => CodecReader::Bits(BitReader::new(data), codec)
+        }
+    }
+
+    pub fn read(&mut self) -> Result<usize> {
+        match self {
+            CodecReader::Bytes(reader) => reader.read_vb(),
+            CodecReader::Bits(reader, Codec::Delta) => Ok(delta_decode(reader)? - 1),
+            CodecReader::Bits(reader, _) => Ok(gamma_decode(reader)? - 1)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            CodecReader::Bytes(reader) => reader.is_empty(),
+            CodecReader::Bits(reader, _) => reader.is_empty()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [usize; 9] = [0, 1, 2, 3, 7, 8, 1 << 20, 1 << 62, usize::MAX - 1];
+
+    #[test]
+    fn codecs_round_trip() -> Result<()> {
+        for codec in Codec::ALL {
+            let mut writer = CodecWriter::new(codec);
+            VALUES.iter().for_each(|&value| writer.write(value));
+            let data = writer.into_bytes();
+
+            let mut reader = CodecReader::new(codec, &data);
+            for &value in &VALUES {
+                assert!(!reader.is_empty(), "{codec:?}");
+                assert_eq!(reader.read()?, value, "{codec:?}");
+            }
+            assert!(reader.is_empty(), "{codec:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn padding_is_not_data() -> Result<()> {
+        for codec in [Codec::Gamma, Codec::Delta] {
+            for count in 1..=16 {
+                let mut writer = CodecWriter::new(codec);
+                (0..count).for_each(|_| writer.write(0));
+                let data = writer.into_bytes();
+
+                let mut reader = CodecReader::new(codec, &data);
+                for _ in 0..count {
+                    assert_eq!(reader.read()?, 0, "{codec:?}");
+                }
+                assert!(reader.is_empty(), "{codec:?} {count}");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_gamma_code_is_an_error() {
+        let data = [0u8; 9];
+
+        assert!(gamma_decode(&mut BitReader::new(&data)).is_err());
+        assert!(delta_decode(&mut BitReader::new(&data)).is_err());
+    }
 }
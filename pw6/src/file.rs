@@ -38,12 +38,11 @@ impl FilePool {
         self.files.get(file_id.0)
     }
 
-    pub fn add_file(&mut self, path: &PathBuf) -> Result<FileId> {
-        let file = File::new(path)?;
+    pub fn add(&mut self, file: File) -> FileId {
         let id = self.files.len();
         self.files.push(file);
 
-        Ok(FileId(id))
+        FileId(id)
     }
 }
 
@@ -0,0 +1,69 @@
+use anyhow::Result;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::document::Document;
+use crate::inf_context::InfContext;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DocumentFingerprint {
+    pub path: PathBuf,
+    pub size: usize,
+    pub hash: u32
+}
+
+impl DocumentFingerprint {
+    pub fn new(path: PathBuf, data: &[u8]) -> Self {
+        DocumentFingerprint {
+            path,
+            size: data.len(),
+            hash: crc32fast::hash(data)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CorpusChange {
+    Missing,
+    Resized { before: usize, after: usize },
+    Modified
+}
+
+impl Display for CorpusChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CorpusChange::Missing => write!(f, "missing"),
+            CorpusChange::Resized { before, after } => write!(f, "size changed from {before} to {after} bytes"),
+            CorpusChange::Modified => write!(f, "content changed")
+        }
+    }
+}
+
+// NOTE: Fingerprints are in document id order, so they line up with the registry they were taken with
+pub fn fingerprint_corpus(ctx: &InfContext) -> Result<Vec<DocumentFingerprint>> {
+    ctx.document_ids()
+        .filter_map(|document_id| ctx.document(document_id).map(|document| (document_id, document)))
+        .map(|(document_id, document)| {
+            let Document::File { path, .. } = document;
+
+            Ok(DocumentFingerprint::new(path.clone(), ctx.document_data(document_id)?.as_bytes()))
+        })
+        .collect()
+}
+
+pub fn verify_corpus(fingerprints: &[DocumentFingerprint]) -> Vec<(&DocumentFingerprint, CorpusChange)> {
+    fingerprints.iter()
+        .filter_map(|fingerprint| {
+            let change = match fs::read(&fingerprint.path) {
+                Err(_) => CorpusChange::Missing,
+                Ok(data) if data.len() != fingerprint.size => CorpusChange::Resized { before: fingerprint.size, after: data.len() },
+                Ok(data) if crc32fast::hash(&data) != fingerprint.hash => CorpusChange::Modified,
+                Ok(_) => return None
+            };
+
+            Some((fingerprint, change))
+        })
+        .collect()
+}
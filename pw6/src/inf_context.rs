@@ -1,52 +1,108 @@
 use anyhow::{anyhow, Result, Context};
+use rayon::prelude::*;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use ahash::AHashSet;
 use crate::document::{Document, DocumentRegistry};
-use crate::file::FilePool;
+use crate::file::{File, FilePool};
 use crate::document::DocumentId;
+use crate::boilerplate::BoilerplateFilter;
 
 pub struct InfContext {
     documents: DocumentRegistry,
-    files: FilePool
+    files: FilePool,
+    boilerplate: BoilerplateFilter,
+    // NOTE: Removed documents keep their ids, so ids of the others don't change
+    removed: AHashSet<DocumentId>
 }
 
 impl InfContext {
-    pub fn new(base_path: &str, file_limit: Option<usize>) -> Result<Arc<Self>> {
+    pub fn new(base_path: &str, file_limit: Option<usize>, boilerplate: BoilerplateFilter) -> Result<Arc<Self>> {
         let mut file_names = get_files(base_path)?;
-        let mut files = FilePool::new();
-        let mut documents = DocumentRegistry::new();
+        if let Some(file_limit) = file_limit {
+            file_names.truncate(file_limit);
+        }
 
-        let mut i = 0;
-        for path in file_names.drain(..) {
-            if let Some(file_limit) = file_limit {
-                if i >= file_limit {
-                    break;
-                }
-            }
-            i += 1;
+        // NOTE: Files are opened in parallel, but registered in their original order,
+        //  so document ids don't depend on which file finished opening first
+        let opened = file_names.into_par_iter()
+            .map(|(path, collection)| (File::new(&path), (path, collection)))
+            .collect::<Vec<_>>();
 
-            let file_id = match files.add_file(&path) {
-                Ok(file_id) => file_id,
+        let mut files = FilePool::new();
+        let mut documents = DocumentRegistry::new();
+        for (file, (path, collection)) in opened {
+            let file = match file {
+                Ok(file) => file,
                 Err(err) => {
                     println!("Ignoring file {:?}. Error: {}. Caused by: {}", path, err, err.root_cause());
                     continue;
                 }
             };
-            documents.add_document(Document::File { path, file_id });
+            let file_id = files.add(file);
+            documents.add_document(Document::File { path, file_id, collection });
         }
 
         Ok(Arc::new(InfContext {
             documents,
-            files
+            files,
+            boilerplate,
+            removed: AHashSet::new()
         }))
     }
 
+    pub fn add_document(&mut self, path: PathBuf, collection: Option<String>) -> Result<DocumentId> {
+        if self.indexed_paths().contains(&path) {
+            return Err(anyhow!("File {path:?} is already indexed"));
+        }
+        let file_id = self.files.add(File::new(&path)?);
+
+        Ok(self.documents.add_document(Document::File { path, file_id, collection }))
+    }
+
+    // NOTE: The file stays mapped, the document is only left out of everything that lists documents
+    pub fn remove_document(&mut self, document_id: DocumentId) -> Result<()> {
+        if self.documents.document(document_id).is_none() || !self.removed.insert(document_id) {
+            return Err(anyhow!("Document with id {document_id} doesn't exist"));
+        }
+
+        Ok(())
+    }
+
+    // NOTE: Files of the folder no document was added for yet, in the order they would be indexed in
+    pub fn unindexed_files(&self, base_path: &str) -> Result<Vec<(PathBuf, Option<String>)>> {
+        let indexed = self.indexed_paths();
+
+        Ok(get_files(base_path)?
+            .into_iter()
+            .filter(|(path, _)| !indexed.contains(path))
+            .collect())
+    }
+
+    fn indexed_paths(&self) -> AHashSet<&PathBuf> {
+        self.document_ids()
+            .filter_map(|document_id| self.document(document_id))
+            .map(|Document::File { path, .. }| path)
+            .collect()
+    }
+
+    pub fn vanished_documents(&self) -> Vec<DocumentId> {
+        self.document_ids()
+            .filter(|&document_id| self.document(document_id).is_some_and(|Document::File { path, .. }| !path.exists()))
+            .collect()
+    }
+
     pub fn document_count(&self) -> usize {
-        self.documents.document_count()
+        self.documents.document_count() - self.removed.len()
     }
 
     pub fn document_ids(&self) -> impl Iterator<Item = DocumentId> + '_ {
         self.documents.document_ids()
+            .filter(|document_id| !self.removed.contains(document_id))
+    }
+
+    pub fn documents(&self) -> &DocumentRegistry {
+        &self.documents
     }
 
     pub fn document(&self, document_id: DocumentId) -> Option<&Document> {
@@ -66,12 +122,38 @@ impl InfContext {
         }
     }
 
+    pub fn document_size(&self, document_id: DocumentId) -> usize {
+        self.document_data(document_id).map_or(0, str::len)
+    }
+
+    pub fn document_text(&self, document_id: DocumentId) -> Result<&str> {
+        Ok(self.boilerplate.strip(self.document_data(document_id)?))
+    }
+
     pub fn files(&self) -> &FilePool {
         &self.files
     }
 }
 
-fn get_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+// NOTE: Files in first-level subfolders belong to a collection named after the subfolder
+fn get_files(path: impl AsRef<Path>) -> Result<Vec<(PathBuf, Option<String>)>> {
+    let mut files = Vec::new();
+    for path in std::fs::read_dir(path)?.flatten().map(|entry| entry.path()) {
+        if path.is_file() {
+            files.push((path, None));
+        } else if path.is_dir() {
+            let collection = path.file_name()
+                .map(|name| name.to_string_lossy().to_string());
+            files.extend(get_collection_files(&path)?
+                .into_iter()
+                .map(|path| (path, collection.clone())));
+        }
+    }
+
+    Ok(files)
+}
+
+fn get_collection_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
     Ok(std::fs::read_dir(path)?
         .map(|entry| entry.ok())
         .flatten()
@@ -0,0 +1,36 @@
+// NOTE: Only looking at the beginning of a document is enough to tell the language
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+// NOTE: Letters that exist only in one of the cyrillic alphabets
+const UKRAINIAN_LETTERS: &[char] = &['і', 'ї', 'є', 'ґ'];
+const RUSSIAN_LETTERS: &[char] = &['ы', 'э', 'ё', 'ъ'];
+
+pub const UNKNOWN_LANGUAGE: &str = "unknown";
+
+pub fn detect_language(text: &str) -> &'static str {
+    let mut latin = 0;
+    let mut cyrillic = 0;
+    let mut ukrainian = 0;
+    let mut russian = 0;
+    for ch in text.chars().take(SAMPLE_SIZE).filter(|ch| ch.is_alphabetic()) {
+        let ch = ch.to_lowercase().next().unwrap_or(ch);
+        if ch.is_ascii_alphabetic() {
+            latin += 1;
+        } else if ('\u{0400}'..='\u{04FF}').contains(&ch) {
+            cyrillic += 1;
+            if UKRAINIAN_LETTERS.contains(&ch) {
+                ukrainian += 1;
+            } else if RUSSIAN_LETTERS.contains(&ch) {
+                russian += 1;
+            }
+        }
+    }
+
+    if cyrillic > latin {
+        if ukrainian >= russian { "uk" } else { "ru" }
+    } else if latin > 0 {
+        "en"
+    } else {
+        UNKNOWN_LANGUAGE
+    }
+}
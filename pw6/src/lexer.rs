@@ -4,30 +4,40 @@ use crate::document::DocumentId;
 use crate::inf_context::InfContext;
 use crate::term_index::TermIndex;
 
+// NOTE: Terms longer than this are cut, real words never get close to it
+const MAX_TERM_LENGTH: usize = 64;
+// NOTE: Shorter terms are never considered garbage
+const GARBAGE_MIN_LENGTH: usize = 12;
+// NOTE: Base64 and similar blobs switch letter case far more often than words do
+const GARBAGE_CASE_SWITCH_RATIO: f64 = 0.25;
+
 pub struct Lexer<'a> {
     document_id: DocumentId,
-    iter: Chars<'a>
+    iter: Chars<'a>,
+    // NOTE: Token offset of the next word, dropped words take an offset too, so words around them aren't adjacent
+    offset: usize
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(document_id: DocumentId, ctx: &'a InfContext) -> Result<Self> {
-        let iter = ctx.document_data(document_id)?.chars();
+        let iter = ctx.document_text(document_id)?.chars();
 
         Ok(Lexer {
             document_id,
-            iter
+            iter,
+            offset: 0
         })
     }
 
     pub fn lex(mut self, term_index: &mut dyn TermIndex) -> LexerStats {
-        let mut word = String::new();
+        let mut word = Word::new();
         let mut stats = LexerStats::default();
         stats.lines += 1;
 
         while let Some(ch) = self.iter.next() {
             stats.characters_read += 1;
             if ch.is_alphabetic() || (ch.eq(&'\'') && !word.is_empty()) {
-                ch.to_lowercase().for_each(|ch| word.push(ch));
+                word.push(ch);
 
                 continue;
             }
@@ -37,23 +47,78 @@ impl<'a> Lexer<'a> {
                 stats.lines += 1;
             }
             if !word.is_empty() {
-                Self::add_term(&mut word, self.document_id, term_index);
+                self.add_term(&mut word, term_index, &mut stats);
             }
         }
 
         if !word.is_empty() {
-            Self::add_term(&mut word, self.document_id, term_index);
+            self.add_term(&mut word, term_index, &mut stats);
         }
 
         stats
     }
 
-    fn add_term(word: &mut String, document_id: DocumentId, term_index: &mut dyn TermIndex) {
-        let mut new_word = String::new();
-        std::mem::swap(word, &mut new_word);
+    fn add_term(&mut self, word: &mut Word, term_index: &mut dyn TermIndex, stats: &mut LexerStats) {
+        let word = std::mem::replace(word, Word::new());
+        let offset = self.offset;
+        self.offset += 1;
+        if word.is_garbage() {
+            stats.tokens_dropped += 1;
+            return;
+        }
+        if word.is_truncated() {
+            stats.tokens_truncated += 1;
+        }
 
+        let mut new_word = word.text;
         new_word.shrink_to_fit();
-        term_index.add_term(new_word, document_id);
+        term_index.add_term_at(new_word, self.document_id, offset);
+    }
+}
+
+struct Word {
+    text: String,
+    length: usize,
+    case_switches: usize,
+    last_uppercase: Option<bool>
+}
+
+impl Word {
+    fn new() -> Self {
+        Word {
+            text: String::new(),
+            length: 0,
+            case_switches: 0,
+            last_uppercase: None
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn push(&mut self, ch: char) {
+        if ch.is_alphabetic() {
+            let uppercase = ch.is_uppercase();
+            if self.last_uppercase.is_some_and(|last| last != uppercase) {
+                self.case_switches += 1;
+            }
+            self.last_uppercase = Some(uppercase);
+        }
+
+        self.length += 1;
+        if self.length <= MAX_TERM_LENGTH {
+            ch.to_lowercase().for_each(|ch| self.text.push(ch));
+        }
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.length > MAX_TERM_LENGTH
+    }
+
+    fn is_garbage(&self) -> bool {
+        self.length >= GARBAGE_MIN_LENGTH
+            && self.case_switches as f64 / self.length as f64 > GARBAGE_CASE_SWITCH_RATIO
     }
 }
 
@@ -61,7 +126,9 @@ impl<'a> Lexer<'a> {
 pub struct LexerStats {
     pub characters_read: usize,
     pub characters_ignored: usize,
-    pub lines: usize
+    pub lines: usize,
+    pub tokens_truncated: usize,
+    pub tokens_dropped: usize
 }
 
 impl LexerStats {
@@ -69,6 +136,8 @@ impl LexerStats {
         self.characters_read += other.characters_read;
         self.characters_ignored += other.characters_ignored;
         self.lines += other.lines;
+        self.tokens_truncated += other.tokens_truncated;
+        self.tokens_dropped += other.tokens_dropped;
     }
 }
 
@@ -77,7 +146,9 @@ impl Default for LexerStats {
         LexerStats {
             characters_read: 0,
             characters_ignored: 0,
-            lines: 0
+            lines: 0,
+            tokens_truncated: 0,
+            tokens_dropped: 0
         }
     }
 }
@@ -5,50 +5,134 @@ mod common;
 mod document;
 mod query_lang;
 mod inf_context;
+mod boilerplate;
+mod rewrite;
+mod language;
 mod encoding;
+mod codec_bench;
+mod metrics;
+mod persist;
+mod merge;
+mod cost;
+mod fingerprint;
+mod similarity;
+mod scheduling;
+mod disk_index;
+mod spimi;
+mod dynamic_index;
+mod postings;
+#[cfg(feature = "positions")]
+mod positions;
 
 use std::{env, io};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::BufReader;
+use std::path::PathBuf;
 use std::str::FromStr;
-use anyhow::{Context, Result};
-use threadpool::ThreadPool;
-use std::sync::mpsc::channel;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use anyhow::{anyhow, Context, Result};
 use human_bytes::human_bytes;
 use itertools::Itertools;
+use crate::metrics::{metrics, Metrics};
 use crate::common::add_file_to_index;
 use crate::inf_context::InfContext;
-use crate::term_index::{InvertedIndex, TermIndex};
-use rayon::prelude::*;
+use crate::document::{Document, DocumentId, DocumentRegistry};
+use crate::boilerplate::BoilerplateFilter;
+use crate::rewrite::RewriteRules;
+use crate::term_index::{Compression, InvertedIndex, TermIndex, DEFAULT_DICTIONARY_BLOCK};
 use crate::lexer::LexerStats;
+use crate::merge::DEFAULT_MERGE_BUFFER;
+use crate::cost::{suggest_rewrite, CostGuard, QueryCost};
+use crate::fingerprint::{fingerprint_corpus, verify_corpus};
+use crate::similarity::{SetRanker, SetRanking};
+use crate::scheduling::{largest_first, Pipeline, Scheduling};
+use crate::disk_index::{DiskIndex, DISK_INDEX_PATH};
+use crate::dynamic_index::{DynamicIndex, DEFAULT_MERGE_THRESHOLD};
+use crate::encoding::Codec;
 
-fn time_call<FnT, ResT>(func: FnT) -> (ResT, Duration)
-where FnT: FnOnce() -> ResT
-{
-    let start = Instant::now();
-    let result = func();
-    let time = start.elapsed();
+struct QuerySettings {
+    rewrite_rules: RewriteRules,
+    trace_rewrites: bool,
+    max_results: Option<usize>,
+    cost_guard: CostGuard,
+    cost_guard_ratio: f64,
+    ranker: Option<SetRanker>
+}
+
+fn guard_cost(ast: &query_lang::LogicNode, index: &dyn TermIndex, settings: &QuerySettings) -> Result<()> {
+    let cost = QueryCost::estimate(ast, index);
+    println!("Estimated results: {}.", cost.estimated_results);
+
+    // NOTE: Limited evaluation checks documents one by one and never builds a complement
+    let document_count = index.document_count();
+    if settings.cost_guard == CostGuard::Off || settings.max_results.is_some() || !cost.exceeds(document_count, settings.cost_guard_ratio) {
+        return Ok(());
+    }
 
-    (result, time)
+    let suggestion = suggest_rewrite(ast);
+    let hint = if QueryCost::estimate(&suggestion, index).exceeds(document_count, settings.cost_guard_ratio) {
+        "restrict the negation with a subtraction like 'term \\ excluded' or pass --max-results=N".to_owned()
+    } else {
+        format!("try '{suggestion}' instead")
+    };
+    let message = format!("Query negation builds a set of ~{} out of {} documents", cost.largest_complement, document_count);
+    match settings.cost_guard {
+        CostGuard::Refuse => Err(anyhow!("{message}, {hint}")),
+        _ => {
+            println!("Warning: {message}, {hint}");
+            Ok(())
+        }
+    }
 }
 
-fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()> {
+fn query(query_text: &str, index: &dyn TermIndex, ranking: Option<&SetRanking>, documents: &DocumentRegistry, settings: &QuerySettings) -> Result<()> {
     let ast = query_lang::parse_logic_expr(query_text).context("Invalid query")?;
     // println!("Ast: {ast:?}");
 
-    let (result, time) = time_call(|| index.query(&ast));
+    let (ast, trace) = settings.rewrite_rules.apply(ast);
+    if settings.trace_rewrites {
+        trace.iter().for_each(|rule| println!("Applied rewrite: {rule}"));
+        if !trace.is_empty() {
+            println!("Rewritten query: {ast}");
+        }
+    }
+
+    guard_cost(&ast, index, settings)?;
+
+    let (result, time) = metrics().time("query", || index.query(&ast, settings.max_results));
     let result = result?;
 
     println!("Query time: {time:?}.");
     if !result.is_empty() {
-        let result_str = result.iter()
-            .sorted()
-            .filter_map(|&id| ctx.document(id).map(|doc| (id, doc)))
-            .enumerate()
-            .map(|(i, (id, doc))| format!("\t{}. [{}] {}", i, id, doc.name()))
-            .join("\n");
+        let result_str = match ranking {
+            Some(ranking) => ranking.rank(&ast, &result, index)
+                .into_iter()
+                .filter_map(|(id, score)| documents.document(id).map(|doc| (id, doc, score)))
+                .enumerate()
+                .map(|(i, (id, doc, score))| format!("\t{}. [{}][S: {:.4}] {}", i, id, score, doc.name()))
+                .join("\n"),
+            None => result.iter()
+                .sorted()
+                .filter_map(|&id| documents.document(id).map(|doc| (id, doc)))
+                .enumerate()
+                .map(|(i, (id, doc))| format!("\t{}. [{}] {}", i, id, doc.name()))
+                .join("\n")
+        };
         println!("Result:\n{result_str}");
+        if Some(result.len()) == settings.max_results {
+            println!("Stopped after the first {} matches.", result.len());
+        }
+
+        let collection_hits = result.iter()
+            .filter_map(|&id| documents.document(id).and_then(Document::collection))
+            .counts();
+        if !collection_hits.is_empty() {
+            let collection_hits_str = collection_hits.iter()
+                .sorted()
+                .map(|(collection, count)| format!("\t{collection}: {count}"))
+                .join("\n");
+            println!("Hits per collection:\n{collection_hits_str}");
+        }
     } else {
         println!("No matches found.");
     }
@@ -56,41 +140,280 @@ fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()
     Ok(())
 }
 
-fn main() -> Result<()> {
+fn read_commands(mut handle: impl FnMut(&str) -> Result<()>) -> Result<()> {
+    let mut buffer = String::new();
+    loop {
+        println!("Please input your query or 'q' to exit: ");
+        io::stdin().read_line(&mut buffer)?;
+        if buffer.trim() == "q" {
+            break;
+        }
+
+        if let Err(err) = handle(&buffer) {
+            println!("Error: {}. Caused by: {}", err, err.root_cause());
+        }
+        println!();
+
+        buffer.clear();
+    }
+
+    Ok(())
+}
+
+fn repl(index: &dyn TermIndex, documents: &DocumentRegistry, settings: &QuerySettings) -> Result<()> {
+    let ranking = settings.ranker.map(|ranker| SetRanking::new(ranker, index));
+
+    read_commands(|command| query(command, index, ranking.as_ref(), documents, settings))
+}
+
+// NOTE: Besides queries, ':add <path>' and ':remove <id>' change single documents,
+//  ':refresh' adds files dropped into the folder since and removes documents whose files were deleted
+fn live_repl(index: &mut DynamicIndex, ctx: &mut InfContext, base_path: &str, settings: &QuerySettings) -> Result<()> {
+    let mut ranking = settings.ranker.map(|ranker| SetRanking::new(ranker, &*index));
+
+    read_commands(|command| {
+        let command = command.trim();
+        if command == ":refresh" {
+            refresh(index, ctx, base_path)?;
+        } else if let Some(path) = command.strip_prefix(":add ") {
+            add_document(index, ctx, PathBuf::from(path.trim()), None)?;
+        } else if let Some(document_id) = command.strip_prefix(":remove ") {
+            let document_id = usize::from_str(document_id.trim()).context("Invalid document id")?;
+            remove_document(index, ctx, DocumentId(document_id))?;
+        } else {
+            return query(command, &*index, ranking.as_ref(), ctx.documents(), settings);
+        }
+
+        // NOTE: Document term counts changed, so they're counted again
+        ranking = settings.ranker.map(|ranker| SetRanking::new(ranker, &*index));
+        println!("Documents: {}. Unique word count: {}. Changes not merged into the main index yet: {}",
+                 index.document_count(), index.unique_word_count(), index.pending_changes());
+
+        Ok(())
+    })
+}
+
+fn add_document(index: &mut DynamicIndex, ctx: &mut InfContext, path: PathBuf, collection: Option<String>) -> Result<()> {
+    let document_id = ctx.add_document(path.clone(), collection)?;
+    if let Err(err) = index.add_document(document_id, ctx) {
+        ctx.remove_document(document_id)?;
+        return Err(err);
+    }
+    println!("Added {path:?} as {document_id}");
+
+    Ok(())
+}
+
+fn remove_document(index: &mut DynamicIndex, ctx: &mut InfContext, document_id: DocumentId) -> Result<()> {
+    ctx.remove_document(document_id)?;
+    index.remove_document(document_id);
+    println!("Removed {document_id}");
+
+    Ok(())
+}
+
+fn refresh(index: &mut DynamicIndex, ctx: &mut InfContext, base_path: &str) -> Result<()> {
+    let vanished = ctx.vanished_documents();
+    for &document_id in &vanished {
+        remove_document(index, ctx, document_id)?;
+    }
+
+    let mut added = 0;
+    for (path, collection) in ctx.unindexed_files(base_path)? {
+        match add_document(index, ctx, path.clone(), collection) {
+            Ok(()) => added += 1,
+            Err(err) => println!("Ignoring file {:?}. Error: {}. Caused by: {}", path, err, err.root_cause())
+        }
+    }
+    println!("Refreshed \"{base_path}\": {added} documents added, {} removed", vanished.len());
+
+    Ok(())
+}
+
+fn merge_buffer(flags: &[&str]) -> Result<usize> {
+    flags.iter()
+        .find_map(|flag| flag.strip_prefix("--merge-buffer="))
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid merge buffer")
+        .map(|merge_buffer| merge_buffer.unwrap_or(DEFAULT_MERGE_BUFFER))
+}
+
+fn max_results(flags: &[&str]) -> Result<Option<usize>> {
+    flags.iter()
+        .find_map(|flag| flag.strip_prefix("--max-results="))
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid max results")
+}
+
+fn cost_guard(flags: &[&str]) -> Result<(CostGuard, f64)> {
+    let guard = flags.iter()
+        .find_map(|flag| flag.strip_prefix("--cost-guard="))
+        .map(CostGuard::from_str)
+        .transpose()?
+        .unwrap_or(CostGuard::Warn);
+    let ratio = flags.iter()
+        .find_map(|flag| flag.strip_prefix("--cost-guard-ratio="))
+        .map(f64::from_str)
+        .transpose()
+        .context("Invalid cost guard ratio")?
+        .unwrap_or(QueryCost::DEFAULT_GUARD_RATIO);
+
+    Ok((guard, ratio))
+}
+
+// NOTE: Megabytes, with a budget documents are indexed by SPIMI instead of the partial index pipeline
+fn memory_budget(flags: &[&str]) -> Result<Option<usize>> {
+    flags.iter()
+        .find_map(|flag| flag.strip_prefix("--memory-budget="))
+        .map(|budget| usize::from_str(budget).map(|megabytes| megabytes * 1024 * 1024))
+        .transpose()
+        .context("Invalid memory budget")
+}
+
+// NOTE: Documents added or removed after the build before they're merged into the main index
+fn merge_threshold(flags: &[&str]) -> Result<usize> {
+    flags.iter()
+        .find_map(|flag| flag.strip_prefix("--merge-threshold="))
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid merge threshold")
+        .map(|threshold| threshold.unwrap_or(DEFAULT_MERGE_THRESHOLD))
+}
+
+// NOTE: Codec of the postings and terms per dictionary block of the compressed index, a loaded index knows its own
+fn compression(flags: &[&str]) -> Result<Compression> {
+    let codec = flags.iter()
+        .find_map(|flag| flag.strip_prefix("--codec="))
+        .map(Codec::from_str)
+        .transpose()?
+        .unwrap_or_default();
+    let dictionary_block = flags.iter()
+        .find_map(|flag| flag.strip_prefix("--dictionary-block="))
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid dictionary block size")?
+        .unwrap_or(DEFAULT_DICTIONARY_BLOCK);
+    if dictionary_block == 0 {
+        return Err(anyhow!("Dictionary block size must be positive"));
+    }
+
+    Ok(Compression { codec, dictionary_block })
+}
+
+fn ranker(flags: &[&str]) -> Result<Option<SetRanker>> {
+    flags.iter()
+        .find_map(|flag| flag.strip_prefix("--ranker="))
+        .map(SetRanker::from_str)
+        .transpose()
+}
+
+fn scheduling(flags: &[&str]) -> Result<Scheduling> {
+    let index_threads = flags.iter()
+        .find_map(|flag| flag.strip_prefix("--threads="))
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid thread count")?
+        .unwrap_or_else(Scheduling::default_thread_count);
+    let pipeline = flags.iter()
+        .find_map(|flag| flag.strip_prefix("--pipeline="))
+        .map(Pipeline::from_str)
+        .transpose()?
+        .unwrap_or_default();
+    if index_threads == 0 {
+        return Err(anyhow!("Thread count must be positive"));
+    }
+
+    Ok(Scheduling { index_threads, pipeline })
+}
+
+fn run() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    let base_path = args.get(1).map(AsRef::as_ref).unwrap_or("data/shakespeare");
-    let file_limit = args.get(2).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+    let (flags, positional): (Vec<&str>, Vec<&str>) = args.iter()
+        .skip(1)
+        .map(String::as_str)
+        .partition(|arg| arg.starts_with("--"));
+    if positional.first() == Some(&"bench-codecs") {
+        let index_path = positional.get(1).cloned().unwrap_or("data/index.txt");
+
+        return codec_bench::bench_codecs(index_path, "data/codec_bench.csv");
+    }
+
+    let base_path = positional.first().cloned().unwrap_or("data/shakespeare");
+    let file_limit = positional.get(1).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+    let merge_buffer = merge_buffer(&flags)?;
+    let scheduling = scheduling(&flags)?;
+    let memory_budget = memory_budget(&flags)?;
+    let merge_threshold = merge_threshold(&flags)?;
+    let compression = compression(&flags)?;
+
+    let (cost_guard, cost_guard_ratio) = cost_guard(&flags)?;
+    let settings = QuerySettings {
+        rewrite_rules: match File::open("data/rewrite_rules.txt") {
+            Ok(file) => RewriteRules::load(BufReader::new(file))?,
+            Err(_) => RewriteRules::new()
+        },
+        trace_rewrites: flags.contains(&"--trace-rewrites"),
+        max_results: max_results(&flags)?,
+        cost_guard,
+        cost_guard_ratio,
+        ranker: ranker(&flags)?
+    };
+    if settings.rewrite_rules.rule_count() != 0 {
+        println!("Loaded {} query rewrite rules", settings.rewrite_rules.rule_count());
+    }
+
+    if base_path == "load" {
+        let index_path = positional.get(1).cloned().unwrap_or("data/index_compressed.txt");
+        let (index, documents, fingerprints) = InvertedIndex::read_compressed(&persist::load_complete(index_path)?)?;
+        println!("Loaded {} documents and {} terms from \"{index_path}\"", documents.document_count(), index.unique_word_count());
+        if let Some(reason) = index.positions_unavailable() {
+            println!("Phrase and proximity queries aren't available, {reason}");
+        }
+        let changes = verify_corpus(&fingerprints);
+        if !changes.is_empty() {
+            println!("{} documents changed since the index was built, their results may be stale:", changes.len());
+            changes.iter().for_each(|(fingerprint, change)| println!("\t{:?}: {}", fingerprint.path, change));
+        }
+
+        return repl(&index, &documents, &settings);
+    }
+    // NOTE: Postings stay on disk and are paged in as queries touch them, for corpora whose index doesn't fit in memory
+    if base_path == "disk" {
+        let index_path = positional.get(1).cloned().unwrap_or(DISK_INDEX_PATH);
+        let index = DiskIndex::open(index_path)?;
+        println!("Opened {} documents and {} terms from \"{index_path}\"", index.document_count(), index.unique_word_count());
+        if let Some(reason) = index.positions_unavailable() {
+            println!("Phrase and proximity queries aren't available, {reason}");
+        }
+
+        return repl(&index, index.registry(), &settings);
+    }
+
+    let boilerplate = match File::open("data/boilerplate.txt") {
+        Ok(file) => BoilerplateFilter::load(BufReader::new(file))?,
+        Err(_) => BoilerplateFilter::default()
+    };
 
     println!("Processing...");
-    let (ctx, opening_files_time) = time_call(|| InfContext::new(base_path, file_limit).unwrap());
+    let (ctx, opening_files_time) = metrics().time("opening_files", || InfContext::new(base_path, file_limit, boilerplate).unwrap());
     println!("Opening files took: {opening_files_time:?}");
-    let mut document_ids = ctx.document_ids().collect::<Vec<_>>();
+    let document_ids = largest_first(ctx.document_ids().collect(), |&document_id| ctx.document_size(document_id));
     let document_count = document_ids.len();
     println!("Processing {document_count} documents in folder \"{base_path}\"");
 
-    let pool = ThreadPool::new((num_cpus::get() - 1).max(1));
-    let (tx, rx) = channel();
-    for document_id in document_ids.drain(..) {
-        let tx = tx.clone();
+    let (result, index_time) = metrics().time("indexing", || {
         let ctx1 = ctx.clone();
-
-        pool.execute(move || {
-            tx.send(add_file_to_index(document_id, ctx1).unwrap()).unwrap()
-        });
-    }
-
-    let (result, index_time) = time_call(|| {
-        rx.into_iter()
-            .take(document_count)
-            .flatten()
-            .par_bridge()
-            .reduce(|| (InvertedIndex::new(), LexerStats::default()), |mut a, b| {
+        match memory_budget {
+            Some(memory_budget) => spimi::index(document_ids, ctx1, memory_budget),
+            None => scheduling.index(document_ids, merge_buffer, move |document_id| add_file_to_index(document_id, ctx1.clone()), |a, b| {
                 a.0.merge(b.0);
                 a.1.merge(b.1);
-
-                a
             })
+        }
     });
+    let result = result?.unwrap_or_else(|| (InvertedIndex::new(), LexerStats::default()));
 
     println!("Indexing took: {index_time:?}");
     let data_size: usize = ctx.files().files()
@@ -98,43 +421,86 @@ fn main() -> Result<()> {
         .sum();
     println!("Amount of data indexed: {}", human_bytes(data_size as f64));
     println!("Speed is: {}/s", human_bytes(data_size as f64 / index_time.as_secs_f64()));
+    metrics().add("documents_indexed", document_count as u64);
+    metrics().add("bytes_indexed", data_size as u64);
+    metrics().set("documents_per_second", document_count as f64 / index_time.as_secs_f64());
+    metrics().set("bytes_per_second", data_size as f64 / index_time.as_secs_f64());
 
     if let (index, stats) = result {
         println!("Unique word count: {}.", index.unique_word_count());
         println!("Lines read: {}. Characters read: {}. Characters ignored: {}", stats.lines, stats.characters_read, stats.characters_ignored);
+        metrics().add("lines_read", stats.lines as u64);
+        metrics().add("characters_read", stats.characters_read as u64);
+        println!("Tokens truncated: {}. Tokens dropped: {}", stats.tokens_truncated, stats.tokens_dropped);
 
         println!("Writing index to a file...");
-        index.save(BufWriter::new(File::create("data/index.txt")?))?;
+        persist::save_checked("data/index.txt", |writer| index.save(writer))?;
         let index_size = File::open("data/index.txt")?.metadata()?.len();
         println!("Index size: {}", human_bytes(index_size as f64));
 
         println!("Writing compressed index to a file...");
-        let (_, compression_time) = time_call(|| index.save_compressed(BufWriter::new(File::create("data/index_compressed.txt").unwrap())).unwrap());
+        let fingerprints = fingerprint_corpus(&ctx)?;
+        let mut compressed_size = None;
+        let (compression_result, compression_time) = metrics().time("compression", || persist::save_checked("data/index_compressed.txt", |writer| {
+            compressed_size = Some(index.save_compressed(writer, ctx.documents(), &fingerprints, compression)?);
+            Ok(())
+        }));
+        compression_result?;
         let compressed_index_size = File::open("data/index_compressed.txt")?.metadata()?.len();
-        println!("Compressed index size: {}", human_bytes(compressed_index_size as f64));
+        println!("Compressed index size: {} ({} codec)", human_bytes(compressed_index_size as f64), compression.codec.name());
+        if let Some(compressed_size) = compressed_size {
+            println!("Dictionary: {} in blocks of {} terms. Postings: {}", human_bytes(compressed_size.dictionary as f64),
+                     compression.dictionary_block, human_bytes(compressed_size.postings as f64));
+        }
+        // NOTE: Every codec is encoded in memory once more, so their sizes can be compared
+        let codec_sizes = Codec::ALL.iter()
+            .map(|&codec| {
+                let mut buffer = Vec::new();
+                index.save_compressed(&mut buffer, ctx.documents(), &fingerprints, Compression { codec, ..compression })?;
 
-        let (index_read, decompression_time) = time_call(|| InvertedIndex::read_compressed(BufReader::new(File::open("data/index_compressed.txt").unwrap())).unwrap());
-        println!("Compressed in: {:?}. Decompressed in: {:?}", compression_time, decompression_time);
-        println!("Are index equal: {}", index == index_read);
+                Ok(format!("{}: {}", codec.name(), human_bytes(buffer.len() as f64)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        println!("Compressed index size by codec: {}", codec_sizes.join(", "));
 
-        let mut buffer = String::new();
-        loop {
-            println!("Please input your query or 'q' to exit: ");
-            io::stdin().read_line(&mut buffer)?;
-            if buffer.trim() == "q" {
-                break;
-            }
+        // NOTE: Decoded once more on a single thread, to show what decoding the blocks in parallel gains
+        let compressed_data = persist::load_complete("data/index_compressed.txt")?;
+        let (index_read, decompression_time) = metrics().time("decompression", || InvertedIndex::read_compressed(&compressed_data));
+        let (index_read, documents_read, _) = index_read?;
+        let single_thread_pool = rayon::ThreadPoolBuilder::new().num_threads(1).build()?;
+        let (single_thread_result, single_thread_time) = metrics().time("decompression_single_thread", || {
+            single_thread_pool.install(|| InvertedIndex::read_compressed(&compressed_data))
+        });
+        single_thread_result?;
+        let decode_throughput = compressed_data.len() as f64 / decompression_time.as_secs_f64();
+        metrics().set("decode_bytes_per_second", decode_throughput);
+        println!("Compressed in: {:?}. Decompressed in: {:?} ({:.1} MB/s, {:?} on a single thread, {:.2}x speedup)", compression_time, decompression_time,
+                 decode_throughput / 1_000_000.0, single_thread_time, single_thread_time.as_secs_f64() / decompression_time.as_secs_f64());
+        println!("Are index equal: {}", index == index_read);
+        println!("Documents in compressed index: {}", documents_read.document_count());
 
-            if let Err(err) = query(&buffer, &index, &ctx) {
-                println!("Error: {}. Caused by: {}", err, err.root_cause());
-            }
-            println!();
+        println!("Writing disk index to a file...");
+        DiskIndex::save(&index, ctx.documents(), DISK_INDEX_PATH)?;
+        let disk_index_size = File::open(DISK_INDEX_PATH)?.metadata()?.len();
+        println!("Disk index size: {}", human_bytes(disk_index_size as f64));
 
-            buffer.clear();
+        // NOTE: Indexing is done, so nothing else holds the context anymore
+        let mut ctx = Arc::try_unwrap(ctx).map_err(|_| anyhow!("Programming error. Context is still shared"))?;
+        let mut index = DynamicIndex::new(index, merge_threshold);
+        if let Some(reason) = index.positions_unavailable() {
+            println!("Phrase and proximity queries aren't available, {reason}");
         }
+        live_repl(&mut index, &mut ctx, base_path, &settings)?;
     } else {
         println!("No files were processed.");
     }
 
     Ok(())
 }
+
+fn main() -> Result<()> {
+    let result = run();
+    metrics().save_to_file(Metrics::PATH)?;
+
+    result
+}
@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crc32fast::Hasher;
+
+// NOTE: Fixed size trailer, a file that doesn't end with it was never completely written
+const CHECKSUM_PREFIX: &[u8] = b"#crc32 ";
+const CHECKSUM_LENGTH: usize = CHECKSUM_PREFIX.len() + 8 + 1;
+
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Hasher
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// NOTE: Content goes to a temporary file that replaces the target only once it is
+//  complete and synced, so an interrupted write leaves the previous file intact
+pub fn save_checked(path: impl AsRef<Path>, write: impl FnOnce(&mut ChecksumWriter<BufWriter<File>>) -> Result<()>) -> Result<()> {
+    let path = path.as_ref();
+    let temp_path = path.with_extension("tmp");
+
+    let mut writer = ChecksumWriter {
+        inner: BufWriter::new(File::create(&temp_path)?),
+        hasher: Hasher::new()
+    };
+    write(&mut writer)?;
+    let checksum = writer.hasher.finalize();
+    let mut inner = writer.inner;
+    inner.write_all(CHECKSUM_PREFIX)?;
+    inner.write_all(format!("{checksum:08x}\n").as_bytes())?;
+    inner.into_inner()?.sync_all()?;
+    fs::rename(&temp_path, path)?;
+
+    // NOTE: Directories can't be synced on every platform, the rename is still in place then
+    if let Some(parent) = path.parent() {
+        let _ = File::open(parent).and_then(|directory| directory.sync_all());
+    }
+
+    Ok(())
+}
+
+pub fn load_checked(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    let mut data = fs::read(path)?;
+    let expected = strip_checksum(path, &mut data)?;
+    let actual = crc32fast::hash(&data);
+    if actual != expected {
+        return Err(anyhow!("File {path:?} is corrupt, checksum {actual:08x} doesn't match {expected:08x}"));
+    }
+
+    Ok(data)
+}
+
+// NOTE: Only makes sure the file was completely written, for formats whose
+//  sections carry their own checksums and report damage more precisely
+pub fn load_complete(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    let mut data = fs::read(path)?;
+    strip_checksum(path, &mut data)?;
+
+    Ok(data)
+}
+
+fn strip_checksum(path: &Path, data: &mut Vec<u8>) -> Result<u32> {
+    let content_length = data.len().checked_sub(CHECKSUM_LENGTH)
+        .filter(|&length| data[length..].starts_with(CHECKSUM_PREFIX))
+        .ok_or_else(|| anyhow!("File {path:?} has no checksum, it was either written partially or by an older version"))?;
+
+    let expected = std::str::from_utf8(&data[content_length + CHECKSUM_PREFIX.len()..data.len() - 1]).ok()
+        .and_then(|checksum| u32::from_str_radix(checksum, 16).ok())
+        .ok_or_else(|| anyhow!("File {path:?} has a malformed checksum"))?;
+    data.truncate(content_length);
+
+    Ok(expected)
+}
+
+// NOTE: Section is framed as its length, content and crc32 of the content
+pub fn write_section(writer: &mut impl Write, content: &[u8]) -> Result<()> {
+    writer.write_all(&(content.len() as u64).to_le_bytes())?;
+    writer.write_all(content)?;
+    writer.write_all(&crc32fast::hash(content).to_le_bytes())?;
+
+    Ok(())
+}
+
+pub fn read_section<'a>(data: &mut &'a [u8], name: &str) -> Result<&'a [u8]> {
+    let truncated = || anyhow!("Section '{name}' is truncated");
+
+    let (length, rest) = data.split_first_chunk::<8>().ok_or_else(truncated)?;
+    let length = usize::try_from(u64::from_le_bytes(*length)).map_err(|_| truncated())?;
+    if rest.len() < length {
+        return Err(truncated());
+    }
+    let (content, rest) = rest.split_at(length);
+    let (expected, rest) = rest.split_first_chunk::<4>().ok_or_else(truncated)?;
+
+    let expected = u32::from_le_bytes(*expected);
+    let actual = crc32fast::hash(content);
+    if actual != expected {
+        return Err(anyhow!("Section '{name}' is corrupt, checksum {actual:08x} doesn't match {expected:08x}"));
+    }
+    *data = rest;
+
+    Ok(content)
+}
@@ -0,0 +1,81 @@
+use ahash::AHashMap;
+use itertools::Itertools;
+use crate::document::DocumentId;
+
+// NOTE: Token offsets of a term in every document it occurs in, sorted within a document.
+//  A document is lexed once from start to end, so offsets are appended in order
+#[derive(Clone, Debug, Default)]
+#[derive(Eq, PartialEq)]
+pub struct TermPositions {
+    positions: AHashMap<DocumentId, Vec<usize>>
+}
+
+impl TermPositions {
+    pub fn add_position(&mut self, document_id: DocumentId, offset: usize) {
+        let offsets = self.positions.entry(document_id).or_default();
+        if offsets.last().is_none_or(|&last| last < offset) {
+            offsets.push(offset);
+        } else if let Err(position) = offsets.binary_search(&offset) {
+            offsets.insert(position, offset);
+        }
+    }
+
+    pub fn get(&self, document_id: DocumentId) -> Option<&[usize]> {
+        self.positions.get(&document_id).map(Vec::as_slice)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn merge(&mut self, other: Self) {
+        for (document_id, offsets) in other.positions {
+            match self.positions.get_mut(&document_id) {
+                Some(existing) => *existing = existing.iter().merge(&offsets).dedup().copied().collect(),
+                None => {
+                    self.positions.insert(document_id, offsets);
+                }
+            }
+        }
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&DocumentId) -> bool) {
+        self.positions.retain(|document_id, _| f(document_id));
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.positions.values_mut().for_each(Vec::shrink_to_fit);
+        self.positions.shrink_to_fit();
+    }
+
+    pub fn estimated_size(&self) -> usize {
+        self.positions.values()
+            .map(|offsets| size_of::<DocumentId>() + size_of::<Vec<usize>>() + offsets.capacity() * size_of::<usize>())
+            .sum()
+    }
+}
+
+// NOTE: Left offsets with a right offset at most `left` tokens before or `right` tokens after them, as in pw3.
+//  Right offsets are kept with them only for a window on both sides. Phrase words nest to the right,
+//  so a phrase is matched from its start. Unlike pw3 an offset is never near itself, "very very" needs two words
+pub fn close_union(lhs: &[usize], rhs: &[usize], left: usize, right: usize) -> Vec<usize> {
+    let mut result = Vec::new();
+    for &offset in lhs {
+        let start = rhs.partition_point(|&other| other < offset.saturating_sub(left));
+        let end = rhs.partition_point(|&other| other <= offset.saturating_add(right));
+        let around = rhs[start..end].iter()
+            .filter(|&&other| other != offset)
+            .copied()
+            .collect::<Vec<_>>();
+        if !around.is_empty() {
+            result.push(offset);
+            if left != 0 {
+                result.extend(around);
+            }
+        }
+    }
+    result.sort_unstable();
+    result.dedup();
+
+    result
+}
@@ -0,0 +1,230 @@
+use std::cmp::Ordering;
+use itertools::Itertools;
+use crate::document::DocumentId;
+
+// NOTE: Documents of a term in id order. Skip pointers are implicit, every √n-th entry points √n entries ahead,
+//  so they are derived from the length instead of being stored and stay valid as the list changes
+#[derive(Clone, Debug, Default)]
+#[derive(Eq, PartialEq)]
+pub struct PostingList {
+    documents: Vec<DocumentId>
+}
+
+impl PostingList {
+    pub fn new() -> Self {
+        PostingList {
+            documents: Vec::new()
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        PostingList {
+            documents: Vec::with_capacity(capacity)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.documents.capacity()
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.documents.shrink_to_fit();
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, DocumentId> {
+        self.documents.iter()
+    }
+
+    pub fn contains(&self, document_id: DocumentId) -> bool {
+        self.documents.binary_search(&document_id).is_ok()
+    }
+
+    // NOTE: Documents are indexed one after another, so appending is the common case
+    pub fn insert(&mut self, document_id: DocumentId) {
+        match self.documents.last() {
+            Some(&last) if last == document_id => (),
+            Some(&last) if last > document_id => if let Err(position) = self.documents.binary_search(&document_id) {
+                self.documents.insert(position, document_id);
+            },
+            _ => self.documents.push(document_id)
+        }
+    }
+
+    pub fn merge(&mut self, other: Self) {
+        if self.documents.last().is_none_or(|last| other.documents.first().is_none_or(|first| last < first)) {
+            self.documents.extend(other.documents);
+            return;
+        }
+
+        *self = self.union(&other);
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&DocumentId) -> bool) {
+        self.documents.retain(f);
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut result = PostingList::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.len() && j < other.len() {
+            match self.documents[i].cmp(&other.documents[j]) {
+                Ordering::Equal => {
+                    result.documents.push(self.documents[i]);
+                    i += 1;
+                    j += 1;
+                },
+                Ordering::Less => i = self.advance(i, other.documents[j]),
+                Ordering::Greater => j = other.advance(j, self.documents[i])
+            }
+        }
+
+        result
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        PostingList {
+            documents: self.iter().merge(other.iter()).dedup().copied().collect()
+        }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut result = PostingList::new();
+        let mut j = 0;
+        for &document_id in &self.documents {
+            j = other.advance(j, document_id);
+            if other.documents.get(j) != Some(&document_id) {
+                result.documents.push(document_id);
+            }
+        }
+
+        result
+    }
+
+    fn skip_length(&self) -> usize {
+        self.len().isqrt().max(1)
+    }
+
+    // NOTE: First position from the given one with a document not less than the target. Skip pointers
+    //  are followed while they don't jump past the target, the rest of the way is walked entry by entry
+    fn advance(&self, mut position: usize, target: DocumentId) -> usize {
+        let skip = self.skip_length();
+        while position < self.len() && self.documents[position] < target {
+            if position.is_multiple_of(skip) && self.documents.get(position + skip).is_some_and(|&document_id| document_id <= target) {
+                position += skip;
+            } else {
+                position += 1;
+            }
+        }
+
+        position
+    }
+}
+
+impl FromIterator<DocumentId> for PostingList {
+    fn from_iter<T: IntoIterator<Item = DocumentId>>(iter: T) -> Self {
+        let mut documents = iter.into_iter().collect::<Vec<_>>();
+        documents.sort_unstable();
+        documents.dedup();
+
+        PostingList {
+            documents
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a PostingList {
+    type Item = &'a DocumentId;
+    type IntoIter = std::slice::Iter<'a, DocumentId>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    // NOTE: Empty, single, square and non-square lengths, so skips both land on and fall short of the end
+    const LENGTHS: [usize; 9] = [0, 1, 2, 3, 4, 7, 16, 17, 50];
+
+    fn list(length: usize, step: usize, start: usize) -> BTreeSet<usize> {
+        (0..length).map(|i| start + i * step).collect()
+    }
+
+    fn postings(documents: &BTreeSet<usize>) -> PostingList {
+        documents.iter().copied().map(DocumentId).collect()
+    }
+
+    fn ids(postings: &PostingList) -> Vec<usize> {
+        postings.iter().map(DocumentId::id).collect()
+    }
+
+    #[test]
+    fn advance_stops_at_first_not_less() {
+        for length in LENGTHS {
+            let documents = list(length, 3, 1);
+            let postings = postings(&documents);
+            let last = documents.last().copied().unwrap_or_default();
+            for target in 0..=last + 2 {
+                for position in 0..=length {
+                    let expected = documents.iter().skip(position).position(|&document| document >= target).map_or(length, |i| position + i);
+                    assert_eq!(postings.advance(position, DocumentId(target)), expected, "{target} from {position} of {length}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn set_operations_match_reference() {
+        for (lhs_length, rhs_length) in LENGTHS.into_iter().cartesian_product(LENGTHS) {
+            for (lhs_step, rhs_step) in [(1, 1), (2, 3), (5, 1), (1, 7)] {
+                let lhs = list(lhs_length, lhs_step, 0);
+                let rhs = list(rhs_length, rhs_step, 2);
+                let (lhs_postings, rhs_postings) = (postings(&lhs), postings(&rhs));
+                let case = format!("{lhs_length} by {lhs_step} and {rhs_length} by {rhs_step}");
+
+                assert_eq!(ids(&lhs_postings.intersection(&rhs_postings)), lhs.intersection(&rhs).copied().collect::<Vec<_>>(), "{case}");
+                assert_eq!(ids(&lhs_postings.difference(&rhs_postings)), lhs.difference(&rhs).copied().collect::<Vec<_>>(), "{case}");
+                assert_eq!(ids(&lhs_postings.union(&rhs_postings)), lhs.union(&rhs).copied().collect::<Vec<_>>(), "{case}");
+
+                let mut merged = lhs_postings.clone();
+                merged.merge(rhs_postings);
+                assert_eq!(ids(&merged), lhs.union(&rhs).copied().collect::<Vec<_>>(), "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn insert_keeps_order() {
+        let mut postings = PostingList::new();
+        let mut reference = BTreeSet::new();
+        for document in [5, 1, 9, 9, 3, 0, 12, 3, 7] {
+            postings.insert(DocumentId(document));
+            reference.insert(document);
+            assert_eq!(ids(&postings), reference.iter().copied().collect::<Vec<_>>(), "after {document}");
+        }
+        assert!(postings.contains(DocumentId(7)));
+        assert!(!postings.contains(DocumentId(8)));
+    }
+
+    #[test]
+    fn merge_overlapping() {
+        let mut merged = postings(&BTreeSet::from([1, 4, 6, 10]));
+        merged.merge(postings(&BTreeSet::from([0, 4, 5, 10, 11])));
+        assert_eq!(ids(&merged), [0, 1, 4, 5, 6, 10, 11]);
+
+        // NOTE: Appended as is when every document comes after the last one
+        merged.merge(postings(&BTreeSet::from([12, 20])));
+        assert_eq!(ids(&merged), [0, 1, 4, 5, 6, 10, 11, 12, 20]);
+    }
+}
@@ -1,4 +1,5 @@
-use std::iter::Peekable;
+use std::fmt::{Display, Formatter};
+use std::iter::{Map, Peekable};
 use anyhow::{anyhow, Context, Result};
 use std::str::{Chars, FromStr};
 
@@ -15,22 +16,48 @@ enum Token {
     RightCurlyBracket,
     GreaterThan,
     DoubleQuotes,
-    Backslash
+    Backslash,
+    Colon
 }
 
+// NOTE: Queries pasted from documents and chat apps come with typographic quotes,
+//  fullwidth punctuation and logic symbols, they mean the same as the ASCII operators
+fn normalize_char(ch: char) -> char {
+    match ch {
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{00AB}' | '\u{00BB}' | '\u{300C}' | '\u{300D}' | '\u{300E}' | '\u{300F}' | '\u{FF02}' => '"',
+        '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{02BC}' | '\u{FF07}' => '\'',
+        '\u{FF06}' | '\u{2227}' => '&',
+        '\u{FF5C}' | '\u{2228}' => '|',
+        '\u{FF01}' | '\u{00AC}' => '!',
+        '\u{FF08}' => '(',
+        '\u{FF09}' => ')',
+        '\u{FF5B}' => '{',
+        '\u{FF5D}' => '}',
+        '\u{FF1E}' => '>',
+        '\u{FF3C}' | '\u{2216}' => '\\',
+        '\u{FF1A}' => ':',
+        '\u{FF10}'..='\u{FF19}' => char::from_u32(ch as u32 - 0xFF10 + '0' as u32).unwrap_or(ch),
+        _ => ch
+    }
+}
+
+type NormalizedChars<'a> = Map<Chars<'a>, fn(char) -> char>;
+
 struct Lexer<'a> {
-    iter: Peekable<Chars<'a>>
+    iter: Peekable<NormalizedChars<'a>>
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Lexer { iter: input.chars().peekable() }
+        Lexer { iter: input.chars().map(normalize_char as fn(char) -> char).peekable() }
     }
 
     pub fn lex(mut self) -> Result<Vec<Token>> {
         let mut tokens = Vec::new();
         while let Some(&ch) = self.iter.peek() {
-            if let Some(term) = Self::try_consume_term(&mut self.iter) {
+            if tokens.last() == Some(&Token::Colon) && ch.is_alphanumeric() {
+                tokens.push(Self::consume_field_value(&mut self.iter));
+            } else if let Some(term) = Self::try_consume_term(&mut self.iter) {
                 tokens.push(term);
             } else if ch.is_whitespace() {
                 Self::skip_whitespaces(&mut self.iter);
@@ -60,7 +87,22 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        None
+        (!word.is_empty()).then_some(Token::Term(word))
+    }
+
+    // NOTE: Field values keep their digits, so "collection:rock2019" is one value and not a term and a number
+    fn consume_field_value(iter: &mut Peekable<impl Iterator<Item = char>>) -> Token {
+        let mut value = String::new();
+        while let Some(&ch) = iter.peek() {
+            if !ch.is_alphanumeric() {
+                break;
+            }
+
+            ch.to_lowercase().for_each(|ch| value.push(ch));
+            iter.next();
+        }
+
+        Token::Term(value)
     }
 
     fn try_consume_punctuator(iter: &mut Peekable<impl Iterator<Item = char>>) -> Option<Token> {
@@ -76,6 +118,7 @@ impl<'a> Lexer<'a> {
                 '>' => Token::GreaterThan,
                 '"' => Token::DoubleQuotes,
                 '\\' => Token::Backslash,
+                ':' => Token::Colon,
                 _ => return None
             });
 
@@ -150,7 +193,7 @@ impl Operator {
 }
 
 
-#[derive(Debug)]
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub enum LogicNode {
     False,
     Term(String),
@@ -158,7 +201,24 @@ pub enum LogicNode {
     Or(Box<LogicNode>, Box<LogicNode>),
     Not(Box<LogicNode>),
     Near(Box<LogicNode>, Box<LogicNode>, usize, usize),
-    Subtract(Box<LogicNode>, Box<LogicNode>)
+    Subtract(Box<LogicNode>, Box<LogicNode>),
+    Field(String, String)
+}
+
+impl Display for LogicNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogicNode::False => write!(f, "()"),
+            LogicNode::Term(term) => write!(f, "{term}"),
+            LogicNode::And(lhs, rhs) => write!(f, "({lhs} & {rhs})"),
+            LogicNode::Or(lhs, rhs) => write!(f, "({lhs} | {rhs})"),
+            LogicNode::Not(operand) => write!(f, "!{operand}"),
+            LogicNode::Near(lhs, rhs, 0, 1) => write!(f, "({lhs} > {rhs})"),
+            LogicNode::Near(lhs, rhs, distance, _) => write!(f, "({lhs} {{{distance}}} {rhs})"),
+            LogicNode::Subtract(lhs, rhs) => write!(f, "({lhs} \\ {rhs})"),
+            LogicNode::Field(name, value) => write!(f, "{name}:{value}")
+        }
+    }
 }
 
 struct Parser {
@@ -178,7 +238,18 @@ impl Parser {
         while let Some(token) = iter.next() {
             match token {
                 Token::Term(term) => {
-                    operand_stack.push(LogicNode::Term(term));
+                    if let Some(Token::Colon) = iter.peek() {
+                        iter.next();
+                        let value = match iter.next() {
+                            Some(Token::Term(value)) => value,
+                            Some(Token::Number(value)) => value.to_string(),
+                            _ => return Err(anyhow!("Expected value for field '{term}'"))
+                        };
+
+                        operand_stack.push(LogicNode::Field(term, value));
+                    } else {
+                        operand_stack.push(LogicNode::Term(term));
+                    }
                 },
                 Token::Ampersand | Token::Pipe | Token::Exclaim | Token::Backslash => {
                     let operator = Operator::from_token(&token)
@@ -0,0 +1,112 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt::{Display, Formatter};
+use std::io::BufRead;
+use crate::query_lang::{parse_logic_expr, LogicNode};
+
+pub struct RewriteRule {
+    pattern: LogicNode,
+    rewrite: LogicNode
+}
+
+impl RewriteRule {
+    pub fn new(pattern: LogicNode, rewrite: LogicNode) -> Self {
+        RewriteRule { pattern, rewrite }
+    }
+
+    fn apply(&self, node: LogicNode) -> (LogicNode, usize) {
+        if node == self.pattern {
+            return (self.rewrite.clone(), 1);
+        }
+
+        match node {
+            LogicNode::False | LogicNode::Term(_) | LogicNode::Field(_, _) => (node, 0),
+            LogicNode::And(lhs, rhs) => self.apply_binary(*lhs, *rhs, LogicNode::And),
+            LogicNode::Or(lhs, rhs) => self.apply_binary(*lhs, *rhs, LogicNode::Or),
+            LogicNode::Subtract(lhs, rhs) => self.apply_binary(*lhs, *rhs, LogicNode::Subtract),
+            LogicNode::Near(lhs, rhs, left, right) => {
+                self.apply_binary(*lhs, *rhs, |lhs, rhs| LogicNode::Near(lhs, rhs, left, right))
+            },
+            LogicNode::Not(operand) => {
+                let (operand, count) = self.apply(*operand);
+
+                (LogicNode::Not(Box::new(operand)), count)
+            }
+        }
+    }
+
+    fn apply_binary(&self, lhs: LogicNode, rhs: LogicNode, constructor: impl FnOnce(Box<LogicNode>, Box<LogicNode>) -> LogicNode) -> (LogicNode, usize) {
+        let (lhs, lhs_count) = self.apply(lhs);
+        let (rhs, rhs_count) = self.apply(rhs);
+
+        (constructor(Box::new(lhs), Box::new(rhs)), lhs_count + rhs_count)
+    }
+}
+
+impl Display for RewriteRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.pattern, RewriteRules::ARROW, self.rewrite)
+    }
+}
+
+pub struct RewriteRules {
+    rules: Vec<RewriteRule>
+}
+
+impl RewriteRules {
+    const ARROW: &'static str = "=>";
+    const COMMENT: &'static str = "#";
+
+    pub fn new() -> Self {
+        RewriteRules { rules: Vec::new() }
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn add_rule(&mut self, rule: RewriteRule) {
+        self.rules.push(rule);
+    }
+
+    pub fn load(reader: impl BufRead) -> Result<Self> {
+        let mut rules = RewriteRules::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(Self::COMMENT) {
+                continue;
+            }
+
+            let (pattern, rewrite) = line.split_once(Self::ARROW)
+                .ok_or_else(|| anyhow!("Expected rule in form 'pattern {} rewrite' on line {}", Self::ARROW, i + 1))?;
+            let pattern = parse_logic_expr(pattern).context(anyhow!("Invalid pattern on line {}", i + 1))?;
+            let rewrite = parse_logic_expr(rewrite).context(anyhow!("Invalid rewrite on line {}", i + 1))?;
+
+            rules.add_rule(RewriteRule::new(pattern, rewrite));
+        }
+
+        Ok(rules)
+    }
+
+    // NOTE: Every rule runs once over the output of the previous one,
+    //  rewritten subtrees aren't matched again by the same rule
+    pub fn apply(&self, mut query_ast: LogicNode) -> (LogicNode, Vec<String>) {
+        let mut trace = Vec::new();
+        for rule in &self.rules {
+            let (result, count) = rule.apply(query_ast);
+            if count != 0 {
+                trace.push(format!("{rule} (x{count})"));
+            }
+
+            query_ast = result;
+        }
+
+        (query_ast, trace)
+    }
+}
+
+impl Default for RewriteRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
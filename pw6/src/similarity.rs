@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+use ahash::{AHashMap, AHashSet};
+use std::str::FromStr;
+use itertools::Itertools;
+use crate::document::DocumentId;
+use crate::query_lang::LogicNode;
+use crate::term_index::{InvertedIndex, TermIndex};
+
+// NOTE: Coefficients compare the set of query terms with the set of terms of a document
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SetRanker {
+    Jaccard,
+    Dice,
+    Overlap
+}
+
+impl SetRanker {
+    pub fn score(self, common: usize, query_size: usize, document_size: usize) -> f64 {
+        let (common, query_size, document_size) = (common as f64, query_size as f64, document_size as f64);
+        let denominator = match self {
+            SetRanker::Jaccard => query_size + document_size - common,
+            SetRanker::Dice => (query_size + document_size) / 2.0,
+            SetRanker::Overlap => query_size.min(document_size)
+        };
+        if denominator == 0.0 {
+            return 0.0;
+        }
+
+        common / denominator
+    }
+}
+
+impl FromStr for SetRanker {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jaccard" => Ok(SetRanker::Jaccard),
+            "dice" => Ok(SetRanker::Dice),
+            "overlap" => Ok(SetRanker::Overlap),
+            _ => Err(anyhow!("Unknown ranker '{s}', expected 'jaccard', 'dice' or 'overlap'"))
+        }
+    }
+}
+
+// NOTE: Sizes of document term sets are counted once, the boolean index only knows documents of every term
+pub struct SetRanking {
+    ranker: SetRanker,
+    document_sizes: AHashMap<DocumentId, usize>
+}
+
+impl SetRanking {
+    pub fn new(ranker: SetRanker, index: &dyn TermIndex) -> Self {
+        SetRanking {
+            ranker,
+            document_sizes: index.document_term_counts()
+        }
+    }
+
+    // NOTE: Highest score first, ties are kept in document id order
+    pub fn rank(&self, query_ast: &LogicNode, result: &AHashSet<DocumentId>, index: &dyn TermIndex) -> Vec<(DocumentId, f64)> {
+        let terms = positive_terms(query_ast);
+
+        result.iter()
+            .map(|&document_id| {
+                let common = terms.iter()
+                    .filter(|term| index.contains(term, document_id))
+                    .count();
+                let document_size = self.document_sizes.get(&document_id).cloned().unwrap_or(0);
+
+                (document_id, self.ranker.score(common, terms.len(), document_size))
+            })
+            .sorted_by(|(id_a, a), (id_b, b)| a.partial_cmp(b).unwrap().reverse().then(id_a.cmp(id_b)))
+            .collect()
+    }
+}
+
+// NOTE: Terms a matching document is expected to contain, negated and subtracted ones aren't
+fn positive_terms(query_ast: &LogicNode) -> AHashSet<String> {
+    let mut terms = AHashSet::new();
+    collect_positive_terms(query_ast, &mut terms);
+
+    terms
+}
+
+fn collect_positive_terms(query_ast: &LogicNode, terms: &mut AHashSet<String>) {
+    match query_ast {
+        LogicNode::False | LogicNode::Not(_) => {},
+        LogicNode::Term(value) => {
+            terms.insert(value.clone());
+        },
+        LogicNode::Field(name, value) => {
+            terms.insert(InvertedIndex::field_term(name, value));
+        },
+        LogicNode::And(lhs, rhs) | LogicNode::Or(lhs, rhs) | LogicNode::Near(lhs, rhs, _, _) => {
+            collect_positive_terms(lhs, terms);
+            collect_positive_terms(rhs, terms);
+        },
+        LogicNode::Subtract(lhs, _) => collect_positive_terms(lhs, terms)
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use itertools::Itertools;
+use crate::common::add_file_to_index;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::lexer::LexerStats;
+use crate::metrics::metrics;
+use crate::persist;
+use crate::term_index::InvertedIndex;
+
+const BLOCK_FOLDER: &str = "data/spimi";
+const MERGED_FILE: &str = "index.txt";
+
+// NOTE: Single-pass in-memory indexing, documents are added to one in-memory block until its
+//  estimated size exceeds the budget, then the block is written sorted by term and a new one started.
+//  Blocks are merged in one pass at the end, only the current line of every block is held in memory
+pub fn index(document_ids: Vec<DocumentId>, ctx: Arc<InfContext>, memory_budget: usize) -> Result<Option<(InvertedIndex, LexerStats)>> {
+    if document_ids.is_empty() {
+        return Ok(None);
+    }
+
+    let folder = Path::new(BLOCK_FOLDER);
+    fs::create_dir_all(folder)?;
+
+    let mut block_paths = Vec::new();
+    let mut block = InvertedIndex::new();
+    let mut block_size = 0;
+    let mut stats = LexerStats::default();
+    for document_id in document_ids {
+        let Some((partial, partial_stats)) = add_file_to_index(document_id, ctx.clone())? else {
+            continue;
+        };
+        // NOTE: Sizes of partials are summed, terms already in the block are counted again, so spills come early rather than late
+        block_size += partial.estimated_size();
+        block.merge(partial);
+        stats.merge(partial_stats);
+
+        if block_size > memory_budget {
+            block_paths.push(spill(&block, folder, block_paths.len())?);
+            block = InvertedIndex::new();
+            block_size = 0;
+        }
+    }
+    if block.unique_word_count() != 0 {
+        block_paths.push(spill(&block, folder, block_paths.len())?);
+    }
+    drop(block);
+    println!("SPIMI wrote {} blocks to \"{BLOCK_FOLDER}\"", block_paths.len());
+    metrics().add("spimi_blocks", block_paths.len() as u64);
+
+    let merged_path = folder.join(MERGED_FILE);
+    let (result, merge_time) = metrics().time("spimi_merge", || merge_blocks(&block_paths, &merged_path));
+    result?;
+    println!("Merged blocks in {merge_time:?}");
+
+    let index = InvertedIndex::load(persist::load_checked(&merged_path)?.as_slice())?;
+    fs::remove_dir_all(folder)?;
+
+    Ok(Some((index, stats)))
+}
+
+fn spill(block: &InvertedIndex, folder: &Path, number: usize) -> Result<PathBuf> {
+    let path = folder.join(format!("block_{number}.txt"));
+    let mut writer = BufWriter::new(File::create(&path)?);
+    block.save(&mut writer)?;
+    writer.flush()?;
+
+    Ok(path)
+}
+
+// NOTE: K-way merge, a heap holds the current term of every block,
+//  documents of a term found in several blocks are joined into one line
+fn merge_blocks(block_paths: &[PathBuf], output_path: &Path) -> Result<()> {
+    let mut blocks = block_paths.iter()
+        .map(|path| Ok(BufReader::new(File::open(path)?).lines()))
+        .collect::<Result<Vec<_>>>()?;
+    let mut heap = BinaryHeap::new();
+    for (i, block) in blocks.iter_mut().enumerate() {
+        if let Some(entry) = next_entry(block)? {
+            heap.push(Reverse((entry, i)));
+        }
+    }
+
+    persist::save_checked(output_path, |writer| {
+        while let Some(Reverse(((term, mut documents), i))) = heap.pop() {
+            if let Some(entry) = next_entry(&mut blocks[i])? {
+                heap.push(Reverse((entry, i)));
+            }
+            while let Some(Reverse(((next_term, _), _))) = heap.peek() {
+                if *next_term != term {
+                    break;
+                }
+
+                let Reverse(((_, next_documents), j)) = heap.pop().ok_or_else(|| anyhow!("Programming error. Heap is empty"))?;
+                documents.extend(next_documents);
+                if let Some(entry) = next_entry(&mut blocks[j])? {
+                    heap.push(Reverse((entry, j)));
+                }
+            }
+
+            let documents = documents.into_iter().sorted().dedup().collect::<Vec<_>>();
+            InvertedIndex::write_line(writer, &term, &documents)?;
+        }
+
+        Ok(())
+    })
+}
+
+fn next_entry(block: &mut Lines<BufReader<File>>) -> Result<Option<(String, Vec<DocumentId>)>> {
+    block.next()
+        .map(|line| InvertedIndex::read_line(&line?))
+        .transpose()
+}
@@ -1,36 +1,92 @@
 use anyhow::{anyhow, Result};
 use ahash::{AHashMap, AHashSet};
 use std::io::{BufRead, Write};
-use std::iter::Peekable;
 use std::str::FromStr;
 use itertools::Itertools;
-use crate::document::DocumentId;
+use rayon::prelude::*;
+use crate::document::{DocumentId, DocumentRegistry};
 use crate::query_lang::LogicNode;
-use crate::encoding::{vb_decode, vb_encode};
+use crate::encoding::{vb_encode, ByteReader, Codec, CodecReader, CodecWriter};
+use crate::persist;
+use crate::fingerprint::DocumentFingerprint;
+use crate::postings::PostingList;
+#[cfg(feature = "positions")]
+use crate::positions::{close_union, TermPositions};
 
 pub trait TermIndex {
     fn add_term(&mut self, term: String, document_id: DocumentId);
-    fn query(&self, query_ast: &LogicNode) -> Result<AHashSet<DocumentId>>;
+    // NOTE: Term at the given token offset of the document, indexes without positions drop the offset
+    fn add_term_at(&mut self, term: String, document_id: DocumentId, _offset: usize) {
+        self.add_term(term, document_id);
+    }
+    // NOTE: With a limit, evaluation stops at the first `limit` matching documents in id order
+    fn query(&self, query_ast: &LogicNode, limit: Option<usize>) -> Result<AHashSet<DocumentId>>;
+    fn document_frequency(&self, term: &str) -> usize;
+    fn document_count(&self) -> usize;
+    fn contains(&self, term: &str, document_id: DocumentId) -> bool;
+    // NOTE: Number of distinct terms of every document, fields included
+    fn document_term_counts(&self) -> AHashMap<DocumentId, usize>;
+}
+
+pub const DEFAULT_DICTIONARY_BLOCK: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Compression {
+    pub codec: Codec,
+    // NOTE: Terms per front coded dictionary block
+    pub dictionary_block: usize
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            codec: Codec::default(),
+            dictionary_block: DEFAULT_DICTIONARY_BLOCK
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CompressedSize {
+    pub dictionary: usize,
+    pub postings: usize
 }
 
 #[derive(Debug)]
-#[derive(Eq, PartialEq)]
 pub struct InvertedIndex {
-    documents: AHashSet<DocumentId>,
-    index: AHashMap<String, AHashSet<DocumentId>>
+    documents: PostingList,
+    index: AHashMap<String, PostingList>,
+    #[cfg(feature = "positions")]
+    positions: AHashMap<String, TermPositions>,
+    // NOTE: Only postings are saved, indexes loaded from a file have no positions unless they are empty
+    #[cfg(feature = "positions")]
+    positional: bool
 }
 
 impl InvertedIndex {
     pub fn new() -> Self {
+        Self::with_postings(PostingList::new(), AHashMap::new())
+    }
+
+    fn with_postings(documents: PostingList, index: AHashMap<String, PostingList>) -> Self {
         InvertedIndex {
-            documents: AHashSet::new(),
-            index: AHashMap::new()
+            #[cfg(feature = "positions")]
+            positions: AHashMap::new(),
+            #[cfg(feature = "positions")]
+            positional: index.is_empty(),
+            documents,
+            index
         }
     }
 
     pub fn shrink_to_fit(&mut self) {
         self.documents.shrink_to_fit();
         self.index.shrink_to_fit();
+        #[cfg(feature = "positions")]
+        {
+            self.positions.values_mut().for_each(TermPositions::shrink_to_fit);
+            self.positions.shrink_to_fit();
+        }
     }
 
     pub fn unique_word_count(&self) -> usize {
@@ -38,100 +94,334 @@ impl InvertedIndex {
     }
 
     pub fn term_positions(&self, term: &str) -> AHashSet<DocumentId> {
+        self.index.get(term)
+            .map(|documents| documents.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    fn postings(&self, term: &str) -> PostingList {
         self.index.get(term)
             .cloned()
-            .unwrap_or_else(AHashSet::new)
+            .unwrap_or_default()
+    }
+
+    // NOTE: Fields are stored as reserved terms, lexer never produces terms with ':'.
+    //  Values keep their letters and digits in lowercase, the same way the query lexer reads field values
+    pub fn field_term(name: &str, value: &str) -> String {
+        let value: String = value.chars()
+            .filter(|ch| ch.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect();
+
+        format!("{name}:{value}")
+    }
+
+    pub fn add_field(&mut self, name: &str, value: &str, document_id: DocumentId) {
+        self.add_term(Self::field_term(name, value), document_id);
+    }
+
+    // NOTE: Terms in order with their sorted documents
+    pub fn sorted_terms(&self) -> impl Iterator<Item = (&str, Vec<DocumentId>)> {
+        self.index.iter()
+            .sorted_by_key(|(term, _)| *term)
+            .map(|(term, documents)| (term.as_str(), documents.iter().copied().collect()))
     }
 
-    fn documents(&self) -> &AHashSet<DocumentId> {
+    // NOTE: Sorted document ids of every term, in term order
+    pub fn posting_lists(&self) -> Vec<Vec<usize>> {
+        self.index.iter()
+            .sorted_by_key(|(term, _)| *term)
+            .map(|(_, documents)| documents.iter().map(DocumentId::id).collect())
+            .collect()
+    }
+
+    pub fn documents(&self) -> &PostingList {
         &self.documents
     }
 
-    pub fn merge(&mut self, mut other: Self) {
-        other.index.drain()
+    pub fn merge(&mut self, other: Self) {
+        self.documents.merge(other.documents);
+        other.index.into_iter()
             .for_each(|(term, positions)| self.merge_term_positions(term, positions));
+        #[cfg(feature = "positions")]
+        {
+            self.positional &= other.positional;
+            for (term, positions) in other.positions {
+                self.positions.entry(term)
+                    .or_default()
+                    .merge(positions);
+            }
+        }
     }
 
-    fn merge_term_positions(&mut self, term: String, positions: AHashSet<DocumentId>) {
-        self.documents.extend(&positions);
+    // NOTE: Terms left without documents are dropped
+    pub fn remove_documents(&mut self, removed: &AHashSet<DocumentId>) {
+        if removed.is_empty() {
+            return;
+        }
+
+        self.documents.retain(|document_id| !removed.contains(document_id));
+        self.index.retain(|_, documents| {
+            documents.retain(|document_id| !removed.contains(document_id));
+            !documents.is_empty()
+        });
+        #[cfg(feature = "positions")]
+        self.positions.retain(|_, positions| {
+            positions.retain(|document_id| !removed.contains(document_id));
+            !positions.is_empty()
+        });
+    }
 
+    fn merge_term_positions(&mut self, term: String, positions: PostingList) {
         self.index.entry(term)
-            .or_insert_with(AHashSet::new)
-            .extend(positions);
+            .or_insert_with(PostingList::new)
+            .merge(positions);
     }
 
-    fn query_rec(&self, query_ast: &LogicNode) -> Result<AHashSet<DocumentId>> {
+    // NOTE: Intermediate results are sorted posting lists, so conjunctions and differences
+    //  skip over runs of documents instead of hashing every one of them
+    fn query_rec(&self, query_ast: &LogicNode) -> Result<PostingList> {
         Ok(match query_ast {
-            LogicNode::False => AHashSet::new(),
-            LogicNode::Term(term) => self.term_positions(term),
+            LogicNode::False => PostingList::new(),
+            LogicNode::Term(term) => self.postings(term),
             LogicNode::And(lhs, rhs) => {
-                &self.query_rec(lhs)? & &self.query_rec(rhs)?
+                self.query_rec(lhs)?.intersection(&self.query_rec(rhs)?)
             },
             LogicNode::Or(lhs, rhs) => {
-                &self.query_rec(lhs)? | &self.query_rec(rhs)?
+                self.query_rec(lhs)?.union(&self.query_rec(rhs)?)
             },
             LogicNode::Not(operand) => {
-                self.documents() - &self.query_rec(&operand)?
+                self.documents.difference(&self.query_rec(operand)?)
             },
             LogicNode::Near(_, _, _, _) => {
-                return Err(anyhow!("Operation not supported."));
+                let candidates = self.candidates(query_ast).into_iter()
+                    .flatten()
+                    .copied()
+                    .sorted()
+                    .dedup();
+                let mut documents = PostingList::new();
+                for document_id in candidates {
+                    if self.near_matches(query_ast, document_id)? {
+                        documents.insert(document_id);
+                    }
+                }
+
+                documents
             },
             LogicNode::Subtract(lhs, rhs) => {
-                &self.query_rec(lhs)? - &self.query_rec(rhs)?
-            }
+                self.query_rec(lhs)?.difference(&self.query_rec(rhs)?)
+            },
+            LogicNode::Field(name, value) => self.postings(&Self::field_term(name, value))
+        })
+    }
+    // NOTE: Every matching document is in one of the returned posting lists.
+    //  Conjunctions only keep the smaller side, so they are never intersected up front
+    fn candidates(&self, query_ast: &LogicNode) -> Vec<&PostingList> {
+        match query_ast {
+            LogicNode::False => Vec::new(),
+            LogicNode::Term(term) => self.index.get(term).into_iter().collect(),
+            LogicNode::And(lhs, rhs) | LogicNode::Near(lhs, rhs, _, _) => {
+                let lhs = self.candidates(lhs);
+                let rhs = self.candidates(rhs);
+                let size = |lists: &Vec<&PostingList>| lists.iter().map(|list| list.len()).sum::<usize>();
+
+                if size(&lhs) <= size(&rhs) { lhs } else { rhs }
+            },
+            LogicNode::Or(lhs, rhs) => {
+                let mut candidates = self.candidates(lhs);
+                candidates.extend(self.candidates(rhs));
+
+                candidates
+            },
+            LogicNode::Not(_) => vec![&self.documents],
+            LogicNode::Subtract(lhs, _) => self.candidates(lhs),
+            LogicNode::Field(name, value) => self.index.get(&Self::field_term(name, value)).into_iter().collect()
+        }
+    }
+
+    fn matches(&self, query_ast: &LogicNode, document_id: DocumentId) -> Result<bool> {
+        Ok(match query_ast {
+            LogicNode::False => false,
+            LogicNode::Term(term) => self.contains(term, document_id),
+            LogicNode::And(lhs, rhs) => self.matches(lhs, document_id)? && self.matches(rhs, document_id)?,
+            LogicNode::Or(lhs, rhs) => self.matches(lhs, document_id)? || self.matches(rhs, document_id)?,
+            LogicNode::Not(operand) => !self.matches(operand, document_id)?,
+            LogicNode::Near(_, _, _, _) => self.near_matches(query_ast, document_id)?,
+            LogicNode::Subtract(lhs, rhs) => self.matches(lhs, document_id)? && !self.matches(rhs, document_id)?,
+            LogicNode::Field(name, value) => self.contains(&Self::field_term(name, value), document_id)
         })
     }
+
+    // NOTE: Why phrase and proximity queries can't be answered, none when they can
+    #[cfg(not(feature = "positions"))]
+    pub fn positions_unavailable(&self) -> Option<&'static str> {
+        Some("pw6 was built without the \"positions\" feature")
+    }
+
+    #[cfg(feature = "positions")]
+    pub fn positions_unavailable(&self) -> Option<&'static str> {
+        (!self.positional).then_some("the index was read back from a file, which keeps only postings")
+    }
+
+    fn near_matches(&self, query_ast: &LogicNode, document_id: DocumentId) -> Result<bool> {
+        match self.positions_unavailable() {
+            Some(reason) => Err(anyhow!("Phrase and proximity queries aren't available, {reason}")),
+            None => Ok(self.positions_in(query_ast, document_id).is_some())
+        }
+    }
+
+    #[cfg(not(feature = "positions"))]
+    fn positions_in(&self, _query_ast: &LogicNode, _document_id: DocumentId) -> Option<Vec<usize>> {
+        None
+    }
+
+    // NOTE: Offsets the query matched at in the document, none when the document doesn't match it.
+    //  Fields, negations and subtracted terms match without offsets of their own, so proximity to them never holds
+    #[cfg(feature = "positions")]
+    fn positions_in(&self, query_ast: &LogicNode, document_id: DocumentId) -> Option<Vec<usize>> {
+        match query_ast {
+            LogicNode::False => None,
+            LogicNode::Term(term) => self.contains(term, document_id).then(|| {
+                self.positions.get(term)
+                    .and_then(|positions| positions.get(document_id))
+                    .map(<[usize]>::to_vec)
+                    .unwrap_or_default()
+            }),
+            LogicNode::And(lhs, rhs) => {
+                let lhs = self.positions_in(lhs, document_id)?;
+                let rhs = self.positions_in(rhs, document_id)?;
+
+                Some(lhs.into_iter().merge(rhs).dedup().collect())
+            },
+            LogicNode::Or(lhs, rhs) => match (self.positions_in(lhs, document_id), self.positions_in(rhs, document_id)) {
+                (Some(lhs), Some(rhs)) => Some(lhs.into_iter().merge(rhs).dedup().collect()),
+                (lhs, rhs) => lhs.or(rhs)
+            },
+            LogicNode::Not(operand) => {
+                (self.documents.contains(document_id) && self.positions_in(operand, document_id).is_none()).then(Vec::new)
+            },
+            LogicNode::Near(lhs, rhs, left, right) => {
+                let lhs = self.positions_in(lhs, document_id)?;
+                let rhs = self.positions_in(rhs, document_id)?;
+                let near = close_union(&lhs, &rhs, *left, *right);
+
+                (!near.is_empty()).then_some(near)
+            },
+            LogicNode::Subtract(lhs, rhs) => match self.positions_in(rhs, document_id) {
+                Some(_) => None,
+                None => self.positions_in(lhs, document_id)
+            },
+            LogicNode::Field(name, value) => self.contains(&Self::field_term(name, value), document_id).then(Vec::new)
+        }
+    }
+
+    // NOTE: Candidate lists are sorted, so they're merged lazily and nothing past the last match is read
+    fn query_limited(&self, query_ast: &LogicNode, limit: usize) -> Result<AHashSet<DocumentId>> {
+        let candidates = self.candidates(query_ast).into_iter()
+            .kmerge()
+            .dedup()
+            .copied();
+
+        let mut result = AHashSet::new();
+        for document_id in candidates {
+            if result.len() == limit {
+                break;
+            }
+            if self.matches(query_ast, document_id)? {
+                result.insert(document_id);
+            }
+        }
+
+        Ok(result)
+    }
 }
 
+// NOTE: Positions aren't saved, so an index read back from a file equals the one it was saved from when their postings do
+impl PartialEq for InvertedIndex {
+    fn eq(&self, other: &Self) -> bool {
+        self.documents == other.documents && self.index == other.index
+    }
+}
+
+impl Eq for InvertedIndex {}
+
 impl TermIndex for InvertedIndex {
     fn add_term(&mut self, term: String, document_id: DocumentId) {
         self.index.entry(term)
-            .or_insert_with(AHashSet::new)
+            .or_insert_with(PostingList::new)
             .insert(document_id);
 
         self.documents.insert(document_id);
     }
 
-    fn query(&self, query_ast: &LogicNode) -> Result<AHashSet<DocumentId>> {
-        self.query_rec(query_ast)
+    #[cfg(feature = "positions")]
+    fn add_term_at(&mut self, term: String, document_id: DocumentId, offset: usize) {
+        self.positions.entry(term.clone())
+            .or_default()
+            .add_position(document_id, offset);
+        self.add_term(term, document_id);
+    }
+
+    fn query(&self, query_ast: &LogicNode, limit: Option<usize>) -> Result<AHashSet<DocumentId>> {
+        match limit {
+            Some(limit) => self.query_limited(query_ast, limit),
+            None => Ok(self.query_rec(query_ast)?.iter().copied().collect())
+        }
+    }
+
+    fn document_frequency(&self, term: &str) -> usize {
+        self.index.get(term).map_or(0, |documents| documents.len())
+    }
+
+    fn document_count(&self) -> usize {
+        self.documents.len()
+    }
+
+    fn contains(&self, term: &str, document_id: DocumentId) -> bool {
+        self.index.get(term).is_some_and(|documents| documents.contains(document_id))
+    }
+
+    fn document_term_counts(&self) -> AHashMap<DocumentId, usize> {
+        let mut counts = AHashMap::new();
+        self.index.values()
+            .flatten()
+            .for_each(|&document_id| *counts.entry(document_id).or_default() += 1);
+
+        counts
     }
 }
 
 impl InvertedIndex {
     const TERM_POSITIONS_SEPARATOR: &'static str = ":";
     const POSITIONS_SEPARATOR: &'static str = ",";
-
+    // NOTE: Sections are only read in the layout of this version, an index saved in any other is built again
+    const FORMAT: &'static [u8] = b"pw6 compressed index 1";
+    const FORMAT_SECTION: &'static str = "format";
+    const DICTIONARY_SECTION: &'static str = "dictionary";
+    const POSTINGS_SECTION: &'static str = "postings";
+    const REGISTRY_SECTION: &'static str = "registry";
+    const FINGERPRINTS_SECTION: &'static str = "fingerprints";
+    const BLOCKS_SECTION: &'static str = "blocks";
+    const CODEC_SECTION: &'static str = "codec";
+    const DICTIONARY_BLOCK_SECTION: &'static str = "dictionary block";
+    // NOTE: Terms per independently decoded block, a few per thread is enough to keep them all busy
+    const BLOCK_TERMS: usize = 1024;
+
+    // NOTE: Terms and documents are sorted, so the same corpus always produces the same file
     pub fn save(&self, mut writer: impl Write) -> Result<()> {
-        for (term, documents) in &self.index {
-            writer.write_all(term.as_bytes())?;
-            writer.write_all(Self::TERM_POSITIONS_SEPARATOR.as_bytes())?;
-            for (i, document) in documents.iter().enumerate() {
-                writer.write_all(format!("{}", document.id()).as_bytes())?;
-                if i + 1 != documents.len() {
-                    writer.write_all(Self::POSITIONS_SEPARATOR.as_bytes())?;
-                }
-            }
-
-            writer.write_all("\n".as_bytes())?;
+        for (term, documents) in self.sorted_terms() {
+            Self::write_line(&mut writer, term, &documents)?;
         }
 
         Ok(())
     }
 
     pub fn load(reader: impl BufRead) -> Result<Self> {
-        let mut index = AHashMap::new();
+        let mut index = AHashMap::<String, PostingList>::new();
         for line in reader.lines() {
-            let line = line?;
-            let (term, positions_str) = line.split(Self::TERM_POSITIONS_SEPARATOR).collect_tuple()
-                .ok_or_else(|| anyhow!("Expected term and document ids"))?;
-            let mut positions = AHashSet::new();
-            for position_str in positions_str.split(Self::POSITIONS_SEPARATOR) {
-                let document_id = usize::from_str(position_str)?;
-
-                positions.insert(DocumentId(document_id));
-            }
+            let (term, documents) = Self::read_line(&line?)?;
 
-            index.insert(term.to_owned(), positions);
+            index.insert(term, documents.into_iter().collect());
         }
 
         let documents = index.iter()
@@ -139,137 +429,420 @@ impl InvertedIndex {
             .cloned()
             .collect();
 
-        Ok(InvertedIndex {
-            documents,
-            index
-        })
+        Ok(Self::with_postings(documents, index))
     }
 
-    pub fn save_compressed(&self, mut writer: impl Write) -> Result<()> {
-        let terms = self.write_dictionary_compressed(&mut writer)?;
-
-        for documents in terms.iter().map(|&term| self.index.get(term).unwrap()) {
-            let mut prev_document_id = 0;
-
-            let documents_count = documents.len();
-            writer.write_all(&vb_encode(documents_count))?;
-            for document in documents.iter().sorted() {
-                let delta = document.id() - prev_document_id;
-                prev_document_id = document.id();
-
-                let delta_vb = vb_encode(delta);
-                writer.write_all(&delta_vb)?;
+    // NOTE: One term with its sorted documents per line, the format of `save`
+    pub fn write_line(writer: &mut impl Write, term: &str, documents: &[DocumentId]) -> Result<()> {
+        writer.write_all(term.as_bytes())?;
+        writer.write_all(Self::TERM_POSITIONS_SEPARATOR.as_bytes())?;
+        for (i, document) in documents.iter().enumerate() {
+            writer.write_all(format!("{}", document.id()).as_bytes())?;
+            if i + 1 != documents.len() {
+                writer.write_all(Self::POSITIONS_SEPARATOR.as_bytes())?;
             }
         }
 
+        writer.write_all("\n".as_bytes())?;
+
         Ok(())
     }
 
-    pub fn read_compressed(reader: impl BufRead) -> Result<Self> {
-        let mut iter = reader.bytes().peekable();
+    pub fn read_line(line: &str) -> Result<(String, Vec<DocumentId>)> {
+        // NOTE: Field terms contain the separator themselves, so split on the last one
+        let (term, positions_str) = line.rsplit_once(Self::TERM_POSITIONS_SEPARATOR)
+            .ok_or_else(|| anyhow!("Expected term and document ids"))?;
+        let documents = positions_str.split(Self::POSITIONS_SEPARATOR)
+            .map(|position_str| Ok(DocumentId(usize::from_str(position_str)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((term.to_owned(), documents))
+    }
 
-        let mut terms = Self::read_dictionary_compressed(&mut iter)?;
-        let mut index = AHashMap::with_capacity(terms.len());
-        for term in terms.drain(..) {
-            let document_count = vb_decode(&mut iter)?;
-            let mut documents = AHashSet::with_capacity(document_count);
-            let mut prev_document_id = 0;
-            for _ in 0..document_count {
-                let delta = vb_decode(&mut iter)?;
-                prev_document_id += delta;
+    // NOTE: Rough heap size of the terms, posting lists and positions
+    pub fn estimated_size(&self) -> usize {
+        let postings: usize = self.index.iter()
+            .map(|(term, documents)| size_of::<String>() + term.len() + size_of::<PostingList>() + documents.capacity() * size_of::<DocumentId>())
+            .sum();
+        #[cfg(feature = "positions")]
+        let postings = postings + self.positions.iter()
+            .map(|(term, positions)| size_of::<String>() + term.len() + positions.estimated_size())
+            .sum::<usize>();
+
+        postings
+    }
 
-                documents.insert(DocumentId(prev_document_id));
+    // NOTE: The document registry is stored too, so document ids can be resolved to names without the corpus.
+    //  Fingerprints of the documents let a reloaded index notice the corpus has changed since.
+    //  Terms are split into blocks, each block front codes its terms from scratch and the offsets
+    //  of every block are stored, so blocks can be decoded independently of each other.
+    //  Postings of a block are coded with the chosen codec, bit codecs pad every block to a whole byte
+    pub fn save_compressed(&self, mut writer: impl Write, documents: &DocumentRegistry, fingerprints: &[DocumentFingerprint], compression: Compression) -> Result<CompressedSize> {
+        let terms: Vec<&String> = self.index.keys().sorted().collect();
+        let mut dictionary = Vec::new();
+        let mut postings = Vec::new();
+        let mut blocks = vb_encode(terms.len().div_ceil(Self::BLOCK_TERMS));
+        for block in terms.chunks(Self::BLOCK_TERMS) {
+            blocks.extend(vb_encode(dictionary.len()));
+            blocks.extend(vb_encode(postings.len()));
+            Self::write_dictionary_blocked(block, compression.dictionary_block, &mut dictionary);
+
+            let mut block_postings = CodecWriter::new(compression.codec);
+            for documents in block.iter().map(|&term| self.index.get(term).unwrap()) {
+                let mut prev_document_id = 0;
+
+                let documents_count = documents.len();
+                block_postings.write(documents_count);
+                for document in documents {
+                    let delta = document.id() - prev_document_id;
+                    prev_document_id = document.id();
+
+                    block_postings.write(delta);
+                }
             }
+            postings.extend(block_postings.into_bytes());
+        }
 
-            index.insert(term, documents);
+        persist::write_section(&mut writer, Self::FORMAT)?;
+        persist::write_section(&mut writer, &dictionary)?;
+        persist::write_section(&mut writer, &postings)?;
+        persist::write_section(&mut writer, &serde_json::to_vec(documents)?)?;
+        persist::write_section(&mut writer, &serde_json::to_vec(fingerprints)?)?;
+        persist::write_section(&mut writer, &blocks)?;
+        persist::write_section(&mut writer, &[compression.codec.tag()])?;
+        persist::write_section(&mut writer, &vb_encode(compression.dictionary_block))?;
+
+        Ok(CompressedSize {
+            dictionary: dictionary.len(),
+            postings: postings.len()
+        })
+    }
+
+    pub fn read_compressed(mut data: &[u8]) -> Result<(Self, DocumentRegistry, Vec<DocumentFingerprint>)> {
+        if persist::read_section(&mut data, Self::FORMAT_SECTION).ok() != Some(Self::FORMAT) {
+            return Err(anyhow!("Index wasn't compressed in the current format, build and save it again"));
         }
+        let dictionary = persist::read_section(&mut data, Self::DICTIONARY_SECTION)?;
+        let postings = persist::read_section(&mut data, Self::POSTINGS_SECTION)?;
+        let registry = serde_json::from_slice(persist::read_section(&mut data, Self::REGISTRY_SECTION)?)?;
+        let fingerprints = serde_json::from_slice(persist::read_section(&mut data, Self::FINGERPRINTS_SECTION)?)?;
+        let blocks = Self::read_blocks(persist::read_section(&mut data, Self::BLOCKS_SECTION)?)?;
+        let codec = match persist::read_section(&mut data, Self::CODEC_SECTION)? {
+            &[tag] => Codec::from_tag(tag)?,
+            section => return Err(anyhow!("Codec section is {} bytes long, expected 1", section.len()))
+        };
+        let dictionary_block = ByteReader::new(persist::read_section(&mut data, Self::DICTIONARY_BLOCK_SECTION)?).read_vb()?;
+        if dictionary_block == 0 {
+            return Err(anyhow!("Dictionary block size must be positive"));
+        }
+        if !data.is_empty() {
+            return Err(anyhow!("Unexpected {} bytes after the last section", data.len()));
+        }
+
+        let ends = blocks.iter()
+            .skip(1)
+            .cloned()
+            .chain(std::iter::once((dictionary.len(), postings.len())));
+        let decoded = blocks.iter()
+            .zip(ends)
+            .map(|(&(dictionary_start, postings_start), (dictionary_end, postings_end))| {
+                let dictionary = dictionary.get(dictionary_start..dictionary_end);
+                let postings = postings.get(postings_start..postings_end);
+                dictionary.zip(postings).ok_or_else(|| anyhow!("Block offsets point outside of the index"))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_par_iter()
+            .map(|(dictionary, postings)| Self::read_block(dictionary, postings, codec, dictionary_block))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut index = AHashMap::with_capacity(decoded.iter().map(Vec::len).sum());
+        index.extend(decoded.into_iter().flatten());
 
         let documents = index.iter()
             .flat_map(|(_, documents)| documents.iter())
             .cloned()
             .collect();
 
-        Ok(InvertedIndex {
-            index,
-            documents
-        })
+        Ok((Self::with_postings(documents, index), registry, fingerprints))
     }
 
-    fn write_dictionary_compressed(&self, writer: &mut impl Write) -> Result<Vec<&String>> {
-        let mut anchor = None;
-        let terms: Vec<&String> = self.index.keys().sorted().collect();
-        for term in terms.iter() {
-            let prefix_len = if let Some(anchor) = anchor {
-                Self::longest_prefix(anchor, term)
-            } else {
-                0
-            };
-
-            anchor = Some(term);
-            writer.write_all(format!("{}", prefix_len).as_bytes())?;
-            writer.write_all(term[prefix_len..].as_bytes())?;
+    fn read_blocks(data: &[u8]) -> Result<Vec<(usize, usize)>> {
+        let mut reader = ByteReader::new(data);
+        let block_count = reader.read_vb()?;
+        let blocks = (0..block_count)
+            .map(|_| Ok((reader.read_vb()?, reader.read_vb()?)))
+            .collect::<Result<Vec<_>>>()?;
+        if blocks.windows(2).any(|pair| pair[0].0 > pair[1].0 || pair[0].1 > pair[1].1) {
+            return Err(anyhow!("Block offsets aren't sorted"));
         }
-        writer.write_all(&[0u8])?;
 
-        Ok(terms)
+        Ok(blocks)
     }
 
-    fn read_dictionary_compressed(iter: &mut Peekable<impl Iterator<Item = Result<u8, std::io::Error>>>) -> Result<Vec<String>> {
-        let mut terms = Vec::<String>::new();
+    fn read_block(dictionary: &[u8], postings: &[u8], codec: Codec, dictionary_block: usize) -> Result<Vec<(String, PostingList)>> {
+        let terms = Self::read_dictionary_blocked(dictionary, dictionary_block)?;
+        let mut reader = CodecReader::new(codec, postings);
+        let block = terms.into_iter()
+            .map(|term| {
+                let document_count = reader.read()?;
+                let mut documents = PostingList::with_capacity(document_count);
+                let mut prev_document_id = 0;
+                for _ in 0..document_count {
+                    let delta = reader.read()?;
+                    prev_document_id += delta;
+
+                    documents.insert(DocumentId(prev_document_id));
+                }
 
-        while let Some(&Ok(byte)) = iter.peek() {
-            if byte == 0u8 {
-                iter.next();
-                break;
-            }
+                Ok((term, documents))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !reader.is_empty() {
+            return Err(anyhow!("Unexpected data after the postings of a block"));
+        }
 
-            let prefix_len = Self::read_number(iter)?;
-            let text = Self::read_text(iter)?;
+        Ok(block)
+    }
 
-            if let Some(anchor) = terms.last() {
-                terms.push(anchor[..prefix_len].to_owned() + &text);
+    // NOTE: Every dictionary block starts with its first term in full, the others store the length of the prefix
+    //  they share with it and their remaining suffix. Lengths are variable byte coded, so terms may contain digits.
+    //  Larger blocks store fewer full terms, but share shorter prefixes with the first one
+    fn write_dictionary_blocked(terms: &[&String], block_size: usize, dictionary: &mut Vec<u8>) {
+        for block in terms.chunks(block_size) {
+            let head = block[0];
+            dictionary.extend(vb_encode(head.len()));
+            dictionary.extend(head.as_bytes());
+
+            for term in &block[1..] {
+                let prefix_len = Self::longest_prefix(head, term);
+                dictionary.extend(vb_encode(prefix_len));
+                dictionary.extend(vb_encode(term.len() - prefix_len));
+                dictionary.extend(term[prefix_len..].as_bytes());
+            }
+        }
+    }
+
+    fn read_dictionary_blocked(data: &[u8], block_size: usize) -> Result<Vec<String>> {
+        let mut reader = ByteReader::new(data);
+        let mut terms = Vec::new();
+        let mut head = "";
+        while !reader.is_empty() {
+            if terms.len() % block_size == 0 {
+                let head_len = reader.read_vb()?;
+                head = std::str::from_utf8(reader.read_bytes(head_len)?)?;
+                terms.push(head.to_owned());
             } else {
-                terms.push(text);
+                let prefix_len = reader.read_vb()?;
+                let suffix_len = reader.read_vb()?;
+                let prefix = head.get(..prefix_len)
+                    .ok_or_else(|| anyhow!("Term shares {prefix_len} bytes with \"{head}\", which is shorter"))?;
+                terms.push(prefix.to_owned() + std::str::from_utf8(reader.read_bytes(suffix_len)?)?);
             }
         }
 
         Ok(terms)
     }
 
-    fn read_number(iter: &mut Peekable<impl Iterator<Item = Result<u8, std::io::Error>>>) -> Result<usize> {
-        let mut number_str = String::new();
-        while let Some(&Ok(byte)) = iter.peek() {
-            if !byte.is_ascii_digit() {
-                break;
+    fn longest_prefix(anchor: &str, term: &str) -> usize {
+        anchor
+            .char_indices()
+            .zip(term.chars())
+            .find(|((_, c1), c2)| c1 != c2)
+            .map(|((i, _), _)| i)
+            .unwrap_or_else(|| anchor.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query_lang::parse_logic_expr;
+
+    // NOTE: Shared prefixes of different lengths, a multibyte one and a term that is a prefix of the next
+    const TERMS: [&str; 10] = ["a", "ab", "abc", "abd", "b", "bar", "bark", "zebra", "ёж", "ёжик"];
+
+    #[test]
+    fn dictionary_blocks_round_trip() -> Result<()> {
+        let terms = TERMS.iter().map(|term| term.to_string()).collect::<Vec<_>>();
+        let terms = terms.iter().collect::<Vec<_>>();
+        for block_size in [1, 2, 3, 4, 10, 16] {
+            let mut dictionary = Vec::new();
+            InvertedIndex::write_dictionary_blocked(&terms, block_size, &mut dictionary);
+
+            assert_eq!(InvertedIndex::read_dictionary_blocked(&dictionary, block_size)?, TERMS, "block size {block_size}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn field_values_keep_digits() -> Result<()> {
+        let mut index = InvertedIndex::new();
+        index.add_field("collection", "2019", DocumentId(0));
+        index.add_field("collection", "2020", DocumentId(1));
+        index.add_field("collection", "Rock-2019", DocumentId(2));
+
+        for (query, document_id) in [("collection:2019", 0), ("collection:2020", 1), ("collection:rock2019", 2)] {
+            let result = index.query(&parse_logic_expr(query)?, None)?;
+            assert_eq!(result, AHashSet::from_iter([DocumentId(document_id)]), "{query}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_index_round_trip() -> Result<()> {
+        let mut index = InvertedIndex::new();
+        for (i, term) in TERMS.iter().enumerate() {
+            for document_id in (0..40).filter(|document_id| document_id % (i + 1) == 0) {
+                index.add_term(term.to_string(), DocumentId(document_id));
             }
+        }
+
+        for codec in Codec::ALL {
+            for dictionary_block in [1, 3, DEFAULT_DICTIONARY_BLOCK, 16] {
+                let mut data = Vec::new();
+                index.save_compressed(&mut data, &DocumentRegistry::new(), &[], Compression { codec, dictionary_block })?;
+                let (index_read, _, _) = InvertedIndex::read_compressed(&data)?;
 
-            number_str.push(byte as char);
-            iter.next();
+                assert_eq!(index_read, index, "{codec:?} in blocks of {dictionary_block}");
+            }
         }
 
-        Ok(number_str.parse()?)
+        Ok(())
     }
 
-    fn read_text(iter: &mut Peekable<impl Iterator<Item = Result<u8, std::io::Error>>>) -> Result<String> {
-        let mut buf = Vec::new();
-        while let Some(&Ok(byte)) = iter.peek() {
-            if byte == 0u8 || byte.is_ascii_digit() {
-                break;
+    #[test]
+    fn limited_queries_keep_lowest_ids() -> Result<()> {
+        let mut index = InvertedIndex::new();
+        for (i, term) in TERMS.iter().enumerate() {
+            for document_id in (0..40).filter(|document_id| document_id % (i + 2) == 1) {
+                index.add_term(term.to_string(), DocumentId(document_id));
             }
+        }
 
-            buf.push(byte);
-            iter.next();
+        for query in ["ab", "a | zebra", "a & ab", "!a", "!(a | ab)", "bar \\ bark", "missing"] {
+            let query_ast = parse_logic_expr(query)?;
+            let all = index.query(&query_ast, None)?.into_iter().sorted().collect::<Vec<_>>();
+            for limit in [0, 1, 3, 100] {
+                let limited = index.query(&query_ast, Some(limit))?;
+                assert_eq!(limited, all.iter().copied().take(limit).collect(), "{query} limited to {limit}");
+            }
         }
 
-        Ok(String::from_utf8(buf)?)
+        Ok(())
     }
 
-    fn longest_prefix(anchor: &str, term: &str) -> usize {
-        anchor
-            .char_indices()
-            .zip(term.chars())
-            .find(|((_, c1), c2)| c1 != c2)
-            .map(|((i, _), _)| i)
-            .unwrap_or_else(|| anchor.len())
+    #[test]
+    fn other_formats_are_rejected() -> Result<()> {
+        let mut index = InvertedIndex::new();
+        TERMS.iter().for_each(|term| index.add_term(term.to_string(), DocumentId(0)));
+        let mut data = Vec::new();
+        index.save_compressed(&mut data, &DocumentRegistry::new(), &[], Compression::default())?;
+
+        // NOTE: Without the format section, like the layouts written before it, and with another version
+        let format_length = 8 + InvertedIndex::FORMAT.len() + 4;
+        let mut other_version = Vec::new();
+        persist::write_section(&mut other_version, b"pw6 compressed index 0")?;
+        other_version.extend(&data[format_length..]);
+        for data in [&data[format_length..], &other_version] {
+            let error = InvertedIndex::read_compressed(data).unwrap_err();
+            assert!(error.to_string().contains("current format"), "{error}");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "positions"))]
+mod positions_tests {
+    use super::*;
+    use crate::query_lang::parse_logic_expr;
+
+    const TEXTS: [&str; 4] = [
+        "to be or not to be",
+        "not to be is the question",
+        "be it or not",
+        "very very good"
+    ];
+
+    fn index_texts() -> InvertedIndex {
+        let mut index = InvertedIndex::new();
+        for (document_id, text) in TEXTS.iter().enumerate() {
+            for (offset, word) in text.split_whitespace().enumerate() {
+                index.add_term_at(word.to_owned(), DocumentId(document_id), offset);
+            }
+        }
+
+        index
+    }
+
+    fn assert_matches(index: &InvertedIndex, query: &str, expected: &[usize]) -> Result<()> {
+        let expected = expected.iter().copied().map(DocumentId).collect::<AHashSet<_>>();
+        let query_ast = parse_logic_expr(query)?;
+        assert_eq!(index.query(&query_ast, None)?, expected, "{query}");
+        assert_eq!(index.query(&query_ast, Some(TEXTS.len()))?, expected, "{query} with a limit");
+
+        Ok(())
+    }
+
+    #[test]
+    fn adjacent_words() -> Result<()> {
+        let index = index_texts();
+        assert_matches(&index, "\"to be\"", &[0, 1])?;
+        assert_matches(&index, "to > be", &[0, 1])?;
+        assert_matches(&index, "\"be to\"", &[])?;
+        assert_matches(&index, "\"or not\"", &[0, 2])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn three_word_phrases() -> Result<()> {
+        let index = index_texts();
+        assert_matches(&index, "\"not to be\"", &[0, 1])?;
+        assert_matches(&index, "\"be or not\"", &[0])?;
+        // NOTE: Every word is in the document, just not in that order
+        assert_matches(&index, "\"be not or\"", &[])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn windows_work_both_ways() -> Result<()> {
+        let index = index_texts();
+        assert_matches(&index, "question {4} be", &[1])?;
+        assert_matches(&index, "be {4} question", &[1])?;
+        assert_matches(&index, "question {1} be", &[])?;
+        assert_matches(&index, "it {2} not", &[2])?;
+        assert_matches(&index, "it {1} not", &[])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_words_need_separate_occurrences() -> Result<()> {
+        let index = index_texts();
+        assert_matches(&index, "\"very very\"", &[3])?;
+        assert_matches(&index, "\"very good\"", &[3])?;
+        assert_matches(&index, "\"very very very\"", &[])?;
+        assert_matches(&index, "good {1} good", &[])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn saved_index_has_no_positions() -> Result<()> {
+        let index = index_texts();
+        assert_eq!(index.positions_unavailable(), None);
+
+        let mut data = Vec::new();
+        index.save_compressed(&mut data, &DocumentRegistry::new(), &[], Compression::default())?;
+        let (index_read, _, _) = InvertedIndex::read_compressed(&data)?;
+        assert!(index_read.positions_unavailable().is_some());
+        let error = index_read.query(&parse_logic_expr("\"to be\"")?, None).unwrap_err();
+        assert!(error.to_string().contains("aren't available"), "{error}");
+
+        Ok(())
     }
 }
@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Context, Result};
+use ahash::AHashMap;
+use std::io::BufRead;
+use std::str::FromStr;
+use crate::document::Document;
+use crate::segment::SegmentKind;
+
+pub struct IndexBoosts {
+    zones: AHashMap<SegmentKind, f64>,
+    documents: AHashMap<String, f64>
+}
+
+impl IndexBoosts {
+    const ZONE_PREFIX: &'static str = "zone:";
+    const DOCUMENT_PREFIX: &'static str = "document:";
+    const COMMENT: &'static str = "#";
+
+    pub fn new() -> Self {
+        IndexBoosts {
+            zones: AHashMap::new(),
+            documents: AHashMap::new()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty() && self.documents.is_empty()
+    }
+
+    // NOTE: Lines look like "zone:title = 1.5" or "document:hamlet.txt = 2",
+    //  documents are matched either by full path or by file name
+    pub fn load(reader: impl BufRead) -> Result<Self> {
+        let mut boosts = IndexBoosts::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(Self::COMMENT) {
+                continue;
+            }
+
+            let (key, boost) = line.rsplit_once('=')
+                .ok_or_else(|| anyhow!("Expected boost in form 'key = boost' on line {}", i + 1))?;
+            let boost = f64::from_str(boost.trim()).context(anyhow!("Invalid boost on line {}", i + 1))?;
+            if let Some(zone) = key.trim().strip_prefix(Self::ZONE_PREFIX) {
+                boosts.zones.insert(SegmentKind::from_str(zone)?, boost);
+            } else if let Some(document) = key.trim().strip_prefix(Self::DOCUMENT_PREFIX) {
+                boosts.documents.insert(document.trim().to_owned(), boost);
+            } else {
+                return Err(anyhow!("Expected key to start with \"{}\" or \"{}\" on line {}", Self::ZONE_PREFIX, Self::DOCUMENT_PREFIX, i + 1));
+            }
+        }
+
+        Ok(boosts)
+    }
+
+    pub fn zone_boost(&self, segment_kind: SegmentKind) -> f64 {
+        self.zones.get(&segment_kind)
+            .cloned()
+            .unwrap_or(1.0)
+    }
+
+    pub fn document_boost(&self, document: &Document) -> f64 {
+        let path = document.path();
+        let file_name = path.file_name()
+            .and_then(|file_name| file_name.to_str());
+
+        self.documents.get(path.to_string_lossy().as_ref())
+            .or_else(|| file_name.and_then(|file_name| self.documents.get(file_name)))
+            .cloned()
+            .unwrap_or(1.0)
+    }
+}
+
+impl Default for IndexBoosts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,59 +1,86 @@
 use std::borrow::Cow;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::sync::Arc;
 use crate::inf_context::InfContext;
 use crate::term_index::InvertedIndex;
 use crate::lexer::{Lexer, LexerStats};
-use crate::document::{Document, DocumentId};
+use crate::document::DocumentId;
 use crate::fb2_segmenter::Fb2Segmenter;
 use crate::plain_text_segmenter::PlainTextSegmenter;
+use crate::html_segmenter::HtmlSegmenter;
 use crate::segment::{Segmenter, SegmentKind, Segments};
+use crate::boost::IndexBoosts;
+use crate::ledger::LedgerStage;
+use crate::format::DocumentFormat;
 
-fn get_segmenter(document_id: DocumentId, ctx: &InfContext) -> Result<Box<dyn Segmenter + '_>> {
-    if let Some(document) = ctx.document(document_id) {
-        if let Document::File { path, .. } = document {
-            if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
-                return Ok(match extension {
-                    "fb2" => Box::new(Fb2Segmenter::new(document_id, ctx)?),
-                    _ => Box::new(PlainTextSegmenter::new(document_id, ctx)?)
-                });
-            }
+// NOTE: Format specific segmenter of the document, plain text and PDF have none
+fn get_segmenter(document_id: DocumentId, ctx: &InfContext, format: DocumentFormat) -> Option<Result<Box<dyn Segmenter + '_>>> {
+    Some(match format {
+        DocumentFormat::Fb2 => Fb2Segmenter::new(document_id, ctx).map(|segmenter| Box::new(segmenter) as Box<dyn Segmenter>),
+        DocumentFormat::Html => HtmlSegmenter::new(document_id, ctx).map(|segmenter| Box::new(segmenter) as Box<dyn Segmenter>),
+        DocumentFormat::PlainText | DocumentFormat::Pdf => return None
+    })
+}
+
+// NOTE: A malformed file of a known format is still indexed as plain text, the failure is recorded in the ledger.
+//  PDF can't be segmented, its binary content would only add noise terms, so only its file name is indexed
+fn segment_with_fallback(document_id: DocumentId, ctx: &InfContext) -> Result<Segments<'_>> {
+    let Some(document) = ctx.document(document_id) else {
+        return Box::new(PlainTextSegmenter::new(document_id, ctx)?).segment();
+    };
+    let format = match ctx.unsupported_format(document_id) {
+        Some(format) => format,
+        None => DocumentFormat::detect(document.path(), ctx.document_data(document_id)?.as_bytes())
+    };
+    if format == DocumentFormat::Pdf {
+        ctx.ledger().record(document.path().to_owned(), LedgerStage::Unsupported, Some(document_id), &anyhow!("PDF documents can't be segmented"));
+        return Ok(Segments::new());
+    }
+
+    if let Some(segmenter) = get_segmenter(document_id, ctx, format) {
+        match segmenter.and_then(|segmenter| segmenter.segment()) {
+            Ok(segments) => return Ok(segments),
+            Err(err) => ctx.ledger().record(document.path().to_owned(), LedgerStage::Segmenting, Some(document_id), &err)
         }
     }
 
-    Ok(Box::new(PlainTextSegmenter::new(document_id, ctx)?))
+    Box::new(PlainTextSegmenter::new(document_id, ctx)?).segment()
 }
 
-fn segment_file(document_id: DocumentId, ctx: &InfContext) -> Result<Segments> {
-    let segmenter = get_segmenter(document_id, &ctx)?;
-    let mut segments = segmenter.segment()?;
+pub fn segment_file(document_id: DocumentId, ctx: &InfContext) -> Result<Segments> {
+    let mut segments = segment_with_fallback(document_id, ctx)?;
 
     if let Some(document) = ctx.document(document_id) {
-        if let Document::File { path, .. } = document {
-            path.iter()
-                .map(|component| component.to_str())
-                .flatten()
-                .for_each(|component| segments.add(SegmentKind::Filename, Cow::Owned(component.to_owned())));
-        }
+        document.path().iter()
+            .map(|component| component.to_str())
+            .flatten()
+            .for_each(|component| segments.add(SegmentKind::Filename, Cow::Owned(component.to_owned())));
     }
 
     Ok(segments)
 }
 
-fn lex_file(document_id: DocumentId, ctx: Arc<InfContext>) -> Result<Option<(InvertedIndex, LexerStats)>> {
+pub fn index_document(document_id: DocumentId, ctx: &InfContext, boosts: &IndexBoosts) -> Result<(InvertedIndex, LexerStats)> {
     let mut inverted_index = InvertedIndex::new();
     let mut stats = LexerStats::default();
-    for (&segment_kind, segments) in segment_file(document_id, &ctx)?.iter() {
+    for (&segment_kind, segments) in segment_file(document_id, ctx)?.iter() {
+        let mut offset = 0;
         for segment in segments {
-            let lexer = Lexer::new(document_id, segment, &ctx)?;
-            stats.merge(lexer.lex(&mut inverted_index, segment_kind));
+            let lexer = Lexer::new(document_id, segment, ctx)?.starting_at(offset);
+            let segment_stats = lexer.lex(&mut inverted_index, segment_kind);
+            offset += segment_stats.tokens + ctx.segment_gap();
+            stats.merge(segment_stats);
         }
     }
+    if let Some(document) = ctx.document(document_id) {
+        let document_boost = boosts.document_boost(document);
+        inverted_index.apply_boost(|position| document_boost * boosts.zone_boost(position.segment_kind));
+    }
     inverted_index.shrink_to_fit();
 
-    Ok(Some((inverted_index, stats)))
+    Ok((inverted_index, stats))
 }
 
-pub fn add_file_to_index(document_id: DocumentId, ctx: Arc<InfContext>) -> Result<Option<(InvertedIndex, LexerStats)>> {
-    lex_file(document_id, ctx)
+pub fn add_file_to_index(document_id: DocumentId, ctx: Arc<InfContext>, boosts: Arc<IndexBoosts>) -> Result<Option<(InvertedIndex, LexerStats)>> {
+    Ok(Some(index_document(document_id, &ctx, &boosts)?))
 }
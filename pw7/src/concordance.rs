@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Context, Result};
+use std::str::FromStr;
+use itertools::Itertools;
+use crate::common::segment_file;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::lexer::token_spans;
+use crate::segment::SegmentKind;
+use crate::query_lang::LogicNode;
+use crate::term_index::TermIndex;
+
+pub const DEFAULT_CONTEXT_WIDTH: usize = 30;
+
+pub struct ConcordanceLine {
+    pub segment_kind: SegmentKind,
+    pub left: String,
+    pub keyword: String,
+    pub right: String
+}
+
+// NOTE: Postings only keep word offsets, so the document is segmented again to find the text around them
+pub fn document_lines(document_id: DocumentId, segments: &[(SegmentKind, Vec<usize>)], ctx: &InfContext, width: usize) -> Result<Vec<ConcordanceLine>> {
+    let document_segments = segment_file(document_id, ctx)?;
+    let mut lines = Vec::new();
+    for (segment_kind, texts) in document_segments.iter().sorted_by_key(|(&segment_kind, _)| segment_kind) {
+        let Some((_, positions)) = segments.iter().find(|(kind, _)| kind == segment_kind) else {
+            continue;
+        };
+
+        let mut offset = 0;
+        for text in texts {
+            let spans = token_spans(text);
+            for &position in positions.iter().filter(|&&position| position >= offset && position < offset + spans.len()) {
+                let span = &spans[position - offset];
+                lines.push(ConcordanceLine {
+                    segment_kind: *segment_kind,
+                    left: context(text[..span.start].chars().rev().take(width).collect::<Vec<_>>().into_iter().rev()),
+                    keyword: text[span.clone()].to_owned(),
+                    right: context(text[span.end..].chars().take(width))
+                });
+            }
+            offset += spans.len() + ctx.segment_gap();
+        }
+    }
+
+    Ok(lines)
+}
+
+pub fn context(chars: impl Iterator<Item = char>) -> String {
+    chars.map(|ch| if ch.is_whitespace() { ' ' } else { ch })
+        .collect()
+}
+
+pub fn concordance(args: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()> {
+    let mut args = args.split_whitespace();
+    let term = args.next()
+        .context("Expected term")?
+        .to_lowercase();
+    let width = args.next()
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid context width")?
+        .unwrap_or(DEFAULT_CONTEXT_WIDTH);
+
+    let (frequencies, _) = index.query(&LogicNode::Term(term.clone()))?;
+    if frequencies.is_empty() {
+        return Err(anyhow!("Term '{term}' isn't indexed"));
+    }
+
+    let documents = frequencies.iter()
+        .map(|(position, posting)| (position.document, (position.segment_kind, posting.positions.iter().cloned().sorted().collect())))
+        .into_group_map();
+    for (document_id, segments) in documents.into_iter().sorted_by_key(|(document_id, _)| *document_id) {
+        let Some(document) = ctx.document(document_id) else {
+            continue;
+        };
+
+        println!("{}:", document.name());
+        for line in document_lines(document_id, &segments, ctx, width)? {
+            println!("\t{:?}\t{:>width$}[{}]{}", line.segment_kind, line.left, line.keyword, line.right);
+        }
+    }
+
+    Ok(())
+}
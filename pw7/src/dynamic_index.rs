@@ -0,0 +1,144 @@
+use anyhow::Result;
+use ahash::{AHashMap, AHashSet};
+use itertools::Itertools;
+use crate::boost::IndexBoosts;
+use crate::common::index_document;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::lexer::LexerStats;
+use crate::query_lang::LogicNode;
+use crate::segment::TermPosition;
+use crate::term::{Posting, TermFrequencies};
+use crate::term_index::{Expansion, InvertedIndex, TermIndex};
+
+pub const DEFAULT_MERGE_THRESHOLD: usize = 64;
+
+// NOTE: Documents added after the index was built go to a small auxiliary index, removed ones are
+//  only remembered. Both are queried along with the main index, and once enough documents were added
+//  or removed, the auxiliary index is merged into the main one and removed documents are purged from it.
+//  Every document lives in exactly one of the two indexes, so phrases never span them and results can simply be joined
+pub struct DynamicIndex {
+    main: InvertedIndex,
+    auxiliary: InvertedIndex,
+    removed: AHashSet<DocumentId>,
+    merge_threshold: usize
+}
+
+impl DynamicIndex {
+    pub fn new(main: InvertedIndex, merge_threshold: usize) -> Self {
+        DynamicIndex {
+            main,
+            auxiliary: InvertedIndex::new(),
+            removed: AHashSet::new(),
+            merge_threshold
+        }
+    }
+
+    // NOTE: K-grams of the auxiliary index are built again on every addition, it's small enough for that
+    pub fn add_document(&mut self, document_id: DocumentId, ctx: &InfContext, boosts: &IndexBoosts) -> Result<LexerStats> {
+        let (partial, stats) = index_document(document_id, ctx, boosts)?;
+        self.auxiliary.merge(partial);
+        self.auxiliary.build_kgram_index(self.main.kgram_length());
+        self.merge_if_full();
+
+        Ok(stats)
+    }
+
+    pub fn remove_document(&mut self, document_id: DocumentId) {
+        self.removed.insert(document_id);
+        self.merge_if_full();
+    }
+
+    // NOTE: Number of documents added or removed since the last merge
+    pub fn pending_changes(&self) -> usize {
+        self.auxiliary.documents().len() + self.removed.len()
+    }
+
+    fn merge_if_full(&mut self) {
+        if self.pending_changes() >= self.merge_threshold {
+            self.merge();
+        }
+    }
+
+    pub fn merge(&mut self) {
+        let auxiliary = std::mem::replace(&mut self.auxiliary, InvertedIndex::new());
+        self.main.merge(auxiliary);
+        self.main.remove_documents(&self.removed);
+        self.main.shrink_to_fit();
+        self.main.build_kgram_index(self.main.kgram_length());
+        self.removed.clear();
+    }
+
+    pub fn unique_word_count(&self) -> usize {
+        self.main.unique_word_count() + self.auxiliary.terms()
+            .filter(|term| !self.main.contains_term(term))
+            .count()
+    }
+
+    fn joined_expansions(main: Vec<Expansion>, auxiliary: Vec<Expansion>) -> Vec<Expansion> {
+        let mut counts = AHashMap::<String, usize>::new();
+        for expansion in main.into_iter().chain(auxiliary) {
+            *counts.entry(expansion.term).or_default() += expansion.count;
+        }
+
+        counts.into_iter()
+            .map(|(term, count)| Expansion { term, count })
+            .sorted_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)))
+            .collect()
+    }
+}
+
+impl TermIndex for DynamicIndex {
+    fn add_term(&mut self, term: String, term_position: TermPosition, offset: usize) {
+        self.auxiliary.add_term(term, term_position, offset);
+    }
+
+    fn query(&self, query_ast: &LogicNode) -> Result<(TermFrequencies, Vec<Expansion>)> {
+        let (main, main_expansions) = self.main.query(query_ast)?;
+        let (auxiliary, auxiliary_expansions) = self.auxiliary.query(query_ast)?;
+        let mut result = main.union(&auxiliary);
+        result.remove_documents(&self.removed);
+
+        Ok((result, Self::joined_expansions(main_expansions, auxiliary_expansions)))
+    }
+
+    fn posting(&self, term: &str, term_position: TermPosition) -> Option<&Posting> {
+        if self.removed.contains(&term_position.document) {
+            return None;
+        }
+
+        self.auxiliary.posting(term, term_position)
+            .or_else(|| self.main.posting(term, term_position))
+    }
+
+    fn document_length(&self, document: DocumentId) -> usize {
+        if self.removed.contains(&document) {
+            return 0;
+        }
+
+        self.auxiliary.document_length(document) + self.main.document_length(document)
+    }
+
+    fn average_document_length(&self) -> f64 {
+        let lengths = self.main.documents().iter()
+            .chain(self.auxiliary.documents())
+            .filter(|(document_id, _)| !self.removed.contains(document_id))
+            .map(|(_, &length)| length)
+            .collect::<Vec<_>>();
+
+        lengths.iter().sum::<usize>() as f64 / lengths.len().max(1) as f64
+    }
+
+    // NOTE: Suggestions of the main index come first, the auxiliary one only fills up the rest
+    fn suggestions(&self, term: &str, count: usize) -> Vec<String> {
+        if self.main.contains_term(term) || self.auxiliary.contains_term(term) {
+            return Vec::new();
+        }
+
+        self.main.suggestions(term, count).into_iter()
+            .chain(self.auxiliary.suggestions(term, count))
+            .unique()
+            .take(count)
+            .collect()
+    }
+}
@@ -4,6 +4,7 @@ use memmap::Mmap;
 use std::fs;
 use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
+use crate::format::DocumentFormat;
 
 #[derive(Serialize, Deserialize)]
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
@@ -38,30 +39,39 @@ impl FilePool {
         self.files.get(file_id.0)
     }
 
-    pub fn add_file(&mut self, path: &PathBuf) -> Result<FileId> {
-        let file = File::new(path)?;
+    pub fn add(&mut self, file: File) -> FileId {
         let id = self.files.len();
         self.files.push(file);
 
-        Ok(FileId(id))
+        FileId(id)
     }
 }
 
 pub struct File {
-    mmap: Option<Mmap>
+    mmap: Option<Mmap>,
+    // NOTE: Content of a format that can't be indexed isn't kept, only its name is indexed
+    unsupported: Option<DocumentFormat>
 }
 
 impl File {
     pub fn new(path: &PathBuf) -> Result<Self> {
         let file = fs::File::open(path)?;
         if file.metadata()?.len() == 0 {
-            return Ok(File { mmap: None });
+            return Ok(File { mmap: None, unsupported: None });
         }
         let mmap = unsafe { Mmap::map(&file)? };
 
+        // NOTE: Sniffed before the UTF-8 check, a binary PDF would otherwise be skipped along with its name
+        if DocumentFormat::detect(path, &mmap) == DocumentFormat::Pdf {
+            return Ok(File { mmap: None, unsupported: Some(DocumentFormat::Pdf) });
+        }
         std::str::from_utf8(&mmap).context("File contains non UTF-8 data")?;
 
-        Ok(File { mmap: Some(mmap) })
+        Ok(File { mmap: Some(mmap), unsupported: None })
+    }
+
+    pub fn unsupported(&self) -> Option<DocumentFormat> {
+        self.unsupported
     }
 
     pub fn str(&self) -> &str {
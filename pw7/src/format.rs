@@ -0,0 +1,50 @@
+use std::path::Path;
+
+// NOTE: Formats are told apart by their first bytes, nothing further into a file is read
+const SNIFF_LENGTH: usize = 1024;
+const BYTE_ORDER_MARK: &[u8] = b"\xef\xbb\xbf";
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum DocumentFormat {
+    PlainText,
+    Fb2,
+    Html,
+    Pdf
+}
+
+impl DocumentFormat {
+    // NOTE: Content wins over the extension, so misnamed files still get the right segmenter.
+    //  The extension only decides when the content doesn't look like any known format.
+    //  Raw bytes are sniffed, binary formats like PDF aren't valid UTF-8
+    pub fn detect(path: &Path, data: &[u8]) -> Self {
+        Self::sniff(data).unwrap_or_else(|| Self::from_extension(path))
+    }
+
+    fn sniff(data: &[u8]) -> Option<Self> {
+        let head = &data[..data.len().min(SNIFF_LENGTH)];
+        let head = head.strip_prefix(BYTE_ORDER_MARK).unwrap_or(head).trim_ascii_start();
+        if head.starts_with(b"%PDF-") {
+            return Some(DocumentFormat::Pdf);
+        }
+
+        // NOTE: The head may end inside a multibyte character, lossy conversion only affects that last one
+        let head = String::from_utf8_lossy(head);
+        let lowercase = head.to_lowercase();
+        if lowercase.starts_with("<?xml") && head.contains("<FictionBook") || head.starts_with("<FictionBook") {
+            Some(DocumentFormat::Fb2)
+        } else if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+            Some(DocumentFormat::Html)
+        } else {
+            None
+        }
+    }
+
+    fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("fb2") => DocumentFormat::Fb2,
+            Some("html" | "htm") => DocumentFormat::Html,
+            Some("pdf") => DocumentFormat::Pdf,
+            _ => DocumentFormat::PlainText
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::str::FromStr;
+use itertools::Itertools;
+use serde::Serialize;
+use crate::document::DocumentId;
+use crate::segment::{SegmentKind, TermPosition};
+use crate::term_index::TermIndex;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(anyhow!("Unknown output format '{s}', expected 'text' or 'json'"))
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TermMatch {
+    pub term: String,
+    pub count: usize,
+    pub positions: Vec<usize>
+}
+
+#[derive(Serialize)]
+pub struct SegmentMatch {
+    pub segment: SegmentKind,
+    pub weight: f64,
+    pub terms: Vec<TermMatch>
+}
+
+#[derive(Serialize)]
+pub struct Hit {
+    pub rank: usize,
+    pub document: DocumentId,
+    pub name: String,
+    pub weight: f64,
+    // NOTE: Query terms found in any allowed zone of the document
+    pub terms: Vec<String>,
+    pub snippet: Option<String>,
+    // NOTE: Byte ranges of the matches in the original text, front ends mark them up instead of finding them again
+    pub highlights: Vec<Range<usize>>,
+    pub segments: Vec<SegmentMatch>
+}
+
+impl SegmentMatch {
+    pub fn new(index: &dyn TermIndex, position: TermPosition, weight: f64, terms: &[&str]) -> Self {
+        let terms = terms.iter()
+            .unique()
+            .filter_map(|&term| index.posting(term, position).map(|posting| (term, posting)))
+            .map(|(term, posting)| TermMatch {
+                term: term.to_owned(),
+                count: posting.count,
+                positions: posting.positions.iter().cloned().sorted().collect()
+            })
+            .collect();
+
+        SegmentMatch {
+            segment: position.segment_kind,
+            weight,
+            terms
+        }
+    }
+}
+
+impl Display for Hit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\t{}. [{}][{:.4}] {}", self.rank, self.document, self.weight, self.name)?;
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n\t\t\"{snippet}\"")?;
+        }
+        for segment in &self.segments {
+            let terms_str = segment.terms.iter()
+                .map(|term| format!("{} x{} @ {}", term.term, term.count, term.positions.iter().join(", ")))
+                .join("; ");
+            write!(f, "\n\t\t{:?} [{:.4}]: {}", segment.segment, segment.weight, terms_str)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::borrow::Cow;
+use anyhow::Result;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::segment::{Segmenter, SegmentKind, Segments};
+
+const SKIPPED_ELEMENTS: [&str; 3] = ["script", "style", "title"];
+
+pub struct HtmlSegmenter<'a> {
+    document_id: DocumentId,
+    ctx: &'a InfContext
+}
+
+impl<'a> HtmlSegmenter<'a> {
+    pub fn new(document_id: DocumentId, ctx: &'a InfContext) -> Result<Self> {
+        Ok(HtmlSegmenter {
+            document_id,
+            ctx
+        })
+    }
+
+    // NOTE: Text of the first element with the given name, tag names are matched ignoring case
+    fn element_text(html: &str, lowercase: &str, element: &str) -> Option<String> {
+        let start = lowercase.find(&format!("<{element}"))?;
+        let content_start = start + lowercase[start..].find('>')? + 1;
+        let content_end = content_start + lowercase[content_start..].find(&format!("</{element}"))?;
+
+        Some(strip_markup(&html[content_start..content_end]))
+    }
+}
+
+impl<'a> Segmenter<'a> for HtmlSegmenter<'a> {
+    fn segment(self: Box<Self>) -> Result<Segments<'a>> {
+        let mut segments = Segments::new();
+
+        let data = self.ctx.document_data(self.document_id)?;
+        let lowercase = data.to_ascii_lowercase();
+        if let Some(title) = Self::element_text(data, &lowercase, "title") {
+            segments.add(SegmentKind::Title, Cow::Owned(title));
+        }
+        let body = Self::element_text(data, &lowercase, "body")
+            .unwrap_or_else(|| strip_markup(data));
+        segments.add(SegmentKind::Body, Cow::Owned(body));
+
+        Ok(segments)
+    }
+}
+
+// NOTE: Tags are replaced with spaces so words on both sides of one aren't joined,
+//  contents of scripts, styles and the title are left out and the most common entities are decoded
+fn strip_markup(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        text.push(' ');
+        rest = &rest[start..];
+
+        let tag_end = rest.find('>').map_or(rest.len(), |end| end + 1);
+        let element = rest[1..tag_end].split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        rest = &rest[tag_end..];
+        if SKIPPED_ELEMENTS.contains(&element.as_str()) {
+            let close = format!("</{element}");
+            rest = rest.to_ascii_lowercase().find(&close)
+                .map_or("", |end| &rest[end..]);
+        }
+    }
+    text.push_str(rest);
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
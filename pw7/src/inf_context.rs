@@ -1,58 +1,131 @@
 use anyhow::{anyhow, Result, Context};
+use rayon::prelude::*;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use ahash::AHashSet;
 use crate::document::{Document, DocumentRegistry};
-use crate::file::FilePool;
+use crate::file::{File, FilePool};
 use crate::document::DocumentId;
+use crate::boilerplate::BoilerplateFilter;
+use crate::ledger::{ErrorLedger, LedgerStage};
+use crate::format::DocumentFormat;
 
 pub struct InfContext {
     documents: DocumentRegistry,
-    files: FilePool
+    files: FilePool,
+    boilerplate: BoilerplateFilter,
+    segment_gap: usize,
+    // NOTE: Removed documents keep their ids, so ids of the others don't change
+    removed: AHashSet<DocumentId>,
+    ledger: ErrorLedger
 }
 
 impl InfContext {
-    pub fn new(base_path: &str, file_limit: Option<usize>) -> Result<Arc<Self>> {
+    pub fn new(base_path: &str, file_limit: Option<usize>, boilerplate: BoilerplateFilter, segment_gap: usize) -> Result<Arc<Self>> {
         let mut file_names = get_files(base_path)?;
-        let mut files = FilePool::new();
-        let mut documents = DocumentRegistry::new();
+        if let Some(file_limit) = file_limit {
+            file_names.truncate(file_limit);
+        }
 
-        let mut i = 0;
-        for path in file_names.drain(..) {
-            if let Some(file_limit) = file_limit {
-                if i >= file_limit {
-                    break;
-                }
-            }
-            i += 1;
+        // NOTE: Files are opened in parallel, but registered in their original order,
+        //  so document ids don't depend on which file finished opening first
+        let opened = file_names.into_par_iter()
+            .map(|path| (File::new(&path), path))
+            .collect::<Vec<_>>();
 
-            let file_id = match files.add_file(&path) {
-                Ok(file_id) => file_id,
+        let mut files = FilePool::new();
+        let mut documents = DocumentRegistry::new();
+        let ledger = ErrorLedger::new();
+        for (file, path) in opened {
+            let file = match file {
+                Ok(file) => file,
                 Err(err) => {
-                    println!("Ignoring file {:?}. Error: {}. Caused by: {}", path, err, err.root_cause());
+                    ledger.record(path, LedgerStage::Opening, None, &err);
                     continue;
                 }
             };
+            let file_id = files.add(file);
             documents.add_document(Document::File { path, file_id });
         }
 
         Ok(Arc::new(InfContext {
             documents,
-            files
+            files,
+            boilerplate,
+            segment_gap,
+            removed: AHashSet::new(),
+            ledger
         }))
     }
 
+    pub fn add_document(&mut self, path: PathBuf) -> Result<DocumentId> {
+        if self.indexed_paths().contains(&path) {
+            return Err(anyhow!("File {path:?} is already indexed"));
+        }
+        let file_id = self.files.add(File::new(&path)?);
+
+        Ok(self.documents.add_document(Document::File { path, file_id }))
+    }
+
+    pub fn add_inline(&mut self, name: impl Into<String>, text: impl Into<String>) -> DocumentId {
+        self.documents.add_inline(name, text)
+    }
+
+    // NOTE: The file stays mapped, the document is only left out of everything that lists documents
+    pub fn remove_document(&mut self, document_id: DocumentId) -> Result<()> {
+        if self.documents.document(document_id).is_none() || !self.removed.insert(document_id) {
+            return Err(anyhow!("Document with id {document_id} doesn't exist"));
+        }
+
+        Ok(())
+    }
+
+    // NOTE: Files of the folder no document was added for yet
+    pub fn unindexed_files(&self, base_path: &str) -> Result<Vec<PathBuf>> {
+        let indexed = self.indexed_paths();
+
+        Ok(get_files(base_path)?
+            .into_iter()
+            .filter(|path| !indexed.contains(path))
+            .collect())
+    }
+
+    fn indexed_paths(&self) -> AHashSet<&PathBuf> {
+        self.document_ids()
+            .filter_map(|document_id| match self.document(document_id) {
+                Some(Document::File { path, .. }) => Some(path),
+                _ => None
+            })
+            .collect()
+    }
+
+    pub fn vanished_documents(&self) -> Vec<DocumentId> {
+        self.document_ids()
+            .filter(|&document_id| matches!(self.document(document_id), Some(Document::File { path, .. }) if !path.exists()))
+            .collect()
+    }
+
     pub fn document_count(&self) -> usize {
-        self.documents.document_count()
+        self.documents.document_count() - self.removed.len()
     }
 
     pub fn document_ids(&self) -> impl Iterator<Item = DocumentId> + '_ {
         self.documents.document_ids()
+            .filter(|document_id| !self.removed.contains(document_id))
     }
 
     pub fn document(&self, document_id: DocumentId) -> Option<&Document> {
         self.documents.document(document_id)
     }
 
+    // NOTE: Format of a file whose content wasn't kept because it can't be indexed
+    pub fn unsupported_format(&self, document_id: DocumentId) -> Option<DocumentFormat> {
+        match self.documents.document(document_id)? {
+            Document::File { file_id, .. } => self.files.file(*file_id)?.unsupported(),
+            Document::Inline { .. } => None
+        }
+    }
+
     pub fn document_data(&self, document_id: DocumentId) -> Result<&str> {
         let document = self.documents.document(document_id)
             .context(anyhow!("Document with id {document_id} doesn't exist"))?;
@@ -62,13 +135,30 @@ impl InfContext {
                     .context(anyhow!("File with id {file_id} doesn't exist"))?;
 
                 Ok(file.str())
-            }
+            },
+            Document::Inline { text, .. } => Ok(text)
         }
     }
 
+    pub fn document_size(&self, document_id: DocumentId) -> usize {
+        self.document_data(document_id).map_or(0, str::len)
+    }
+
+    pub fn document_text(&self, document_id: DocumentId) -> Result<&str> {
+        Ok(self.boilerplate.strip(self.document_data(document_id)?))
+    }
+
     pub fn files(&self) -> &FilePool {
         &self.files
     }
+
+    pub fn segment_gap(&self) -> usize {
+        self.segment_gap
+    }
+
+    pub fn ledger(&self) -> &ErrorLedger {
+        &self.ledger
+    }
 }
 
 fn get_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
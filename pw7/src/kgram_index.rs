@@ -0,0 +1,163 @@
+use ahash::AHashMap;
+use itertools::Itertools;
+
+pub const DEFAULT_KGRAM_LENGTH: usize = 3;
+// NOTE: Marks the start and the end of a term, so "$ki" only matches terms starting with "ki"
+const BOUNDARY: char = '$';
+const WILDCARD: char = '*';
+// NOTE: Share of k-grams two terms need in common to be offered as a spelling of each other
+const MIN_SUGGESTION_OVERLAP: f64 = 0.4;
+
+// NOTE: Maps character k-grams of every term to the terms containing them. A wildcard pattern is
+//  split on '*', the terms containing all k-grams of its pieces are candidates, and candidates are
+//  checked against the whole pattern, since "k*ng" k-grams are found in "kingdom" and "ngk" too
+#[derive(PartialEq, Debug)]
+pub struct KGramIndex {
+    k: usize,
+    terms: Vec<String>,
+    // NOTE: Term ids are indexes into `terms`, sorted, so candidates are found by intersecting sorted lists
+    grams: AHashMap<String, Vec<usize>>
+}
+
+impl KGramIndex {
+    pub fn new(k: usize) -> Self {
+        KGramIndex {
+            k: k.max(1),
+            terms: Vec::new(),
+            grams: AHashMap::new()
+        }
+    }
+
+    pub fn build<'a>(k: usize, terms: impl Iterator<Item = &'a str>) -> Self {
+        let mut index = KGramIndex::new(k);
+        index.terms = terms.sorted().map(str::to_owned).collect();
+        for (term_id, term) in index.terms.iter().enumerate() {
+            for gram in Self::kgrams(index.k, &format!("{BOUNDARY}{term}{BOUNDARY}")).into_iter().unique() {
+                index.grams.entry(gram).or_default().push(term_id);
+            }
+        }
+
+        index
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    pub fn gram_count(&self) -> usize {
+        self.grams.len()
+    }
+
+    fn kgrams(k: usize, text: &str) -> Vec<String> {
+        text.chars()
+            .collect::<Vec<_>>()
+            .windows(k)
+            .map(|window| window.iter().collect())
+            .collect()
+    }
+
+    // NOTE: Terms matching the pattern, sorted. Pieces shorter than k add no k-grams,
+    //  a pattern without any k-grams is checked against every term
+    pub fn matching(&self, pattern: &str) -> Vec<&str> {
+        let pieces = pattern.split(WILDCARD).collect::<Vec<_>>();
+        let last = pieces.len() - 1;
+        let grams = pieces.iter()
+            .enumerate()
+            .flat_map(|(i, piece)| {
+                let start = if i == 0 { BOUNDARY.to_string() } else { String::new() };
+                let end = if i == last { BOUNDARY.to_string() } else { String::new() };
+                Self::kgrams(self.k, &format!("{start}{piece}{end}"))
+            })
+            .unique()
+            .collect::<Vec<_>>();
+
+        let candidates = grams.iter()
+            .map(|gram| self.grams.get(gram).map(Vec::as_slice).unwrap_or(&[]))
+            .sorted_by_key(|term_ids| term_ids.len())
+            .fold(None, |candidates: Option<Vec<usize>>, term_ids| Some(match candidates {
+                Some(candidates) => Self::intersect(&candidates, term_ids),
+                None => term_ids.to_vec()
+            }));
+
+        match candidates {
+            Some(term_ids) => term_ids.into_iter()
+                .map(|term_id| self.terms[term_id].as_str())
+                .filter(|term| Self::matches(&pieces, term))
+                .collect(),
+            None => self.terms.iter()
+                .map(String::as_str)
+                .filter(|term| Self::matches(&pieces, term))
+                .collect()
+        }
+    }
+
+    // NOTE: Terms sharing the most k-grams with the given one by Jaccard coefficient, best first
+    pub fn suggestions(&self, term: &str, count: usize) -> Vec<(&str, f64)> {
+        let grams = Self::kgrams(self.k, &format!("{BOUNDARY}{term}{BOUNDARY}")).into_iter()
+            .unique()
+            .collect::<Vec<_>>();
+
+        let mut overlaps = AHashMap::<usize, usize>::new();
+        for term_ids in grams.iter().filter_map(|gram| self.grams.get(gram)) {
+            term_ids.iter().for_each(|&term_id| *overlaps.entry(term_id).or_default() += 1);
+        }
+
+        overlaps.into_iter()
+            .filter(|&(term_id, _)| self.terms[term_id] != term)
+            .map(|(term_id, overlap)| {
+                let other = &self.terms[term_id];
+                let other_count = Self::kgrams(self.k, &format!("{BOUNDARY}{other}{BOUNDARY}")).into_iter().unique().count();
+
+                (other.as_str(), overlap as f64 / (grams.len() + other_count - overlap) as f64)
+            })
+            .filter(|&(_, overlap)| overlap >= MIN_SUGGESTION_OVERLAP)
+            .sorted_by(|(a_term, a), (b_term, b)| b.partial_cmp(a).unwrap().then_with(|| a_term.cmp(b_term)))
+            .take(count)
+            .collect()
+    }
+
+    fn intersect(a: &[usize], b: &[usize]) -> Vec<usize> {
+        let (mut i, mut j) = (0, 0);
+        let mut result = Vec::new();
+        while i < a.len() && j < b.len() {
+            match a[i].cmp(&b[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    result.push(a[i]);
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        result
+    }
+
+    // NOTE: First piece is a prefix, last one a suffix, the ones between are found left to right without overlapping
+    fn matches(pieces: &[&str], term: &str) -> bool {
+        let (first, last) = (pieces[0], pieces[pieces.len() - 1]);
+        if pieces.len() == 1 {
+            return term == first;
+        }
+        if term.len() < first.len() + last.len() || !term.starts_with(first) || !term.ends_with(last) {
+            return false;
+        }
+
+        let mut rest = &term[first.len()..term.len() - last.len()];
+        for piece in &pieces[1..pieces.len() - 1] {
+            match rest.find(piece) {
+                Some(start) => rest = &rest[start + piece.len()..],
+                None => return false
+            }
+        }
+
+        true
+    }
+}
+
+impl Default for KGramIndex {
+    fn default() -> Self {
+        Self::new(DEFAULT_KGRAM_LENGTH)
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use crate::document::DocumentId;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum LedgerStage {
+    // NOTE: File couldn't be opened, it has no document
+    Opening,
+    // NOTE: Format specific segmenter failed, the document was indexed as plain text instead
+    Segmenting,
+    // NOTE: Format can't be segmented at all, only the file name of the document was indexed
+    Unsupported
+}
+
+#[derive(Clone, Debug)]
+pub struct LedgerEntry {
+    pub path: PathBuf,
+    pub stage: LedgerStage,
+    pub document: Option<DocumentId>,
+    pub reason: String
+}
+
+/// Files that were skipped or indexed in a degraded way, in the order it happened.
+pub struct ErrorLedger {
+    entries: Mutex<Vec<LedgerEntry>>
+}
+
+impl ErrorLedger {
+    pub fn new() -> Self {
+        ErrorLedger { entries: Mutex::new(Vec::new()) }
+    }
+
+    // NOTE: Documents are segmented again for snippets and highlights, only the first failure of a document is kept
+    pub fn record(&self, path: PathBuf, stage: LedgerStage, document: Option<DocumentId>, err: &anyhow::Error) {
+        let mut entries = self.entries.lock().unwrap();
+        if document.is_some() && entries.iter().any(|entry| entry.stage == stage && entry.document == document) {
+            return;
+        }
+
+        match stage {
+            LedgerStage::Opening => println!("Ignoring file {:?}. Error: {}. Caused by: {}", path, err, err.root_cause()),
+            LedgerStage::Segmenting => println!("Indexing file {:?} as plain text. Error: {}. Caused by: {}", path, err, err.root_cause()),
+            LedgerStage::Unsupported => println!("Indexing only the name of file {:?}. Error: {}", path, err)
+        }
+        entries.push(LedgerEntry {
+            path,
+            stage,
+            document,
+            reason: format!("{err:#}")
+        });
+    }
+
+    pub fn entries(&self) -> Vec<LedgerEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ErrorLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for LedgerStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerStage::Opening => write!(f, "skipped"),
+            LedgerStage::Segmenting => write!(f, "indexed as plain text"),
+            LedgerStage::Unsupported => write!(f, "indexed by name only")
+        }
+    }
+}
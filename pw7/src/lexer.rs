@@ -1,13 +1,22 @@
 use anyhow::Result;
+use std::ops::Range;
 use std::str::Chars;
 use crate::document::DocumentId;
 use crate::inf_context::InfContext;
 use crate::segment::{SegmentKind, TermPosition};
 use crate::term_index::TermIndex;
 
+// NOTE: Terms longer than this are cut, real words never get close to it
+const MAX_TERM_LENGTH: usize = 64;
+// NOTE: Shorter terms are never considered garbage
+const GARBAGE_MIN_LENGTH: usize = 12;
+// NOTE: Base64 and similar blobs switch letter case far more often than words do
+const GARBAGE_CASE_SWITCH_RATIO: f64 = 0.25;
+
 pub struct Lexer<'a> {
     document_id: DocumentId,
-    iter: Chars<'a>
+    iter: Chars<'a>,
+    offset: usize
 }
 
 impl<'a> Lexer<'a> {
@@ -16,19 +25,26 @@ impl<'a> Lexer<'a> {
 
         Ok(Lexer {
             document_id,
-            iter
+            iter,
+            offset: 0
         })
     }
 
+    // NOTE: Zones are lexed segment by segment, so positions continue where the previous segment stopped
+    pub fn starting_at(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
     pub fn lex(mut self, term_index: &mut dyn TermIndex, segment_kind: SegmentKind) -> LexerStats {
-        let mut word = String::new();
+        let mut word = Word::new();
         let mut stats = LexerStats::default();
         stats.lines += 1;
 
         while let Some(ch) = self.iter.next() {
             stats.characters_read += 1;
             if ch.is_alphabetic() || (ch.eq(&'\'') && !word.is_empty()) {
-                ch.to_lowercase().for_each(|ch| word.push(ch));
+                word.push(ch);
 
                 continue;
             }
@@ -38,23 +54,97 @@ impl<'a> Lexer<'a> {
                 stats.lines += 1;
             }
             if !word.is_empty() {
-                Self::add_term(&mut word, TermPosition { document: self.document_id, segment_kind }, term_index);
+                self.add_term(&mut word, segment_kind, term_index, &mut stats);
             }
         }
 
         if !word.is_empty() {
-            Self::add_term(&mut word, TermPosition { document: self.document_id, segment_kind }, term_index);
+            self.add_term(&mut word, segment_kind, term_index, &mut stats);
         }
 
         stats
     }
 
-    fn add_term(word: &mut String, term_position: TermPosition, term_index: &mut dyn TermIndex) {
-        let mut new_word = String::new();
-        std::mem::swap(word, &mut new_word);
+    fn add_term(&self, word: &mut Word, segment_kind: SegmentKind, term_index: &mut dyn TermIndex, stats: &mut LexerStats) {
+        let word = std::mem::replace(word, Word::new());
+        // NOTE: Dropped tokens still take up a position, so positions match the text
+        let offset = self.offset + stats.tokens;
+        stats.tokens += 1;
+        if word.is_garbage() {
+            stats.tokens_dropped += 1;
+            return;
+        }
+        if word.is_truncated() {
+            stats.tokens_truncated += 1;
+        }
 
+        let mut new_word = word.text;
         new_word.shrink_to_fit();
-        term_index.add_term(new_word, term_position);
+        term_index.add_term(new_word, TermPosition { document: self.document_id, segment_kind }, offset);
+    }
+}
+
+// NOTE: Byte ranges of tokens in the order `lex` numbers them, including dropped ones
+pub fn token_spans(data: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, ch) in data.char_indices() {
+        if ch.is_alphabetic() || (ch.eq(&'\'') && start.is_some()) {
+            start.get_or_insert(i);
+        } else if let Some(start) = start.take() {
+            spans.push(start..i);
+        }
+    }
+    if let Some(start) = start {
+        spans.push(start..data.len());
+    }
+
+    spans
+}
+
+struct Word {
+    text: String,
+    length: usize,
+    case_switches: usize,
+    last_uppercase: Option<bool>
+}
+
+impl Word {
+    fn new() -> Self {
+        Word {
+            text: String::new(),
+            length: 0,
+            case_switches: 0,
+            last_uppercase: None
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn push(&mut self, ch: char) {
+        if ch.is_alphabetic() {
+            let uppercase = ch.is_uppercase();
+            if self.last_uppercase.is_some_and(|last| last != uppercase) {
+                self.case_switches += 1;
+            }
+            self.last_uppercase = Some(uppercase);
+        }
+
+        self.length += 1;
+        if self.length <= MAX_TERM_LENGTH {
+            ch.to_lowercase().for_each(|ch| self.text.push(ch));
+        }
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.length > MAX_TERM_LENGTH
+    }
+
+    fn is_garbage(&self) -> bool {
+        self.length >= GARBAGE_MIN_LENGTH
+            && self.case_switches as f64 / self.length as f64 > GARBAGE_CASE_SWITCH_RATIO
     }
 }
 
@@ -62,7 +152,10 @@ impl<'a> Lexer<'a> {
 pub struct LexerStats {
     pub characters_read: usize,
     pub characters_ignored: usize,
-    pub lines: usize
+    pub lines: usize,
+    pub tokens_truncated: usize,
+    pub tokens_dropped: usize,
+    pub tokens: usize
 }
 
 impl LexerStats {
@@ -70,6 +163,9 @@ impl LexerStats {
         self.characters_read += other.characters_read;
         self.characters_ignored += other.characters_ignored;
         self.lines += other.lines;
+        self.tokens_truncated += other.tokens_truncated;
+        self.tokens_dropped += other.tokens_dropped;
+        self.tokens += other.tokens;
     }
 }
 
@@ -78,7 +174,10 @@ impl Default for LexerStats {
         LexerStats {
             characters_read: 0,
             characters_ignored: 0,
-            lines: 0
+            lines: 0,
+            tokens_truncated: 0,
+            tokens_dropped: 0,
+            tokens: 0
         }
     }
 }
@@ -5,126 +5,321 @@ mod common;
 mod document;
 mod query_lang;
 mod inf_context;
+mod boilerplate;
 mod encoding;
 mod segment;
 mod fb2_segmenter;
 mod plain_text_segmenter;
+mod html_segmenter;
+mod term;
+mod zone;
+mod boost;
+mod metrics;
+mod persist;
+mod merge;
+mod hit;
+mod concordance;
+mod term_breakdown;
+mod scheduling;
+mod ranking;
+mod search;
+mod kgram_index;
+mod dynamic_index;
+mod server;
+mod percolator;
+mod ledger;
+mod format;
 
 use std::{env, io};
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::BufReader;
+use std::path::PathBuf;
 use std::str::FromStr;
-use anyhow::{Context, Result};
-use threadpool::ThreadPool;
-use std::sync::mpsc::channel;
-use std::time::{Duration, Instant};
-use ahash::HashMap;
+use anyhow::{anyhow, Context, Result};
+use std::sync::Arc;
 use human_bytes::human_bytes;
 use itertools::Itertools;
+use crate::metrics::{metrics, Metrics};
 use crate::common::add_file_to_index;
 use crate::inf_context::InfContext;
+use crate::boilerplate::BoilerplateFilter;
 use crate::term_index::{InvertedIndex, TermIndex};
-use rayon::prelude::*;
-use crate::document::DocumentId;
 use crate::lexer::LexerStats;
-use crate::segment::SegmentKind;
+use crate::merge::DEFAULT_MERGE_BUFFER;
+use crate::zone::{split_flags, Flags};
+use crate::boost::IndexBoosts;
+use crate::hit::OutputFormat;
+use crate::scheduling::{largest_first, Pipeline, Scheduling};
+use crate::segment::DEFAULT_SEGMENT_GAP;
+use crate::search::{highlight, search, SearchRequest};
+use crate::kgram_index::DEFAULT_KGRAM_LENGTH;
+use crate::document::DocumentId;
+use crate::dynamic_index::{DynamicIndex, DEFAULT_MERGE_THRESHOLD};
+use crate::server::{Access, Server};
+use crate::percolator::Percolator;
 
-fn time_call<FnT, ResT>(func: FnT) -> (ResT, Duration)
-where FnT: FnOnce() -> ResT
-{
-    let start = Instant::now();
-    let result = func();
-    let time = start.elapsed();
+const MERGE_BUFFER_FLAG: &str = "merge-buffer";
+const FORMAT_FLAG: &str = "format";
+const THREADS_FLAG: &str = "threads";
+const PIPELINE_FLAG: &str = "pipeline";
+const SEGMENT_GAP_FLAG: &str = "segment-gap";
+const KGRAM_LENGTH_FLAG: &str = "kgram-length";
+const MERGE_THRESHOLD_FLAG: &str = "merge-threshold";
+const SERVE_FLAG: &str = "serve";
+const API_KEY_FLAG: &str = "api-key";
+const READ_ONLY_FLAG: &str = "read-only";
+const RUN_FLAGS: [&str; 10] = [MERGE_BUFFER_FLAG, FORMAT_FLAG, THREADS_FLAG, PIPELINE_FLAG, SEGMENT_GAP_FLAG, KGRAM_LENGTH_FLAG, MERGE_THRESHOLD_FLAG, SERVE_FLAG,
+                               API_KEY_FLAG, READ_ONLY_FLAG];
+// NOTE: Flags that take no value, they're set to "true" when given
+const SWITCHES: [&str; 1] = [READ_ONLY_FLAG];
+const WEBHOOK_FLAG: &str = "webhook";
 
-    (result, time)
-}
+fn parse_args(args: &[String]) -> Result<(Vec<&str>, Flags)> {
+    let mut positional = Vec::new();
+    let mut flags = Vec::new();
 
-fn get_segment_weight(segment_kind: SegmentKind) -> f64 {
-    match segment_kind {
-        SegmentKind::Filename => 0.2,
-        SegmentKind::Authors => 0.1,
-        SegmentKind::Title => 0.4,
-        SegmentKind::Epigraph => 0.1,
-        SegmentKind::Body => 0.2
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if let Some(name) = arg.strip_prefix("--").filter(|name| SWITCHES.contains(name)) {
+            flags.push((name.to_owned(), "true".to_owned()));
+        } else if let Some(name) = arg.strip_prefix("--") {
+            let value = iter.next().context(anyhow!("Missing value for flag '{arg}'"))?;
+            flags.push((name.to_owned(), value.to_owned()));
+        } else {
+            positional.push(arg.as_str());
+        }
     }
+
+    Ok((positional, flags))
 }
 
-fn calculate_weight<'a>(term_positions: impl Iterator<Item = &'a SegmentKind>) -> f64 {
-    term_positions
-        .cloned()
-        .map(get_segment_weight)
-        .sum()
+fn flag_value<'a>(flags: &'a Flags, name: &str) -> Option<&'a str> {
+    flags.iter()
+        .rev()
+        .find(|(flag, _)| flag == name)
+        .map(|(_, value)| value.as_str())
 }
 
-fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()> {
-    let ast = query_lang::parse_logic_expr(query_text).context("Invalid query")?;
-    // println!("Ast: {ast:?}");
-
-    let (result, time) = time_call(|| index.query(&ast));
-    let result = result?;
-
-    let result = result.iter()
-        .map(|position| (position.document, position.segment_kind))
-        .sorted_by_key(|(document, _)| document.id())
-        .group_by(|(document, _)| document.id())
-        .into_iter()
-        .map(|(document, group)| (DocumentId(document), group.map(|(_, kind)| kind).collect::<Vec<_>>()))
-        .collect::<HashMap<_, _>>();
-
-    println!("Query time: {time:?}.");
-    if !result.is_empty() {
-        let result_str = result.iter()
-            .map(|(document_id, segments)| (document_id, segments, calculate_weight(segments.iter())))
-            .sorted_by(|(_, _, a), (_, _, b)| a.partial_cmp(b).unwrap().reverse())
-            .filter_map(|(&document_id, segments, weight)| ctx.document(document_id).map(|doc| (document_id, doc, segments, weight)))
-            .enumerate()
-            .map(|(i, (id, doc, segments, weight))| {
-                format!("\t{}. [{}]{:?}[{:.4}] {}", i, id, segments, weight, doc.name())
-            })
-            .join("\n");
-        println!("Result:\n{result_str}");
+fn query(input: &str, index: &dyn TermIndex, ctx: &InfContext, defaults: &SearchRequest, format: OutputFormat) -> Result<Vec<String>> {
+    let (flags, query_text) = split_flags(input)?;
+    let (format_flags, search_flags): (Flags, Flags) = flags.into_iter()
+        .partition(|(name, _)| name == FORMAT_FLAG);
+    let format = flag_value(&format_flags, FORMAT_FLAG)
+        .map(OutputFormat::from_str)
+        .transpose()?
+        .unwrap_or(format);
+    let mut request = defaults.with_query(query_text);
+    request.apply_flags(&search_flags)?;
+
+    let response = search(&request, index, ctx)?;
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+        return Ok(response.terms);
+    }
+
+    println!("Query time: {:?}. Ranked with: {}.", response.query_time, response.ranker);
+    if !response.expansions.is_empty() {
+        let expansions = response.expansions.iter()
+            .map(|expansion| format!("{} ({})", expansion.term, expansion.count))
+            .join(", ");
+        println!("Wildcard expanded to: {expansions}");
+    }
+    if !response.hits.is_empty() {
+        println!("Result:\n{}", response.hits.iter().join("\n"));
+        if response.total > response.hits.len() {
+            println!("Showing {} of {} matches", response.hits.len(), response.total);
+        }
     } else {
         println!("No matches found.");
     }
+    if !response.suggestions.is_empty() {
+        println!("Did you mean: {}?", response.suggestions.iter().join(", "));
+    }
+
+    Ok(response.terms)
+}
+
+// NOTE: Every match is shown within its line, marked up from the ranges the engine returns
+fn highlight_document(args: &str, index: &dyn TermIndex, ctx: &InfContext, defaults: &SearchRequest) -> Result<()> {
+    let (document_id, input) = args.trim().split_once(char::is_whitespace).context("Expected document id and query")?;
+    let document_id = DocumentId(usize::from_str(document_id).context("Invalid document id")?);
+    let (flags, query_text) = split_flags(input)?;
+    let mut request = defaults.with_query(query_text);
+    request.apply_flags(&flags)?;
+
+    let ranges = highlight(document_id, &request, index, ctx)?;
+    let data = ctx.document_data(document_id)?;
+    println!("Matches in {document_id}: {}", ranges.len());
+    for range in ranges {
+        let line_start = data[..range.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = data[range.end..].find('\n').map_or(data.len(), |i| range.end + i);
+        println!("\t{range:?}\t{}[{}]{}", data[line_start..range.start].trim_start(), &data[range.clone()], data[range.end..line_end].trim_end());
+    }
 
     Ok(())
 }
 
-fn main() -> Result<()> {
+fn add_document(index: &mut DynamicIndex, ctx: &mut InfContext, boosts: &IndexBoosts, percolator: &Percolator, defaults: &SearchRequest, path: PathBuf) -> Result<()> {
+    let document_id = ctx.add_document(path.clone())?;
+    if let Err(err) = index.add_document(document_id, ctx, boosts) {
+        ctx.remove_document(document_id)?;
+        return Err(err);
+    }
+    println!("Added {path:?} as {document_id}");
+    percolator.percolate(document_id, ctx, boosts, defaults);
+
+    Ok(())
+}
+
+fn remove_document(index: &mut DynamicIndex, ctx: &mut InfContext, document_id: DocumentId) -> Result<()> {
+    ctx.remove_document(document_id)?;
+    index.remove_document(document_id);
+    println!("Removed {document_id}");
+
+    Ok(())
+}
+
+// NOTE: Adds files dropped into the folder since it was indexed and removes documents whose files were deleted
+fn refresh(index: &mut DynamicIndex, ctx: &mut InfContext, boosts: &IndexBoosts, percolator: &Percolator, defaults: &SearchRequest, base_path: &str) -> Result<()> {
+    let vanished = ctx.vanished_documents();
+    for &document_id in &vanished {
+        remove_document(index, ctx, document_id)?;
+    }
+
+    let mut added = 0;
+    for path in ctx.unindexed_files(base_path)? {
+        match add_document(index, ctx, boosts, percolator, defaults, path.clone()) {
+            Ok(()) => added += 1,
+            Err(err) => println!("Ignoring file {:?}. Error: {}. Caused by: {}", path, err, err.root_cause())
+        }
+    }
+    println!("Refreshed \"{base_path}\": {added} documents added, {} removed", vanished.len());
+
+    Ok(())
+}
+
+// NOTE: Input looks like "--webhook http://host/path king & lear", the webhook is optional
+fn watch(percolator: &mut Percolator, input: &str) -> Result<()> {
+    let (flags, query_text) = split_flags(input)?;
+    if let Some((name, _)) = flags.iter().find(|(name, _)| name != WEBHOOK_FLAG) {
+        return Err(anyhow!("Unknown flag '--{name}'"));
+    }
+
+    let id = percolator.register(query_text, flag_value(&flags, WEBHOOK_FLAG).map(str::to_owned))?;
+    println!("Registered standing query #{id}, it runs against every document added from now on");
+
+    Ok(())
+}
+
+fn print_standing_queries(percolator: &Percolator) {
+    if percolator.queries().is_empty() {
+        println!("No standing queries.");
+    }
+    for query in percolator.queries() {
+        match &query.webhook {
+            Some(webhook) => println!("#{}: {}, notifies {webhook}", query.id, query.query),
+            None => println!("#{}: {}", query.id, query.query)
+        }
+    }
+}
+
+fn print_ledger(ctx: &InfContext) {
+    let entries = ctx.ledger().entries();
+    if entries.is_empty() {
+        println!("No files were skipped or only partially indexed.");
+    }
+    for entry in entries {
+        match entry.document {
+            Some(document_id) => println!("[{document_id}] {:?} {}: {}", entry.path, entry.stage, entry.reason),
+            None => println!("{:?} {}: {}", entry.path, entry.stage, entry.reason)
+        }
+    }
+}
+
+fn print_changes(index: &DynamicIndex) {
+    println!("Unique word count: {}. Average document length: {:.1}. Changes not merged into the main index yet: {}",
+             index.unique_word_count(), index.average_document_length(), index.pending_changes());
+}
+
+fn run() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    let base_path = args.get(1).map(AsRef::as_ref).unwrap_or("data/shakespeare");
-    let file_limit = args.get(2).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+    let (positional, flags) = parse_args(&args)?;
+    let base_path = positional.first().cloned().unwrap_or("data/shakespeare");
+    let file_limit = positional.get(1).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+    let (run_flags, search_flags): (Flags, Flags) = flags.into_iter()
+        .partition(|(name, _)| RUN_FLAGS.contains(&name.as_str()));
+    let merge_buffer = flag_value(&run_flags, MERGE_BUFFER_FLAG)
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid merge buffer")?
+        .unwrap_or(DEFAULT_MERGE_BUFFER);
+    let format = flag_value(&run_flags, FORMAT_FLAG)
+        .map(OutputFormat::from_str)
+        .transpose()?
+        .unwrap_or(OutputFormat::Text);
+    let index_threads = flag_value(&run_flags, THREADS_FLAG)
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid thread count")?
+        .unwrap_or_else(Scheduling::default_thread_count);
+    if index_threads == 0 {
+        return Err(anyhow!("Thread count must be positive"));
+    }
+    let pipeline = flag_value(&run_flags, PIPELINE_FLAG)
+        .map(Pipeline::from_str)
+        .transpose()?
+        .unwrap_or_default();
+    let scheduling = Scheduling { index_threads, pipeline };
+    let segment_gap = flag_value(&run_flags, SEGMENT_GAP_FLAG)
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid segment gap")?
+        .unwrap_or(DEFAULT_SEGMENT_GAP);
+    let kgram_length = flag_value(&run_flags, KGRAM_LENGTH_FLAG)
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid k-gram length")?
+        .unwrap_or(DEFAULT_KGRAM_LENGTH);
+    if kgram_length == 0 {
+        return Err(anyhow!("K-gram length must be positive"));
+    }
+    let merge_threshold = flag_value(&run_flags, MERGE_THRESHOLD_FLAG)
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid merge threshold")?
+        .unwrap_or(DEFAULT_MERGE_THRESHOLD);
+    let mut defaults = SearchRequest::new();
+    defaults.apply_flags(&search_flags)?;
+
+    let boilerplate = match File::open("data/boilerplate.txt") {
+        Ok(file) => BoilerplateFilter::load(BufReader::new(file))?,
+        Err(_) => BoilerplateFilter::default()
+    };
 
     println!("Processing...");
-    let (ctx, opening_files_time) = time_call(|| InfContext::new(base_path, file_limit).unwrap());
+    let (ctx, opening_files_time) = metrics().time("opening_files", || InfContext::new(base_path, file_limit, boilerplate, segment_gap).unwrap());
     println!("Opening files took: {opening_files_time:?}");
-    let mut document_ids = ctx.document_ids().collect::<Vec<_>>();
+    let document_ids = largest_first(ctx.document_ids().collect(), |&document_id| ctx.document_size(document_id));
     let document_count = document_ids.len();
     println!("Processing {document_count} documents in folder \"{base_path}\"");
 
-    let pool = ThreadPool::new((num_cpus::get() - 1).max(1));
-    let (tx, rx) = channel();
-    for document_id in document_ids.drain(..) {
-        let tx = tx.clone();
-        let ctx1 = ctx.clone();
-
-        pool.execute(move || {
-            tx.send(add_file_to_index(document_id, ctx1).unwrap()).unwrap()
-        });
+    let boosts = Arc::new(match File::open("data/boosts.txt") {
+        Ok(file) => IndexBoosts::load(BufReader::new(file))?,
+        Err(_) => IndexBoosts::new()
+    });
+    if !boosts.is_empty() {
+        println!("Using index-time boosts from \"data/boosts.txt\"");
     }
 
-    let ((index, stats), index_time) = time_call(|| {
-        rx.into_iter()
-            .take(document_count)
-            .flatten()
-            .par_bridge()
-            .reduce(|| (InvertedIndex::new(), LexerStats::default()), |mut a, b| {
-                a.0.merge(b.0);
-                a.1.merge(b.1);
-
-                a
-            })
+    let (result, index_time) = metrics().time("indexing", || {
+        let ctx1 = ctx.clone();
+        let boosts1 = boosts.clone();
+        scheduling.index(document_ids, merge_buffer, move |document_id| add_file_to_index(document_id, ctx1.clone(), boosts1.clone()), |a, b| {
+            a.0.merge(b.0);
+            a.1.merge(b.1);
+        })
     });
+    let (mut index, stats) = result?.unwrap_or_else(|| (InvertedIndex::new(), LexerStats::default()));
 
     println!("Indexing took: {index_time:?}");
     let data_size: usize = ctx.files().files()
@@ -132,16 +327,44 @@ fn main() -> Result<()> {
         .sum();
     println!("Amount of data indexed: {}", human_bytes(data_size as f64));
     println!("Speed is: {}/s", human_bytes(data_size as f64 / index_time.as_secs_f64()));
+    metrics().add("documents_indexed", document_count as u64);
+    metrics().add("bytes_indexed", data_size as u64);
+    metrics().set("documents_per_second", document_count as f64 / index_time.as_secs_f64());
+    metrics().set("bytes_per_second", data_size as f64 / index_time.as_secs_f64());
 
-    println!("Unique word count: {}.", index.unique_word_count());
+    println!("Unique word count: {}. Average document length: {:.1}", index.unique_word_count(), index.average_document_length());
     println!("Lines read: {}. Characters read: {}. Characters ignored: {}", stats.lines, stats.characters_read, stats.characters_ignored);
+    metrics().add("lines_read", stats.lines as u64);
+    metrics().add("characters_read", stats.characters_read as u64);
+    println!("Tokens truncated: {}. Tokens dropped: {}", stats.tokens_truncated, stats.tokens_dropped);
+    if !ctx.ledger().is_empty() {
+        println!("{} files were skipped or only partially indexed, ':ledger' lists them", ctx.ledger().len());
+    }
+
+    let (gram_count, kgram_time) = metrics().time("kgram_indexing", || index.build_kgram_index(kgram_length));
+    println!("Indexed {gram_count} distinct {kgram_length}-grams for wildcards in {kgram_time:?}");
 
     println!("Writing index to a file...");
-    serde_json::to_writer_pretty(BufWriter::new(File::create("data/index.txt")?), &index)?;
+    persist::save_checked("data/index.txt", |writer| Ok(serde_json::to_writer_pretty(writer, &index)?))?;
     let index_size = File::open("data/index.txt")?.metadata()?.len();
     println!("Index size: {}", human_bytes(index_size as f64));
 
+    // NOTE: Indexing is done, so nothing else holds the context anymore
+    let mut ctx = Arc::try_unwrap(ctx).map_err(|_| anyhow!("Programming error. Context is still shared"))?;
+    let mut index = DynamicIndex::new(index, merge_threshold);
+    let mut percolator = Percolator::new(kgram_length);
+    // NOTE: Server mode takes the place of the REPL
+    if let Some(address) = flag_value(&run_flags, SERVE_FLAG) {
+        let access = Access {
+            api_key: flag_value(&run_flags, API_KEY_FLAG).map(str::to_owned),
+            read_only: flag_value(&run_flags, READ_ONLY_FLAG).is_some()
+        };
+
+        return Server::new(&mut index, &mut ctx, &boosts, &defaults, &mut percolator, access).serve(address);
+    }
+
     let mut buffer = String::new();
+    let mut last_terms = Vec::new();
     loop {
         println!("Please input your query or 'q' to exit: ");
         io::stdin().read_line(&mut buffer)?;
@@ -149,7 +372,38 @@ fn main() -> Result<()> {
             break;
         }
 
-        if let Err(err) = query(&buffer, &index, &ctx) {
+        let result = if buffer.trim() == ":refresh" {
+            refresh(&mut index, &mut ctx, &boosts, &percolator, &defaults, base_path).map(|()| print_changes(&index))
+        } else if let Some(path) = buffer.trim().strip_prefix(":add ") {
+            add_document(&mut index, &mut ctx, &boosts, &percolator, &defaults, PathBuf::from(path.trim())).map(|()| print_changes(&index))
+        } else if buffer.trim() == ":ledger" {
+            print_ledger(&ctx);
+            Ok(())
+        } else if buffer.trim() == ":watches" {
+            print_standing_queries(&percolator);
+            Ok(())
+        } else if let Some(input) = buffer.trim().strip_prefix(":watch ") {
+            watch(&mut percolator, input)
+        } else if let Some(id) = buffer.trim().strip_prefix(":unwatch ") {
+            usize::from_str(id.trim())
+                .context("Invalid standing query id")
+                .and_then(|id| percolator.remove(id))
+                .map(|()| println!("Removed standing query #{}", id.trim()))
+        } else if let Some(document_id) = buffer.trim().strip_prefix(":remove ") {
+            usize::from_str(document_id.trim())
+                .context("Invalid document id")
+                .and_then(|document_id| remove_document(&mut index, &mut ctx, DocumentId(document_id)))
+                .map(|()| print_changes(&index))
+        } else if let Some(args) = buffer.trim().strip_prefix(":concordance") {
+            concordance::concordance(args, &index, &ctx)
+        } else if let Some(args) = buffer.trim().strip_prefix(":highlight ") {
+            highlight_document(args, &index, &ctx, &defaults)
+        } else if let Some(args) = buffer.trim().strip_prefix(":tf") {
+            term_breakdown::term_breakdown(args, &last_terms, &index, &ctx)
+        } else {
+            query(&buffer, &index, &ctx, &defaults, format).map(|terms| last_terms = terms)
+        };
+        if let Err(err) = result {
             println!("Error: {}. Caused by: {}", err, err.root_cause());
         }
         println!();
@@ -159,3 +413,10 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn main() -> Result<()> {
+    let result = run();
+    metrics().save_to_file(Metrics::PATH)?;
+
+    result
+}
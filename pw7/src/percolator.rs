@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use serde::Serialize;
+use serde_json::json;
+use crate::boost::IndexBoosts;
+use crate::common::index_document;
+use crate::document::DocumentId;
+use crate::hit::Hit;
+use crate::inf_context::InfContext;
+use crate::query_lang;
+use crate::search::{search, SearchRequest};
+use crate::term_index::{InvertedIndex, TermIndex};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const WEBHOOK_SCHEME: &str = "http://";
+
+#[derive(Serialize)]
+#[derive(Clone, Debug)]
+pub struct StandingQuery {
+    pub id: usize,
+    pub query: String,
+    // NOTE: Url matches are posted to as json, besides being logged
+    pub webhook: Option<String>
+}
+
+/// Standing queries, run against every document added after the build instead of against the whole index.
+pub struct Percolator {
+    queries: Vec<StandingQuery>,
+    next_id: usize,
+    kgram_length: usize
+}
+
+impl Percolator {
+    pub fn new(kgram_length: usize) -> Self {
+        Percolator {
+            queries: Vec::new(),
+            next_id: 1,
+            kgram_length
+        }
+    }
+
+    // NOTE: Queries are checked once here against an empty index, so a broken or unsupported one
+    //  is refused instead of failing on every new document
+    pub fn register(&mut self, query: &str, webhook: Option<String>) -> Result<usize> {
+        let query_ast = query_lang::parse_logic_expr(query).context("Invalid query")?;
+        InvertedIndex::new().query(&query_ast).context("Invalid query")?;
+        if let Some(webhook) = &webhook {
+            WebhookUrl::parse(webhook)?;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queries.push(StandingQuery {
+            id,
+            query: query.trim().to_owned(),
+            webhook
+        });
+
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Result<()> {
+        let position = self.queries.iter()
+            .position(|query| query.id == id)
+            .ok_or_else(|| anyhow!("Standing query #{id} doesn't exist"))?;
+        self.queries.remove(position);
+
+        Ok(())
+    }
+
+    pub fn queries(&self) -> &[StandingQuery] {
+        &self.queries
+    }
+
+    // NOTE: The document is indexed once more on its own, so standing queries only see it and not the rest of the index.
+    //  Queries go through the usual search, so their zones and ranker are the REPL defaults. A query that fails is reported
+    //  and skipped, the document stays added either way. Ids of the queries that matched are returned
+    pub fn percolate(&self, document_id: DocumentId, ctx: &InfContext, boosts: &IndexBoosts, defaults: &SearchRequest) -> Vec<usize> {
+        if self.queries.is_empty() {
+            return Vec::new();
+        }
+
+        let mut index = match index_document(document_id, ctx, boosts) {
+            Ok((index, _)) => index,
+            Err(err) => {
+                println!("Standing queries weren't run for {document_id}. Error: {}. Caused by: {}", err, err.root_cause());
+                return Vec::new();
+            }
+        };
+        index.build_kgram_index(self.kgram_length);
+
+        let mut matched = Vec::new();
+        for query in &self.queries {
+            match search(&defaults.with_query(&query.query), &index, ctx) {
+                Ok(response) => if let Some(hit) = response.hits.first() {
+                    notify(query, hit);
+                    matched.push(query.id);
+                },
+                Err(err) => println!("Standing query #{} failed. Error: {}. Caused by: {}", query.id, err, err.root_cause())
+            }
+        }
+
+        matched
+    }
+}
+
+// NOTE: Webhooks are posted to from a thread of their own, so a slow receiver doesn't hold up adding documents
+fn notify(query: &StandingQuery, hit: &Hit) {
+    println!("Standing query #{} matched {} \"{}\": {}", query.id, hit.document, hit.name, query.query);
+
+    if let Some(webhook) = query.webhook.clone() {
+        let body = json!({
+            "query_id": query.id,
+            "query": query.query,
+            "hit": hit
+        }).to_string();
+
+        std::thread::spawn(move || {
+            if let Err(err) = WebhookUrl::parse(&webhook).and_then(|url| url.post_json(&body)) {
+                println!("Couldn't notify webhook \"{webhook}\". Error: {}. Caused by: {}", err, err.root_cause());
+            }
+        });
+    }
+}
+
+struct WebhookUrl {
+    // NOTE: Host together with the port, as it is written in the url and sent in the Host header
+    host: String,
+    path: String
+}
+
+impl WebhookUrl {
+    fn parse(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix(WEBHOOK_SCHEME)
+            .ok_or_else(|| anyhow!("Only {WEBHOOK_SCHEME} webhooks are supported, got \"{url}\""))?;
+        // NOTE: Host and path are written into the request as they are, a line break in them would start a header of its own
+        if let Some(ch) = rest.chars().find(|ch| ch.is_control() || ch.is_whitespace()) {
+            return Err(anyhow!("Webhook {url:?} contains {ch:?}, which isn't allowed in a url"));
+        }
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if host.is_empty() {
+            return Err(anyhow!("Webhook \"{url}\" has no host"));
+        }
+
+        Ok(WebhookUrl {
+            host: host.to_owned(),
+            path: if path.is_empty() { "/".to_owned() } else { path.to_owned() }
+        })
+    }
+
+    // NOTE: HTTP/1.0 closes the connection after the response, only its status line is read
+    fn post_json(&self, body: &str) -> Result<()> {
+        let address = if self.host.contains(':') { self.host.clone() } else { format!("{}:80", self.host) };
+        let mut stream = TcpStream::connect(&address).context(anyhow!("Couldn't connect to {address}"))?;
+        stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+        stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+        write!(stream, "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+               self.path, self.host, body.len(), body)?;
+
+        let mut status = String::new();
+        BufReader::new(&stream).read_line(&mut status)?;
+        if !status.split_whitespace().nth(1).is_some_and(|code| code.starts_with('2')) {
+            return Err(anyhow!("Webhook answered \"{}\"", status.trim()));
+        }
+
+        Ok(())
+    }
+}
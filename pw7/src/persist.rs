@@ -0,0 +1,52 @@
+use anyhow::Result;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crc32fast::Hasher;
+
+// NOTE: Fixed size trailer, a file that doesn't end with it was never completely written
+const CHECKSUM_PREFIX: &[u8] = b"#crc32 ";
+
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Hasher
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// NOTE: Content goes to a temporary file that replaces the target only once it is
+//  complete and synced, so an interrupted write leaves the previous file intact
+pub fn save_checked(path: impl AsRef<Path>, write: impl FnOnce(&mut ChecksumWriter<BufWriter<File>>) -> Result<()>) -> Result<()> {
+    let path = path.as_ref();
+    let temp_path = path.with_extension("tmp");
+
+    let mut writer = ChecksumWriter {
+        inner: BufWriter::new(File::create(&temp_path)?),
+        hasher: Hasher::new()
+    };
+    write(&mut writer)?;
+    let checksum = writer.hasher.finalize();
+    let mut inner = writer.inner;
+    inner.write_all(CHECKSUM_PREFIX)?;
+    inner.write_all(format!("{checksum:08x}\n").as_bytes())?;
+    inner.into_inner()?.sync_all()?;
+    fs::rename(&temp_path, path)?;
+
+    // NOTE: Directories can't be synced on every platform, the rename is still in place then
+    if let Some(parent) = path.parent() {
+        let _ = File::open(parent).and_then(|directory| directory.sync_all());
+    }
+
+    Ok(())
+}
@@ -22,7 +22,7 @@ impl<'a> Segmenter<'a> for PlainTextSegmenter<'a> {
     fn segment(self: Box<Self>) -> Result<Segments<'a>> {
         let mut segments = Segments::new();
 
-        segments.add(SegmentKind::Body, Cow::Borrowed(self.ctx.document_data(self.document_id)?));
+        segments.add(SegmentKind::Body, Cow::Borrowed(self.ctx.document_text(self.document_id)?));
 
         Ok(segments)
     }
@@ -1,10 +1,12 @@
-use std::iter::Peekable;
+use std::iter::{Map, Peekable};
 use anyhow::{anyhow, Context, Result};
 use std::str::{Chars, FromStr};
 
 #[derive(Eq, PartialEq, Clone, Debug)]
 enum Token {
     Term(String),
+    // NOTE: Term with '*' inside, a lone '*' is an Asterisk
+    Pattern(String),
     Number(usize),
     Ampersand,
     Pipe,
@@ -15,16 +17,40 @@ enum Token {
     RightCurlyBracket,
     GreaterThan,
     DoubleQuotes,
-    Backslash
+    Backslash,
+    Asterisk
 }
 
+// NOTE: Queries pasted from documents and chat apps come with typographic quotes,
+//  fullwidth punctuation and logic symbols, they mean the same as the ASCII operators
+fn normalize_char(ch: char) -> char {
+    match ch {
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{00AB}' | '\u{00BB}' | '\u{300C}' | '\u{300D}' | '\u{300E}' | '\u{300F}' | '\u{FF02}' => '"',
+        '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{02BC}' | '\u{FF07}' => '\'',
+        '\u{FF06}' | '\u{2227}' => '&',
+        '\u{FF5C}' | '\u{2228}' => '|',
+        '\u{FF01}' | '\u{00AC}' => '!',
+        '\u{FF08}' => '(',
+        '\u{FF09}' => ')',
+        '\u{FF5B}' => '{',
+        '\u{FF5D}' => '}',
+        '\u{FF1E}' => '>',
+        '\u{FF3C}' | '\u{2216}' => '\\',
+        '\u{FF0A}' => '*',
+        '\u{FF10}'..='\u{FF19}' => char::from_u32(ch as u32 - 0xFF10 + '0' as u32).unwrap_or(ch),
+        _ => ch
+    }
+}
+
+type NormalizedChars<'a> = Map<Chars<'a>, fn(char) -> char>;
+
 struct Lexer<'a> {
-    iter: Peekable<Chars<'a>>
+    iter: Peekable<NormalizedChars<'a>>
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Lexer { iter: input.chars().peekable() }
+        Lexer { iter: input.chars().map(normalize_char as fn(char) -> char).peekable() }
     }
 
     pub fn lex(mut self) -> Result<Vec<Token>> {
@@ -53,14 +79,29 @@ impl<'a> Lexer<'a> {
             if ch.is_alphabetic() || (ch.eq(&'\'') && !word.is_empty()) {
                 ch.to_lowercase().for_each(|ch| word.push(ch));
                 iter.next();
-            } else if !word.is_empty() {
-                return Some(Token::Term(word))
+            } else if ch.eq(&'*') && !word.ends_with('*') {
+                word.push('*');
+                iter.next();
+            } else if ch.eq(&'*') {
+                iter.next();
             } else {
-                return None
+                break;
             }
         }
 
-        None
+        Self::term_token(word)
+    }
+
+    fn term_token(word: String) -> Option<Token> {
+        if word.is_empty() {
+            None
+        } else if word == "*" {
+            Some(Token::Asterisk)
+        } else if word.contains('*') {
+            Some(Token::Pattern(word))
+        } else {
+            Some(Token::Term(word))
+        }
     }
 
     fn try_consume_punctuator(iter: &mut Peekable<impl Iterator<Item = char>>) -> Option<Token> {
@@ -76,6 +117,7 @@ impl<'a> Lexer<'a> {
                 '>' => Token::GreaterThan,
                 '"' => Token::DoubleQuotes,
                 '\\' => Token::Backslash,
+                '*' => Token::Asterisk,
                 _ => return None
             });
 
@@ -158,7 +200,27 @@ pub enum LogicNode {
     Or(Box<LogicNode>, Box<LogicNode>),
     Not(Box<LogicNode>),
     Near(Box<LogicNode>, Box<LogicNode>, usize, usize),
-    Subtract(Box<LogicNode>, Box<LogicNode>)
+    Subtract(Box<LogicNode>, Box<LogicNode>),
+    // NOTE: Terms matching a pattern with any number of '*' in it, like "k*ng*"
+    Pattern(String),
+    // NOTE: Any term right after the phrase before it, only allowed at the end of a phrase
+    Wildcard
+}
+
+impl LogicNode {
+    pub fn terms(&self) -> Vec<&str> {
+        match self {
+            LogicNode::False | LogicNode::Pattern(_) | LogicNode::Wildcard => Vec::new(),
+            LogicNode::Term(term) => vec![term.as_str()],
+            LogicNode::Not(operand) => operand.terms(),
+            LogicNode::And(lhs, rhs) | LogicNode::Or(lhs, rhs) | LogicNode::Near(lhs, rhs, _, _) | LogicNode::Subtract(lhs, rhs) => {
+                let mut terms = lhs.terms();
+                terms.extend(rhs.terms());
+
+                terms
+            }
+        }
+    }
 }
 
 struct Parser {
@@ -180,6 +242,9 @@ impl Parser {
                 Token::Term(term) => {
                     operand_stack.push(LogicNode::Term(term));
                 },
+                Token::Pattern(pattern) => {
+                    operand_stack.push(LogicNode::Pattern(pattern));
+                },
                 Token::Ampersand | Token::Pipe | Token::Exclaim | Token::Backslash => {
                     let operator = Operator::from_token(&token)
                         .context(anyhow!("Programming error. Token {token:?} is not an operator."))?;
@@ -222,15 +287,31 @@ impl Parser {
                     operator_stack.push(Operator::Next);
                 },
                 Token::DoubleQuotes => {
+                    let mut phrase_length = 0;
                     while let Some(token) = iter.peek() {
                         match token {
-                            Token::Term(term) => {
-                                operand_stack.push(LogicNode::Term(term.clone()));
-                                iter.next();
-                                if let Some(Token::Term(_)) = iter.peek() {
+                            Token::Term(_) | Token::Pattern(_) => {
+                                operand_stack.push(match iter.next() {
+                                    Some(Token::Pattern(pattern)) => LogicNode::Pattern(pattern),
+                                    Some(Token::Term(term)) => LogicNode::Term(term),
+                                    _ => return Err(anyhow!("Programming error. Expected a term inside phrase literal"))
+                                });
+                                phrase_length += 1;
+                                if let Some(Token::Term(_) | Token::Pattern(_) | Token::Asterisk) = iter.peek() {
+                                    // NOTE: Phrase is built left to right, so a trailing wildcard follows the whole prefix
+                                    while let Some(Operator::Next) = operator_stack.last() {
+                                        Self::construct_operator(&mut operator_stack, &mut operand_stack)?;
+                                    }
                                     operator_stack.push(Operator::Next);
                                 }
                             },
+                            Token::Asterisk => {
+                                iter.next();
+                                if phrase_length == 0 || iter.peek() != Some(&Token::DoubleQuotes) {
+                                    return Err(anyhow!("Wildcard '*' is only supported at the end of a phrase"));
+                                }
+                                operand_stack.push(LogicNode::Wildcard);
+                            },
                             Token::DoubleQuotes => break,
                             _ => return Err(anyhow!("Unexpected token {:?} inside phrase literal", token))
                         }
@@ -0,0 +1,68 @@
+use anyhow::{anyhow, Result};
+use ahash::HashMap;
+use std::sync::Arc;
+use itertools::Itertools;
+use crate::document::DocumentId;
+use crate::segment::SegmentKind;
+use crate::term::Posting;
+use crate::zone::ZoneOptions;
+
+// NOTE: Zones of every matching document with the posting of the query term found there
+pub type Candidates<'a> = HashMap<DocumentId, Vec<(SegmentKind, &'a Posting)>>;
+
+/// Scores documents matched by a query, most relevant first.
+pub trait Ranker: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn segment_weight(&self, segment_kind: SegmentKind, posting: &Posting, options: &ZoneOptions) -> f64;
+
+    fn score(&self, candidates: &Candidates, options: &ZoneOptions) -> Vec<(DocumentId, f64)> {
+        candidates.iter()
+            .map(|(&document_id, segments)| {
+                let weight = segments.iter()
+                    .map(|&(segment_kind, posting)| self.segment_weight(segment_kind, posting, options))
+                    .sum::<f64>();
+
+                (document_id, weight)
+            })
+            .sorted_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap().reverse())
+            .collect()
+    }
+}
+
+// NOTE: Sublinear term frequency with index-time boosts, weighted by the zone it was found in
+pub struct ZoneRanker;
+
+impl Ranker for ZoneRanker {
+    fn name(&self) -> &'static str {
+        "zone"
+    }
+
+    fn segment_weight(&self, segment_kind: SegmentKind, posting: &Posting, options: &ZoneOptions) -> f64 {
+        options.weight(segment_kind) * posting.weight()
+    }
+}
+
+// NOTE: Raw occurrence count, zones only filter matches and don't weigh them
+pub struct TermFrequencyRanker;
+
+impl Ranker for TermFrequencyRanker {
+    fn name(&self) -> &'static str {
+        "tf"
+    }
+
+    fn segment_weight(&self, segment_kind: SegmentKind, posting: &Posting, options: &ZoneOptions) -> f64 {
+        if !options.allows(segment_kind) {
+            return 0.0;
+        }
+
+        posting.count as f64
+    }
+}
+
+pub fn ranker_by_name(name: &str) -> Result<Arc<dyn Ranker>> {
+    Ok(match name {
+        "zone" => Arc::new(ZoneRanker),
+        "tf" => Arc::new(TermFrequencyRanker),
+        _ => return Err(anyhow!("Unknown ranker '{name}', expected 'zone' or 'tf'"))
+    })
+}
@@ -0,0 +1,268 @@
+use anyhow::{anyhow, Context, Result};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use itertools::Itertools;
+use serde::Serialize;
+use crate::common::segment_file;
+use crate::concordance::context;
+use crate::document::DocumentId;
+use crate::hit::{Hit, SegmentMatch};
+use crate::inf_context::InfContext;
+use crate::lexer::token_spans;
+use crate::metrics::metrics;
+use crate::query_lang;
+use crate::ranking::{ranker_by_name, Candidates, Ranker, ZoneRanker};
+use crate::segment::{SegmentKind, TermPosition};
+use crate::term::Posting;
+use crate::term_index::{Expansion, TermIndex};
+use crate::zone::ZoneOptions;
+
+// NOTE: Snippets and highlights segment the document again, so only this many hits get them
+pub const DEFAULT_SEARCH_LIMIT: usize = 50;
+const SNIPPET_WIDTH: usize = 40;
+const SUGGESTION_COUNT: usize = 3;
+
+/// Everything needed to answer a query, shared by the REPL and the command line.
+#[derive(Clone)]
+pub struct SearchRequest {
+    pub query: String,
+    // NOTE: Zones restrict which matches count and how much each of them weighs
+    pub zones: ZoneOptions,
+    pub limit: usize,
+    pub ranker: Arc<dyn Ranker>,
+    // NOTE: Slope of pivoted length normalization, without it long documents keep whatever the ranker gave them
+    pub pivot_slope: Option<f64>
+}
+
+impl SearchRequest {
+    const RANKER_FLAG: &'static str = "ranker";
+    const LIMIT_FLAG: &'static str = "limit";
+    const PIVOT_SLOPE_FLAG: &'static str = "pivot-slope";
+
+    pub fn new() -> Self {
+        SearchRequest {
+            query: String::new(),
+            zones: ZoneOptions::new(),
+            limit: DEFAULT_SEARCH_LIMIT,
+            ranker: Arc::new(ZoneRanker),
+            pivot_slope: None
+        }
+    }
+
+    pub fn with_query(&self, query: &str) -> Self {
+        SearchRequest {
+            query: query.to_owned(),
+            ..self.clone()
+        }
+    }
+
+    pub fn apply_flag(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            Self::RANKER_FLAG => self.ranker = ranker_by_name(value)?,
+            Self::LIMIT_FLAG => self.limit = usize::from_str(value).context(anyhow!("Invalid limit '{value}'"))?,
+            Self::PIVOT_SLOPE_FLAG => {
+                let slope = f64::from_str(value).context(anyhow!("Invalid pivot slope '{value}'"))?;
+                if !(0.0..=1.0).contains(&slope) {
+                    return Err(anyhow!("Pivot slope must be between 0 and 1, got {slope}"));
+                }
+
+                self.pivot_slope = Some(slope);
+            },
+            _ => self.zones.apply_flag(name, value)?
+        }
+
+        Ok(())
+    }
+
+    pub fn apply_flags(&mut self, flags: &[(String, String)]) -> Result<()> {
+        flags.iter()
+            .try_for_each(|(name, value)| self.apply_flag(name, value))
+    }
+}
+
+impl Default for SearchRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    pub terms: Vec<String>,
+    // NOTE: Terms a wildcard was expanded to, most frequent first
+    pub expansions: Vec<Expansion>,
+    // NOTE: Spellings of query terms the index doesn't have, only looked for when nothing matched
+    pub suggestions: Vec<String>,
+    pub ranker: &'static str,
+    // NOTE: Matching documents before the limit was applied
+    pub total: usize,
+    pub hits: Vec<Hit>,
+    pub query_time: Duration
+}
+
+pub fn search(request: &SearchRequest, index: &dyn TermIndex, ctx: &InfContext) -> Result<SearchResponse> {
+    let ast = query_lang::parse_logic_expr(&request.query).context("Invalid query")?;
+    let (result, query_time) = metrics().time("query", || index.query(&ast));
+    let (result, expansions) = result?;
+    let query_terms = ast.terms();
+    let terms = query_terms.iter()
+        .cloned()
+        .chain(expansions.iter().map(|expansion| expansion.term.as_str()))
+        .collect::<Vec<_>>();
+
+    let options = &request.zones;
+    let candidates = result.iter()
+        .filter(|(position, _)| options.allows(position.segment_kind))
+        .map(|(position, posting)| (position.document, position.segment_kind, posting))
+        .sorted_by_key(|&(document, segment_kind, _)| (document.id(), segment_kind))
+        .group_by(|(document, _, _)| document.id())
+        .into_iter()
+        .map(|(document, group)| (DocumentId(document), group.map(|(_, kind, posting)| (kind, posting)).collect::<Vec<_>>()))
+        .collect::<Candidates>();
+
+    let scores = request.ranker.score(&candidates, options);
+    let scores = match request.pivot_slope {
+        Some(slope) => pivoted(scores, slope, index),
+        None => scores
+    };
+    let total = scores.len();
+    let suggestions = if total == 0 {
+        query_terms.iter()
+            .flat_map(|term| index.suggestions(term, SUGGESTION_COUNT))
+            .unique()
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let hits = scores.into_iter()
+        .filter_map(|(document_id, weight)| ctx.document(document_id).map(|doc| (document_id, doc, &candidates[&document_id], weight)))
+        .take(request.limit)
+        .enumerate()
+        .map(|(rank, (document, doc, segments, weight))| {
+            let segments = segments.iter()
+                .map(|&(segment_kind, posting)| {
+                    let weight = request.ranker.segment_weight(segment_kind, posting, options);
+                    SegmentMatch::new(index, TermPosition { document, segment_kind }, weight, &terms)
+                })
+                .collect::<Vec<_>>();
+
+            let matches = match_ranges(document, candidates[&document].as_slice(), ctx)?;
+
+            Ok(Hit {
+                rank,
+                document,
+                name: doc.name(),
+                weight,
+                terms: segments.iter()
+                    .flat_map(|segment| segment.terms.iter().map(|term| term.term.clone()))
+                    .unique()
+                    .collect(),
+                snippet: snippet(document, &matches, candidates[&document].as_slice(), &request.ranker, options, ctx)?,
+                highlights: matches.into_iter().map(|(_, range)| range).collect(),
+                segments
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(SearchResponse {
+        terms: query_terms.iter().map(|&term| term.to_owned()).collect(),
+        expansions,
+        suggestions,
+        ranker: request.ranker.name(),
+        total,
+        hits,
+        query_time
+    })
+}
+
+/// Byte ranges of the query's matches in the original text of the document, in text order.
+/// Zones of the request restrict which matches count, the same way they do for search.
+pub fn highlight(document_id: DocumentId, request: &SearchRequest, index: &dyn TermIndex, ctx: &InfContext) -> Result<Vec<Range<usize>>> {
+    ctx.document(document_id).context(anyhow!("Document with id {document_id} doesn't exist"))?;
+    let ast = query_lang::parse_logic_expr(&request.query).context("Invalid query")?;
+    let (result, _) = index.query(&ast)?;
+    let segments = result.iter()
+        .filter(|(position, _)| position.document == document_id && request.zones.allows(position.segment_kind))
+        .map(|(position, posting)| (position.segment_kind, posting))
+        .collect::<Vec<_>>();
+
+    Ok(match_ranges(document_id, &segments, ctx)?
+        .into_iter()
+        .map(|(_, range)| range)
+        .collect())
+}
+
+// NOTE: Postings only keep word offsets, so the document is segmented again to find their bytes. Only zones that are
+//  slices of the document text can be mapped back to it, text a segmenter built itself, like stripped html or file names, is left out.
+//  Highlights and snippets both come from here, every hit segments its document once
+fn match_ranges(document_id: DocumentId, segments: &[(SegmentKind, &Posting)], ctx: &InfContext) -> Result<Vec<(SegmentKind, Range<usize>)>> {
+    if segments.iter().all(|(_, posting)| posting.positions.is_empty()) {
+        return Ok(Vec::new());
+    }
+
+    let data = ctx.document_data(document_id)?;
+    let data_start = data.as_ptr() as usize;
+    let mut ranges = Vec::new();
+    for (segment_kind, texts) in segment_file(document_id, ctx)?.iter() {
+        let Some((_, posting)) = segments.iter().find(|(kind, _)| kind == segment_kind) else {
+            continue;
+        };
+
+        let mut offset = 0;
+        for text in texts {
+            let spans = token_spans(text);
+            let text_start = text.as_ptr() as usize;
+            if matches!(text, Cow::Borrowed(_)) && text_start >= data_start && text_start + text.len() <= data_start + data.len() {
+                let base = text_start - data_start;
+                ranges.extend(posting.positions.iter()
+                    .filter_map(|&position| position.checked_sub(offset).and_then(|position| spans.get(position)))
+                    .map(|span| (*segment_kind, base + span.start..base + span.end)));
+            }
+            offset += spans.len() + ctx.segment_gap();
+        }
+    }
+    ranges.sort_by_key(|(_, range)| (range.start, range.end));
+    ranges.dedup_by(|(_, a), (_, b)| a == b);
+
+    Ok(ranges)
+}
+
+// NOTE: Scores are divided by (1 - slope) + slope * length / average length, a document of average length keeps its score.
+//  Compared to dividing by the length itself, long fb2 novels are penalized less and short plays favoured less
+fn pivoted(scores: Vec<(DocumentId, f64)>, slope: f64, index: &dyn TermIndex) -> Vec<(DocumentId, f64)> {
+    let average_length = index.average_document_length();
+    if average_length == 0.0 {
+        return scores;
+    }
+
+    scores.into_iter()
+        .map(|(document_id, weight)| {
+            let length = index.document_length(document_id) as f64;
+
+            (document_id, weight / (1.0 - slope + slope * length / average_length))
+        })
+        .sorted_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap().reverse())
+        .collect()
+}
+
+// NOTE: Text around the first highlighted match in the highest weighted zone, so the snippet marks the same words
+//  as the highlights. Zones that aren't part of the document text, like the file name, have no snippet
+fn snippet(document_id: DocumentId, matches: &[(SegmentKind, Range<usize>)], segments: &[(SegmentKind, &Posting)], ranker: &Arc<dyn Ranker>,
+           options: &ZoneOptions, ctx: &InfContext) -> Result<Option<String>> {
+    let weight = |segment_kind: SegmentKind| segments.iter()
+        .find(|(kind, _)| *kind == segment_kind)
+        .map_or(0.0, |&(kind, posting)| ranker.segment_weight(kind, posting, options));
+    let Some((_, range)) = matches.iter()
+        .max_by(|(a, a_range), (b, b_range)| weight(*a).partial_cmp(&weight(*b)).unwrap().then(b_range.start.cmp(&a_range.start))) else {
+        return Ok(None);
+    };
+
+    let data = ctx.document_data(document_id)?;
+    let left = context(data[..range.start].chars().rev().take(SNIPPET_WIDTH).collect::<Vec<_>>().into_iter().rev());
+    let right = context(data[range.end..].chars().take(SNIPPET_WIDTH));
+
+    Ok(Some(format!("{}[{}]{}", left.trim_start(), &data[range.clone()], right.trim_end())))
+}
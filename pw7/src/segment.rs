@@ -1,7 +1,8 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 use crate::document::DocumentId;
 
@@ -28,6 +29,21 @@ impl SegmentKind {
     }
 }
 
+impl FromStr for SegmentKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        SegmentKind::values().iter()
+            .find(|segment_kind| format!("{segment_kind:?}").eq_ignore_ascii_case(s.trim()))
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown zone '{s}'"))
+    }
+}
+
+// NOTE: Positions skipped between consecutive segments of a zone. Without a gap a phrase
+//  can continue into the next paragraph, a gap wider than any 'near' distance prevents that
+pub const DEFAULT_SEGMENT_GAP: usize = 0;
+
 // TODO: Data either should be all owned, or all shared
 #[derive(Debug)]
 pub struct Segments<'a> {
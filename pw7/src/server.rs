@@ -0,0 +1,397 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+use serde::Serialize;
+use serde_json::json;
+use crate::boost::IndexBoosts;
+use crate::dynamic_index::DynamicIndex;
+use crate::inf_context::InfContext;
+use crate::percolator::Percolator;
+use crate::document::DocumentId;
+use crate::search::{highlight, search, SearchRequest};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+const QUERY_PARAMETER: &str = "q";
+const NAME_PARAMETER: &str = "name";
+const DOCUMENT_PARAMETER: &str = "document";
+const WEBHOOK_PARAMETER: &str = "webhook";
+const QUERIES_PATH: &str = "/queries";
+const QUERY_PATH_PREFIX: &str = "/queries/";
+
+/// Answers queries and takes new documents over HTTP, one connection at a time.
+///
+/// `POST /documents` indexes the body as a document, its content type picks the segmenter:
+/// `text/plain`, `text/html` or `application/x-fictionbook+xml`. The `name` parameter names the document.
+/// `GET /search?q=...` runs a query, other parameters are search flags like `limit` or `ranker`.
+/// `GET /highlight?document=<id>&q=...` returns byte ranges of the query's matches in the text of that document.
+/// `POST /queries` registers the body as a standing query, run against every document added from then on,
+/// with an optional `webhook` parameter to be notified at. `GET /queries` lists them, `DELETE /queries/<id>` removes one.
+///
+/// Querying is always open. Requests that change the index need the API key when one is set,
+/// sent as `Authorization: Bearer <key>` or `X-Api-Key: <key>`, and are refused altogether when read-only.
+pub struct Server<'a> {
+    index: &'a mut DynamicIndex,
+    ctx: &'a mut InfContext,
+    boosts: &'a IndexBoosts,
+    defaults: &'a SearchRequest,
+    percolator: &'a mut Percolator,
+    access: Access
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Access {
+    pub api_key: Option<String>,
+    pub read_only: bool
+}
+
+struct Request {
+    method: String,
+    path: String,
+    parameters: Vec<(String, String)>,
+    content_type: Option<String>,
+    api_key: Option<String>,
+    body: Vec<u8>
+}
+
+struct Response {
+    status: u16,
+    body: String
+}
+
+impl Response {
+    fn json(status: u16, value: &impl Serialize) -> Self {
+        Response {
+            status,
+            body: serde_json::to_string(value).unwrap_or_default()
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, &json!({ "error": message.into() }))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            415 => "Unsupported Media Type",
+            _ => "Internal Server Error"
+        }
+    }
+}
+
+impl<'a> Server<'a> {
+    pub fn new(index: &'a mut DynamicIndex, ctx: &'a mut InfContext, boosts: &'a IndexBoosts, defaults: &'a SearchRequest,
+               percolator: &'a mut Percolator, access: Access) -> Self {
+        Server {
+            index,
+            ctx,
+            boosts,
+            defaults,
+            percolator,
+            access
+        }
+    }
+
+    // NOTE: Runs until the process is stopped, a failed connection is reported and the next one is taken
+    pub fn serve(&mut self, address: &str) -> Result<()> {
+        let listener = TcpListener::bind(address).context(anyhow!("Couldn't listen on {address}"))?;
+        println!("Listening on http://{}", listener.local_addr()?);
+        if self.access.read_only {
+            println!("Server is read-only, documents can't be added");
+        } else if self.access.api_key.is_some() {
+            println!("Adding documents requires the API key");
+        }
+
+        for stream in listener.incoming() {
+            if let Err(err) = stream.map_err(anyhow::Error::from).and_then(|stream| self.handle_connection(stream)) {
+                println!("Error: {}. Caused by: {}", err, err.root_cause());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_connection(&mut self, mut stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut reader = BufReader::new(&stream);
+        let request = read_request_head(&mut reader).and_then(|(mut request, content_length)| {
+            // NOTE: Changes are authorized before their body is read, so a client without the key can't make the server buffer one
+            if let Some(response) = Self::changes_state(&request).then(|| self.refuse_change(&request)).flatten() {
+                return Err(response);
+            }
+            request.body = read_body(&mut reader, &request.method, content_length)?;
+
+            Ok(request)
+        });
+        let response = match request {
+            Ok(request) => {
+                let response = self.route(&request);
+                println!("{} {} {}", request.method, request.path, response.status);
+                response
+            },
+            Err(response) => {
+                println!("Rejected a request with {}", response.status);
+                response
+            }
+        };
+
+        write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               response.status, response.reason(), response.body.len(), response.body)?;
+
+        Ok(stream.flush()?)
+    }
+
+    fn route(&mut self, request: &Request) -> Response {
+        let result = match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/documents") => self.add_document(request),
+            ("GET", "/search") => self.search(request),
+            ("GET", "/highlight") => self.highlight(request),
+            ("GET", QUERIES_PATH) => Ok(Response::json(200, &self.percolator.queries())),
+            ("POST", QUERIES_PATH) => self.add_standing_query(request),
+            ("DELETE", path) if path.starts_with(QUERY_PATH_PREFIX) => self.remove_standing_query(&path[QUERY_PATH_PREFIX.len()..]),
+            (_, "/documents") | (_, "/search") | (_, "/highlight") | (_, QUERIES_PATH) => return Response::error(405, format!("{} isn't allowed on {}", request.method, request.path)),
+            _ => return Response::error(404, format!("Nothing at {}", request.path))
+        };
+
+        result.unwrap_or_else(|err| Response::error(400, format!("{}. Caused by: {}", err, err.root_cause())))
+    }
+
+    // NOTE: Requests that `route` answers by changing the index or the standing queries
+    fn changes_state(request: &Request) -> bool {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/documents") | ("POST", QUERIES_PATH) => true,
+            ("DELETE", path) => path.starts_with(QUERY_PATH_PREFIX),
+            _ => false
+        }
+    }
+
+    fn refuse_change(&self, request: &Request) -> Option<Response> {
+        if self.access.read_only {
+            return Some(Response::error(403, "Server is read-only"));
+        }
+
+        match (&self.access.api_key, &request.api_key) {
+            (None, _) => None,
+            (Some(expected), Some(actual)) if keys_match(expected, actual) => None,
+            (Some(_), Some(_)) => Some(Response::error(401, "API key is invalid")),
+            (Some(_), None) => Some(Response::error(401, "API key is required"))
+        }
+    }
+
+    // NOTE: The name gets the extension of the content type, the segmenter is picked by it
+    fn add_document(&mut self, request: &Request) -> Result<Response> {
+        let Some(extension) = request.content_type.as_deref().and_then(document_extension) else {
+            return Ok(Response::error(415, format!("Content type {:?} can't be indexed", request.content_type.as_deref().unwrap_or_default())));
+        };
+        let text = String::from_utf8(request.body.clone()).context("Document isn't valid UTF-8")?;
+
+        let name = parameter(request, NAME_PARAMETER)
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("document-{}", self.ctx.document_count()));
+        let name = match Path::new(&name).extension() {
+            Some(name_extension) if name_extension == extension => name,
+            _ => format!("{name}.{extension}")
+        };
+
+        let document_id = self.ctx.add_inline(name.clone(), text);
+        let stats = match self.index.add_document(document_id, self.ctx, self.boosts) {
+            Ok(stats) => stats,
+            Err(err) => {
+                self.ctx.remove_document(document_id)?;
+                return Err(err);
+            }
+        };
+        println!("Added \"{name}\" as {document_id}");
+        let matched_queries = self.percolator.percolate(document_id, self.ctx, self.boosts, self.defaults);
+
+        Ok(Response::json(201, &json!({
+            "id": document_id,
+            "name": name,
+            "tokens": stats.tokens,
+            "pending_changes": self.index.pending_changes(),
+            "matched_queries": matched_queries
+        })))
+    }
+
+    fn add_standing_query(&mut self, request: &Request) -> Result<Response> {
+        let query = std::str::from_utf8(&request.body).context("Query isn't valid UTF-8")?;
+        let webhook = parameter(request, WEBHOOK_PARAMETER).map(str::to_owned);
+        let id = self.percolator.register(query, webhook)?;
+        println!("Registered standing query #{id}");
+
+        Ok(Response::json(201, &json!({ "id": id })))
+    }
+
+    fn remove_standing_query(&mut self, id: &str) -> Result<Response> {
+        let id = id.parse::<usize>().context(anyhow!("Invalid standing query id \"{id}\""))?;
+        if let Err(err) = self.percolator.remove(id) {
+            return Ok(Response::error(404, err.to_string()));
+        }
+        println!("Removed standing query #{id}");
+
+        Ok(Response::json(200, &json!({ "id": id })))
+    }
+
+    fn search(&self, request: &Request) -> Result<Response> {
+        let search_request = self.search_request(request, &[QUERY_PARAMETER])?;
+
+        Ok(Response::json(200, &search(&search_request, &*self.index, self.ctx)?))
+    }
+
+    fn highlight(&self, request: &Request) -> Result<Response> {
+        let document = parameter(request, DOCUMENT_PARAMETER).context(anyhow!("Missing document parameter '{DOCUMENT_PARAMETER}'"))?;
+        let document_id = DocumentId(document.parse().context(anyhow!("Invalid document id \"{document}\""))?);
+        if self.ctx.document(document_id).is_none() {
+            return Ok(Response::error(404, format!("Document with id {document_id} doesn't exist")));
+        }
+        let search_request = self.search_request(request, &[QUERY_PARAMETER, DOCUMENT_PARAMETER])?;
+
+        Ok(Response::json(200, &json!({
+            "document": document_id,
+            "ranges": highlight(document_id, &search_request, &*self.index, self.ctx)?
+        })))
+    }
+
+    // NOTE: Parameters besides the query and the given ones are search flags
+    fn search_request(&self, request: &Request, reserved: &[&str]) -> Result<SearchRequest> {
+        let query = parameter(request, QUERY_PARAMETER).context(anyhow!("Missing query parameter '{QUERY_PARAMETER}'"))?;
+        let mut search_request = self.defaults.with_query(query);
+        for (name, value) in request.parameters.iter().filter(|(name, _)| !reserved.contains(&name.as_str())) {
+            search_request.apply_flag(name, value)?;
+        }
+
+        Ok(search_request)
+    }
+}
+
+// NOTE: Every byte is compared, so the time taken doesn't tell how much of the key was right
+fn keys_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len() && expected.bytes()
+        .zip(actual.bytes())
+        .fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+fn parameter<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request.parameters.iter()
+        .find(|(parameter, _)| parameter == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// NOTE: Parameters after the content type, like the charset, are ignored
+fn document_extension(content_type: &str) -> Option<&'static str> {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    match media_type.as_str() {
+        "text/plain" => Some("txt"),
+        "text/html" | "application/xhtml+xml" => Some("html"),
+        "application/x-fictionbook+xml" | "application/x-fictionbook" => Some("fb2"),
+        _ => None
+    }
+}
+
+// NOTE: Only bodies with a length are read, chunked ones are refused
+fn bad_request(err: std::io::Error) -> Response {
+    Response::error(400, format!("Couldn't read the request. Error: {err}"))
+}
+
+// NOTE: Request line and headers, the body is left in the reader along with its length
+fn read_request_head(reader: &mut impl BufRead) -> Result<(Request, Option<usize>), Response> {
+
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(bad_request)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Response::error(400, "Malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let parameters = query.split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| {
+            let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+            Ok((decode_component(name)?, decode_component(value)?))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map_err(|err| Response::error(400, err.to_string()))?;
+
+    let mut content_type = None;
+    let mut content_length = None;
+    let mut api_key = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).map_err(bad_request)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-type" => content_type = Some(value.trim().to_owned()),
+            "x-api-key" => api_key = Some(value.trim().to_owned()),
+            "authorization" => api_key = value.trim().strip_prefix("Bearer ").map(|key| key.trim().to_owned()).or(api_key),
+            "content-length" => content_length = Some(value.trim().parse::<usize>()
+                .map_err(|_| Response::error(400, format!("Invalid content length \"{}\"", value.trim())))?),
+            _ => {}
+        }
+    }
+
+    let request = Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        parameters,
+        content_type,
+        api_key,
+        body: Vec::new()
+    };
+
+    Ok((request, content_length))
+}
+
+fn read_body(reader: &mut impl BufRead, method: &str, content_length: Option<usize>) -> Result<Vec<u8>, Response> {
+    match content_length {
+        Some(length) if length > MAX_BODY_SIZE => Err(Response::error(413, format!("Body can't be larger than {MAX_BODY_SIZE} bytes"))),
+        Some(length) => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).map_err(bad_request)?;
+
+            Ok(body)
+        },
+        None if method == "POST" => Err(Response::error(411, "Content-Length header is required")),
+        None => Ok(Vec::new())
+    }
+}
+
+// NOTE: Percent escapes are decoded as UTF-8, '+' stands for a space as in forms
+fn decode_component(component: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(component.len());
+    let mut iter = component.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [iter.next(), iter.next()];
+                let [Some(high), Some(low)] = hex else {
+                    return Err(anyhow!("Incomplete escape in \"{component}\""));
+                };
+                let hex = std::str::from_utf8(&[high, low]).ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| anyhow!("Invalid escape in \"{component}\""))?;
+                bytes.push(hex);
+            },
+            byte => bytes.push(byte)
+        }
+    }
+
+    String::from_utf8(bytes).context(anyhow!("Parameter \"{component}\" isn't valid UTF-8"))
+}
@@ -0,0 +1,179 @@
+use ahash::{AHashMap, AHashSet};
+use std::collections::BTreeSet;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use crate::document::DocumentId;
+use crate::segment::TermPosition;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Posting {
+    pub count: usize,
+    pub boost: f64,
+    // NOTE: Word offsets inside the zone, indexes written before positions were recorded have none
+    #[serde(default)]
+    pub positions: Vec<usize>
+}
+
+impl Posting {
+    pub fn new(count: usize) -> Self {
+        Posting { count, boost: 1.0, positions: Vec::new() }
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.boost * (1.0 + (self.count as f64).ln())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(from = "Vec<(TermPosition, Posting)>", into = "Vec<(TermPosition, Posting)>")]
+#[derive(Clone, PartialEq, Debug)]
+pub struct TermFrequencies {
+    frequencies: AHashMap<TermPosition, Posting>
+}
+
+impl TermFrequencies {
+    pub fn new() -> Self {
+        TermFrequencies { frequencies: AHashMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frequencies.is_empty()
+    }
+
+    pub fn count(&self, term_position: TermPosition) -> usize {
+        self.frequencies.get(&term_position)
+            .map(|posting| posting.count)
+            .unwrap_or(0)
+    }
+
+    pub fn add_position(&mut self, term_position: TermPosition, offset: usize) {
+        let posting = self.frequencies.entry(term_position)
+            .or_insert_with(|| Posting::new(0));
+        posting.count += 1;
+        posting.positions.push(offset);
+    }
+
+    pub fn get(&self, term_position: TermPosition) -> Option<&Posting> {
+        self.frequencies.get(&term_position)
+    }
+
+    pub fn merge(&mut self, mut other: Self) {
+        other.frequencies.drain()
+            .for_each(|(term_position, mut posting)| {
+                self.frequencies.entry(term_position)
+                    .and_modify(|existing| {
+                        existing.count += posting.count;
+                        existing.positions.append(&mut posting.positions);
+                    })
+                    .or_insert(posting);
+            });
+    }
+
+    pub fn remove_documents(&mut self, removed: &AHashSet<DocumentId>) {
+        self.frequencies.retain(|term_position, _| !removed.contains(&term_position.document));
+    }
+
+    pub fn apply_boost(&mut self, boost: impl Fn(TermPosition) -> f64) {
+        self.frequencies.iter_mut()
+            .for_each(|(&term_position, posting)| posting.boost = boost(term_position));
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.frequencies.shrink_to_fit();
+        self.frequencies.values_mut()
+            .for_each(|posting| posting.positions.shrink_to_fit());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TermPosition, &Posting)> {
+        self.frequencies.iter()
+    }
+
+    // NOTE: Keeps positions of both sides that are at most `left` words before or `right` words
+    //  after each other, only within the same zone of a document
+    pub fn close_union(&self, other: &Self, left: usize, right: usize) -> TermFrequencies {
+        let frequencies = self.frequencies.iter()
+            .filter_map(|(&term_position, posting)| other.frequencies.get(&term_position).map(|other_posting| (term_position, posting, other_posting)))
+            .filter_map(|(term_position, posting, other_posting)| {
+                let mut positions = BTreeSet::new();
+                for &position in &posting.positions {
+                    let close = other_posting.positions.iter()
+                        .filter(|&&other| other + left >= position && other <= position + right)
+                        .collect::<Vec<_>>();
+                    if !close.is_empty() {
+                        positions.insert(position);
+                        positions.extend(close);
+                    }
+                }
+
+                (!positions.is_empty()).then(|| (term_position, Posting {
+                    count: positions.len(),
+                    boost: posting.boost,
+                    positions: positions.into_iter().collect()
+                }))
+            })
+            .collect();
+
+        TermFrequencies { frequencies }
+    }
+
+    // NOTE: Positions of `other` directly after a position of `self` that aren't part of `self`,
+    //  for a phrase those are the words right after its last word
+    pub fn following(&self, other: &Self) -> TermFrequencies {
+        let frequencies = self.frequencies.iter()
+            .filter_map(|(&term_position, posting)| other.frequencies.get(&term_position).map(|other_posting| (term_position, posting, other_posting)))
+            .filter_map(|(term_position, posting, other_posting)| {
+                let positions = other_posting.positions.iter()
+                    .filter(|&&other| other > 0 && posting.positions.contains(&(other - 1)) && !posting.positions.contains(&other))
+                    .cloned()
+                    .collect::<BTreeSet<_>>();
+
+                (!positions.is_empty()).then(|| (term_position, Posting {
+                    count: positions.len(),
+                    boost: other_posting.boost,
+                    positions: positions.into_iter().collect()
+                }))
+            })
+            .collect();
+
+        TermFrequencies { frequencies }
+    }
+
+    // NOTE: Drops zones of documents that `other` doesn't contain
+    pub fn restrict_to(mut self, other: &Self) -> TermFrequencies {
+        self.frequencies.retain(|term_position, _| other.frequencies.contains_key(term_position));
+
+        self
+    }
+
+    // NOTE: Positions found on both sides are counted once
+    pub fn union(&self, other: &Self) -> TermFrequencies {
+        let mut frequencies = self.frequencies.clone();
+        for (&term_position, other_posting) in &other.frequencies {
+            let posting = frequencies.entry(term_position)
+                .or_insert_with(|| Posting { count: 0, boost: other_posting.boost, positions: Vec::new() });
+            let positions = posting.positions.iter()
+                .chain(other_posting.positions.iter())
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            posting.count = positions.len();
+            posting.positions = positions.into_iter().collect();
+        }
+
+        TermFrequencies { frequencies }
+    }
+}
+
+impl From<Vec<(TermPosition, Posting)>> for TermFrequencies {
+    fn from(frequencies: Vec<(TermPosition, Posting)>) -> Self {
+        TermFrequencies { frequencies: frequencies.into_iter().collect() }
+    }
+}
+
+impl From<TermFrequencies> for Vec<(TermPosition, Posting)> {
+    fn from(frequencies: TermFrequencies) -> Self {
+        frequencies.frequencies.into_iter()
+            .sorted_by_key(|(term_position, _)| *term_position)
+            .collect()
+    }
+}
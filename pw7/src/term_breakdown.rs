@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Context, Result};
+use std::str::FromStr;
+use itertools::Itertools;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::segment::{SegmentKind, TermPosition};
+use crate::term_index::TermIndex;
+
+// NOTE: Without listed terms, the terms of the last query are broken down
+pub fn term_breakdown(args: &str, last_terms: &[String], index: &dyn TermIndex, ctx: &InfContext) -> Result<()> {
+    let mut args = args.split_whitespace();
+    let document_id = args.next()
+        .map(usize::from_str)
+        .context("Expected document id")?
+        .context("Invalid document id")?;
+    let document_id = DocumentId(document_id);
+    let document = ctx.document(document_id)
+        .context(anyhow!("Document with id {document_id} doesn't exist"))?;
+
+    let mut terms = args.map(str::to_lowercase).collect::<Vec<_>>();
+    if terms.is_empty() {
+        terms = last_terms.to_vec();
+    }
+    if terms.is_empty() {
+        return Err(anyhow!("Expected terms, there is no previous query to take them from"));
+    }
+
+    println!("{} {}:", document_id, document.name());
+    for term in terms.iter().unique() {
+        let segments = SegmentKind::values().iter()
+            .filter_map(|&segment_kind| {
+                index.posting(term, TermPosition { document: document_id, segment_kind })
+                    .map(|posting| (segment_kind, posting))
+            })
+            .collect::<Vec<_>>();
+        let total = segments.iter()
+            .map(|(_, posting)| posting.count)
+            .sum::<usize>();
+
+        let segments_str = segments.iter()
+            .map(|(segment_kind, posting)| {
+                format!("{:?}: {} @ {}", segment_kind, posting.count, posting.positions.iter().sorted().join(", "))
+            })
+            .join("; ");
+        if segments_str.is_empty() {
+            println!("\t{term}: {total}");
+        } else {
+            println!("\t{term}: {total} ({segments_str})");
+        }
+    }
+
+    Ok(())
+}
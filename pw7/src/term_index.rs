@@ -1,72 +1,141 @@
 use anyhow::{anyhow, Result};
 use ahash::{AHashMap, AHashSet};
-use std::io::{BufRead, Write};
-use std::str::FromStr;
 use itertools::Itertools;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use crate::document::DocumentId;
+use crate::kgram_index::KGramIndex;
 use crate::query_lang::LogicNode;
 use crate::segment::TermPosition;
+use crate::term::{Posting, TermFrequencies};
+
+// NOTE: Term a wildcard stood for and how many times it followed the phrase before the wildcard,
+//  or how many times it occurs for a wildcard inside a term
+#[derive(Serialize)]
+pub struct Expansion {
+    pub term: String,
+    pub count: usize
+}
 
 pub trait TermIndex {
-    fn add_term(&mut self, term: String, term_position: TermPosition);
-    fn query(&self, query_ast: &LogicNode) -> Result<AHashSet<TermPosition>>;
+    fn add_term(&mut self, term: String, term_position: TermPosition, offset: usize);
+    fn query(&self, query_ast: &LogicNode) -> Result<(TermFrequencies, Vec<Expansion>)>;
+    fn posting(&self, term: &str, term_position: TermPosition) -> Option<&Posting>;
+    fn document_length(&self, document: DocumentId) -> usize;
+    fn average_document_length(&self) -> f64;
+    fn suggestions(&self, term: &str, count: usize) -> Vec<String>;
 }
 
 #[derive(Debug)]
-#[derive(Eq, PartialEq)]
+#[derive(PartialEq)]
 #[derive(Serialize, Deserialize)]
 pub struct InvertedIndex {
+    // NOTE: Number of terms of every document across all of its zones
+    #[serde(skip)]
+    documents: AHashMap<DocumentId, usize>,
+    #[serde(flatten, serialize_with = "serialize_sorted")]
+    index: AHashMap<String, TermFrequencies>,
+    // NOTE: Built once indexing is done, terms added afterwards aren't found by wildcards
     #[serde(skip)]
-    documents: AHashSet<DocumentId>,
-    #[serde(flatten)]
-    index: AHashMap<String, AHashSet<TermPosition>>
+    kgrams: KGramIndex
+}
+
+// NOTE: Terms are written sorted, so the same corpus always produces the same file
+fn serialize_sorted<S: Serializer>(index: &AHashMap<String, TermFrequencies>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(index.iter().sorted_by_key(|(term, _)| *term))
 }
 
 impl InvertedIndex {
     pub fn new() -> Self {
         InvertedIndex {
-            documents: AHashSet::new(),
-            index: AHashMap::new()
+            documents: AHashMap::new(),
+            index: AHashMap::new(),
+            kgrams: KGramIndex::default()
         }
     }
 
     pub fn shrink_to_fit(&mut self) {
         self.documents.shrink_to_fit();
         self.index.shrink_to_fit();
+        self.index.values_mut()
+            .for_each(TermFrequencies::shrink_to_fit);
+    }
+
+    pub fn apply_boost(&mut self, boost: impl Fn(TermPosition) -> f64) {
+        self.index.values_mut()
+            .for_each(|frequencies| frequencies.apply_boost(&boost));
+    }
+
+    pub fn build_kgram_index(&mut self, k: usize) -> usize {
+        self.kgrams = KGramIndex::build(k, self.terms());
+
+        self.kgrams.gram_count()
+    }
+
+    pub fn kgram_length(&self) -> usize {
+        self.kgrams.k()
+    }
+
+    pub fn terms(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(String::as_str)
+    }
+
+    pub fn contains_term(&self, term: &str) -> bool {
+        self.index.contains_key(term)
     }
 
     pub fn unique_word_count(&self) -> usize {
         self.index.len()
     }
 
-    pub fn term_positions(&self, term: &str) -> AHashSet<TermPosition> {
+    pub fn term_frequencies(&self, term: &str) -> TermFrequencies {
         self.index.get(term)
             .cloned()
-            .unwrap_or_else(AHashSet::new)
+            .unwrap_or_else(TermFrequencies::new)
     }
 
-    fn documents(&self) -> &AHashSet<DocumentId> {
+    pub fn documents(&self) -> &AHashMap<DocumentId, usize> {
         &self.documents
     }
 
     pub fn merge(&mut self, mut other: Self) {
         other.index.drain()
-            .for_each(|(term, positions)| self.merge_term_positions(term, positions));
+            .for_each(|(term, frequencies)| self.merge_term_frequencies(term, frequencies));
+    }
+
+    // NOTE: Terms left without documents are dropped, the k-gram index has to be built again afterwards
+    pub fn remove_documents(&mut self, removed: &AHashSet<DocumentId>) {
+        if removed.is_empty() {
+            return;
+        }
+
+        self.documents.retain(|document_id, _| !removed.contains(document_id));
+        self.index.retain(|_, frequencies| {
+            frequencies.remove_documents(removed);
+            !frequencies.is_empty()
+        });
     }
 
-    fn merge_term_positions(&mut self, term: String, positions: AHashSet<TermPosition>) {
-        self.documents.extend(positions.iter().map(|position| position.document));
+    fn merge_term_frequencies(&mut self, term: String, frequencies: TermFrequencies) {
+        for (position, posting) in frequencies.iter() {
+            *self.documents.entry(position.document).or_default() += posting.count;
+        }
 
         self.index.entry(term)
-            .or_insert_with(AHashSet::new)
-            .extend(positions);
+            .or_insert_with(TermFrequencies::new)
+            .merge(frequencies);
     }
 
-    fn query_rec(&self, query_ast: &LogicNode) -> Result<AHashSet<TermPosition>> {
+    fn query_rec(&self, query_ast: &LogicNode, expansions: &mut Vec<Expansion>) -> Result<TermFrequencies> {
         Ok(match query_ast {
-            LogicNode::False => AHashSet::new(),
-            LogicNode::Term(term) => self.term_positions(term),
+            LogicNode::False => TermFrequencies::new(),
+            LogicNode::Term(term) => self.term_frequencies(term),
+            LogicNode::Pattern(pattern) => self.expand_pattern(pattern, expansions),
+            LogicNode::Near(lhs, rhs, _, _) if matches!(**rhs, LogicNode::Wildcard) => {
+                self.expand_wildcard(&self.query_rec(lhs, expansions)?, expansions)
+            },
+            LogicNode::Near(lhs, rhs, left, right) => {
+                self.query_rec(lhs, expansions)?.close_union(&self.query_rec(rhs, expansions)?, *left, *right)
+            },
             _ => {
                 return Err(anyhow!("Operation not supported."));
             }
@@ -74,16 +143,93 @@ impl InvertedIndex {
     }
 }
 
+impl InvertedIndex {
+    // NOTE: Every term is checked against the prefix, so the cost grows with the dictionary
+    fn expand_wildcard(&self, prefix: &TermFrequencies, expansions: &mut Vec<Expansion>) -> TermFrequencies {
+        let mut result = TermFrequencies::new();
+        if prefix.is_empty() {
+            return result;
+        }
+
+        let mut found = Vec::new();
+        for (term, frequencies) in &self.index {
+            let following = prefix.following(frequencies);
+            if following.is_empty() {
+                continue;
+            }
+
+            found.push(Expansion {
+                term: term.clone(),
+                count: following.iter().map(|(_, posting)| posting.count).sum()
+            });
+            result = result.union(&following);
+        }
+        found.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+        expansions.extend(found);
+
+        prefix.union(&result).restrict_to(&result)
+    }
+
+    // NOTE: Any number of '*' anywhere in the term, matching terms are looked up in the k-gram index
+    fn expand_pattern(&self, pattern: &str, expansions: &mut Vec<Expansion>) -> TermFrequencies {
+        let mut result = TermFrequencies::new();
+        let mut found = Vec::new();
+        for term in self.kgrams.matching(pattern) {
+            let Some(frequencies) = self.index.get(term) else {
+                continue;
+            };
+
+            found.push(Expansion {
+                term: term.to_owned(),
+                count: frequencies.iter().map(|(_, posting)| posting.count).sum()
+            });
+            result = result.union(frequencies);
+        }
+        found.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+        expansions.extend(found);
+
+        result
+    }
+}
+
 impl TermIndex for InvertedIndex {
-    fn add_term(&mut self, term: String, term_position: TermPosition) {
+    fn add_term(&mut self, term: String, term_position: TermPosition, offset: usize) {
         self.index.entry(term)
-            .or_insert_with(AHashSet::new)
-            .insert(term_position);
+            .or_insert_with(TermFrequencies::new)
+            .add_position(term_position, offset);
 
-        self.documents.insert(term_position.document);
+        *self.documents.entry(term_position.document).or_default() += 1;
     }
 
-    fn query(&self, query_ast: &LogicNode) -> Result<AHashSet<TermPosition>> {
-        self.query_rec(query_ast)
+    fn query(&self, query_ast: &LogicNode) -> Result<(TermFrequencies, Vec<Expansion>)> {
+        let mut expansions = Vec::new();
+        let result = self.query_rec(query_ast, &mut expansions)?;
+
+        Ok((result, expansions))
+    }
+
+    fn posting(&self, term: &str, term_position: TermPosition) -> Option<&Posting> {
+        self.index.get(term)?.get(term_position)
+    }
+
+    fn document_length(&self, document: DocumentId) -> usize {
+        self.documents.get(&document)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    fn average_document_length(&self) -> f64 {
+        self.documents.values().sum::<usize>() as f64 / self.documents.len().max(1) as f64
+    }
+
+    // NOTE: Only for terms the index doesn't have, a known term is spelled the way the corpus spells it
+    fn suggestions(&self, term: &str, count: usize) -> Vec<String> {
+        if self.index.contains_key(term) {
+            return Vec::new();
+        }
+
+        self.kgrams.suggestions(term, count).into_iter()
+            .map(|(term, _)| term.to_owned())
+            .collect()
     }
 }
@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Context, Result};
+use ahash::{AHashMap, AHashSet};
+use std::str::FromStr;
+use crate::segment::SegmentKind;
+
+pub type Flags = Vec<(String, String)>;
+
+#[derive(Clone, Debug)]
+pub struct ZoneOptions {
+    weights: AHashMap<SegmentKind, f64>,
+    zones: Option<AHashSet<SegmentKind>>
+}
+
+impl ZoneOptions {
+    const ZONES_FLAG: &'static str = "zones";
+    const ZONE_WEIGHT_FLAG: &'static str = "zone-weight";
+
+    pub fn new() -> Self {
+        ZoneOptions {
+            weights: SegmentKind::values().iter()
+                .map(|&segment_kind| (segment_kind, Self::default_weight(segment_kind)))
+                .collect(),
+            zones: None
+        }
+    }
+
+    fn default_weight(segment_kind: SegmentKind) -> f64 {
+        match segment_kind {
+            SegmentKind::Filename => 0.2,
+            SegmentKind::Authors => 0.1,
+            SegmentKind::Title => 0.4,
+            SegmentKind::Epigraph => 0.1,
+            SegmentKind::Body => 0.2
+        }
+    }
+
+    pub fn allows(&self, segment_kind: SegmentKind) -> bool {
+        self.zones.as_ref()
+            .map(|zones| zones.contains(&segment_kind))
+            .unwrap_or(true)
+    }
+
+    pub fn weight(&self, segment_kind: SegmentKind) -> f64 {
+        if !self.allows(segment_kind) {
+            return 0.0;
+        }
+
+        self.weights.get(&segment_kind)
+            .cloned()
+            .unwrap_or_else(|| Self::default_weight(segment_kind))
+    }
+
+    pub fn apply_flag(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            Self::ZONES_FLAG => {
+                let zones = value.split(',')
+                    .map(SegmentKind::from_str)
+                    .collect::<Result<AHashSet<_>>>()?;
+
+                self.zones = Some(zones);
+            },
+            Self::ZONE_WEIGHT_FLAG => {
+                for pair in value.split(',') {
+                    let (segment_kind, weight) = pair.split_once('=')
+                        .ok_or_else(|| anyhow!("Expected zone weight in form 'zone=weight', got '{pair}'"))?;
+                    let segment_kind = SegmentKind::from_str(segment_kind)?;
+                    let weight = f64::from_str(weight).context(anyhow!("Invalid weight '{weight}'"))?;
+
+                    self.weights.insert(segment_kind, weight);
+                }
+            },
+            _ => return Err(anyhow!("Unknown flag '--{name}'"))
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ZoneOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn split_flags(mut input: &str) -> Result<(Flags, &str)> {
+    let mut flags = Vec::new();
+    loop {
+        input = input.trim_start();
+        let Some(rest) = input.strip_prefix("--") else {
+            break;
+        };
+
+        let (name, rest) = split_word(rest);
+        let (value, rest) = split_word(rest.trim_start());
+        if value.is_empty() {
+            return Err(anyhow!("Missing value for flag '--{name}'"));
+        }
+
+        flags.push((name.to_owned(), value.to_owned()));
+        input = rest;
+    }
+
+    Ok((flags, input))
+}
+
+fn split_word(input: &str) -> (&str, &str) {
+    input.split_at(input.find(char::is_whitespace).unwrap_or(input.len()))
+}
@@ -0,0 +1,29 @@
+#ifndef IR_H
+#define IR_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct IrEngine IrEngine;
+
+IrEngine* ir_engine_new(void);
+
+/* Adds a UTF-8 document of `length` bytes, returns 0 on success and -1 otherwise. */
+int ir_engine_add_document(IrEngine* engine, const char* name, const char* data, size_t length);
+
+/* Runs a ranked query and returns JSON, either {"results": [...]} or {"error": "..."}.
+   Zero `limit` returns every result. The string must be released with ir_string_free. */
+char* ir_engine_query(IrEngine* engine, const char* query, size_t limit);
+
+void ir_string_free(char* text);
+
+void ir_engine_free(IrEngine* engine);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
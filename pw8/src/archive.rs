@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use crate::corpus::{Corpus, CorpusDocument, CorpusEntry, UnreadableDocument};
+
+const BLOCK_SIZE: usize = 512;
+const NAME: (usize, usize) = (0, 100);
+const SIZE: (usize, usize) = (124, 136);
+const TYPE_FLAG: usize = 156;
+const MAGIC: (usize, usize) = (257, 262);
+const PREFIX: (usize, usize) = (345, 500);
+const REGULAR_FILE: [u8; 2] = [b'0', 0];
+// NOTE: GNU tar stores names longer than 100 bytes as an entry of its own, the name of the next one
+const LONG_NAME: u8 = b'L';
+
+/// Regular files of an uncompressed tar archive, named by the archive path and their path inside it.
+///
+/// Folders, links and other entries are left out, files that aren't valid UTF-8 are skipped.
+pub struct ArchiveCorpus {
+    path: PathBuf
+}
+
+impl ArchiveCorpus {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        ArchiveCorpus {
+            path: path.as_ref().to_owned()
+        }
+    }
+}
+
+impl Corpus for ArchiveCorpus {
+    fn name(&self) -> String {
+        format!("archive {:?}", self.path)
+    }
+
+    fn documents(self: Box<Self>) -> Result<Box<dyn Iterator<Item = CorpusEntry>>> {
+        let file = fs::File::open(&self.path).context(format!("Couldn't open archive {:?}", self.path))?;
+
+        Ok(Box::new(ArchiveEntries {
+            reader: BufReader::new(file),
+            archive: self.path,
+            done: false
+        }))
+    }
+}
+
+struct ArchiveEntries {
+    reader: BufReader<fs::File>,
+    archive: PathBuf,
+    done: bool
+}
+
+impl ArchiveEntries {
+    // NOTE: Next regular file with its name, None at the two zero blocks ending the archive
+    fn next_file(&mut self) -> Result<Option<(String, Vec<u8>)>> {
+        let mut long_name = None;
+        loop {
+            let mut header = [0; BLOCK_SIZE];
+            if !self.read_block(&mut header)? || header.iter().all(|&byte| byte == 0) {
+                return Ok(None);
+            }
+
+            let size = parse_octal(&header[SIZE.0..SIZE.1])?;
+            let mut data = vec![0; size];
+            self.reader.read_exact(&mut data).context("Archive ends inside of an entry")?;
+            let padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+            self.reader.read_exact(&mut vec![0; padding]).context("Archive ends inside of an entry")?;
+
+            let type_flag = header[TYPE_FLAG];
+            if type_flag == LONG_NAME {
+                long_name = Some(field(&data).to_owned());
+                continue;
+            }
+            if !REGULAR_FILE.contains(&type_flag) {
+                long_name = None;
+                continue;
+            }
+
+            let name = match long_name.take() {
+                Some(name) => name,
+                None => {
+                    let name = field(&header[NAME.0..NAME.1]);
+                    let prefix = if header[MAGIC.0..MAGIC.1] == *b"ustar" { field(&header[PREFIX.0..PREFIX.1]) } else { "" };
+                    if prefix.is_empty() { name.to_owned() } else { format!("{prefix}/{name}") }
+                }
+            };
+
+            return Ok(Some((name, data)));
+        }
+    }
+
+    // NOTE: Some writers leave out the end of archive blocks, so end of file before a header also ends the archive
+    fn read_block(&mut self, block: &mut [u8; BLOCK_SIZE]) -> Result<bool> {
+        let mut read = 0;
+        while read < BLOCK_SIZE {
+            match self.reader.read(&mut block[read..])? {
+                0 if read == 0 => return Ok(false),
+                0 => return Err(anyhow!("Archive ends inside of a header")),
+                count => read += count
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl Iterator for ArchiveEntries {
+    type Item = CorpusEntry;
+
+    // NOTE: A malformed header ends the archive, since the position of the next entry isn't known
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.next_file() {
+            Ok(Some((name, data))) => {
+                let name = self.archive.join(name);
+                Some(match String::from_utf8(data) {
+                    Ok(text) => Ok(CorpusDocument::buffer(name, text)),
+                    Err(err) => Err(UnreadableDocument::new(name, err.into()))
+                })
+            },
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(err) => {
+                self.done = true;
+                Some(Err(UnreadableDocument::new(&self.archive, err)))
+            }
+        }
+    }
+}
+
+// NOTE: Text fields end at the first zero byte or fill the whole field
+fn field(bytes: &[u8]) -> &str {
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+
+    std::str::from_utf8(&bytes[..end]).unwrap_or_default()
+}
+
+fn parse_octal(bytes: &[u8]) -> Result<usize> {
+    let text = field(bytes).trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+
+    usize::from_str_radix(text, 8).context(format!("Invalid entry size \"{text}\""))
+}
@@ -0,0 +1,71 @@
+use std::io::Write;
+use crate::error::{Result, StorageError};
+
+// NOTE: Little endian fixed width values, lengths are written before strings and lists
+pub fn write_u64(writer: &mut impl Write, value: u64) -> Result<(), StorageError> {
+    writer.write_all(&value.to_le_bytes())?;
+
+    Ok(())
+}
+
+pub fn write_usize(writer: &mut impl Write, value: usize) -> Result<(), StorageError> {
+    write_u64(writer, value as u64)
+}
+
+pub fn write_f64(writer: &mut impl Write, value: f64) -> Result<(), StorageError> {
+    write_u64(writer, value.to_bits())
+}
+
+pub fn write_str(writer: &mut impl Write, value: &str) -> Result<(), StorageError> {
+    write_usize(writer, value.len())?;
+    writer.write_all(value.as_bytes())?;
+
+    Ok(())
+}
+
+pub struct BinaryReader<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> BinaryReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BinaryReader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn take(&mut self, length: usize) -> Result<&'a [u8], StorageError> {
+        if self.data.len() < length {
+            return Err(StorageError::UnexpectedEnd { expected: length, found: self.data.len() });
+        }
+
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+
+        Ok(taken)
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StorageError> {
+        let bytes = self.take(size_of::<u64>())?;
+
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, StorageError> {
+        let value = self.read_u64()?;
+
+        usize::try_from(value).map_err(|_| StorageError::Malformed(format!("{value} doesn't fit into usize")))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, StorageError> {
+        Ok(f64::from_bits(self.read_u64()?))
+    }
+
+    pub fn read_str(&mut self) -> Result<&'a str, StorageError> {
+        let length = self.read_usize()?;
+
+        std::str::from_utf8(self.take(length)?).map_err(|err| StorageError::Malformed(err.to_string()))
+    }
+}
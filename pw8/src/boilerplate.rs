@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use std::io::BufRead;
+use serde::{Deserialize, Serialize};
+
+// NOTE: Headers and license footers are never further than this from the start/end of a file
+const SEARCH_WINDOW: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize)]
+pub struct BoilerplateFilter {
+    start_markers: Vec<String>,
+    end_markers: Vec<String>
+}
+
+impl BoilerplateFilter {
+    const START_PREFIX: &'static str = "start:";
+    const END_PREFIX: &'static str = "end:";
+
+    pub fn new(start_markers: Vec<String>, end_markers: Vec<String>) -> Self {
+        BoilerplateFilter {
+            start_markers: start_markers.iter().map(|marker| marker.to_ascii_lowercase()).collect(),
+            end_markers: end_markers.iter().map(|marker| marker.to_ascii_lowercase()).collect()
+        }
+    }
+
+    pub fn gutenberg() -> Self {
+        Self::new(
+            vec![
+                "*** start of the project gutenberg ebook".to_owned(),
+                "*** start of this project gutenberg ebook".to_owned()
+            ],
+            vec![
+                "*** end of the project gutenberg ebook".to_owned(),
+                "*** end of this project gutenberg ebook".to_owned()
+            ]
+        )
+    }
+
+    pub fn load(reader: impl BufRead) -> Result<Self> {
+        let mut start_markers = Vec::new();
+        let mut end_markers = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (markers, marker) = if let Some(marker) = line.strip_prefix(Self::START_PREFIX) {
+                (&mut start_markers, marker)
+            } else if let Some(marker) = line.strip_prefix(Self::END_PREFIX) {
+                (&mut end_markers, marker)
+            } else {
+                return Err(anyhow!("Expected line to start with \"{}\" or \"{}\"", Self::START_PREFIX, Self::END_PREFIX));
+            };
+            // NOTE: An empty marker is found at the very start of every text, it would strip whole documents
+            let marker = marker.trim();
+            if marker.is_empty() {
+                return Err(anyhow!("Marker of line \"{line}\" is empty"));
+            }
+            markers.push(marker.to_owned());
+        }
+
+        Ok(Self::new(start_markers, end_markers))
+    }
+
+    pub fn strip<'a>(&self, text: &'a str) -> &'a str {
+        let start = self.find_start(text);
+        let end = self.find_end(&text[start..]) + start;
+
+        &text[start..end]
+    }
+
+    fn find_start(&self, text: &str) -> usize {
+        let window_end = char_boundary(text, SEARCH_WINDOW.min(text.len()));
+        let window = text[..window_end].to_ascii_lowercase();
+
+        self.start_markers.iter()
+            .filter_map(|marker| window.find(marker))
+            .min()
+            .map(|offset| text[offset..].find('\n').map(|line_end| offset + line_end + 1).unwrap_or(text.len()))
+            .unwrap_or(0)
+    }
+
+    fn find_end(&self, text: &str) -> usize {
+        let window_start = char_boundary(text, text.len().saturating_sub(SEARCH_WINDOW));
+        let window = text[window_start..].to_ascii_lowercase();
+
+        self.end_markers.iter()
+            .filter_map(|marker| window.find(marker))
+            .min()
+            .map(|offset| window_start + offset)
+            .map(|offset| text[..offset].rfind('\n').map(|line_start| line_start + 1).unwrap_or(0))
+            .unwrap_or(text.len())
+    }
+}
+
+impl Default for BoilerplateFilter {
+    fn default() -> Self {
+        Self::gutenberg()
+    }
+}
+
+fn char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+
+    index
+}
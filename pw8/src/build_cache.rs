@@ -0,0 +1,126 @@
+use anyhow::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::binary::{self, BinaryReader};
+use crate::document::DocumentId;
+use crate::error::StorageError;
+use crate::lexer::{self, LexerStats};
+use crate::persist;
+use crate::stopwords::Stopwords;
+use crate::term_index::InvertedIndex;
+
+// NOTE: Bumped whenever the entry format changes, lexer changes are covered by the analyzer fingerprint
+const CACHE_VERSION: u32 = 1;
+const ENTRY_EXTENSION: &str = "part";
+const ENTRY_MAGIC: &[u8] = b"PW8PART1";
+
+// NOTE: Partial indexes of single files, keyed by a hash of the text the lexer sees and of the analyzer.
+//  Boilerplate is stripped before hashing, so changing its markers only misses for files it actually changes.
+//  Entries don't keep document ids, those change whenever a file is added to or removed from the corpus
+pub struct BuildCache {
+    folder: PathBuf,
+    analyzer_hash: u64,
+    hits: AtomicUsize,
+    misses: AtomicUsize
+}
+
+impl BuildCache {
+    pub fn open(folder: impl AsRef<Path>, stopwords: &Stopwords) -> Result<Self> {
+        let folder = folder.as_ref().to_owned();
+        fs::create_dir_all(&folder)?;
+
+        let mut hasher = DefaultHasher::new();
+        CACHE_VERSION.hash(&mut hasher);
+        lexer::analyzer_fingerprint(stopwords).hash(&mut hasher);
+
+        Ok(BuildCache {
+            folder,
+            analyzer_hash: hasher.finish(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0)
+        })
+    }
+
+    fn entry_path(&self, text: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        self.analyzer_hash.hash(&mut hasher);
+        text.hash(&mut hasher);
+
+        self.folder.join(format!("{:016x}", hasher.finish())).with_extension(ENTRY_EXTENSION)
+    }
+
+    // NOTE: Unreadable or corrupted entries count as misses, the file is indexed again and the entry rewritten
+    pub fn get(&self, text: &str, document_id: DocumentId) -> Option<(InvertedIndex, LexerStats)> {
+        let entry = persist::load_checked(self.entry_path(text)).ok()
+            .and_then(|data| Self::read_entry(&data, document_id).ok());
+        let counter = if entry.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        entry
+    }
+
+    // NOTE: A cache that can't be written only makes the next build slower, so errors are reported and ignored
+    pub fn put(&self, text: &str, document_id: DocumentId, index: &InvertedIndex, stats: &LexerStats) {
+        let path = self.entry_path(text);
+        let result = persist::save_checked(&path, |writer| Ok(Self::write_entry(writer, document_id, index, stats)?));
+        if let Err(err) = result {
+            println!("Couldn't write build cache entry {:?}. Error: {}. Caused by: {}", path, err, err.root_cause());
+        }
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn write_entry(writer: &mut impl std::io::Write, document_id: DocumentId, index: &InvertedIndex, stats: &LexerStats) -> Result<(), StorageError> {
+        writer.write_all(ENTRY_MAGIC)?;
+        for value in [stats.characters_read, stats.characters_ignored, stats.lines, stats.tokens_truncated, stats.tokens_dropped, stats.stopwords_removed] {
+            binary::write_usize(writer, value)?;
+        }
+
+        let terms = index.document_terms(document_id)
+            .filter(|&(_, count)| count != 0)
+            .collect::<Vec<_>>();
+        binary::write_usize(writer, terms.len())?;
+        for (term, count) in terms {
+            binary::write_str(writer, term)?;
+            binary::write_usize(writer, count)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_entry(data: &[u8], document_id: DocumentId) -> Result<(InvertedIndex, LexerStats), StorageError> {
+        let mut reader = BinaryReader::new(data);
+        if reader.take(ENTRY_MAGIC.len())? != ENTRY_MAGIC {
+            return Err(StorageError::Malformed("Not a build cache entry".to_owned()));
+        }
+
+        let stats = LexerStats {
+            characters_read: reader.read_usize()?,
+            characters_ignored: reader.read_usize()?,
+            lines: reader.read_usize()?,
+            tokens_truncated: reader.read_usize()?,
+            tokens_dropped: reader.read_usize()?,
+            stopwords_removed: reader.read_usize()?
+        };
+
+        let mut index = InvertedIndex::new();
+        for _ in 0..reader.read_usize()? {
+            let term = reader.read_str()?.to_owned();
+            index.add_term_with_count(term, document_id, reader.read_usize()?);
+        }
+        if !reader.is_empty() {
+            return Err(StorageError::TrailingData);
+        }
+
+        Ok((index, stats))
+    }
+}
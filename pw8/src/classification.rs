@@ -0,0 +1,69 @@
+use anyhow::{anyhow, Result};
+use std::io::BufRead;
+use std::path::Path;
+use ahash::AHashMap;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::term_index::InvertedIndex;
+
+// NOTE: One "document<TAB>class" per line, document is either its full path or file name
+pub struct Labels {
+    labels: AHashMap<String, String>
+}
+
+impl Labels {
+    const SEPARATOR: char = '\t';
+
+    pub fn new() -> Self {
+        Labels { labels: AHashMap::new() }
+    }
+
+    pub fn load(reader: impl BufRead) -> Result<Self> {
+        let mut labels = Labels::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (document, class) = line.split_once(Self::SEPARATOR)
+                .ok_or_else(|| anyhow!("Expected document and class on line {}", i + 1))?;
+            labels.labels.insert(document.trim().to_owned(), class.trim().to_owned());
+        }
+
+        Ok(labels)
+    }
+
+    pub fn label(&self, document_name: &str) -> Option<&str> {
+        self.labels.get(document_name)
+            .or_else(|| {
+                let file_name = Path::new(document_name).file_name()?.to_string_lossy();
+                self.labels.get(file_name.as_ref())
+            })
+            .map(String::as_str)
+    }
+
+    // NOTE: Only documents that have both a label and a vector can be used for training
+    pub fn examples<'a>(&'a self, index: &InvertedIndex, ctx: &InfContext) -> Vec<(DocumentId, &'a str)> {
+        ctx.document_ids()
+            .filter(|&document_id| index.document_vector(document_id).is_some())
+            .filter_map(|document_id| {
+                let document = ctx.document(document_id)?;
+
+                self.label(&document.name()).map(|class| (document_id, class))
+            })
+            .collect()
+    }
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn accuracy<'a>(predictions: impl Iterator<Item = (Option<&'a str>, &'a str)>) -> (usize, usize) {
+    predictions.fold((0, 0), |(correct, total), (predicted, expected)| {
+        (correct + usize::from(predicted == Some(expected)), total + 1)
+    })
+}
@@ -0,0 +1,89 @@
+use ahash::AHashSet;
+use crate::term_index::{InvertedIndex, QueryResult};
+use crate::vector::{unit, SparseVector};
+use crate::keywords::{cluster_keywords, KeywordMethod, KEYWORD_COUNT};
+
+const MAX_ITERATIONS: usize = 20;
+
+pub struct ResultCluster {
+    pub label: Vec<String>,
+    pub documents: QueryResult
+}
+
+// NOTE: Spherical k-means, vectors are normalized so dot product is cosine similarity.
+//  Seeds are chosen farthest-first starting from the best result, so output is stable
+pub fn cluster_results(index: &InvertedIndex, result: &QueryResult, top_count: usize, cluster_count: usize, keywords: KeywordMethod) -> Vec<ResultCluster> {
+    let mut seen = AHashSet::new();
+    let documents = result.iter()
+        .filter(|(document_id, _)| seen.insert(*document_id))
+        .filter_map(|&(document_id, weight)| index.document_vector(document_id).map(|vector| ((document_id, weight), unit(vector))))
+        .take(top_count)
+        .collect::<Vec<_>>();
+    if documents.is_empty() {
+        return Vec::new();
+    }
+    let cluster_count = cluster_count.clamp(1, documents.len());
+
+    let mut centroids = vec![documents[0].1.clone()];
+    while centroids.len() < cluster_count {
+        let (_, farthest) = documents.iter()
+            .min_by(|(_, a), (_, b)| closest_similarity(a, &centroids).partial_cmp(&closest_similarity(b, &centroids)).unwrap())
+            .unwrap();
+        centroids.push(farthest.clone());
+    }
+
+    let mut assignment = Vec::new();
+    for _ in 0..MAX_ITERATIONS {
+        let new_assignment = documents.iter()
+            .map(|(_, vector)| nearest_centroid(vector, &centroids))
+            .collect::<Vec<_>>();
+        if new_assignment == assignment {
+            break;
+        }
+        assignment = new_assignment;
+
+        for (cluster, centroid) in centroids.iter_mut().enumerate() {
+            let members = documents.iter()
+                .zip(&assignment)
+                .filter(|(_, &assigned)| assigned == cluster)
+                .map(|((_, vector), _)| vector);
+            let sum = members.fold(SparseVector::new(), |sum, vector| &sum + vector);
+            if sum.magnitude_squared() != 0.0 {
+                *centroid = unit(&sum);
+            }
+        }
+    }
+
+    (0..cluster_count)
+        .map(|cluster| {
+            let documents = documents.iter()
+                .zip(&assignment)
+                .filter(|(_, &assigned)| assigned == cluster)
+                .map(|((document, _), _)| *document)
+                .collect::<Vec<_>>();
+
+            let members = documents.iter().map(|&(document_id, _)| document_id).collect::<Vec<_>>();
+
+            ResultCluster {
+                label: cluster_keywords(index, &members, keywords, KEYWORD_COUNT),
+                documents
+            }
+        })
+        .filter(|cluster| !cluster.documents.is_empty())
+        .collect()
+}
+
+fn closest_similarity(vector: &SparseVector, centroids: &[SparseVector]) -> f64 {
+    centroids.iter()
+        .map(|centroid| centroid.dot(vector))
+        .fold(f64::MIN, f64::max)
+}
+
+fn nearest_centroid(vector: &SparseVector, centroids: &[SparseVector]) -> usize {
+    centroids.iter()
+        .map(|centroid| centroid.dot(vector))
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(cluster, _)| cluster)
+        .unwrap_or(0)
+}
@@ -1,15 +1,36 @@
 use anyhow::Result;
+use std::path::PathBuf;
 use std::sync::Arc;
+use crate::build_cache::BuildCache;
 use crate::inf_context::InfContext;
 use crate::term_index::InvertedIndex;
 use crate::lexer::{Lexer, LexerStats};
 use crate::document::DocumentId;
+use crate::skipped::SkipStage;
+
+pub fn add_file_to_index(document_id: DocumentId, ctx: Arc<InfContext>, cache: Option<&BuildCache>) -> Result<Option<(InvertedIndex, LexerStats)>> {
+    // NOTE: Files are only read once they're indexed, so one that became unreadable is skipped like at opening
+    let data = match ctx.document_data(document_id) {
+        Ok(data) => data,
+        Err(err) => {
+            let path = ctx.document(document_id).map(|document| PathBuf::from(document.name())).unwrap_or_default();
+            ctx.skipped().record(path, SkipStage::Reading, Some(document_id), &err);
+            return Ok(None);
+        }
+    };
+
+    let text = ctx.strip_boilerplate(&data);
+    if let Some(cached) = cache.and_then(|cache| cache.get(text, document_id)) {
+        return Ok(Some(cached));
+    }
 
-pub fn add_file_to_index(document_id: DocumentId, ctx: Arc<InfContext>) -> Result<Option<(InvertedIndex, LexerStats)>> {
     let mut inverted_index = InvertedIndex::new();
-    let lexer = Lexer::new(document_id, ctx.document_data(document_id)?, &ctx)?;
+    let lexer = Lexer::new(document_id, text, &ctx);
     let stats = lexer.lex(&mut inverted_index);
     inverted_index.shrink_to_fit();
+    if let Some(cache) = cache {
+        cache.put(text, document_id, &inverted_index, &stats);
+    }
 
     Ok(Some((inverted_index, stats)))
 }
@@ -0,0 +1,115 @@
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::metrics::metrics;
+use crate::wal::{self, WriteAheadLog};
+use crate::{snapshot, PREPROCESS_LEADER_COUNT};
+use crate::term_index::DEFAULT_TIER_SIZE;
+
+pub const DEFAULT_COMPACTION_THRESHOLD: usize = 64;
+
+pub struct CompactionReport {
+    pub operations: usize,
+    pub skipped: usize,
+    pub time: Duration
+}
+
+// NOTE: Snapshot is the large segment and the write-ahead log the small one,
+//  compaction folds the log into a new snapshot built entirely from disk,
+//  so the index that serves queries is never locked for it
+pub fn compact(snapshot_path: &str, corpus_root: Option<&Path>, wal: &Mutex<WriteAheadLog>, progress: impl Fn(&str)) -> Result<CompactionReport> {
+    let (result, time) = metrics().time("compaction", || -> Result<(usize, usize)> {
+        progress("restoring snapshot");
+        let (mut loaded, snapshot_sequence) = snapshot::restore(snapshot_path, corpus_root)?;
+        let wal_path = wal.lock().unwrap().path().to_owned();
+        let entries = WriteAheadLog::read(&wal_path)?
+            .into_iter()
+            .filter(|entry| entry.sequence > snapshot_sequence)
+            .collect::<Vec<_>>();
+        let Some(sequence) = entries.last().map(|entry| entry.sequence) else {
+            return Ok((0, 0));
+        };
+
+        progress(&format!("replaying {} operations", entries.len()));
+        let skipped = wal::replay(&mut loaded, &entries);
+        loaded.index.preprocess(PREPROCESS_LEADER_COUNT, DEFAULT_TIER_SIZE);
+
+        progress("writing snapshot");
+        snapshot::save(&loaded, snapshot_path, sequence)?;
+        wal.lock().unwrap().retain_after(sequence)?;
+
+        Ok((entries.len(), skipped))
+    });
+    let (operations, skipped) = result?;
+    metrics().add("operations_compacted", operations as u64);
+
+    Ok(CompactionReport { operations, skipped, time })
+}
+
+// NOTE: Budget is a single background thread, a compaction only starts
+//  once the previous one finished and the log grew past the threshold
+pub struct Compactor {
+    snapshot_path: String,
+    corpus_root: Option<PathBuf>,
+    wal: Arc<Mutex<WriteAheadLog>>,
+    threshold: usize,
+    running: Option<JoinHandle<Result<CompactionReport>>>
+}
+
+impl Compactor {
+    pub fn new(snapshot_path: &str, corpus_root: Option<&Path>, wal: WriteAheadLog, threshold: usize) -> Self {
+        Compactor {
+            snapshot_path: snapshot_path.to_owned(),
+            corpus_root: corpus_root.map(Path::to_owned),
+            wal: Arc::new(Mutex::new(wal)),
+            threshold,
+            running: None
+        }
+    }
+
+    pub fn wal(&self) -> &Mutex<WriteAheadLog> {
+        &self.wal
+    }
+
+    pub fn maybe_start(&mut self) {
+        if self.threshold == 0 || self.running.is_some() || self.wal.lock().unwrap().entry_count() < self.threshold {
+            return;
+        }
+
+        let snapshot_path = self.snapshot_path.clone();
+        let corpus_root = self.corpus_root.clone();
+        let wal = self.wal.clone();
+        self.running = Some(thread::spawn(move || compact(&snapshot_path, corpus_root.as_deref(), &wal, |_| {})));
+    }
+
+    pub fn poll(&mut self) -> Option<Result<CompactionReport>> {
+        if self.running.as_ref().is_some_and(|running| running.is_finished()) {
+            return self.wait();
+        }
+
+        None
+    }
+
+    pub fn wait(&mut self) -> Option<Result<CompactionReport>> {
+        self.running.take()
+            .map(|running| running.join().unwrap_or_else(|_| Err(anyhow!("Compaction thread panicked"))))
+    }
+
+    pub fn compact_now(&mut self) -> Result<CompactionReport> {
+        if let Some(report) = self.wait() {
+            print_report(report?);
+        }
+
+        compact(&self.snapshot_path, self.corpus_root.as_deref(), &self.wal, |stage| println!("Compaction: {stage}..."))
+    }
+}
+
+pub fn print_report(report: CompactionReport) {
+    if report.operations == 0 {
+        println!("Nothing to compact");
+    } else {
+        println!("Compacted {} operations ({} skipped) into the snapshot in {:?}", report.operations, report.skipped, report.time);
+    }
+}
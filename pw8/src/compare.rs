@@ -0,0 +1,129 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+use ahash::{AHashMap, AHashSet};
+use crate::config::Config;
+use crate::qrels::Qrels;
+use crate::metrics::metrics;
+use crate::{build_index_with, query_terms, read_query_lines, BuildSettings, LoadedIndex, QuerySettings, PREPROCESS_LEADER_COUNT, QUERY_LEADER_COUNT};
+use crate::term_index::DEFAULT_TIER_SIZE;
+
+// NOTE: Overlap is measured on the part of the ranking a user actually looks at
+const OVERLAP_DEPTH: usize = 10;
+
+struct Side {
+    loaded: LoadedIndex,
+    settings: QuerySettings
+}
+
+pub struct Run {
+    pub time: Duration,
+    pub documents: Vec<String>
+}
+
+pub fn run(loaded: &LoadedIndex, settings: &QuerySettings, query_text: &str) -> Result<Run> {
+    let terms = query_terms(query_text, &loaded.ctx)?;
+    let (result, time) = metrics().time("compare_query", || settings.ranking.rank(&loaded.index, &terms, QUERY_LEADER_COUNT));
+    // NOTE: Query without known words is an empty ranking, not a failure of the comparison
+    let result = settings.rescore(result.unwrap_or_default(), &loaded.ctx);
+
+    Ok(Run {
+        time,
+        documents: result.iter()
+            .filter_map(|&(document_id, _)| loaded.ctx.document(document_id))
+            .map(|document| document.name())
+            .collect()
+    })
+}
+
+impl Side {
+    fn new(config: &Config, index_path: &str) -> Result<Self> {
+        let base_path = config.base_path.as_deref().unwrap_or("data/shakespeare");
+
+        let mut loaded = build_index_with(base_path, config.file_limit, index_path, &BuildSettings::from_config(config)?)?;
+        if let Some(percentile) = config.prune_percentile {
+            let pruned = loaded.index.prune(percentile);
+            loaded.index.preprocess(PREPROCESS_LEADER_COUNT, DEFAULT_TIER_SIZE);
+            println!("Pruned {pruned} postings below {percentile} percentile");
+        }
+
+        Ok(Side {
+            loaded,
+            settings: QuerySettings::from_config(config)?
+        })
+    }
+
+    fn run(&self, query_text: &str) -> Result<Run> {
+        run(&self.loaded, &self.settings, query_text)
+    }
+}
+
+pub fn overlap(a: &[String], b: &[String], depth: usize) -> f64 {
+    let a = a.iter().take(depth).collect::<AHashSet<_>>();
+    let b = b.iter().take(depth).collect::<AHashSet<_>>();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+pub fn flag<'a>(flags: &AHashMap<&str, &'a str>, name: &str) -> Result<&'a str> {
+    flags.get(name).cloned().context(anyhow!("Missing flag '--{name}'"))
+}
+
+pub fn compare(flags: &AHashMap<&str, &str>) -> Result<()> {
+    let config_a = Config::load(flag(flags, "config-a")?)?;
+    let config_b = Config::load(flag(flags, "config-b")?)?;
+    let queries = read_query_lines(flag(flags, "queries")?)?;
+    let qrels = flags.get("qrels")
+        .map(|path| Qrels::load(BufReader::new(File::open(path)?)))
+        .transpose()?;
+
+    println!("Building configuration A...");
+    let a = Side::new(&config_a, "data/index_a.txt")?;
+    println!("Building configuration B...");
+    let b = Side::new(&config_b, "data/index_b.txt")?;
+
+    let mut total_time = (Duration::ZERO, Duration::ZERO);
+    let mut total_overlap = 0.0;
+    let mut total_precision = (0.0, 0.0);
+    let mut judged_count = 0;
+    for query_text in &queries {
+        let run_a = a.run(query_text)?;
+        let run_b = b.run(query_text)?;
+        let query_overlap = overlap(&run_a.documents, &run_b.documents, OVERLAP_DEPTH);
+        total_time.0 += run_a.time;
+        total_time.1 += run_b.time;
+        total_overlap += query_overlap;
+
+        println!("Query: {}", query_text.trim());
+        println!("\tA: {:?}, {} results", run_a.time, run_a.documents.len());
+        println!("\tB: {:?}, {} results", run_b.time, run_b.documents.len());
+        println!("\tOverlap@{OVERLAP_DEPTH}: {query_overlap:.4}");
+
+        let precision = qrels.as_ref().and_then(|qrels| {
+            Some((qrels.average_precision(query_text, &run_a.documents)?, qrels.average_precision(query_text, &run_b.documents)?))
+        });
+        if let Some((precision_a, precision_b)) = precision {
+            println!("\tAP: A {precision_a:.4}, B {precision_b:.4}, delta {:+.4}", precision_b - precision_a);
+            total_precision.0 += precision_a;
+            total_precision.1 += precision_b;
+            judged_count += 1;
+        }
+    }
+
+    let count = queries.len().max(1);
+    println!("Summary over {} queries:", queries.len());
+    println!("\tMean time: A {:?}, B {:?}", total_time.0 / count as u32, total_time.1 / count as u32);
+    println!("\tMean overlap@{OVERLAP_DEPTH}: {:.4}", total_overlap / count as f64);
+    if judged_count != 0 {
+        let map_a = total_precision.0 / judged_count as f64;
+        let map_b = total_precision.1 / judged_count as f64;
+        println!("\tMAP over {judged_count} judged queries: A {map_a:.4}, B {map_b:.4}, delta {:+.4}", map_b - map_a);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+// NOTE: Keys mirror command line flags, so any run can be reproduced from a file
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub base_path: Option<String>,
+    pub file_limit: Option<usize>,
+    pub ranker: Option<String>,
+    pub normalization: Option<String>,
+    pub recency_half_life: Option<f64>,
+    pub recency_boost: Option<f64>,
+    pub prune_percentile: Option<f64>,
+    pub threads: Option<usize>,
+    pub pipeline: Option<String>
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path).context(format!("Couldn't read config \"{path}\""))?;
+
+        toml::from_str(&text).context(format!("Invalid config \"{path}\""))
+    }
+}
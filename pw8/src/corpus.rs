@@ -0,0 +1,283 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use ahash::AHashSet;
+use serde::Deserialize;
+use crate::archive::ArchiveCorpus;
+use crate::crawler::{CrawlerCorpus, DEFAULT_PAGE_LIMIT};
+use crate::records::{RecordCorpus, RecordFormat};
+
+pub const CORPUS_SETTINGS_PATH: &str = "data/corpus.toml";
+/// Path that reads the corpus from the standard input.
+pub const STDIN_CORPUS: &str = "-";
+const STDIN_DOCUMENT_NAME: &str = "<stdin>";
+
+#[derive(Deserialize, Clone, Copy, Default, Eq, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Every path is indexed, even if it leads to a file that was already seen.
+    #[default]
+    Keep,
+    /// Only the first path leading to a file is indexed.
+    Skip
+}
+
+/// Decides which entries of a corpus folder become documents.
+///
+/// Defaults keep every file directly in the folder, following symlinks.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields, default)]
+pub struct CorpusPolicy {
+    pub follow_symlinks: bool,
+    pub include_hidden: bool,
+    pub duplicates: DuplicatePolicy,
+    pub recursive: bool
+}
+
+#[derive(Default, Debug)]
+pub struct PolicySkips {
+    pub hidden: usize,
+    pub symlinks: usize,
+    pub duplicates: usize,
+    pub cycles: usize
+}
+
+impl CorpusPolicy {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).context(format!("Couldn't read corpus settings \"{path}\""))?;
+
+        toml::from_str(&text).context(format!("Invalid corpus settings \"{path}\""))
+    }
+
+    // NOTE: Entries are sorted, so the same folder always gets the same document ids
+    pub fn collect_files(&self, base_path: impl AsRef<Path>) -> Result<(Vec<PathBuf>, PolicySkips)> {
+        let mut files = Vec::new();
+        let mut skips = PolicySkips::default();
+        let mut visited_folders = AHashSet::new();
+        let mut seen_files = AHashSet::new();
+
+        let base_path = base_path.as_ref();
+        visited_folders.insert(fs::canonicalize(base_path)?);
+        self.collect_folder(base_path, &mut files, &mut skips, &mut visited_folders, &mut seen_files)?;
+
+        Ok((files, skips))
+    }
+
+    fn collect_folder(&self, folder: &Path, files: &mut Vec<PathBuf>, skips: &mut PolicySkips,
+                      visited_folders: &mut AHashSet<PathBuf>, seen_files: &mut AHashSet<PathBuf>) -> Result<()> {
+        let mut paths = fs::read_dir(folder)?
+            .flatten()
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let hidden = path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with('.'));
+            if hidden && !self.include_hidden {
+                skips.hidden += 1;
+                continue;
+            }
+
+            let is_symlink = fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_symlink());
+            if is_symlink && !self.follow_symlinks {
+                skips.symlinks += 1;
+                continue;
+            }
+
+            // NOTE: Broken symlinks have no metadata and are left out like before
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                if !self.recursive {
+                    continue;
+                }
+
+                // NOTE: A symlink back to a folder on the current path would otherwise recurse forever
+                if !visited_folders.insert(fs::canonicalize(&path)?) {
+                    skips.cycles += 1;
+                    continue;
+                }
+                self.collect_folder(&path, files, skips, visited_folders, seen_files)?;
+            } else if metadata.is_file() {
+                if self.duplicates == DuplicatePolicy::Skip && !seen_files.insert(fs::canonicalize(&path)?) {
+                    skips.duplicates += 1;
+                    continue;
+                }
+                files.push(path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for CorpusPolicy {
+    fn default() -> Self {
+        CorpusPolicy {
+            follow_symlinks: true,
+            include_hidden: true,
+            duplicates: DuplicatePolicy::Keep,
+            recursive: false
+        }
+    }
+}
+
+impl PolicySkips {
+    pub fn total(&self) -> usize {
+        self.hidden + self.symlinks + self.duplicates + self.cycles
+    }
+}
+
+/// Text of a corpus document.
+pub enum DocumentText {
+    /// File on disk, it is mapped when the document is read.
+    File(PathBuf),
+    /// Text that is already in memory.
+    Buffer(String)
+}
+
+/// Document yielded by a [`Corpus`], it gets an id when it is added to the context.
+pub struct CorpusDocument {
+    pub name: PathBuf,
+    /// Fields of the source that aren't indexed, like other columns of a record.
+    pub metadata: Vec<(String, String)>,
+    pub text: DocumentText
+}
+
+/// Document of a corpus that couldn't be read, it is skipped and the rest of the corpus is indexed.
+pub struct UnreadableDocument {
+    pub name: PathBuf,
+    pub error: anyhow::Error
+}
+
+pub type CorpusEntry = std::result::Result<CorpusDocument, UnreadableDocument>;
+
+/// Source documents are indexed from.
+///
+/// A corpus is read once, documents keep the order they are yielded in as their ids.
+pub trait Corpus {
+    fn name(&self) -> String;
+
+    fn documents(self: Box<Self>) -> Result<Box<dyn Iterator<Item = CorpusEntry>>>;
+}
+
+impl CorpusDocument {
+    pub fn file(path: PathBuf) -> Self {
+        CorpusDocument {
+            name: path.clone(),
+            metadata: Vec::new(),
+            text: DocumentText::File(path)
+        }
+    }
+
+    pub fn buffer(name: impl Into<PathBuf>, text: String) -> Self {
+        CorpusDocument {
+            name: name.into(),
+            metadata: Vec::new(),
+            text: DocumentText::Buffer(text)
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Vec<(String, String)>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+impl UnreadableDocument {
+    pub fn new(name: impl Into<PathBuf>, error: anyhow::Error) -> Self {
+        UnreadableDocument {
+            name: name.into(),
+            error
+        }
+    }
+}
+
+// NOTE: Picks the source by the form of the path, a folder is the default
+pub fn open(path: &str, policy: &CorpusPolicy) -> Box<dyn Corpus> {
+    if path == STDIN_CORPUS {
+        return Box::new(StdinCorpus);
+    }
+    if path.starts_with("http://") || path.starts_with("https://") {
+        return Box::new(CrawlerCorpus::new(path, DEFAULT_PAGE_LIMIT));
+    }
+
+    match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+        Some("tar") => Box::new(ArchiveCorpus::new(path)),
+        Some("csv") => Box::new(RecordCorpus::new(path, RecordFormat::Csv)),
+        Some("jsonl") => Box::new(RecordCorpus::new(path, RecordFormat::Jsonl)),
+        _ => Box::new(DirectoryCorpus::new(path, policy.clone()))
+    }
+}
+
+/// Files of a folder, picked by a [`CorpusPolicy`].
+pub struct DirectoryCorpus {
+    path: PathBuf,
+    policy: CorpusPolicy
+}
+
+impl DirectoryCorpus {
+    pub fn new(path: impl AsRef<Path>, policy: CorpusPolicy) -> Self {
+        DirectoryCorpus {
+            path: path.as_ref().to_owned(),
+            policy
+        }
+    }
+}
+
+impl Corpus for DirectoryCorpus {
+    fn name(&self) -> String {
+        format!("folder {:?}", self.path)
+    }
+
+    fn documents(self: Box<Self>) -> Result<Box<dyn Iterator<Item = CorpusEntry>>> {
+        let (files, skips) = self.policy.collect_files(&self.path)?;
+        if skips.total() != 0 {
+            println!("Left out by corpus policy: {} hidden, {} symlinks, {} duplicates, {} repeated folders",
+                     skips.hidden, skips.symlinks, skips.duplicates, skips.cycles);
+        }
+
+        Ok(Box::new(files.into_iter().map(|path| Ok(CorpusDocument::file(path)))))
+    }
+}
+
+/// Documents given as (name, text) pairs, kept in memory.
+pub struct MemoryCorpus {
+    documents: Vec<(String, String)>
+}
+
+impl MemoryCorpus {
+    pub fn new(documents: Vec<(String, String)>) -> Self {
+        MemoryCorpus { documents }
+    }
+}
+
+impl Corpus for MemoryCorpus {
+    fn name(&self) -> String {
+        "documents in memory".to_owned()
+    }
+
+    fn documents(self: Box<Self>) -> Result<Box<dyn Iterator<Item = CorpusEntry>>> {
+        Ok(Box::new(self.documents.into_iter().map(|(name, text)| Ok(CorpusDocument::buffer(name, text)))))
+    }
+}
+
+/// Whole standard input as one document.
+pub struct StdinCorpus;
+
+impl Corpus for StdinCorpus {
+    fn name(&self) -> String {
+        "standard input".to_owned()
+    }
+
+    fn documents(self: Box<Self>) -> Result<Box<dyn Iterator<Item = CorpusEntry>>> {
+        let mut text = String::new();
+        io::stdin().lock().read_to_string(&mut text).context("Couldn't read the standard input")?;
+
+        Ok(Box::new(std::iter::once(Ok(CorpusDocument::buffer(STDIN_DOCUMENT_NAME, text)))))
+    }
+}
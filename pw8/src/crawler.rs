@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use ahash::AHashSet;
+use crate::corpus::{Corpus, CorpusDocument, CorpusEntry, UnreadableDocument};
+
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+// NOTE: A server could keep sending within every read timeout, a page this large is most likely not text anyway
+pub const MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+const TIMEOUT: Duration = Duration::from_secs(10);
+const SCHEME: &str = "http://";
+const SKIPPED_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// Pages reachable by links from a start page, on the same host, read breadth first.
+///
+/// Only plain http is spoken, pages are fetched one at a time and their markup is stripped.
+pub struct CrawlerCorpus {
+    start: String,
+    page_limit: usize
+}
+
+impl CrawlerCorpus {
+    pub fn new(start: impl Into<String>, page_limit: usize) -> Self {
+        CrawlerCorpus {
+            start: start.into(),
+            page_limit
+        }
+    }
+}
+
+impl Corpus for CrawlerCorpus {
+    fn name(&self) -> String {
+        format!("site \"{}\"", self.start)
+    }
+
+    fn documents(self: Box<Self>) -> Result<Box<dyn Iterator<Item = CorpusEntry>>> {
+        let start = Url::parse(&self.start)?;
+        let mut queue = VecDeque::from([start.clone()]);
+        let mut seen = AHashSet::from([start.to_string()]);
+        let mut fetched = 0;
+
+        Ok(Box::new(std::iter::from_fn(move || {
+            if fetched == self.page_limit {
+                return None;
+            }
+            let url = queue.pop_front()?;
+            fetched += 1;
+
+            let page = match fetch(&url) {
+                Ok(page) => page,
+                Err(err) => return Some(Err(UnreadableDocument::new(url.to_string(), err)))
+            };
+            for link in links(&page.body).filter_map(|link| url.join(link)) {
+                if link.host == start.host && seen.insert(link.to_string()) {
+                    queue.push_back(link);
+                }
+            }
+
+            let text = if page.content_type.starts_with("text/html") { strip_markup(&page.body) } else { page.body };
+            let metadata = vec![("content-type".to_owned(), page.content_type)];
+
+            Some(Ok(CorpusDocument::buffer(url.to_string(), text).with_metadata(metadata)))
+        })))
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct Url {
+    // NOTE: Host together with the port, as it is written in the url and sent in the Host header
+    host: String,
+    path: String
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix(SCHEME).ok_or_else(|| anyhow!("Only {SCHEME} urls can be crawled, got \"{url}\""))?;
+        let (host, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/")
+        };
+        if host.is_empty() {
+            return Err(anyhow!("Url \"{url}\" has no host"));
+        }
+
+        Ok(Url {
+            host: host.to_owned(),
+            path: path.split('#').next().unwrap_or_default().to_owned()
+        })
+    }
+
+    // NOTE: Links to other schemes and fragments of the same page are left out
+    fn join(&self, link: &str) -> Option<Url> {
+        let link = link.split('#').next()?.trim();
+        if link.is_empty() {
+            return None;
+        }
+        if link.starts_with(SCHEME) {
+            return Url::parse(link).ok();
+        }
+        if link.contains(':') && !link.starts_with('/') {
+            return None;
+        }
+
+        let path = if link.starts_with('/') {
+            link.to_owned()
+        } else {
+            let folder = &self.path[..self.path.rfind('/').map_or(0, |slash| slash + 1)];
+            format!("{folder}{link}")
+        };
+
+        Some(Url {
+            host: self.host.clone(),
+            path: normalize_path(&path)
+        })
+    }
+}
+
+impl std::fmt::Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SCHEME}{}{}", self.host, self.path)
+    }
+}
+
+struct Page {
+    content_type: String,
+    body: String
+}
+
+// NOTE: HTTP/1.0 closes the connection after the response, so the body is everything after the headers
+fn fetch(url: &Url) -> Result<Page> {
+    let address = if url.host.contains(':') { url.host.clone() } else { format!("{}:80", url.host) };
+    let mut stream = TcpStream::connect(&address).context(format!("Couldn't connect to {address}"))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: pw8\r\nAccept: text/html, text/plain\r\n\r\n", url.path, url.host)?;
+
+    // NOTE: One byte past the limit is read, so a response of exactly the limit isn't taken for a longer one
+    let mut response = Vec::new();
+    stream.take(MAX_RESPONSE_SIZE as u64 + 1).read_to_end(&mut response)?;
+    if response.len() > MAX_RESPONSE_SIZE {
+        return Err(anyhow!("Response is larger than {MAX_RESPONSE_SIZE} bytes"));
+    }
+    let header_end = response.windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("Response has no end of headers"))?;
+    let headers = String::from_utf8_lossy(&response[..header_end]);
+    let mut lines = headers.lines();
+
+    let status = lines.next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(anyhow!("Server answered \"{status}\""));
+    }
+    let content_type = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-type"))
+        .map_or("text/plain".to_owned(), |(_, value)| value.trim().to_ascii_lowercase());
+    if !content_type.starts_with("text/") {
+        return Err(anyhow!("Page isn't text, its content type is \"{content_type}\""));
+    }
+
+    Ok(Page {
+        content_type,
+        body: String::from_utf8_lossy(&response[header_end + 4..]).into_owned()
+    })
+}
+
+// NOTE: Values of href attributes, quoted with either kind of quote
+fn links(html: &str) -> impl Iterator<Item = &str> {
+    html.match_indices("href=")
+        .filter_map(|(start, _)| {
+            let value = &html[start + "href=".len()..];
+            let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+            let value = &value[1..];
+
+            value.find(quote).map(|end| &value[..end])
+        })
+}
+
+fn normalize_path(path: &str) -> String {
+    let mut segments = Vec::new();
+    for segment in path.split('/').skip(1) {
+        match segment {
+            "." => {},
+            ".." => {
+                segments.pop();
+            },
+            segment => segments.push(segment)
+        }
+    }
+
+    format!("/{}", segments.join("/"))
+}
+
+// NOTE: Tags are replaced with spaces so words on both sides of one aren't joined,
+//  contents of scripts and styles are left out and the most common entities are decoded
+fn strip_markup(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        text.push(' ');
+        rest = &rest[start..];
+
+        let tag_end = rest.find('>').map_or(rest.len(), |end| end + 1);
+        let element = rest[1..tag_end].split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        rest = &rest[tag_end..];
+        if SKIPPED_ELEMENTS.contains(&element.as_str()) {
+            let close = format!("</{element}");
+            rest = rest.to_ascii_lowercase().find(&close)
+                .map_or("", |end| &rest[end..]);
+        }
+    }
+    text.push_str(rest);
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
@@ -1,5 +1,5 @@
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
 use crate::file::FileId;
 
@@ -56,18 +56,39 @@ impl DocumentRegistry {
 
         DocumentId(id)
     }
+
+    pub fn add_inline(&mut self, name: impl Into<String>, text: impl Into<String>) -> DocumentId {
+        self.add_document(Document::Inline { name: name.into(), text: text.into() })
+    }
+}
+
+impl Default for DocumentRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 pub enum Document {
-    File { path: PathBuf, file_id: FileId }
+    File { path: PathBuf, file_id: FileId },
+    // NOTE: Text that never was a file, like the standard input or a document added from memory
+    Inline { name: String, text: String }
 }
 
 impl Document {
     pub fn name(&self) -> String {
         match self {
-            Document::File { path, .. } => path.to_string_lossy().to_string()
+            Document::File { path, .. } => path.to_string_lossy().to_string(),
+            Document::Inline { name, .. } => name.clone()
+        }
+    }
+
+    // NOTE: Inline documents have no file, their name stands in for the path
+    pub fn path(&self) -> &Path {
+        match self {
+            Document::File { path, .. } => path,
+            Document::Inline { name, .. } => Path::new(name)
         }
     }
 }
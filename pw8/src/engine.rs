@@ -0,0 +1,574 @@
+use itertools::Itertools;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use crate::boilerplate::BoilerplateFilter;
+use crate::build_cache::BuildCache;
+use crate::common::add_file_to_index;
+use crate::corpus::{Corpus, CorpusPolicy, DirectoryCorpus, MemoryCorpus};
+use crate::document::DocumentId;
+use crate::error::{CorpusError, IndexError, ParseError, Result};
+use crate::file::{File, DEFAULT_MAX_OPEN_MAPS};
+use crate::inf_context::InfContext;
+use crate::lexer::{self, Lexer, LexerStats};
+use crate::memory::MemoryReport;
+use crate::merge::DEFAULT_MERGE_BUFFER;
+use crate::metrics::metrics;
+use crate::ranking::Ranking;
+use crate::recency::RecencyScoring;
+use crate::skipped::SkipStage;
+use crate::stopwords::Stopwords;
+use crate::term::{MatchedTerm, Posting};
+use crate::term_index::{self, InvertedIndex, QueryResult, TermStatistics, DEFAULT_SEED, DEFAULT_TIER_SIZE};
+use crate::normalization::ScoreNormalization;
+use crate::scheduling::{largest_first, Scheduling};
+use crate::vector::SimilarityMetric;
+
+pub const DEFAULT_LEADER_COUNT: usize = 2;
+pub const QUERY_BOOST_SEPARATOR: char = '^';
+
+// NOTE: A word can be boosted with a suffix, like "king^2"
+fn split_boost(word: &str) -> Result<(&str, f64), ParseError> {
+    match word.rsplit_once(QUERY_BOOST_SEPARATOR) {
+        Some((word, boost)) => {
+            let invalid = || ParseError::InvalidBoost { word: word.to_owned(), boost: boost.to_owned() };
+
+            Ok((word, f64::from_str(boost).map_err(|_| invalid())?))
+        },
+        None => Ok((word, 1.0))
+    }
+}
+
+pub fn query_terms(query_text: &str, ctx: &InfContext) -> Result<term_index::Query, ParseError> {
+    if query_text.trim().is_empty() {
+        return Err(ParseError::EmptyQuery);
+    }
+
+    let mut terms = term_index::Query::new();
+    for word in query_text.split_whitespace() {
+        let (word, boost) = split_boost(word)?;
+
+        let lexer = Lexer::new(DocumentId(0), word, ctx);
+        let mut word_index = InvertedIndex::new();
+        lexer.lex(&mut word_index);
+        for (term, count) in word_index.document_terms(DocumentId(0)) {
+            *terms.entry(term.to_owned()).or_default() += count as f64 * boost;
+        }
+    }
+
+    Ok(terms)
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TermChange {
+    Unchanged,
+    Normalized,
+    Truncated,
+    Stopword,
+    Dropped
+}
+
+impl Display for TermChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TermChange::Unchanged => write!(f, "unchanged"),
+            TermChange::Normalized => write!(f, "normalized"),
+            TermChange::Truncated => write!(f, "truncated"),
+            TermChange::Stopword => write!(f, "removed as a stopword"),
+            TermChange::Dropped => write!(f, "dropped, not a word")
+        }
+    }
+}
+
+pub struct QueryTerm {
+    pub word: String,
+    pub term: Option<(String, TermStatistics)>,
+    pub change: TermChange
+}
+
+// NOTE: Words are lexed one by one, so every term can be traced back to the word it came from
+pub fn explain_query(query_text: &str, index: &InvertedIndex, ctx: &InfContext) -> Result<Vec<QueryTerm>, ParseError> {
+    let mut explained = Vec::new();
+    for word in query_text.split_whitespace() {
+        let (word, _) = split_boost(word)?;
+        let lexer = Lexer::new(DocumentId(0), word, ctx);
+        let mut word_index = InvertedIndex::new();
+        let stats = lexer.lex(&mut word_index);
+
+        let terms = word_index.terms().into_iter().sorted().collect::<Vec<_>>();
+        if terms.is_empty() {
+            let change = if stats.stopwords_removed != 0 { TermChange::Stopword } else { TermChange::Dropped };
+            explained.push(QueryTerm { word: word.to_owned(), term: None, change });
+        }
+        for term in terms {
+            let change = if stats.tokens_truncated != 0 {
+                TermChange::Truncated
+            } else if term != word {
+                TermChange::Normalized
+            } else {
+                TermChange::Unchanged
+            };
+            let statistics = index.term_statistics(&term);
+            explained.push(QueryTerm { word: word.to_owned(), term: Some((term, statistics)), change });
+        }
+    }
+
+    Ok(explained)
+}
+
+pub struct BuildReport {
+    pub opening_files_time: Duration,
+    pub index_time: Duration,
+    pub data_size: usize,
+    pub stats: LexerStats,
+    // NOTE: Files whose partial index came from the build cache and files that had to be lexed, zero without a cache
+    pub cache_hits: usize,
+    pub cache_misses: usize
+}
+
+/// Configures and builds a [`SearchEngine`] over a folder of documents, or any other [`Corpus`].
+///
+/// ```no_run
+/// # use pw8::{IndexBuilder, Query};
+/// # fn main() -> anyhow::Result<()> {
+/// let engine = IndexBuilder::new("data/shakespeare")
+///     .file_limit(100)
+///     .build()?;
+/// for result in engine.search(&Query::new("king lear").limit(10))? {
+///     println!("{} {:.4}", result.name, result.score);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct IndexBuilder {
+    base_path: Option<PathBuf>,
+    corpora: Vec<Box<dyn Corpus>>,
+    buffers: Vec<(String, String)>,
+    file_limit: Option<usize>,
+    policy: CorpusPolicy,
+    boilerplate: BoilerplateFilter,
+    stopwords: Stopwords,
+    merge_buffer: usize,
+    max_open_maps: usize,
+    retry_failed: bool,
+    leader_count: usize,
+    tier_size: usize,
+    similarity: SimilarityMetric,
+    seed: u64,
+    scheduling: Scheduling,
+    build_cache: Option<PathBuf>
+}
+
+impl IndexBuilder {
+    pub fn new(base_path: impl AsRef<Path>) -> Self {
+        IndexBuilder {
+            base_path: Some(base_path.as_ref().to_owned()),
+            ..Self::default()
+        }
+    }
+
+    /// Adds a source whose documents are indexed after the ones of the base folder.
+    pub fn corpus(mut self, corpus: Box<dyn Corpus>) -> Self {
+        self.corpora.push(corpus);
+        self
+    }
+
+    /// Adds a document that is kept in memory instead of being read from the base folder.
+    pub fn document(mut self, name: impl Into<String>, text: impl Into<String>) -> Self {
+        self.buffers.push((name.into(), text.into()));
+        self
+    }
+
+    pub fn file_limit(mut self, file_limit: usize) -> Self {
+        self.file_limit = Some(file_limit);
+        self
+    }
+
+    pub fn boilerplate(mut self, boilerplate: BoilerplateFilter) -> Self {
+        self.boilerplate = boilerplate;
+        self
+    }
+
+    pub fn stopwords(mut self, stopwords: Stopwords) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+
+    /// Number of partial indexes held in memory at once while merging them into one.
+    pub fn merge_buffer(mut self, merge_buffer: usize) -> Self {
+        self.merge_buffer = merge_buffer;
+        self
+    }
+
+    /// Number of files kept memory mapped at once, others are mapped again when read.
+    pub fn max_open_maps(mut self, max_open_maps: usize) -> Self {
+        self.max_open_maps = max_open_maps;
+        self
+    }
+
+    /// Tries files that couldn't be opened or read once more after the rest is indexed.
+    pub fn retry_failed(mut self, retry_failed: bool) -> Self {
+        self.retry_failed = retry_failed;
+        self
+    }
+
+    /// Which files of the base folder are indexed.
+    pub fn corpus_policy(mut self, policy: CorpusPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Number of leaders every follower is assigned to during preprocessing.
+    pub fn leader_count(mut self, leader_count: usize) -> Self {
+        self.leader_count = leader_count;
+        self
+    }
+
+    /// Number of documents in the champion list of every term, the first tier tiered rankings search.
+    pub fn tier_size(mut self, tier_size: usize) -> Self {
+        self.tier_size = tier_size;
+        self
+    }
+
+    /// Metric leaders are assigned and cluster queries are ranked with.
+    pub fn similarity(mut self, similarity: SimilarityMetric) -> Self {
+        self.similarity = similarity;
+        self
+    }
+
+    /// Seed leaders are picked with, the same seed and documents always give the same clusters.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Folder partial indexes of single files are cached in, unchanged files aren't lexed again by the next build.
+    pub fn build_cache(mut self, folder: impl AsRef<Path>) -> Self {
+        self.build_cache = Some(folder.as_ref().to_owned());
+        self
+    }
+
+    /// Number of indexing threads and how partial indexes are handed over to be merged.
+    pub fn scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    pub fn build(self) -> Result<SearchEngine> {
+        let cache = self.build_cache.as_ref()
+            .map(|folder| BuildCache::open(folder, &self.stopwords))
+            .transpose()
+            .map_err(|err| IndexError::Indexing(err.into()))?
+            .map(Arc::new);
+        let mut corpora = Vec::new();
+        if let Some(base_path) = &self.base_path {
+            corpora.push(Box::new(DirectoryCorpus::new(base_path, self.policy)) as Box<dyn Corpus>);
+        }
+        corpora.extend(self.corpora);
+        if !self.buffers.is_empty() {
+            corpora.push(Box::new(MemoryCorpus::new(self.buffers)));
+        }
+        let (ctx, opening_files_time) = metrics().time("opening_files", || {
+            InfContext::new(corpora, self.file_limit, self.boilerplate, self.stopwords)
+        });
+        let mut ctx = ctx.map_err(|err| CorpusError::Open(err.into()))?;
+        if self.retry_failed {
+            retry_opening(&mut ctx);
+        }
+        ctx.files().set_max_open_maps(self.max_open_maps);
+        let document_ids = largest_first(ctx.document_ids().collect(), |&document_id| ctx.document_size(document_id));
+
+        let (result, index_time) = metrics().time("indexing", || {
+            let ctx1 = ctx.clone();
+            let cache1 = cache.clone();
+            self.scheduling.index(document_ids, self.merge_buffer, move |document_id| add_file_to_index(document_id, ctx1.clone(), cache1.as_deref()), |a, b| {
+                a.0.merge(b.0);
+                a.1.merge(b.1);
+            })
+        });
+        let (mut index, mut stats) = result.map_err(|err| IndexError::Indexing(err.into()))?
+            .unwrap_or_else(|| (InvertedIndex::new(), LexerStats::default()));
+        if self.retry_failed {
+            retry_reading(&ctx, &mut index, &mut stats).map_err(|err| IndexError::Indexing(err.into()))?;
+        }
+
+        let data_size = ctx.files().files()
+            .map(File::len)
+            .sum();
+        index.set_similarity(self.similarity);
+        index.set_seed(self.seed);
+        index.set_analyzer(lexer::analyzer_fingerprint(ctx.stopwords()));
+        index.preprocess(self.leader_count, self.tier_size);
+
+        Ok(SearchEngine {
+            ctx,
+            index,
+            report: BuildReport {
+                opening_files_time,
+                index_time,
+                data_size,
+                stats,
+                cache_hits: cache.as_ref().map_or(0, |cache| cache.hits()),
+                cache_misses: cache.as_ref().map_or(0, |cache| cache.misses())
+            }
+        })
+    }
+}
+
+// NOTE: Builder without a base folder only indexes the corpora and documents added to it
+impl Default for IndexBuilder {
+    fn default() -> Self {
+        IndexBuilder {
+            base_path: None,
+            corpora: Vec::new(),
+            buffers: Vec::new(),
+            file_limit: None,
+            policy: CorpusPolicy::default(),
+            boilerplate: BoilerplateFilter::default(),
+            stopwords: Stopwords::new(),
+            merge_buffer: DEFAULT_MERGE_BUFFER,
+            max_open_maps: DEFAULT_MAX_OPEN_MAPS,
+            retry_failed: false,
+            leader_count: DEFAULT_LEADER_COUNT,
+            tier_size: DEFAULT_TIER_SIZE,
+            similarity: SimilarityMetric::default(),
+            seed: DEFAULT_SEED,
+            scheduling: Scheduling::default(),
+            build_cache: None
+        }
+    }
+}
+
+// NOTE: Context was just created, so nothing else holds it yet
+fn retry_opening(ctx: &mut Arc<InfContext>) {
+    let Some(ctx) = Arc::get_mut(ctx) else {
+        return;
+    };
+
+    for skipped in ctx.skipped().take_stage(SkipStage::Opening) {
+        match ctx.add_file(skipped.path.clone()) {
+            Ok(document_id) => println!("Recovered {:?} as {document_id}", skipped.path),
+            Err(err) => ctx.skipped().record(skipped.path, SkipStage::Opening, None, &err)
+        }
+    }
+}
+
+fn retry_reading(ctx: &Arc<InfContext>, index: &mut InvertedIndex, stats: &mut LexerStats) -> anyhow::Result<()> {
+    for skipped in ctx.skipped().take_stage(SkipStage::Reading) {
+        let Some(document_id) = skipped.document else {
+            continue;
+        };
+
+        if let Some((document_index, document_stats)) = add_file_to_index(document_id, ctx.clone(), None)? {
+            index.merge(document_index);
+            stats.merge(document_stats);
+            println!("Recovered {:?} as {document_id}", skipped.path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Posting of a term together with the path of its document.
+#[derive(Clone, Debug, Serialize)]
+pub struct ResolvedPosting {
+    pub document_id: DocumentId,
+    pub path: Option<PathBuf>,
+    pub count: usize
+}
+
+// NOTE: Documents that were removed from the registry since the index was built have no path
+pub fn resolved_postings<'a>(term: &str, index: &'a InvertedIndex, ctx: &'a InfContext) -> impl Iterator<Item = ResolvedPosting> + 'a {
+    index.postings(term)
+        .map(|Posting { document_id, count }| ResolvedPosting {
+            document_id,
+            path: ctx.document(document_id).map(|document| document.path().to_owned()),
+            count
+        })
+}
+
+/// Document picked while exploring the corpus, with what the index knows about it.
+#[derive(Clone, Debug, Serialize)]
+pub struct DocumentSummary {
+    pub document_id: DocumentId,
+    pub name: String,
+    pub size: usize,
+    pub term_count: usize,
+    pub top_terms: Vec<(String, f64)>,
+    pub metadata: Vec<(String, String)>
+}
+
+pub fn document_summary(document_id: DocumentId, index: &InvertedIndex, ctx: &InfContext, top_term_count: usize) -> Option<DocumentSummary> {
+    let document = ctx.document(document_id)?;
+
+    Some(DocumentSummary {
+        document_id,
+        name: document.name(),
+        size: ctx.document_size(document_id),
+        term_count: index.document_term_count(document_id),
+        top_terms: index.top_terms(document_id, top_term_count)
+            .into_iter()
+            .map(|(term, weight)| (term.to_owned(), weight))
+            .collect(),
+        metadata: ctx.document_metadata(document_id).to_vec()
+    })
+}
+
+// NOTE: Filter is normalized like a query, a document has to contain every one of its terms.
+//  Documents that were removed or never indexed aren't picked
+pub fn random_document(filter: Option<&str>, index: &InvertedIndex, ctx: &InfContext, rng: &mut impl Rng) -> Result<Option<DocumentId>> {
+    let terms = filter.map(|filter| query_terms(filter, ctx)).transpose()?;
+    let positions = terms.iter()
+        .flat_map(|terms| terms.keys())
+        .map(|term| index.term_positions(term))
+        .collect::<Option<Vec<_>>>();
+    let Some(positions) = positions else {
+        return Ok(None);
+    };
+
+    Ok(ctx.document_ids()
+        .filter(|&document_id| index.document_vector(document_id).is_some())
+        .filter(|&document_id| positions.iter().all(|positions| positions.count(document_id) > 0))
+        .choose(rng))
+}
+
+/// Query text together with the way its results are ranked.
+pub struct Query {
+    text: String,
+    ranking: Ranking,
+    recency: Option<RecencyScoring>,
+    normalization: ScoreNormalization,
+    leader_count: usize,
+    limit: Option<usize>
+}
+
+impl Query {
+    pub fn new(text: impl Into<String>) -> Self {
+        Query {
+            text: text.into(),
+            ranking: Ranking::default(),
+            recency: None,
+            normalization: ScoreNormalization::default(),
+            leader_count: DEFAULT_LEADER_COUNT,
+            limit: None
+        }
+    }
+
+    pub fn ranking(mut self, ranking: Ranking) -> Self {
+        self.ranking = ranking.normalized(self.normalization);
+        self
+    }
+
+    /// Makes scores of different rankers comparable, fusion normalizes every ranker before combining them.
+    pub fn normalization(mut self, normalization: ScoreNormalization) -> Self {
+        self.normalization = normalization;
+        self.ranking = self.ranking.normalized(normalization);
+        self
+    }
+
+    pub fn recency(mut self, recency: RecencyScoring) -> Self {
+        self.recency = Some(recency);
+        self
+    }
+
+    /// Number of closest leaders whose followers are searched by the cluster ranking.
+    pub fn leader_count(mut self, leader_count: usize) -> Self {
+        self.leader_count = leader_count;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchResult {
+    pub document_id: DocumentId,
+    pub name: String,
+    pub score: f64,
+    pub normalization: ScoreNormalization,
+    // NOTE: Query terms after lexing that the document contains, with their counts in it
+    pub matched_terms: Vec<MatchedTerm>
+}
+
+/// Built index together with the documents it was built from.
+pub struct SearchEngine {
+    ctx: Arc<InfContext>,
+    index: InvertedIndex,
+    report: BuildReport
+}
+
+impl SearchEngine {
+    pub fn ctx(&self) -> &Arc<InfContext> {
+        &self.ctx
+    }
+
+    pub fn index(&self) -> &InvertedIndex {
+        &self.index
+    }
+
+    pub fn report(&self) -> &BuildReport {
+        &self.report
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport::new(&self.index, &self.ctx)
+    }
+
+    pub fn into_parts(self) -> (Arc<InfContext>, InvertedIndex) {
+        (self.ctx, self.index)
+    }
+
+    /// Postings the index holds for an already normalized term, in document id order.
+    pub fn postings(&self, term: &str) -> impl Iterator<Item = ResolvedPosting> + '_ {
+        resolved_postings(term, &self.index, &self.ctx)
+    }
+
+    /// Random document containing every term of the filter, or any document without one.
+    pub fn random_document(&self, filter: Option<&str>, top_term_count: usize, rng: &mut impl Rng) -> Result<Option<DocumentSummary>> {
+        Ok(random_document(filter, &self.index, &self.ctx, rng)?
+            .and_then(|document_id| document_summary(document_id, &self.index, &self.ctx, top_term_count)))
+    }
+
+    pub fn rank(&self, query: &Query) -> Result<QueryResult> {
+        self.rank_terms(query, &query_terms(&query.text, &self.ctx)?)
+    }
+
+    fn rank_terms(&self, query: &Query, terms: &term_index::Query) -> Result<QueryResult> {
+        let (result, _) = metrics().time("query", || query.ranking.rank(&self.index, terms, query.leader_count));
+        let result = match &query.recency {
+            Some(recency) => recency.rescore(result?, &self.ctx),
+            None => result?
+        };
+        let mut result = query.ranking.normalize_result(result, query.normalization);
+        if let Some(limit) = query.limit {
+            result.truncate(limit);
+        }
+
+        Ok(result)
+    }
+
+    pub fn search(&self, query: &Query) -> Result<Vec<SearchResult>> {
+        let terms = query_terms(&query.text, &self.ctx)?;
+
+        Ok(self.rank_terms(query, &terms)?
+            .into_iter()
+            .filter_map(|(document_id, score)| {
+                self.ctx.document(document_id).map(|document| SearchResult {
+                    document_id,
+                    name: document.name(),
+                    score,
+                    normalization: query.normalization,
+                    matched_terms: self.index.matched_terms(&terms, document_id)
+                })
+            })
+            .collect())
+    }
+}
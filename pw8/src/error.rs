@@ -0,0 +1,98 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+// NOTE: Failures deep inside indexing are still reported through anyhow,
+//  they're boxed so the chain of causes isn't lost
+pub type Cause = Box<dyn std::error::Error + Send + Sync>;
+
+/// Query text, ranker names and other user input that couldn't be understood.
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error("Query can't be empty")]
+    EmptyQuery,
+    #[error("Invalid boost '{boost}' of '{word}'")]
+    InvalidBoost { word: String, boost: String },
+    #[error("Unknown ranker '{0}'")]
+    UnknownRanker(String),
+    #[error("Fusion needs at least two rankers")]
+    FusionTooSmall,
+    #[error("Unknown score normalization '{0}'")]
+    UnknownNormalization(String),
+    #[error("Unknown similarity metric '{0}'")]
+    UnknownSimilarity(String),
+    #[error("Invalid pivot slope '{0}', expected a number between 0 and 1")]
+    InvalidSlope(String),
+    #[error("Invalid BM25 parameters '{0}', expected k1 of at least 0 and optionally b between 0 and 1, like '1.2:0.75'")]
+    InvalidBm25Parameters(String),
+    #[error("Invalid number of exact results '{0}', expected a positive number")]
+    InvalidTopK(String)
+}
+
+/// Index that can't answer a query or couldn't be built.
+#[derive(Error, Debug)]
+pub enum IndexError {
+    #[error("Index doesn't contain any word from the query")]
+    NoMatchingTerms,
+    #[error("Failed to index documents")]
+    Indexing(#[source] Cause),
+    #[error("Index was built with analyzer {index:08x}, but queries are analyzed with {query:08x}, rebuild the index")]
+    AnalyzerMismatch { index: u32, query: u32 },
+    #[error("Index changed since it was preprocessed, preprocess it again before querying")]
+    NotPreprocessed
+}
+
+/// Index file that couldn't be read or written.
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Not a binary index")]
+    NotBinaryIndex,
+    #[error("Unexpected end of data, expected {expected} more bytes, found {found}")]
+    UnexpectedEnd { expected: usize, found: usize },
+    #[error("Unexpected data after the end of the binary index")]
+    TrailingData,
+    #[error("Malformed index: {0}")]
+    Malformed(String),
+    #[error("File {0:?} has no checksum, it was either written partially or by an older version")]
+    MissingChecksum(PathBuf),
+    #[error("File {0:?} has a malformed checksum")]
+    MalformedChecksum(PathBuf),
+    #[error("File {path:?} is corrupt, checksum {actual:08x} doesn't match {expected:08x}")]
+    ChecksumMismatch { path: PathBuf, actual: u32, expected: u32 }
+}
+
+/// Corpus folder or document that couldn't be opened.
+#[derive(Error, Debug)]
+pub enum CorpusError {
+    #[error("Failed to open the corpus")]
+    Open(#[source] Cause)
+}
+
+/// Every error of the public API, grouped by what went wrong.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Index(#[from] IndexError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Corpus(#[from] CorpusError)
+}
+
+impl Error {
+    // NOTE: Stable name of the group, for callers that report errors outside of Rust
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Parse(_) => "parse",
+            Error::Index(_) => "index",
+            Error::Storage(_) => "storage",
+            Error::Corpus(_) => "corpus"
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
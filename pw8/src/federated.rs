@@ -0,0 +1,20 @@
+use itertools::Itertools;
+use crate::document::DocumentId;
+use crate::normalization::ScoreNormalization;
+use crate::term_index::QueryResult;
+
+pub type FederatedResult = Vec<(usize, DocumentId, f64)>;
+
+// NOTE: Similarities from different indexes aren't comparable, because idf depends
+//  on the collection, so this is used unless another normalization is chosen
+pub const DEFAULT_FEDERATED_NORMALIZATION: ScoreNormalization = ScoreNormalization::ZScore;
+
+pub fn merge(results: impl Iterator<Item = (usize, QueryResult)>, normalization: ScoreNormalization) -> FederatedResult {
+    results
+        .flat_map(|(index, result)| {
+            normalization.apply(&result).into_iter()
+                .map(move |(document_id, score)| (index, document_id, score))
+        })
+        .sorted_by(|(_, _, a), (_, _, b)| a.partial_cmp(b).unwrap().reverse())
+        .collect()
+}
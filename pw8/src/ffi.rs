@@ -0,0 +1,122 @@
+use anyhow::{anyhow, Result};
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+use serde_json::json;
+use crate::engine::{IndexBuilder, Query, SearchEngine};
+use crate::error::Error;
+
+// NOTE: Documents are only indexed on the first query after they were added,
+//  adding documents one by one doesn't rebuild the index every time
+pub struct IrEngine {
+    documents: Vec<(String, String)>,
+    engine: Option<SearchEngine>
+}
+
+impl IrEngine {
+    fn add_document(&mut self, name: String, text: String) {
+        self.documents.push((name, text));
+        self.engine = None;
+    }
+
+    fn query(&mut self, text: &str, limit: usize) -> Result<String> {
+        let engine = match self.engine.take() {
+            Some(engine) => engine,
+            None => {
+                self.documents.iter()
+                    .fold(IndexBuilder::default(), |builder, (name, text)| builder.document(name.as_str(), text.as_str()))
+                    .build()?
+            }
+        };
+
+        let mut query = Query::new(text);
+        if limit != 0 {
+            query = query.limit(limit);
+        }
+        let results = engine.search(&query);
+        self.engine = Some(engine);
+
+        Ok(json!({ "results": results? }).to_string())
+    }
+}
+
+unsafe fn read_str<'a>(text: *const c_char) -> Result<&'a str> {
+    if text.is_null() {
+        return Err(anyhow!("Unexpected null string"));
+    }
+
+    Ok(CStr::from_ptr(text).to_str()?)
+}
+
+#[no_mangle]
+pub extern "C" fn ir_engine_new() -> *mut IrEngine {
+    Box::into_raw(Box::new(IrEngine {
+        documents: Vec::new(),
+        engine: None
+    }))
+}
+
+/// Adds a UTF-8 document of `length` bytes, returns 0 on success and -1 otherwise.
+///
+/// # Safety
+/// `engine` must come from `ir_engine_new`, `name` must be a null terminated string
+/// and `data` must point to at least `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ir_engine_add_document(engine: *mut IrEngine, name: *const c_char, data: *const u8, length: usize) -> c_int {
+    let Some(engine) = engine.as_mut() else {
+        return -1;
+    };
+    let Ok(name) = read_str(name) else {
+        return -1;
+    };
+    if data.is_null() && length != 0 {
+        return -1;
+    }
+    let bytes = if length == 0 { &[][..] } else { std::slice::from_raw_parts(data, length) };
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return -1;
+    };
+
+    engine.add_document(name.to_owned(), text.to_owned());
+
+    0
+}
+
+/// Runs a ranked query and returns JSON, either `{"results": [...]}` or `{"error": "...", "kind": "..."}`,
+/// where kind is one of `parse`, `index`, `storage` or `corpus` and is missing for invalid arguments.
+/// Zero `limit` returns every result. The string must be released with `ir_string_free`.
+///
+/// # Safety
+/// `engine` must come from `ir_engine_new` and `query` must be a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ir_engine_query(engine: *mut IrEngine, query: *const c_char, limit: usize) -> *mut c_char {
+    let result = match engine.as_mut() {
+        Some(engine) => read_str(query).and_then(|query| engine.query(query, limit)),
+        None => Err(anyhow!("Unexpected null engine"))
+    };
+    let json = result.unwrap_or_else(|err| {
+        match err.downcast_ref::<Error>() {
+            Some(error) => json!({ "error": err.to_string(), "kind": error.kind() }),
+            None => json!({ "error": err.to_string() })
+        }.to_string()
+    });
+
+    CString::new(json).map(CString::into_raw).unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+/// `text` must come from `ir_engine_query` and can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ir_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+/// # Safety
+/// `engine` must come from `ir_engine_new` and can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ir_engine_free(engine: *mut IrEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
@@ -1,12 +1,20 @@
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
+use std::ops::Deref;
 use anyhow::{Context, Result};
+use ahash::AHashMap;
 use memmap::Mmap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use serde::{Deserialize, Serialize};
+use crate::metrics::metrics;
+
+pub const DEFAULT_MAX_OPEN_MAPS: usize = 1024;
 
 #[derive(Serialize, Deserialize)]
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct FileId(usize);
 
 impl Display for FileId {
@@ -15,17 +23,27 @@ impl Display for FileId {
     }
 }
 
+// NOTE: Files are mapped on first access and only the most recently used maps
+//  are kept, so a large corpus doesn't run into the limit of open maps
 pub struct FilePool {
-    files: Vec<File>
+    files: Vec<File>,
+    open_maps: Mutex<OpenMaps>
 }
 
 impl FilePool {
     pub fn new() -> Self {
         FilePool {
-            files: Vec::new()
+            files: Vec::new(),
+            open_maps: Mutex::new(OpenMaps::new(DEFAULT_MAX_OPEN_MAPS))
         }
     }
 
+    pub fn set_max_open_maps(&self, max_open_maps: usize) {
+        let mut open_maps = self.open_maps.lock().unwrap();
+        open_maps.max = max_open_maps.max(1);
+        open_maps.evict();
+    }
+
     pub fn file_count(&self) -> usize {
         self.files.len()
     }
@@ -39,41 +57,183 @@ impl FilePool {
     }
 
     pub fn add_file(&mut self, path: &PathBuf) -> Result<FileId> {
-        let file = File::new(path)?;
+        Ok(self.add(File::new(path)?))
+    }
+
+    pub fn add(&mut self, file: File) -> FileId {
         let id = self.files.len();
         self.files.push(file);
 
-        Ok(FileId(id))
+        FileId(id)
+    }
+
+    pub fn add_buffer(&mut self, text: String) -> FileId {
+        let id = self.files.len();
+        self.files.push(File::from_buffer(text));
+
+        FileId(id)
+    }
+
+    // NOTE: Mapping happens outside of the lock, so threads reading different files don't wait for each other
+    pub fn content(&self, file_id: FileId) -> Result<FileContent<'_>> {
+        let file = self.file(file_id).context(format!("File with id {file_id} doesn't exist"))?;
+        let path = match &file.data {
+            FileData::Empty => return Ok(FileContent::Borrowed("")),
+            FileData::Buffer(text) => return Ok(FileContent::Borrowed(text)),
+            FileData::Lazy(path) => path
+        };
+
+        if let Some(mmap) = self.open_maps.lock().unwrap().get(file_id) {
+            return Ok(FileContent::Mapped(mmap));
+        }
+
+        let mmap = Arc::new(map_file(path)?);
+        metrics().add("file_maps_opened", 1);
+
+        Ok(FileContent::Mapped(self.open_maps.lock().unwrap().insert(file_id, mmap)))
+    }
+
+    pub fn mapped_size(&self) -> usize {
+        self.open_maps.lock().unwrap().maps
+            .values()
+            .map(|(mmap, _)| mmap.len())
+            .sum()
     }
 }
 
+impl Default for FilePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct OpenMaps {
+    max: usize,
+    tick: u64,
+    maps: AHashMap<FileId, (Arc<Mmap>, u64)>,
+    // NOTE: Least recently used first
+    order: BTreeMap<u64, FileId>
+}
+
+impl OpenMaps {
+    fn new(max: usize) -> Self {
+        OpenMaps {
+            max,
+            tick: 0,
+            maps: AHashMap::new(),
+            order: BTreeMap::new()
+        }
+    }
+
+    fn get(&mut self, file_id: FileId) -> Option<Arc<Mmap>> {
+        self.tick += 1;
+        let (mmap, last_used) = self.maps.get_mut(&file_id)?;
+        self.order.remove(last_used);
+        self.order.insert(self.tick, file_id);
+        *last_used = self.tick;
+
+        Some(mmap.clone())
+    }
+
+    // NOTE: Another thread may have mapped the same file in the meantime, its map is kept then
+    fn insert(&mut self, file_id: FileId, mmap: Arc<Mmap>) -> Arc<Mmap> {
+        if let Some(mmap) = self.get(file_id) {
+            return mmap;
+        }
+
+        self.maps.insert(file_id, (mmap.clone(), self.tick));
+        self.order.insert(self.tick, file_id);
+        self.evict();
+
+        mmap
+    }
+
+    // NOTE: Readers hold their own reference, so an evicted map is only unmapped once they're done
+    fn evict(&mut self) {
+        while self.maps.len() > self.max {
+            let Some((_, file_id)) = self.order.pop_first() else {
+                break;
+            };
+            self.maps.remove(&file_id);
+            metrics().add("file_maps_evicted", 1);
+        }
+    }
+}
+
+fn map_file(path: &PathBuf) -> Result<Mmap> {
+    let file = fs::File::open(path)?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    std::str::from_utf8(&mmap).context("File contains non UTF-8 data")?;
+
+    Ok(mmap)
+}
+
+// NOTE: Empty files can't be mapped
+enum FileData {
+    Empty,
+    Lazy(PathBuf),
+    Buffer(String)
+}
+
 pub struct File {
-    mmap: Option<Mmap>
+    data: FileData,
+    len: usize,
+    modified: Option<SystemTime>
 }
 
 impl File {
     pub fn new(path: &PathBuf) -> Result<Self> {
         let file = fs::File::open(path)?;
-        if file.metadata()?.len() == 0 {
-            return Ok(File { mmap: None });
-        }
-        let mmap = unsafe { Mmap::map(&file)? };
+        let metadata = file.metadata()?;
+        let modified = metadata.modified().ok();
+        let len = metadata.len() as usize;
+        let data = if len == 0 { FileData::Empty } else { FileData::Lazy(path.clone()) };
 
-        std::str::from_utf8(&mmap).context("File contains non UTF-8 data")?;
+        Ok(File { data, len, modified })
+    }
 
-        Ok(File { mmap: Some(mmap) })
+    pub fn from_buffer(text: String) -> Self {
+        File {
+            len: text.len(),
+            data: FileData::Buffer(text),
+            modified: None
+        }
     }
 
-    pub fn str(&self) -> &str {
-        unsafe {
-            std::str::from_utf8_unchecked(self.bytes())
+    pub fn buffer_size(&self) -> usize {
+        match &self.data {
+            FileData::Buffer(text) => text.capacity(),
+            _ => 0
         }
     }
 
-    pub fn bytes(&self) -> &[u8] {
-        match &self.mmap {
-            Some(mmap) => &mmap,
-            None => &[]
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Text of a file, keeps its map open for as long as it's alive.
+pub enum FileContent<'a> {
+    Borrowed(&'a str),
+    Mapped(Arc<Mmap>)
+}
+
+impl Deref for FileContent<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            FileContent::Borrowed(text) => text,
+            // NOTE: Validated when the file was mapped
+            FileContent::Mapped(mmap) => unsafe { std::str::from_utf8_unchecked(mmap) }
         }
     }
 }
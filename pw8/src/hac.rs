@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::str::FromStr;
+use ahash::AHashMap;
+use itertools::Itertools;
+use serde::Serialize;
+use crate::metrics::metrics;
+use crate::document::DocumentId;
+use crate::term_index::InvertedIndex;
+use crate::vector::cosine_sim;
+use crate::keywords::{cluster_keywords, KeywordMethod, KEYWORD_COUNT};
+use crate::build_index;
+
+const DEFAULT_CLUSTER_COUNT: usize = 5;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Linkage {
+    Single,
+    Complete,
+    Average
+}
+
+impl Linkage {
+    fn combine(&self, (a, a_size): (f64, usize), (b, b_size): (f64, usize)) -> f64 {
+        match self {
+            Linkage::Single => a.max(b),
+            Linkage::Complete => a.min(b),
+            Linkage::Average => (a * a_size as f64 + b * b_size as f64) / (a_size + b_size) as f64
+        }
+    }
+}
+
+impl FromStr for Linkage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "single" => Linkage::Single,
+            "complete" => Linkage::Complete,
+            "average" => Linkage::Average,
+            _ => return Err(anyhow!("Unknown linkage '{s}'"))
+        })
+    }
+}
+
+// NOTE: Same layout as scipy linkage matrices, leaves are nodes 0..n,
+//  node created by i-th merge has id n + i
+#[derive(Serialize, Debug)]
+pub struct Merge {
+    pub left: usize,
+    pub right: usize,
+    pub similarity: f64,
+    pub size: usize
+}
+
+pub struct Dendrogram {
+    pub leaves: Vec<DocumentId>,
+    pub merges: Vec<Merge>
+}
+
+impl Dendrogram {
+    pub fn build(index: &InvertedIndex, documents: &[DocumentId], linkage: Linkage) -> Self {
+        let leaves = documents.iter()
+            .cloned()
+            .filter(|&document_id| index.document_vector(document_id).is_some())
+            .collect::<Vec<_>>();
+        let vectors = leaves.iter()
+            .filter_map(|&document_id| index.document_vector(document_id))
+            .collect::<Vec<_>>();
+
+        let count = leaves.len();
+        let mut similarities = vec![vec![0.0; count]; count];
+        for i in 0..count {
+            for j in (i + 1)..count {
+                let similarity = cosine_sim(vectors[i], vectors[j]);
+                similarities[i][j] = similarity;
+                similarities[j][i] = similarity;
+            }
+        }
+
+        // NOTE: Merged cluster takes the slot of its left part, slot of the right one is freed
+        let mut clusters = (0..count).map(|i| Some((i, 1))).collect::<Vec<_>>();
+        let mut merges = Vec::with_capacity(count.saturating_sub(1));
+        for step in 0..count.saturating_sub(1) {
+            let (left, right) = (0..count)
+                .filter(|&i| clusters[i].is_some())
+                .tuple_combinations()
+                .max_by(|&(a, b), &(c, d)| similarities[a][b].partial_cmp(&similarities[c][d]).unwrap())
+                .unwrap();
+            let (left_node, left_size) = clusters[left].unwrap();
+            let (right_node, right_size) = clusters[right].unwrap();
+
+            merges.push(Merge {
+                left: left_node,
+                right: right_node,
+                similarity: similarities[left][right],
+                size: left_size + right_size
+            });
+
+            for other in (0..count).filter(|&other| other != left && other != right && clusters[other].is_some()) {
+                let similarity = linkage.combine((similarities[left][other], left_size), (similarities[right][other], right_size));
+                similarities[left][other] = similarity;
+                similarities[other][left] = similarity;
+            }
+            clusters[left] = Some((count + step, left_size + right_size));
+            clusters[right] = None;
+        }
+
+        Dendrogram { leaves, merges }
+    }
+
+    pub fn cut_by_count(&self, cluster_count: usize) -> Vec<Vec<DocumentId>> {
+        self.clusters(self.leaves.len().saturating_sub(cluster_count.max(1)))
+    }
+
+    pub fn cut_by_similarity(&self, threshold: f64) -> Vec<Vec<DocumentId>> {
+        self.clusters(self.merges.iter().take_while(|merge| merge.similarity >= threshold).count())
+    }
+
+    fn clusters(&self, merge_count: usize) -> Vec<Vec<DocumentId>> {
+        let count = self.leaves.len();
+        let mut members = (0..count).map(|leaf| vec![leaf]).collect::<Vec<_>>();
+        for merge in self.merges.iter().take(merge_count) {
+            let mut merged = std::mem::take(&mut members[merge.left]);
+            merged.append(&mut members[merge.right]);
+            members.push(merged);
+        }
+        members.resize(count + merge_count, Vec::new());
+
+        members.into_iter()
+            .filter(|members| !members.is_empty())
+            .map(|members| members.into_iter().map(|leaf| self.leaves[leaf]).sorted().collect())
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct DendrogramLeaf {
+    node: usize,
+    document: usize,
+    name: String
+}
+
+#[derive(Serialize)]
+struct DendrogramExport<'a> {
+    leaves: Vec<DendrogramLeaf>,
+    merges: &'a [Merge]
+}
+
+pub fn hac(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let linkage = flags.get("linkage")
+        .map(|linkage| Linkage::from_str(linkage))
+        .transpose()?
+        .unwrap_or(Linkage::Average);
+    let dendrogram_path = flags.get("dendrogram").cloned().unwrap_or("data/dendrogram.json");
+    let keywords = flags.get("keywords")
+        .map(|method| KeywordMethod::from_str(method))
+        .transpose()?
+        .unwrap_or_default();
+
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    let documents = loaded.ctx.document_ids().collect::<Vec<_>>();
+    let (dendrogram, time) = metrics().time("hac", || Dendrogram::build(&loaded.index, &documents, linkage));
+    println!("Clustering took: {time:?}");
+
+    let name = |document_id: DocumentId| loaded.ctx.document(document_id).map(|document| document.name()).unwrap_or_default();
+    let export = DendrogramExport {
+        leaves: dendrogram.leaves.iter()
+            .enumerate()
+            .map(|(node, &document_id)| DendrogramLeaf { node, document: document_id.id(), name: name(document_id) })
+            .collect(),
+        merges: &dendrogram.merges
+    };
+    serde_json::to_writer_pretty(BufWriter::new(File::create(dendrogram_path)?), &export)?;
+    println!("Dendrogram written to \"{dendrogram_path}\"");
+
+    let clusters = match flags.get("threshold") {
+        Some(threshold) => dendrogram.cut_by_similarity(f64::from_str(threshold).context("Invalid similarity threshold")?),
+        None => {
+            let cluster_count = flags.get("clusters")
+                .map(|count| usize::from_str(count))
+                .transpose()
+                .context("Invalid cluster count")?
+                .unwrap_or(DEFAULT_CLUSTER_COUNT);
+
+            dendrogram.cut_by_count(cluster_count)
+        }
+    };
+
+    for (i, cluster) in clusters.iter().enumerate() {
+        println!("Cluster {} [{}]:", i, cluster_keywords(&loaded.index, cluster, keywords, KEYWORD_COUNT).join(", "));
+        for &document_id in cluster {
+            println!("\t[{}] {}", document_id, name(document_id));
+        }
+    }
+
+    Ok(())
+}
@@ -1,46 +1,154 @@
 use anyhow::{anyhow, Result, Context};
-use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use std::path::PathBuf;
 use std::sync::Arc;
+use ahash::AHashMap;
+use std::time::SystemTime;
 use crate::document::{Document, DocumentRegistry};
-use crate::file::FilePool;
+use crate::file::{File, FileContent, FilePool};
 use crate::document::DocumentId;
+use crate::boilerplate::BoilerplateFilter;
+use crate::stopwords::Stopwords;
+use crate::corpus::{Corpus, CorpusDocument, DocumentText};
+use crate::memory::MemoryReport;
+use crate::skipped::{SkipLedger, SkipStage};
 
 pub struct InfContext {
     documents: DocumentRegistry,
-    files: FilePool
+    files: FilePool,
+    // NOTE: Fields of documents that aren't indexed, only documents that have any are kept
+    metadata: AHashMap<DocumentId, Vec<(String, String)>>,
+    boilerplate: BoilerplateFilter,
+    stopwords: Stopwords,
+    skipped: SkipLedger
 }
 
 impl InfContext {
-    pub fn new(base_path: &str, file_limit: Option<usize>) -> Result<Arc<Self>> {
-        let mut file_names = get_files(base_path)?;
-        let mut files = FilePool::new();
-        let mut documents = DocumentRegistry::new();
-
-        let mut i = 0;
-        for path in file_names.drain(..) {
-            if let Some(file_limit) = file_limit {
-                if i >= file_limit {
-                    break;
+    // NOTE: Corpora are read one after another, a file limit stops reading once that many documents were found
+    pub fn new(corpora: Vec<Box<dyn Corpus>>, file_limit: Option<usize>, boilerplate: BoilerplateFilter, stopwords: Stopwords) -> Result<Arc<Self>> {
+        let skipped = SkipLedger::new();
+        let mut found = Vec::new();
+        for corpus in corpora {
+            let name = corpus.name();
+            let remaining = file_limit.map_or(usize::MAX, |file_limit| file_limit.saturating_sub(found.len()));
+            let documents = corpus.documents().context(format!("Couldn't read {name}"))?;
+            for document in documents.take(remaining) {
+                match document {
+                    Ok(document) => found.push(document),
+                    Err(unreadable) => skipped.record(unreadable.name, SkipStage::Opening, None, &unreadable.error)
                 }
             }
-            i += 1;
+        }
+
+        // NOTE: Files are opened in parallel, but registered in their original order,
+        //  so document ids don't depend on which file finished opening first
+        let opened = found.into_par_iter()
+            .map(|document| {
+                let text = match document.text {
+                    DocumentText::File(path) => File::new(&path).map(OpenedText::File),
+                    DocumentText::Buffer(text) => Ok(OpenedText::Inline(text))
+                };
+
+                (text, document.name, document.metadata)
+            })
+            .collect::<Vec<_>>();
 
-            let file_id = match files.add_file(&path) {
-                Ok(file_id) => file_id,
+        let mut files = FilePool::new();
+        let mut documents = DocumentRegistry::new();
+        let mut metadata = AHashMap::new();
+        for (text, path, document_metadata) in opened {
+            let document_id = match text {
+                Ok(OpenedText::File(file)) => {
+                    let file_id = files.add(file);
+                    documents.add_document(Document::File { path, file_id })
+                },
+                Ok(OpenedText::Inline(text)) => documents.add_inline(path.to_string_lossy(), text),
                 Err(err) => {
-                    println!("Ignoring file {:?}. Error: {}. Caused by: {}", path, err, err.root_cause());
+                    skipped.record(path, SkipStage::Opening, None, &err);
                     continue;
                 }
             };
-            documents.add_document(Document::File { path, file_id });
+            if !document_metadata.is_empty() {
+                metadata.insert(document_id, document_metadata);
+            }
         }
 
         Ok(Arc::new(InfContext {
             documents,
-            files
+            files,
+            metadata,
+            boilerplate,
+            stopwords,
+            skipped
         }))
     }
 
+    // NOTE: Every document keeps its position as document id, so a file that
+    //  can't be opened anymore is replaced with an empty document
+    pub fn from_documents(found: Vec<CorpusDocument>, boilerplate: BoilerplateFilter, stopwords: Stopwords) -> Arc<Self> {
+        let mut files = FilePool::new();
+        let mut documents = DocumentRegistry::new();
+        for document in found {
+            let path = document.name;
+            let file_id = match document.text {
+                DocumentText::Buffer(text) => {
+                    documents.add_inline(path.to_string_lossy(), text);
+                    continue;
+                },
+                DocumentText::File(file_path) => match files.add_file(&file_path) {
+                    Ok(file_id) => file_id,
+                    Err(err) => {
+                        println!("File {:?} can't be opened, it is kept empty. Error: {}. Caused by: {}", file_path, err, err.root_cause());
+                        files.add_buffer(String::new())
+                    }
+                }
+            };
+            documents.add_document(Document::File { path, file_id });
+        }
+
+        Arc::new(InfContext {
+            documents,
+            files,
+            metadata: AHashMap::new(),
+            boilerplate,
+            stopwords,
+            skipped: SkipLedger::new()
+        })
+    }
+
+    pub fn add_file(&mut self, path: PathBuf) -> Result<DocumentId> {
+        let file_id = self.files.add_file(&path)?;
+
+        Ok(self.documents.add_document(Document::File { path, file_id }))
+    }
+
+    pub fn add_inline(&mut self, name: impl Into<String>, text: impl Into<String>) -> DocumentId {
+        self.documents.add_inline(name, text)
+    }
+
+    pub fn estimate_memory(&self, report: &mut MemoryReport) {
+        report.documents += self.documents.documents()
+            .map(|document| size_of::<Document>() + match document {
+                Document::File { path, .. } => path.capacity(),
+                Document::Inline { name, .. } => name.capacity()
+            })
+            .sum::<usize>();
+        report.documents += self.metadata.values()
+            .flatten()
+            .map(|(key, value)| key.capacity() + value.capacity())
+            .sum::<usize>();
+        report.buffers += self.files.files()
+            .map(File::buffer_size)
+            .sum::<usize>();
+        report.buffers += self.documents.documents()
+            .map(|document| match document {
+                Document::Inline { text, .. } => text.capacity(),
+                Document::File { .. } => 0
+            })
+            .sum::<usize>();
+        report.mmaps += self.files.mapped_size();
+    }
+
     pub fn document_count(&self) -> usize {
         self.documents.document_count()
     }
@@ -53,29 +161,66 @@ impl InfContext {
         self.documents.document(document_id)
     }
 
-    pub fn document_data(&self, document_id: DocumentId) -> Result<&str> {
+    pub fn document_metadata(&self, document_id: DocumentId) -> &[(String, String)] {
+        self.metadata.get(&document_id).map_or(&[], Vec::as_slice)
+    }
+
+    // NOTE: Replaces the value of a field the document already has
+    pub fn set_metadata(&mut self, document_id: DocumentId, key: &str, value: &str) {
+        let metadata = self.metadata.entry(document_id).or_default();
+        match metadata.iter_mut().find(|(existing, _)| existing == key) {
+            Some((_, existing)) => *existing = value.to_owned(),
+            None => metadata.push((key.to_owned(), value.to_owned()))
+        }
+    }
+
+    pub fn document_data(&self, document_id: DocumentId) -> Result<FileContent<'_>> {
         let document = self.documents.document(document_id)
             .context(anyhow!("Document with id {document_id} doesn't exist"))?;
         match document {
-            Document::File { file_id, .. } => {
-                let file = self.files.file(*file_id)
-                    .context(anyhow!("File with id {file_id} doesn't exist"))?;
+            Document::File { file_id, .. } => self.files.content(*file_id),
+            Document::Inline { text, .. } => Ok(FileContent::Borrowed(text))
+        }
+    }
 
-                Ok(file.str())
-            }
+    pub fn document_size(&self, document_id: DocumentId) -> usize {
+        match self.documents.document(document_id) {
+            Some(Document::File { file_id, .. }) => self.files.file(*file_id).map_or(0, File::len),
+            Some(Document::Inline { text, .. }) => text.len(),
+            None => 0
         }
     }
 
+    pub fn document_modified(&self, document_id: DocumentId) -> Option<SystemTime> {
+        match self.documents.document(document_id)? {
+            Document::File { file_id, .. } => self.files.file(*file_id)?.modified(),
+            Document::Inline { .. } => None
+        }
+    }
+
+    pub fn strip_boilerplate<'a>(&self, text: &'a str) -> &'a str {
+        self.boilerplate.strip(text)
+    }
+
+    pub fn boilerplate(&self) -> &BoilerplateFilter {
+        &self.boilerplate
+    }
+
+    pub fn stopwords(&self) -> &Stopwords {
+        &self.stopwords
+    }
+
+    pub fn skipped(&self) -> &SkipLedger {
+        &self.skipped
+    }
+
     pub fn files(&self) -> &FilePool {
         &self.files
     }
 }
 
-fn get_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
-    Ok(std::fs::read_dir(path)?
-        .map(|entry| entry.ok())
-        .flatten()
-        .map(|entry| entry.path())
-        .filter(|path| path.is_file())
-        .collect())
+// NOTE: Text of a corpus document once its file was opened
+enum OpenedText {
+    File(File),
+    Inline(String)
 }
@@ -0,0 +1,70 @@
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use ahash::AHashSet;
+use itertools::Itertools;
+use crate::document::DocumentId;
+use crate::term_index::InvertedIndex;
+use crate::vector::centroid;
+
+pub const KEYWORD_COUNT: usize = 5;
+// NOTE: Added to every count, so terms that never occur outside the cluster don't get infinite odds
+const LOG_ODDS_SMOOTHING: f64 = 0.5;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub enum KeywordMethod {
+    #[default]
+    Centroid,
+    LogOdds
+}
+
+impl FromStr for KeywordMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "centroid" => KeywordMethod::Centroid,
+            "log-odds" => KeywordMethod::LogOdds,
+            _ => return Err(anyhow!("Unknown keyword method '{s}'"))
+        })
+    }
+}
+
+pub fn cluster_keywords(index: &InvertedIndex, members: &[DocumentId], method: KeywordMethod, count: usize) -> Vec<String> {
+    match method {
+        KeywordMethod::Centroid => {
+            let terms = index.term_names();
+            let centroid = centroid(members.iter().filter_map(|&document_id| index.document_vector(document_id)));
+
+            top_terms(centroid.iter().map(|(term_id, weight)| (terms[term_id], weight)), count)
+        },
+        KeywordMethod::LogOdds => {
+            let cluster_length = members.iter()
+                .map(|&document_id| index.document_term_count(document_id))
+                .sum::<usize>();
+            let rest_length = index.total_term_count().saturating_sub(cluster_length);
+            let members = members.iter().cloned().collect::<AHashSet<_>>();
+
+            let log_odds = index.term_counts_in(&members)
+                .into_iter()
+                .filter(|&(_, inside, _)| inside > 0)
+                .map(|(term, inside, total)| {
+                    let outside = total - inside;
+                    let odds_inside = (inside as f64 + LOG_ODDS_SMOOTHING) / (cluster_length.saturating_sub(inside) as f64 + LOG_ODDS_SMOOTHING);
+                    let odds_outside = (outside as f64 + LOG_ODDS_SMOOTHING) / (rest_length.saturating_sub(outside) as f64 + LOG_ODDS_SMOOTHING);
+
+                    (term, odds_inside.ln() - odds_outside.ln())
+                });
+
+            top_terms(log_odds, count)
+        }
+    }
+}
+
+fn top_terms<'a>(weights: impl Iterator<Item = (&'a str, f64)>, count: usize) -> Vec<String> {
+    weights
+        .filter(|&(_, weight)| weight > 0.0)
+        .sorted_by(|(term_a, a), (term_b, b)| a.partial_cmp(b).unwrap().reverse().then(term_a.cmp(term_b)))
+        .take(count)
+        .map(|(term, _)| term.to_owned())
+        .collect()
+}
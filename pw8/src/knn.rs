@@ -0,0 +1,78 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::BufReader;
+use std::str::FromStr;
+use ahash::{AHashMap, AHashSet};
+use itertools::Itertools;
+use crate::classification::Labels;
+use crate::document::DocumentId;
+use crate::lexer::Lexer;
+use crate::term_index::InvertedIndex;
+use crate::{build_index, QUERY_LEADER_COUNT};
+
+const DEFAULT_NEIGHBOR_COUNT: usize = 5;
+
+pub type Neighbors<'a> = Vec<(DocumentId, &'a str, f64)>;
+
+// NOTE: Neighbors are searched among the followers of the closest leaders,
+//  same as queries, so it's approximate but doesn't scan the whole collection
+pub fn classify<'a>(index: &InvertedIndex, labels: &'a Labels, names: &AHashMap<DocumentId, String>, other: &InvertedIndex, k: usize)
+    -> Result<(Option<&'a str>, Neighbors<'a>)> {
+    let needle = index.foreign_tf_idf(other, DocumentId(0));
+    let mut seen = AHashSet::new();
+    let neighbors = index.query_by_vector(&needle, QUERY_LEADER_COUNT)?
+        .into_iter()
+        .filter(|(document_id, _)| seen.insert(*document_id))
+        .filter_map(|(document_id, similarity)| {
+            let class = labels.label(names.get(&document_id)?)?;
+
+            Some((document_id, class, similarity))
+        })
+        .take(k)
+        .collect::<Vec<_>>();
+
+    let class = neighbors.iter()
+        .map(|&(_, class, similarity)| (class, similarity))
+        .into_grouping_map()
+        .sum()
+        .into_iter()
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(class, _)| class);
+
+    Ok((class, neighbors))
+}
+
+pub fn knn(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let labels_path = flags.get("labels").context("Missing flag '--labels'")?;
+    let labels = Labels::load(BufReader::new(File::open(labels_path)?))?;
+    let file_path = flags.get("file").context("Missing flag '--file'")?;
+    let k = flags.get("k")
+        .map(|k| usize::from_str(k))
+        .transpose()
+        .context("Invalid neighbor count")?
+        .unwrap_or(DEFAULT_NEIGHBOR_COUNT);
+    if k == 0 {
+        return Err(anyhow!("Neighbor count must be positive"));
+    }
+
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    let text = std::fs::read_to_string(file_path).context(format!("Couldn't read \"{file_path}\""))?;
+    let mut document_index = InvertedIndex::new();
+    Lexer::new(DocumentId(0), loaded.ctx.strip_boilerplate(&text), &loaded.ctx).lex(&mut document_index);
+
+    let names = loaded.ctx.document_ids()
+        .filter_map(|document_id| loaded.ctx.document(document_id).map(|document| (document_id, document.name())))
+        .collect::<AHashMap<_, _>>();
+    let (class, neighbors) = classify(&loaded.index, &labels, &names, &document_index, k)?;
+
+    println!("Nearest labeled neighbors:");
+    for (i, (document_id, class, similarity)) in neighbors.iter().enumerate() {
+        println!("\t{}. [{}][{}][S: {:.4}] {}", i, document_id, class, similarity, names[document_id]);
+    }
+    match class {
+        Some(class) => println!("Predicted class: {class}"),
+        None => println!("None of the neighbors have a label")
+    }
+
+    Ok(())
+}
@@ -1,33 +1,57 @@
-use anyhow::Result;
 use std::str::Chars;
 use crate::document::DocumentId;
 use crate::inf_context::InfContext;
 use crate::term_index::TermIndex;
+use crate::stopwords::Stopwords;
+
+// NOTE: Terms longer than this are cut, real words never get close to it
+const MAX_TERM_LENGTH: usize = 64;
+// NOTE: Shorter terms are never considered garbage
+const GARBAGE_MIN_LENGTH: usize = 12;
+// NOTE: Base64 and similar blobs switch letter case far more often than words do
+const GARBAGE_CASE_SWITCH_RATIO: f64 = 0.25;
+// NOTE: Bumped whenever the lexer produces different terms for the same text
+pub const LEXER_VERSION: u32 = 1;
+
+// NOTE: Identifies everything that decides which terms a text produces, a query only finds what the index holds
+//  when both went through the same analyzer. Crc32 since it's stored, the std hasher may change between releases
+pub fn analyzer_fingerprint(stopwords: &Stopwords) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&LEXER_VERSION.to_le_bytes());
+    for word in stopwords.words() {
+        hasher.update(word.as_bytes());
+        hasher.update(b"\n");
+    }
+
+    hasher.finalize()
+}
 
 pub struct Lexer<'a> {
     document_id: DocumentId,
-    iter: Chars<'a>
+    iter: Chars<'a>,
+    stopwords: &'a Stopwords
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(document_id: DocumentId, data: &'a str, ctx: &'a InfContext) -> Result<Self> {
+    pub fn new(document_id: DocumentId, data: &'a str, ctx: &'a InfContext) -> Self {
         let iter = data.chars();
 
-        Ok(Lexer {
+        Lexer {
             document_id,
-            iter
-        })
+            iter,
+            stopwords: ctx.stopwords()
+        }
     }
 
     pub fn lex(mut self, term_index: &mut dyn TermIndex) -> LexerStats {
-        let mut word = String::new();
+        let mut word = Word::new();
         let mut stats = LexerStats::default();
         stats.lines += 1;
 
         while let Some(ch) = self.iter.next() {
             stats.characters_read += 1;
             if ch.is_alphabetic() || (ch.eq(&'\'') && !word.is_empty()) {
-                ch.to_lowercase().for_each(|ch| word.push(ch));
+                word.push(ch);
 
                 continue;
             }
@@ -37,23 +61,81 @@ impl<'a> Lexer<'a> {
                 stats.lines += 1;
             }
             if !word.is_empty() {
-                Self::add_term(&mut word, self.document_id, term_index);
+                self.add_term(&mut word, term_index, &mut stats);
             }
         }
 
         if !word.is_empty() {
-            Self::add_term(&mut word, self.document_id, term_index);
+            self.add_term(&mut word, term_index, &mut stats);
         }
 
         stats
     }
 
-    fn add_term(word: &mut String, document_id: DocumentId, term_index: &mut dyn TermIndex) {
-        let mut new_word = String::new();
-        std::mem::swap(word, &mut new_word);
+    fn add_term(&self, word: &mut Word, term_index: &mut dyn TermIndex, stats: &mut LexerStats) {
+        let word = std::mem::replace(word, Word::new());
+        if word.is_garbage() {
+            stats.tokens_dropped += 1;
+            return;
+        }
+        if word.is_truncated() {
+            stats.tokens_truncated += 1;
+        }
+
+        if self.stopwords.contains(&word.text) {
+            stats.stopwords_removed += 1;
+            return;
+        }
 
+        let mut new_word = word.text;
         new_word.shrink_to_fit();
-        term_index.add_term(new_word, document_id);
+        term_index.add_term(new_word, self.document_id);
+    }
+}
+
+struct Word {
+    text: String,
+    length: usize,
+    case_switches: usize,
+    last_uppercase: Option<bool>
+}
+
+impl Word {
+    fn new() -> Self {
+        Word {
+            text: String::new(),
+            length: 0,
+            case_switches: 0,
+            last_uppercase: None
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn push(&mut self, ch: char) {
+        if ch.is_alphabetic() {
+            let uppercase = ch.is_uppercase();
+            if self.last_uppercase.is_some_and(|last| last != uppercase) {
+                self.case_switches += 1;
+            }
+            self.last_uppercase = Some(uppercase);
+        }
+
+        self.length += 1;
+        if self.length <= MAX_TERM_LENGTH {
+            ch.to_lowercase().for_each(|ch| self.text.push(ch));
+        }
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.length > MAX_TERM_LENGTH
+    }
+
+    fn is_garbage(&self) -> bool {
+        self.length >= GARBAGE_MIN_LENGTH
+            && self.case_switches as f64 / self.length as f64 > GARBAGE_CASE_SWITCH_RATIO
     }
 }
 
@@ -61,7 +143,10 @@ impl<'a> Lexer<'a> {
 pub struct LexerStats {
     pub characters_read: usize,
     pub characters_ignored: usize,
-    pub lines: usize
+    pub lines: usize,
+    pub tokens_truncated: usize,
+    pub tokens_dropped: usize,
+    pub stopwords_removed: usize
 }
 
 impl LexerStats {
@@ -69,6 +154,9 @@ impl LexerStats {
         self.characters_read += other.characters_read;
         self.characters_ignored += other.characters_ignored;
         self.lines += other.lines;
+        self.tokens_truncated += other.tokens_truncated;
+        self.tokens_dropped += other.tokens_dropped;
+        self.stopwords_removed += other.stopwords_removed;
     }
 }
 
@@ -77,7 +165,10 @@ impl Default for LexerStats {
         LexerStats {
             characters_read: 0,
             characters_ignored: 0,
-            lines: 0
+            lines: 0,
+            tokens_truncated: 0,
+            tokens_dropped: 0,
+            stopwords_removed: 0
         }
     }
 }
@@ -0,0 +1,49 @@
+//! Ranked retrieval over folders of plain text documents.
+//!
+//! [`IndexBuilder`] indexes a folder into a [`SearchEngine`], which answers
+//! [`Query`]s with ranked [`SearchResult`]s. Lower level building blocks are
+//! available through the public modules.
+
+pub mod lexer;
+pub mod term_index;
+pub mod file;
+pub mod common;
+pub mod error;
+pub mod document;
+pub mod inf_context;
+pub mod boilerplate;
+pub mod recency;
+pub mod term;
+pub mod federated;
+pub mod normalization;
+pub mod ranking;
+pub mod rankers;
+pub mod config;
+pub mod corpus;
+pub mod archive;
+pub mod records;
+pub mod crawler;
+pub mod qrels;
+pub mod clustering;
+pub mod vector;
+pub mod classification;
+pub mod keywords;
+pub mod stopwords;
+pub mod metrics;
+pub mod persist;
+pub mod binary;
+pub mod build_cache;
+pub mod memory;
+pub mod merge;
+pub mod scheduling;
+pub mod skipped;
+pub mod engine;
+pub mod ffi;
+
+pub use engine::{IndexBuilder, Query, SearchEngine, SearchResult};
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(test)]
+mod tests;
@@ -1,138 +1,977 @@
-mod lexer;
-mod term_index;
-mod file;
-mod common;
-mod document;
-mod inf_context;
-mod term;
+mod compare;
+mod rocchio;
+mod knn;
+mod similarity_export;
+mod hac;
+mod pruning;
+mod stopword_proposal;
+mod snapshot;
+mod wal;
+mod compaction;
+mod warmup;
+mod sampling;
+mod routing;
+
+// NOTE: Core modules come from the library, the binary only adds commands on top
+use pw8::{boilerplate, classification, clustering, config, corpus, document, engine, federated, file, inf_context, keywords, lexer, memory, merge,
+          metrics, normalization, persist, qrels, ranking, recency, scheduling, skipped, stopwords, term_index, vector};
 
 use std::{env, io};
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::fs::OpenOptions;
 use std::str::FromStr;
+use std::path::{Path, PathBuf};
 use anyhow::{anyhow, Context, Result};
-use threadpool::ThreadPool;
-use std::sync::mpsc::channel;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
 use human_bytes::human_bytes;
 use itertools::Itertools;
-use crate::common::add_file_to_index;
+use ahash::AHashMap;
+use crate::metrics::{metrics, Metrics};
 use crate::inf_context::InfContext;
-use crate::term_index::{InvertedIndex, TermIndex};
-use rayon::prelude::*;
+use crate::boilerplate::BoilerplateFilter;
+use crate::stopwords::{Stopwords, STOPWORDS_PATH};
+use crate::recency::RecencyScoring;
+use crate::federated::{FederatedResult, DEFAULT_FEDERATED_NORMALIZATION};
+use crate::normalization::ScoreNormalization;
+use crate::ranking::{Ranking, DEFAULT_EXACT_TOP_K};
+use crate::config::Config;
+use crate::qrels::Qrels;
+use crate::keywords::{cluster_keywords, KeywordMethod, KEYWORD_COUNT};
+use crate::term_index::{InvertedIndex, QueryResult, DEFAULT_SEED, DEFAULT_TIER_SIZE};
+use crate::engine::{document_summary, explain_query, query_terms, random_document, resolved_postings, IndexBuilder, QueryTerm, TermChange};
+use crate::compaction::Compactor;
+use crate::memory::MemoryReport;
+use crate::file::DEFAULT_MAX_OPEN_MAPS;
+use crate::corpus::{CorpusPolicy, CORPUS_SETTINGS_PATH};
+use crate::merge::DEFAULT_MERGE_BUFFER;
+use crate::skipped::{SkipLedger, SKIPPED_PATH};
+use crate::wal::{Operation, WriteAheadLog, WAL_PATH};
 use crate::document::DocumentId;
-use crate::lexer::{Lexer, LexerStats};
+use crate::scheduling::{Pipeline, Scheduling};
+use crate::vector::SimilarityMetric;
+use crate::routing::Router;
 
 const PREPROCESS_LEADER_COUNT: usize = 2;
 const QUERY_LEADER_COUNT: usize = 2;
+const CLUSTER_TOP_COUNT: usize = 20;
+const CLUSTER_COUNT: usize = 3;
+const RETRY_FAILED_FLAG: &str = "retry-failed";
+const SKIPPED_FLAG: &str = "skipped";
+const EXACT_FLAG: &str = "exact";
+// NOTE: Flags that don't take a value, every other flag is followed by one
+const SWITCH_FLAGS: [&str; 3] = [RETRY_FAILED_FLAG, SKIPPED_FLAG, EXACT_FLAG];
+const SIMILAR_TERM_COUNT: usize = 20;
+const DEFAULT_SIMILAR_COUNT: usize = 10;
+const DEFAULT_RELATED_COUNT: usize = 10;
+const RANDOM_TOP_TERM_COUNT: usize = 10;
+const BINARY_INDEX_EXTENSION: &str = "bin";
+
+struct BuildSettings {
+    merge_buffer: usize,
+    max_open_maps: usize,
+    retry_failed: bool,
+    similarity: SimilarityMetric,
+    seed: u64,
+    scheduling: Scheduling,
+    build_cache: Option<PathBuf>
+}
+
+impl BuildSettings {
+    fn from_flags(flags: &AHashMap<&str, &str>) -> Result<Self> {
+        let merge_buffer = flags.get("merge-buffer")
+            .map(|merge_buffer| usize::from_str(merge_buffer))
+            .transpose()
+            .context("Invalid merge buffer")?
+            .unwrap_or(DEFAULT_MERGE_BUFFER);
+        let max_open_maps = flags.get("max-open-files")
+            .map(|max_open_maps| usize::from_str(max_open_maps))
+            .transpose()
+            .context("Invalid open file limit")?
+            .unwrap_or(DEFAULT_MAX_OPEN_MAPS);
+        let similarity = flags.get("similarity")
+            .map(|similarity| SimilarityMetric::from_str(similarity))
+            .transpose()?
+            .unwrap_or_default();
+        let seed = flags.get("seed")
+            .map(|seed| u64::from_str(seed))
+            .transpose()
+            .context("Invalid seed")?
+            .unwrap_or(DEFAULT_SEED);
+        let index_threads = flags.get("threads")
+            .map(|threads| usize::from_str(threads))
+            .transpose()
+            .context("Invalid thread count")?;
+        let pipeline = flags.get("pipeline")
+            .map(|pipeline| Pipeline::from_str(pipeline))
+            .transpose()?;
+
+        Ok(BuildSettings {
+            merge_buffer,
+            max_open_maps,
+            retry_failed: flags.contains_key(RETRY_FAILED_FLAG),
+            similarity,
+            seed,
+            scheduling: Self::scheduling(index_threads, pipeline)?,
+            build_cache: flags.get("build-cache").map(PathBuf::from)
+        })
+    }
+
+    fn from_config(config: &Config) -> Result<Self> {
+        let pipeline = config.pipeline.as_deref()
+            .map(Pipeline::from_str)
+            .transpose()?;
+
+        Ok(BuildSettings {
+            scheduling: Self::scheduling(config.threads, pipeline)?,
+            ..Self::default()
+        })
+    }
+
+    fn scheduling(index_threads: Option<usize>, pipeline: Option<Pipeline>) -> Result<Scheduling> {
+        let index_threads = index_threads.unwrap_or_else(Scheduling::default_thread_count);
+        if index_threads == 0 {
+            return Err(anyhow!("Thread count must be positive"));
+        }
+
+        Ok(Scheduling { index_threads, pipeline: pipeline.unwrap_or_default() })
+    }
+}
 
-fn time_call<FnT, ResT>(func: FnT) -> (ResT, Duration)
-where FnT: FnOnce() -> ResT
-{
-    let start = Instant::now();
-    let result = func();
-    let time = start.elapsed();
+impl Default for BuildSettings {
+    fn default() -> Self {
+        BuildSettings {
+            merge_buffer: DEFAULT_MERGE_BUFFER,
+            max_open_maps: DEFAULT_MAX_OPEN_MAPS,
+            retry_failed: false,
+            similarity: SimilarityMetric::default(),
+            seed: DEFAULT_SEED,
+            scheduling: Scheduling::default(),
+            build_cache: None
+        }
+    }
+}
 
-    (result, time)
+struct QuerySettings {
+    ranking: Ranking,
+    keywords: KeywordMethod,
+    recency: Option<RecencyScoring>,
+    normalization: Option<ScoreNormalization>
 }
 
-fn query(query_text: &str, index: &dyn TermIndex, ctx: &InfContext) -> Result<()> {
-    if query_text.is_empty() {
-        return Err(anyhow!("Query can't be empty"));
+impl QuerySettings {
+    fn from_flags(flags: &AHashMap<&str, &str>) -> Result<Self> {
+        let recency = match flags.get("recency-half-life") {
+            Some(half_life) => {
+                let half_life = f64::from_str(half_life).context("Invalid recency half life")?;
+                let boost = flags.get("recency-boost")
+                    .map(|boost| f64::from_str(boost))
+                    .transpose()
+                    .context("Invalid recency boost")?
+                    .unwrap_or(1.0);
+
+                Some(RecencyScoring::new(half_life, boost))
+            },
+            None => None
+        };
+
+        let normalization = flags.get("normalization")
+            .map(|normalization| ScoreNormalization::from_str(normalization))
+            .transpose()?;
+        let ranking = match (flags.get("ranker"), flags.contains_key(EXACT_FLAG)) {
+            (Some(_), true) => return Err(anyhow!("'--{EXACT_FLAG}' can't be combined with '--ranker', use '--ranker exact:<k>' instead")),
+            (Some(ranking), false) => Ranking::from_str(ranking)?,
+            (None, true) => Ranking::Exact(DEFAULT_EXACT_TOP_K),
+            (None, false) => Ranking::default()
+        };
+        let ranking = match flags.get("tier-results") {
+            Some(result_count) => ranking.tiered_result_count(usize::from_str(result_count).context("Invalid tier result count")?),
+            None => ranking
+        };
+
+        let keywords = flags.get("keywords")
+            .map(|method| KeywordMethod::from_str(method))
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(QuerySettings { ranking: ranking.normalized(normalization.unwrap_or_default()), keywords, recency, normalization })
     }
 
-    let mut lexer = Lexer::new(DocumentId(0), query_text, ctx)?;
-    let mut query_index = InvertedIndex::new();
-    lexer.lex(&mut query_index);
+    fn from_config(config: &Config) -> Result<Self> {
+        let normalization = config.normalization.as_deref()
+            .map(ScoreNormalization::from_str)
+            .transpose()?;
+        let ranking = match &config.ranker {
+            Some(ranking) => Ranking::from_str(ranking)?,
+            None => Ranking::default()
+        };
+        let recency = config.recency_half_life
+            .map(|half_life| RecencyScoring::new(half_life, config.recency_boost.unwrap_or(1.0)));
 
-    let (result, time) = time_call(|| index.query(&query_index.terms(), QUERY_LEADER_COUNT));
-    let result = result?;
+        Ok(QuerySettings { ranking: ranking.normalized(normalization.unwrap_or_default()), keywords: KeywordMethod::default(), recency, normalization })
+    }
 
-    println!("Query time: {time:?}.");
+    fn federated_normalization(&self) -> ScoreNormalization {
+        self.normalization.unwrap_or(DEFAULT_FEDERATED_NORMALIZATION)
+    }
+
+    fn rescore(&self, result: QueryResult, ctx: &InfContext) -> QueryResult {
+        let result = match &self.recency {
+            Some(recency) => recency.rescore(result, ctx),
+            None => result
+        };
+
+        self.ranking.normalize_result(result, self.normalization.unwrap_or_default())
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(Vec<&str>, AHashMap<&str, &str>)> {
+    let mut positional = Vec::new();
+    let mut flags = AHashMap::new();
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if let Some(name) = arg.strip_prefix("--").filter(|name| SWITCH_FLAGS.contains(name)) {
+            flags.insert(name, "true");
+        } else if let Some(name) = arg.strip_prefix("--") {
+            let value = iter.next().context(anyhow!("Missing value for flag '{arg}'"))?;
+            flags.insert(name, value.as_str());
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+
+    Ok((positional, flags))
+}
+
+fn print_result(result: &QueryResult, ctx: &InfContext) {
     if !result.is_empty() {
         let result_str = result.iter()
             .filter_map(|&(id, weight)| ctx.document(id).map(|doc| (id, doc, weight)))
             .enumerate()
-            .map(|(i, (id, doc, weight))| format!("\t{}. [{}][W: {:.4}] {}", i, id, weight, doc.name()))
+            .map(|(i, (id, doc, weight))| match routing::category(ctx, id) {
+                Some(category) => format!("\t{}. [{}][W: {:.4}] {} <{}>", i, id, weight, doc.name(), category),
+                None => format!("\t{}. [{}][W: {:.4}] {}", i, id, weight, doc.name())
+            })
             .join("\n");
         println!("Result:\n{result_str}");
     } else {
         println!("No matches found.");
     }
+}
+
+fn print_terms(terms: &[QueryTerm]) {
+    let terms_str = terms.iter()
+        .map(|QueryTerm { word, term, change }| match term {
+            Some((term, statistics)) if statistics.document_frequency == 0 => format!("\t{term}: not in the index"),
+            Some((term, statistics)) => {
+                let source = if *change == TermChange::Unchanged { String::new() } else { format!(" ({change} from '{word}')") };
+                format!("\t{}{}: df {}, cf {}, idf {:.4}", term, source, statistics.document_frequency, statistics.collection_frequency, statistics.idf)
+            },
+            None => format!("\t{word}: {change}")
+        })
+        .join("\n");
+    println!("Terms:\n{terms_str}");
+}
+
+fn result_documents(result: &QueryResult, ctx: &InfContext) -> Vec<String> {
+    result.iter()
+        .filter_map(|&(id, _)| ctx.document(id))
+        .map(|doc| doc.name())
+        .collect()
+}
+
+fn query(query_text: &str, index: &InvertedIndex, ctx: &InfContext, settings: &QuerySettings) -> Result<Vec<String>> {
+    let terms = query_terms(query_text, ctx)?;
+
+    let (result, time) = metrics().time("query", || settings.ranking.rank(index, &terms, QUERY_LEADER_COUNT));
+    let result = settings.rescore(result?, ctx);
+
+    println!("Query time: {time:?}.");
+    print_terms(&explain_query(query_text, index, ctx)?);
+    print_result(&result, ctx);
+
+    Ok(result_documents(&result, ctx))
+}
+
+fn cluster_query(query_text: &str, index: &InvertedIndex, ctx: &InfContext, settings: &QuerySettings) -> Result<()> {
+    let terms = query_terms(query_text, ctx)?;
+    let result = settings.rescore(settings.ranking.rank(index, &terms, QUERY_LEADER_COUNT)?, ctx);
+
+    let (clusters, time) = metrics().time("clustering", || clustering::cluster_results(index, &result, CLUSTER_TOP_COUNT, CLUSTER_COUNT, settings.keywords));
+    println!("Clustering time: {time:?}.");
+    if clusters.is_empty() {
+        println!("No matches found.");
+    }
+    for (i, cluster) in clusters.iter().enumerate() {
+        println!("Cluster {} [{}]:", i, cluster.label.join(", "));
+        let documents_str = cluster.documents.iter()
+            .filter_map(|&(id, weight)| ctx.document(id).map(|doc| (id, doc, weight)))
+            .map(|(id, doc, weight)| format!("\t[{}][W: {:.4}] {}", id, weight, doc.name()))
+            .join("\n");
+        println!("{documents_str}");
+    }
 
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let base_path = args.get(1).map(AsRef::as_ref).unwrap_or("data/shakespeare");
-    let file_limit = args.get(2).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+fn similar(args: &str, index: &InvertedIndex, ctx: &InfContext) -> Result<()> {
+    let mut args = args.split_whitespace();
+    let document_id = args.next()
+        .map(usize::from_str)
+        .context("Expected document id")?
+        .context("Invalid document id")?;
+    let document_id = DocumentId(document_id);
+    let count = args.next()
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid result count")?
+        .unwrap_or(DEFAULT_SIMILAR_COUNT);
 
-    println!("Processing...");
-    let (ctx, opening_files_time) = time_call(|| InfContext::new(base_path, file_limit).unwrap());
-    println!("Opening files took: {opening_files_time:?}");
-    let mut document_ids = ctx.document_ids().collect::<Vec<_>>();
-    let document_count = document_ids.len();
-    println!("Processing {document_count} documents in folder \"{base_path}\"");
-
-    let pool = ThreadPool::new((num_cpus::get() - 1).max(1));
-    let (tx, rx) = channel();
-    for document_id in document_ids.drain(..) {
-        let tx = tx.clone();
-        let ctx1 = ctx.clone();
-
-        pool.execute(move || {
-            tx.send(add_file_to_index(document_id, ctx1).unwrap()).unwrap()
-        });
-    }
-
-    let ((mut index, stats), index_time) = time_call(|| {
-        rx.into_iter()
-            .take(document_count)
-            .flatten()
-            .par_bridge()
-            .reduce(|| (InvertedIndex::new(), LexerStats::default()), |mut a, b| {
-                a.0.merge(b.0);
-                a.1.merge(b.1);
-
-                a
+    let needle = index.top_terms_vector(document_id, SIMILAR_TERM_COUNT)
+        .context(anyhow!("Document with id {document_id} isn't indexed"))?;
+    let (result, time) = metrics().time("similar", || index.query_by_vector(&needle, QUERY_LEADER_COUNT));
+    let result = result?
+        .into_iter()
+        .filter(|&(id, _)| id != document_id)
+        .unique_by(|&(id, _)| id)
+        .take(count)
+        .collect::<Vec<_>>();
+
+    println!("Query time: {time:?}.");
+    print_result(&result, ctx);
+
+    Ok(())
+}
+
+// NOTE: The term is normalized like a query word, so it matches the indexed form
+fn related(args: &str, index: &InvertedIndex, ctx: &InfContext) -> Result<()> {
+    let mut args = args.split_whitespace();
+    let word = args.next().context("Expected a term")?;
+    let count = args.next()
+        .map(usize::from_str)
+        .transpose()
+        .context("Invalid result count")?
+        .unwrap_or(DEFAULT_RELATED_COUNT);
+
+    let terms = query_terms(word, ctx)?;
+    let term = terms.keys()
+        .exactly_one()
+        .map_err(|_| anyhow!("\"{word}\" should produce exactly one index term"))?;
+    let (related, time) = metrics().time("related", || index.related_terms(term, count));
+    if related.is_empty() {
+        return Err(anyhow!("No terms share documents with \"{term}\""));
+    }
+
+    println!("Query time: {time:?}.");
+    println!("Terms related to \"{term}\":");
+    for (i, (related, similarity)) in related.iter().enumerate() {
+        println!("\t{i}. {related} [{similarity:.4}] df {}", index.term_statistics(related).document_frequency);
+    }
+
+    Ok(())
+}
+
+fn random(filter: &str, index: &InvertedIndex, ctx: &InfContext) -> Result<()> {
+    let filter = Some(filter.trim()).filter(|filter| !filter.is_empty());
+    let document_id = random_document(filter, index, ctx, &mut rand::thread_rng())?
+        .context(anyhow!("No document matches \"{}\"", filter.unwrap_or_default()))?;
+    let summary = document_summary(document_id, index, ctx, RANDOM_TOP_TERM_COUNT)
+        .context(anyhow!("Document with id {document_id} doesn't exist"))?;
+
+    println!("[{}] {}", summary.document_id, summary.name);
+    println!("\tSize: {}. Terms: {}", human_bytes(summary.size as f64), summary.term_count);
+    let top_terms = summary.top_terms.iter()
+        .map(|(term, weight)| format!("{term} ({weight:.4})"))
+        .join(", ");
+    println!("\tTop terms: {top_terms}");
+    if !summary.metadata.is_empty() {
+        let metadata = summary.metadata.iter()
+            .map(|(key, value)| format!("{key}: {value}"))
+            .join(", ");
+        println!("\tMetadata: {metadata}");
+    }
+
+    Ok(())
+}
+
+fn print_categories(loaded: &LoadedIndex) {
+    let categories = routing::categories(&loaded.ctx);
+    if categories.is_empty() {
+        println!("No documents of \"{}\" were routed into categories", loaded.name);
+    }
+    for (category, documents) in categories {
+        println!("Category \"{category}\" of \"{}\": {} documents", loaded.name, documents.len());
+        for document_id in documents {
+            if let Some(document) = loaded.ctx.document(document_id) {
+                println!("\t[{document_id}] {}", document.name());
+            }
+        }
+    }
+}
+
+fn print_leaders(index: &InvertedIndex, ctx: &InfContext, settings: &QuerySettings) {
+    for (leader, followers) in index.leader_clusters() {
+        let members = std::iter::once(leader).chain(followers).collect::<Vec<_>>();
+        println!("Leader {} [{}]:", leader, cluster_keywords(index, &members, settings.keywords, KEYWORD_COUNT).join(", "));
+        let members_str = members.iter()
+            .filter_map(|&id| ctx.document(id).map(|doc| (id, doc)))
+            .map(|(id, doc)| format!("\t[{}] {}", id, doc.name()))
+            .join("\n");
+        println!("{members_str}");
+    }
+}
+
+fn read_query_lines(path: &str) -> Result<Vec<String>> {
+    Ok(BufReader::new(File::open(path)?)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .collect())
+}
+
+fn query_batch(path: &str, index: &InvertedIndex, ctx: &InfContext, settings: &QuerySettings) -> Result<()> {
+    let lines = read_query_lines(path)?;
+    let queries = lines.iter()
+        .map(|line| query_terms(line, ctx))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (results, time) = metrics().time("batch_query", || settings.ranking.rank_batch(index, &queries, QUERY_LEADER_COUNT));
+
+    println!("Batch of {} queries took: {time:?}.", queries.len());
+    for (line, result) in lines.iter().zip(results) {
+        println!("Query: {}", line.trim());
+        match result {
+            Ok(result) => print_result(&settings.rescore(result, ctx), ctx),
+            Err(err) => println!("Error: {}", err)
+        }
+    }
+
+    Ok(())
+}
+
+struct LoadedIndex {
+    name: String,
+    ctx: Arc<InfContext>,
+    index: InvertedIndex
+}
+
+// NOTE: Index that doesn't contain any word from the query just doesn't contribute,
+//  the query fails only when it fails for every index
+fn merge_results(results: Vec<Result<QueryResult>>, normalization: ScoreNormalization) -> Result<FederatedResult> {
+    let mut first_error = None;
+    let mut successful = Vec::new();
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(result) => successful.push((index, result)),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    match first_error {
+        Some(err) if successful.is_empty() => Err(err),
+        _ => Ok(federated::merge(successful.into_iter(), normalization))
+    }
+}
+
+fn print_federated_result(result: &FederatedResult, indexes: &[LoadedIndex], normalization: ScoreNormalization) {
+    if !result.is_empty() {
+        println!("Scores normalized with: {normalization}");
+        let result_str = result.iter()
+            .filter_map(|&(index, id, score)| indexes[index].ctx.document(id).map(|doc| (&indexes[index].name, id, doc, score)))
+            .enumerate()
+            .map(|(i, (name, id, doc, score))| format!("\t{}. [{}][{}][S: {:.4}] {}", i, name, id, score, doc.name()))
+            .join("\n");
+        println!("Result:\n{result_str}");
+    } else {
+        println!("No matches found.");
+    }
+}
+
+fn federated_query(query_text: &str, indexes: &[LoadedIndex], settings: &QuerySettings) -> Result<Vec<String>> {
+    let (results, time) = metrics().time("federated_query", || {
+        indexes.iter()
+            .map(|loaded| {
+                let terms = query_terms(query_text, &loaded.ctx)?;
+                let result = settings.ranking.rank(&loaded.index, &terms, QUERY_LEADER_COUNT)?;
+
+                Ok(settings.rescore(result, &loaded.ctx))
+            })
+            .collect::<Vec<_>>()
+    });
+    let result = merge_results(results, settings.federated_normalization())?;
+
+    println!("Query time: {time:?}.");
+    print_federated_result(&result, indexes, settings.federated_normalization());
+
+    Ok(result.iter()
+        .filter_map(|&(index, id, _)| indexes[index].ctx.document(id))
+        .map(|doc| doc.name())
+        .collect())
+}
+
+fn federated_query_batch(path: &str, indexes: &[LoadedIndex], settings: &QuerySettings) -> Result<()> {
+    let lines = read_query_lines(path)?;
+
+    let (results, time) = metrics().time("federated_batch_query", || {
+        indexes.iter()
+            .map(|loaded| {
+                let queries = lines.iter()
+                    .map(|line| query_terms(line, &loaded.ctx))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(settings.ranking.rank_batch(&loaded.index, &queries, QUERY_LEADER_COUNT)
+                    .into_iter()
+                    .map(|result| result.map(|result| settings.rescore(result, &loaded.ctx)).map_err(anyhow::Error::from))
+                    .collect::<Vec<_>>())
             })
+            .collect::<Result<Vec<_>>>()
     });
+    let mut results = results?.into_iter()
+        .map(Vec::into_iter)
+        .collect::<Vec<_>>();
+
+    println!("Batch of {} queries took: {time:?}.", lines.len());
+    for line in &lines {
+        println!("Query: {}", line.trim());
+        let line_results = results.iter_mut()
+            .filter_map(Iterator::next)
+            .collect();
+        match merge_results(line_results, settings.federated_normalization()) {
+            Ok(result) => print_federated_result(&result, indexes, settings.federated_normalization()),
+            Err(err) => println!("Error: {}", err)
+        }
+    }
+
+    Ok(())
+}
+
+struct LastQuery {
+    text: String,
+    documents: Vec<String>
+}
+
+// NOTE: Positions refer to the numbers printed next to the results of the last query
+fn label(positions: &str, relevant: bool, last_query: Option<&LastQuery>, qrels_path: &str) -> Result<()> {
+    let last_query = last_query.context("Nothing to label, run a query first")?;
+    let documents = positions.split_whitespace()
+        .map(|position| {
+            let position = usize::from_str(position).context(anyhow!("Invalid result number '{position}'"))?;
+
+            last_query.documents.get(position).context(anyhow!("Last query has no result number {position}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut writer = OpenOptions::new().create(true).append(true).open(qrels_path)?;
+    for document in &documents {
+        Qrels::write_judgment(&mut writer, &last_query.text, document, relevant)?;
+    }
+    println!("Labeled {} results as {}relevant in \"{qrels_path}\"", documents.len(), if relevant { "" } else { "non-" });
+
+    Ok(())
+}
 
-    println!("Indexing took: {index_time:?}");
-    let total_time = opening_files_time + index_time;
+fn build_index(base_path: &str, file_limit: Option<usize>, index_path: &str) -> Result<LoadedIndex> {
+    build_index_with(base_path, file_limit, index_path, &BuildSettings::default())
+}
+
+fn build_index_with(base_path: &str, file_limit: Option<usize>, index_path: &str, settings: &BuildSettings) -> Result<LoadedIndex> {
+    let boilerplate = match File::open("data/boilerplate.txt") {
+        Ok(file) => BoilerplateFilter::load(BufReader::new(file))?,
+        Err(_) => BoilerplateFilter::default()
+    };
+
+    let stopwords = match File::open(STOPWORDS_PATH) {
+        Ok(file) => Stopwords::load(BufReader::new(file))?,
+        Err(_) => Stopwords::new()
+    };
+    if !stopwords.is_empty() {
+        println!("Using {} stopwords from \"{STOPWORDS_PATH}\"", stopwords.len());
+    }
+
+    let policy = if Path::new(CORPUS_SETTINGS_PATH).exists() {
+        CorpusPolicy::load(CORPUS_SETTINGS_PATH)?
+    } else {
+        CorpusPolicy::default()
+    };
+
+    println!("Processing...");
+    let mut builder = IndexBuilder::default()
+        .corpus(corpus::open(base_path, &policy))
+        .boilerplate(boilerplate)
+        .stopwords(stopwords)
+        .merge_buffer(settings.merge_buffer)
+        .max_open_maps(settings.max_open_maps)
+        .retry_failed(settings.retry_failed)
+        .similarity(settings.similarity)
+        .seed(settings.seed)
+        .scheduling(settings.scheduling)
+        .leader_count(PREPROCESS_LEADER_COUNT);
+    if let Some(file_limit) = file_limit {
+        builder = builder.file_limit(file_limit);
+    }
+    if let Some(build_cache) = &settings.build_cache {
+        builder = builder.build_cache(build_cache);
+    }
+    let engine = builder.build()?;
+    let report = engine.report();
+    let document_count = engine.ctx().document_ids().count();
+
+    println!("Opening files took: {:?}", report.opening_files_time);
+    println!("Processed {document_count} documents from \"{base_path}\"");
+    println!("Indexing took: {:?}", report.index_time);
+    if settings.build_cache.is_some() {
+        println!("Build cache: {} files reused, {} files lexed", report.cache_hits, report.cache_misses);
+        metrics().add("build_cache_hits", report.cache_hits as u64);
+        metrics().add("build_cache_misses", report.cache_misses as u64);
+    }
+    let total_time = report.opening_files_time + report.index_time;
+    let data_size = report.data_size;
     println!("Total time: {total_time:?}");
-    let data_size: usize = ctx.files().files()
-        .map(|file| file.bytes().len())
-        .sum();
     println!("Amount of data indexed: {}", human_bytes(data_size as f64));
     println!("Speed is: {}/s", human_bytes(data_size as f64 / total_time.as_secs_f64()));
+    metrics().add("documents_indexed", document_count as u64);
+    metrics().add("bytes_indexed", data_size as u64);
+    metrics().set("documents_per_second", document_count as f64 / total_time.as_secs_f64());
+    metrics().set("bytes_per_second", data_size as f64 / total_time.as_secs_f64());
 
+    let stats = &report.stats;
+    let index = engine.index();
     println!("Unique word count: {}.", index.term_count());
     println!("Lines read: {}. Characters read: {}. Characters ignored: {}", stats.lines, stats.characters_read, stats.characters_ignored);
+    metrics().add("lines_read", stats.lines as u64);
+    metrics().add("characters_read", stats.characters_read as u64);
+    println!("Tokens truncated: {}. Tokens dropped: {}. Stopwords removed: {}", stats.tokens_truncated, stats.tokens_dropped, stats.stopwords_removed);
+    let memory_report = engine.memory_report();
+    println!("{memory_report}");
+    metrics().set("estimated_heap_bytes", memory_report.heap_total() as f64);
+
+    let skipped = engine.ctx().skipped();
+    skipped.save(SKIPPED_PATH)?;
+    metrics().add("files_skipped", skipped.len() as u64);
+    if !skipped.is_empty() {
+        println!("Skipped {} files, 'stats --{SKIPPED_FLAG}' lists them and '--{RETRY_FAILED_FLAG}' tries them again", skipped.len());
+    }
 
     println!("Writing index to a file...");
-    index.save(BufWriter::new(File::create("data/index.txt")?))?;
-    let index_size = File::open("data/index.txt")?.metadata()?.len();
+    persist::save_checked(index_path, |writer| index.save(writer))?;
+    let index_size = File::open(index_path)?.metadata()?.len();
     println!("Index size: {}", human_bytes(index_size as f64));
 
-    index.preprocess(PREPROCESS_LEADER_COUNT);
+    let binary_path = Path::new(index_path).with_extension(BINARY_INDEX_EXTENSION);
+    persist::save_checked(&binary_path, |writer| Ok(index.save_binary(writer)?))?;
+    let binary_size = File::open(&binary_path)?.metadata()?.len();
+    println!("Binary index size: {}", human_bytes(binary_size as f64));
+
+    let (ctx, index) = engine.into_parts();
+
+    Ok(LoadedIndex {
+        name: base_path.to_owned(),
+        ctx,
+        index
+    })
+}
+
+fn stats(flags: &AHashMap<&str, &str>) -> Result<()> {
+    let skipped = SkipLedger::load(SKIPPED_PATH)?;
+    println!("Files skipped by the last build: {}", skipped.len());
+    if flags.contains_key(SKIPPED_FLAG) {
+        for file in &skipped {
+            println!("\t{:?} [{:?}]: {}", file.path, file.stage, file.reason);
+        }
+    }
+
+    Ok(())
+}
+
+fn dump(index_path: &str) -> Result<()> {
+    let data = persist::load_checked(index_path)?;
+    let index = if Path::new(index_path).extension().is_some_and(|extension| extension == BINARY_INDEX_EXTENSION) {
+        InvertedIndex::load_binary(&data)?
+    } else {
+        InvertedIndex::load(data.as_slice())?
+    };
+
+    let mut writer = BufWriter::new(io::stdout().lock());
+    index.dump(&mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+// NOTE: The term is normalized like a query word, so "King" shows the postings of the indexed "king".
+//  A snapshot is read since it is the only saved index that keeps document paths
+fn postings(term: &str, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let snapshot_path = flags.get("snapshot").cloned().unwrap_or(snapshot::DEFAULT_SNAPSHOT_PATH);
+    let corpus_root = flags.get("corpus-root").map(Path::new);
+    let (loaded, _) = snapshot::restore(snapshot_path, corpus_root)?;
+
+    let terms = query_terms(term, &loaded.ctx)?;
+    if terms.is_empty() {
+        return Err(anyhow!("\"{term}\" doesn't produce any index terms"));
+    }
+
+    let mut writer = BufWriter::new(io::stdout().lock());
+    for term in terms.keys().sorted() {
+        let statistics = loaded.index.term_statistics(term);
+        writeln!(writer, "Term \"{term}\": document frequency {}, collection frequency {}, idf {:.6}",
+                 statistics.document_frequency, statistics.collection_frequency, statistics.idf)?;
+        for posting in resolved_postings(term, &loaded.index, &loaded.ctx) {
+            let path = posting.path.map_or("<unknown>".to_owned(), |path| path.to_string_lossy().to_string());
+            writeln!(writer, "\t{}\t{}\t{path}", posting.document_id.id(), posting.count)?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn run() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let (positional, flags) = parse_args(&args)?;
+    if let Some(query_threads) = flags.get("query-threads") {
+        scheduling::configure_query_threads(usize::from_str(query_threads).context("Invalid query thread count")?)?;
+    }
+    match positional.first() {
+        Some(&"compare") => return compare::compare(&flags),
+        Some(&"stats") => return stats(&flags),
+        Some(&"dump") => return dump(positional.get(1).cloned().unwrap_or("data/index.txt")),
+        Some(&"postings") => return postings(positional.get(1).context("Expected a term to show postings of")?, &flags),
+        Some(&"warmup") => return warmup::warmup(positional.get(1).cloned().unwrap_or("data/index.bin"), &flags),
+        Some(&"train") | Some(&"evaluate") | Some(&"knn") | Some(&"similarities") | Some(&"hac") | Some(&"prune") | Some(&"stopwords") | Some(&"snapshot") | Some(&"sample") | Some(&"split") => {
+            let base_path = positional.get(1).cloned().unwrap_or("data/shakespeare");
+            let file_limit = positional.get(2).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+
+            return match positional[0] {
+                "train" => rocchio::train(base_path, file_limit, &flags),
+                "evaluate" => rocchio::evaluate(base_path, file_limit, &flags),
+                "similarities" => similarity_export::export(base_path, file_limit, &flags),
+                "hac" => hac::hac(base_path, file_limit, &flags),
+                "prune" => pruning::prune(base_path, file_limit, &flags),
+                "stopwords" => stopword_proposal::propose(base_path, file_limit, &flags),
+                "snapshot" => snapshot::snapshot(base_path, file_limit, &flags),
+                "sample" => sampling::sample(base_path, file_limit, &flags),
+                "split" => sampling::split(base_path, file_limit, &flags),
+                _ => knn::knn(base_path, file_limit, &flags)
+            };
+        },
+        _ => {}
+    }
+
+    let base_paths = positional.first().cloned().unwrap_or("data/shakespeare");
+    let file_limit = positional.get(1).map(|str| usize::from_str(str).ok()).unwrap_or(None);
+    let mut settings = QuerySettings::from_flags(&flags)?;
+    let router = Router::from_flags(&flags)?;
+
+    // NOTE: Several comma separated folders are indexed separately and queried together
+    let snapshot_path = flags.get("snapshot").cloned().unwrap_or(snapshot::DEFAULT_SNAPSHOT_PATH);
+    // NOTE: Updates are logged against a snapshot, so only a restored index can be updated
+    let (mut indexes, mut compactor) = if base_paths == "restore" {
+        // NOTE: Overrides the folder the snapshot was built from, for a corpus that was moved since
+        let corpus_root = flags.get("corpus-root").map(Path::new);
+        let (mut loaded, sequence) = snapshot::restore(snapshot_path, corpus_root)?;
+        println!("Restored {} documents and {} terms of \"{}\" from \"{snapshot_path}\"", loaded.ctx.document_count(), loaded.index.term_count(), loaded.name);
+
+        let entries = WriteAheadLog::read(WAL_PATH)?
+            .into_iter()
+            .filter(|entry| entry.sequence > sequence)
+            .collect::<Vec<_>>();
+        if !entries.is_empty() {
+            let skipped = wal::replay(&mut loaded, &entries);
+            loaded.index.preprocess(PREPROCESS_LEADER_COUNT, DEFAULT_TIER_SIZE);
+            println!("Replayed {} operations ({skipped} skipped) from \"{WAL_PATH}\"", entries.len());
+        }
+
+        let threshold = flags.get("compact-after")
+            .map(|threshold| usize::from_str(threshold))
+            .transpose()
+            .context("Invalid compaction threshold")?
+            .unwrap_or(compaction::DEFAULT_COMPACTION_THRESHOLD);
+        let wal = WriteAheadLog::open(WAL_PATH, sequence)?;
+
+        (vec![loaded], Some(Compactor::new(snapshot_path, corpus_root, wal, threshold)))
+    } else {
+        let build_settings = BuildSettings::from_flags(&flags)?;
+        let indexes = base_paths.split(',')
+            .enumerate()
+            .map(|(i, base_path)| {
+                let index_path = if i == 0 { "data/index.txt".to_owned() } else { format!("data/index_{i}.txt") };
+
+                build_index_with(base_path, file_limit, &index_path, &build_settings)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        (indexes, None)
+    };
+    if indexes.len() > 1 {
+        println!("Loaded {} indexes, queries are federated", indexes.len());
+    }
+    // NOTE: Categories aren't stored in snapshots, so a restored index is routed again
+    if let Some(router) = &router {
+        for loaded in &mut indexes {
+            let documents = loaded.ctx.document_ids().collect::<Vec<_>>();
+            println!("{}", router.route(loaded, &documents)?);
+        }
+    }
+
+    let qrels_path = flags.get("qrels").cloned().unwrap_or("data/qrels.txt");
+    let mut last_query = None;
 
     let mut buffer = String::new();
     loop {
         println!("Please input your query or 'q' to exit: ");
-        io::stdin().read_line(&mut buffer)?;
-        if buffer.trim() == "q" {
+        // NOTE: Input also ends the loop, it's already used up when the corpus was read from it
+        if io::stdin().read_line(&mut buffer)? == 0 || buffer.trim() == "q" {
             break;
         }
 
-        if let Err(err) = query(&buffer, &index, &ctx) {
+        let command = buffer.trim();
+        let result = if let Some(positions) = command.strip_prefix(":rel ") {
+            label(positions, true, last_query.as_ref(), qrels_path)
+        } else if let Some(positions) = command.strip_prefix(":nonrel ") {
+            label(positions, false, last_query.as_ref(), qrels_path)
+        } else if let Some(args) = command.strip_prefix(":similar ") {
+            match indexes.as_slice() {
+                [loaded] => similar(args, &loaded.index, &loaded.ctx),
+                _ => Err(anyhow!("Similar documents aren't supported for federated queries"))
+            }
+        } else if let Some(args) = command.strip_prefix(":related ") {
+            match indexes.as_slice() {
+                [loaded] => related(args, &loaded.index, &loaded.ctx),
+                _ => Err(anyhow!("Related terms aren't supported for federated queries"))
+            }
+        } else if let Some(filter) = command.strip_prefix(":random").filter(|filter| filter.is_empty() || filter.starts_with(' ')) {
+            match indexes.as_slice() {
+                [loaded] => random(filter, &loaded.index, &loaded.ctx),
+                _ => Err(anyhow!("Random documents aren't supported for federated queries"))
+            }
+        } else if command == ":leaders" {
+            match indexes.as_slice() {
+                [loaded] => {
+                    print_leaders(&loaded.index, &loaded.ctx, &settings);
+
+                    Ok(())
+                },
+                _ => Err(anyhow!("Leaders can only be listed for a single index"))
+            }
+        } else if let Some(name) = command.strip_prefix(":ranker ") {
+            Ranking::from_str(name.trim())
+                .map(|ranking| {
+                    settings.ranking = ranking.normalized(settings.normalization.unwrap_or_default());
+                    println!("Ranking with: {}", name.trim());
+                })
+                .map_err(anyhow::Error::from)
+        } else if command == ":memory" {
+            for loaded in &indexes {
+                println!("Index \"{}\":\n{}", loaded.name, MemoryReport::new(&loaded.index, &loaded.ctx));
+            }
+
+            Ok(())
+        } else if let Some(path) = command.strip_prefix(":add ") {
+            let document = indexes.first().map_or(0, |loaded| loaded.ctx.document_count());
+            update(Operation::Add { path: PathBuf::from(path.trim()), document }, &mut indexes, compactor.as_mut(), router.as_ref())
+        } else if let Some(document) = command.strip_prefix(":remove ") {
+            usize::from_str(document.trim())
+                .context("Invalid document id")
+                .and_then(|document| update(Operation::Remove { document }, &mut indexes, compactor.as_mut(), router.as_ref()))
+        } else if command == ":categories" {
+            for loaded in &indexes {
+                print_categories(loaded);
+            }
+
+            Ok(())
+        } else if command == ":flush" {
+            flush(snapshot_path, &indexes, compactor.as_mut())
+        } else if command == ":compact" {
+            compactor.as_mut()
+                .ok_or_else(|| anyhow!("Only an index started with 'restore' can be compacted"))
+                .and_then(|compactor| compactor.compact_now())
+                .map(compaction::print_report)
+        } else if let Some(query_text) = command.strip_prefix(":cluster ") {
+            match indexes.as_slice() {
+                [loaded] => cluster_query(query_text, &loaded.index, &loaded.ctx, &settings),
+                _ => Err(anyhow!("Clustering isn't supported for federated queries"))
+            }
+        } else {
+            let documents = match (command.strip_prefix(":batch "), indexes.as_slice()) {
+                (Some(path), [loaded]) => query_batch(path.trim(), &loaded.index, &loaded.ctx, &settings).map(|_| None),
+                (Some(path), indexes) => federated_query_batch(path.trim(), indexes, &settings).map(|_| None),
+                (None, [loaded]) => query(&buffer, &loaded.index, &loaded.ctx, &settings).map(Some),
+                (None, indexes) => federated_query(&buffer, indexes, &settings).map(Some)
+            };
+
+            documents.map(|documents| {
+                if let Some(documents) = documents {
+                    last_query = Some(LastQuery { text: command.to_owned(), documents });
+                }
+            })
+        };
+        if let Err(err) = result {
             println!("Error: {}. Caused by: {}", err, err.root_cause());
         }
+        if let Some(report) = compactor.as_mut().and_then(Compactor::poll) {
+            match report {
+                Ok(report) => compaction::print_report(report),
+                Err(err) => println!("Compaction failed. Error: {}. Caused by: {}", err, err.root_cause())
+            }
+        }
         println!();
 
         buffer.clear();
     }
 
+    if let Some(Err(err)) = compactor.as_mut().and_then(Compactor::wait) {
+        println!("Compaction failed. Error: {}. Caused by: {}", err, err.root_cause());
+    }
+
     Ok(())
 }
+
+fn update(operation: Operation, indexes: &mut [LoadedIndex], compactor: Option<&mut Compactor>, router: Option<&Router>) -> Result<()> {
+    let (Some(compactor), [loaded]) = (compactor, indexes) else {
+        return Err(anyhow!("Only an index started with 'restore' can be updated"));
+    };
+
+    let document_id = wal::apply(loaded, &operation)?;
+    let entry = compactor.wal().lock().unwrap().append(operation)?;
+    loaded.index.preprocess(PREPROCESS_LEADER_COUNT, DEFAULT_TIER_SIZE);
+    match &entry.operation {
+        Operation::Add { path, .. } => println!("Added {path:?} as {document_id}"),
+        Operation::Remove { .. } => println!("Removed {document_id}")
+    }
+    if let (Some(router), Operation::Add { .. }) = (router, &entry.operation) {
+        router.route(loaded, &[document_id])?;
+        let category = routing::category(&loaded.ctx, document_id).unwrap_or("none, below the threshold");
+        println!("Category: {category}");
+    }
+    compactor.maybe_start();
+
+    Ok(())
+}
+
+// NOTE: Running compaction would otherwise overwrite the snapshot with an older state
+fn flush(snapshot_path: &str, indexes: &[LoadedIndex], compactor: Option<&mut Compactor>) -> Result<()> {
+    let (Some(compactor), [loaded]) = (compactor, indexes) else {
+        return Err(anyhow!("Only an index started with 'restore' can be flushed"));
+    };
+    if let Some(report) = compactor.wait() {
+        report?;
+    }
+
+    let mut wal = compactor.wal().lock().unwrap();
+    snapshot::save(loaded, snapshot_path, wal.last_sequence())?;
+    wal.clear()?;
+    println!("Snapshot written to \"{snapshot_path}\", write-ahead log cleared");
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let result = run();
+    metrics().save_to_file(Metrics::PATH)?;
+
+    result
+}
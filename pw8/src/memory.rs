@@ -0,0 +1,58 @@
+use std::fmt::{Display, Formatter};
+use human_bytes::human_bytes;
+use crate::inf_context::InfContext;
+use crate::term_index::InvertedIndex;
+
+/// Estimated bytes held by an index and the documents it was built from.
+///
+/// Sizes are derived from lengths and capacities of the underlying collections,
+/// allocator overhead isn't included.
+#[derive(Default, Debug)]
+pub struct MemoryReport {
+    pub dictionary: usize,
+    pub postings: usize,
+    pub vectors: usize,
+    pub clusters: usize,
+    pub documents: usize,
+    pub buffers: usize,
+    pub mmaps: usize
+}
+
+impl MemoryReport {
+    pub fn new(index: &InvertedIndex, ctx: &InfContext) -> Self {
+        let mut report = MemoryReport::default();
+        index.estimate_memory(&mut report);
+        ctx.estimate_memory(&mut report);
+
+        report
+    }
+
+    // NOTE: Memory mapped files are paged in by the OS and can be dropped under
+    //  memory pressure, so they aren't part of the heap total
+    pub fn heap_total(&self) -> usize {
+        self.dictionary + self.postings + self.vectors + self.clusters + self.documents + self.buffers
+    }
+}
+
+impl Display for MemoryReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let parts = [
+            ("Dictionary", self.dictionary),
+            ("Postings", self.postings),
+            ("Document vectors", self.vectors),
+            ("Clusters", self.clusters),
+            ("Document registry", self.documents),
+            ("In-memory documents", self.buffers)
+        ];
+        for (name, size) in parts {
+            writeln!(f, "{name}: {}", human_bytes(size as f64))?;
+        }
+
+        write!(f, "Heap total: {}. Memory mapped files: {}", human_bytes(self.heap_total() as f64), human_bytes(self.mmaps as f64))
+    }
+}
+
+// NOTE: Hash tables keep a control byte for every slot next to the entry
+pub fn hash_table_size<T>(capacity: usize) -> usize {
+    capacity * (size_of::<T>() + 1)
+}
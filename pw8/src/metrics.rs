@@ -0,0 +1,133 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+#[derive(Default, Serialize)]
+struct TimerStats {
+    count: usize,
+    total_seconds: f64,
+    max_seconds: f64
+}
+
+#[derive(Default, Serialize)]
+struct Registry {
+    timers: BTreeMap<String, TimerStats>,
+    counters: BTreeMap<String, u64>,
+    gauges: BTreeMap<String, f64>
+}
+
+// NOTE: Shared by every subsystem of a run, so it lives in a global and uses interior mutability
+pub struct Metrics {
+    registry: Mutex<Registry>
+}
+
+impl Metrics {
+    pub const PATH: &'static str = "data/metrics.json";
+
+    pub fn new() -> Self {
+        Metrics { registry: Mutex::new(Registry::default()) }
+    }
+
+    pub fn time<FnT, ResT>(&self, name: &str, func: FnT) -> (ResT, Duration)
+    where FnT: FnOnce() -> ResT
+    {
+        let start = Instant::now();
+        let result = func();
+        let time = start.elapsed();
+        self.record_time(name, time);
+
+        (result, time)
+    }
+
+    pub fn record_time(&self, name: &str, time: Duration) {
+        let mut registry = self.registry.lock().unwrap();
+        let timer = registry.timers.entry(name.to_owned()).or_default();
+        timer.count += 1;
+        timer.total_seconds += time.as_secs_f64();
+        timer.max_seconds = timer.max_seconds.max(time.as_secs_f64());
+    }
+
+    pub fn add(&self, name: &str, delta: u64) {
+        *self.registry.lock().unwrap().counters.entry(name.to_owned()).or_default() += delta;
+    }
+
+    pub fn set(&self, name: &str, value: f64) {
+        self.registry.lock().unwrap().gauges.insert(name.to_owned(), value);
+    }
+
+    pub fn save(&self, mut writer: impl Write) -> Result<()> {
+        if let Some(memory) = peak_memory() {
+            self.set("peak_memory_bytes", memory as f64);
+        }
+
+        serde_json::to_writer_pretty(&mut writer, &*self.registry.lock().unwrap())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn save_to_file(&self, path: &str) -> Result<()> {
+        self.save(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// NOTE: Bounded channels don't expose their length, so producers count a partial
+//  index as queued before sending it and the consumer takes it off once received
+pub struct QueueDepth {
+    current: AtomicUsize,
+    max: AtomicUsize
+}
+
+impl QueueDepth {
+    pub fn new() -> Self {
+        QueueDepth {
+            current: AtomicUsize::new(0),
+            max: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn push(&self) {
+        let depth = self.current.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub fn pop(&self) {
+        self.current.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn max(&self) -> usize {
+        self.max.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for QueueDepth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+    METRICS.get_or_init(Metrics::new)
+}
+
+// NOTE: Peak resident set size, only known on Linux
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,82 @@
+use crate::error::ParseError;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use serde::Serialize;
+use crate::term_index::QueryResult;
+
+// NOTE: Scores of different rankers and indexes live on different scales,
+//  normalizing them per result makes them comparable before they're combined
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScoreNormalization {
+    #[default]
+    None,
+    MinMax,
+    ZScore
+}
+
+impl ScoreNormalization {
+    pub fn apply(self, result: &QueryResult) -> QueryResult {
+        match self {
+            ScoreNormalization::None => result.clone(),
+            ScoreNormalization::MinMax => min_max(result),
+            ScoreNormalization::ZScore => z_scores(result)
+        }
+    }
+}
+
+impl FromStr for ScoreNormalization {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        Ok(match s.to_lowercase().as_str() {
+            "none" => ScoreNormalization::None,
+            "min-max" | "minmax" => ScoreNormalization::MinMax,
+            "z-score" | "zscore" => ScoreNormalization::ZScore,
+            _ => return Err(ParseError::UnknownNormalization(s.to_owned()))
+        })
+    }
+}
+
+impl Display for ScoreNormalization {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ScoreNormalization::None => "none",
+            ScoreNormalization::MinMax => "min-max",
+            ScoreNormalization::ZScore => "z-score"
+        })
+    }
+}
+
+// NOTE: Result where every score is the same maps to 1, it is still a full match of its ranker
+pub fn min_max(result: &QueryResult) -> QueryResult {
+    let min = result.iter().map(|&(_, weight)| weight).fold(f64::INFINITY, f64::min);
+    let max = result.iter().map(|&(_, weight)| weight).fold(f64::NEG_INFINITY, f64::max);
+
+    result.iter()
+        .map(|&(document_id, weight)| {
+            let score = if max == min { 1.0 } else { (weight - min) / (max - min) };
+
+            (document_id, score)
+        })
+        .collect()
+}
+
+pub fn z_scores(result: &QueryResult) -> QueryResult {
+    if result.is_empty() {
+        return Vec::new();
+    }
+
+    let count = result.len() as f64;
+    let mean = result.iter().map(|(_, weight)| weight).sum::<f64>() / count;
+    let deviation = (result.iter().map(|(_, weight)| (weight - mean).powi(2)).sum::<f64>() / count).sqrt();
+
+    result.iter()
+        .map(|&(document_id, weight)| {
+            let score = if deviation == 0.0 { 0.0 } else { (weight - mean) / deviation };
+
+            (document_id, score)
+        })
+        .collect()
+}
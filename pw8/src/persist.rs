@@ -0,0 +1,73 @@
+use anyhow::Result;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crc32fast::Hasher;
+use crate::error::StorageError;
+
+// NOTE: Fixed size trailer, a file that doesn't end with it was never completely written
+const CHECKSUM_PREFIX: &[u8] = b"#crc32 ";
+const CHECKSUM_LENGTH: usize = CHECKSUM_PREFIX.len() + 8 + 1;
+
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Hasher
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// NOTE: Content goes to a temporary file that replaces the target only once it is
+//  complete and synced, so an interrupted write leaves the previous file intact
+pub fn save_checked(path: impl AsRef<Path>, write: impl FnOnce(&mut ChecksumWriter<BufWriter<File>>) -> Result<()>) -> Result<()> {
+    let path = path.as_ref();
+    let temp_path = path.with_extension("tmp");
+
+    let mut writer = ChecksumWriter {
+        inner: BufWriter::new(File::create(&temp_path)?),
+        hasher: Hasher::new()
+    };
+    write(&mut writer)?;
+    let checksum = writer.hasher.finalize();
+    let mut inner = writer.inner;
+    inner.write_all(CHECKSUM_PREFIX)?;
+    inner.write_all(format!("{checksum:08x}\n").as_bytes())?;
+    inner.into_inner()?.sync_all()?;
+    fs::rename(&temp_path, path)?;
+
+    // NOTE: Directories can't be synced on every platform, the rename is still in place then
+    if let Some(parent) = path.parent() {
+        let _ = File::open(parent).and_then(|directory| directory.sync_all());
+    }
+
+    Ok(())
+}
+
+pub fn load_checked(path: impl AsRef<Path>) -> Result<Vec<u8>, StorageError> {
+    let path = path.as_ref();
+    let mut data = fs::read(path)?;
+    let content_length = data.len().checked_sub(CHECKSUM_LENGTH)
+        .filter(|&length| data[length..].starts_with(CHECKSUM_PREFIX))
+        .ok_or_else(|| StorageError::MissingChecksum(path.to_owned()))?;
+
+    let expected = std::str::from_utf8(&data[content_length + CHECKSUM_PREFIX.len()..data.len() - 1]).ok()
+        .and_then(|checksum| u32::from_str_radix(checksum, 16).ok())
+        .ok_or_else(|| StorageError::MalformedChecksum(path.to_owned()))?;
+    data.truncate(content_length);
+    let actual = crc32fast::hash(&data);
+    if actual != expected {
+        return Err(StorageError::ChecksumMismatch { path: path.to_owned(), actual, expected });
+    }
+
+    Ok(data)
+}
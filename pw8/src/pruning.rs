@@ -0,0 +1,87 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::BufReader;
+use std::str::FromStr;
+use ahash::AHashMap;
+use human_bytes::human_bytes;
+use crate::persist;
+use crate::compare::{flag, overlap, run};
+use crate::qrels::Qrels;
+use crate::{build_index, read_query_lines, LoadedIndex, QuerySettings, PREPROCESS_LEADER_COUNT};
+use crate::term_index::DEFAULT_TIER_SIZE;
+
+const DEFAULT_TOLERANCE: f64 = 0.05;
+const DEFAULT_DEPTH: usize = 10;
+
+fn parse_flag<T: FromStr>(flags: &AHashMap<&str, &str>, name: &str, default: T) -> Result<T> {
+    flags.get(name)
+        .map(|value| T::from_str(value).map_err(|_| anyhow!("Invalid value for flag '--{name}'")))
+        .transpose()
+        .map(|value| value.unwrap_or(default))
+}
+
+fn run_queries(loaded: &LoadedIndex, settings: &QuerySettings, queries: &[String]) -> Result<Vec<Vec<String>>> {
+    queries.iter()
+        .map(|query_text| run(loaded, settings, query_text).map(|run| run.documents))
+        .collect()
+}
+
+// NOTE: With judgments effectiveness is precision@k, without them it's overlap@k
+//  with the unpruned ranking, i.e. how much of the original top stays in place
+pub fn prune(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let percentile = f64::from_str(flag(flags, "percentile")?).context("Invalid percentile")?;
+    let tolerance = parse_flag(flags, "tolerance", DEFAULT_TOLERANCE)?;
+    let depth = parse_flag(flags, "depth", DEFAULT_DEPTH)?;
+    let output_path = flags.get("output").cloned().unwrap_or("data/index_pruned.txt");
+    let queries = read_query_lines(flag(flags, "queries")?)?;
+    let qrels = flags.get("qrels")
+        .map(|path| Qrels::load(BufReader::new(File::open(path)?)))
+        .transpose()?;
+    let settings = QuerySettings::from_flags(flags)?;
+
+    let mut loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    let before = run_queries(&loaded, &settings, &queries)?;
+    let postings_before = loaded.index.posting_count();
+
+    let pruned = loaded.index.prune(percentile);
+    loaded.index.preprocess(PREPROCESS_LEADER_COUNT, DEFAULT_TIER_SIZE);
+    let after = run_queries(&loaded, &settings, &queries)?;
+
+    persist::save_checked(output_path, |writer| loaded.index.save(writer))?;
+    let index_size = File::open(output_path)?.metadata()?.len();
+    println!("Pruned {} of {} postings, {} terms left", pruned, postings_before, loaded.index.term_count());
+    println!("Pruned index size: {}", human_bytes(index_size as f64));
+
+    let effectiveness_loss = match &qrels {
+        Some(qrels) => {
+            let precisions = queries.iter()
+                .zip(before.iter().zip(&after))
+                .filter_map(|(query_text, (before, after))| {
+                    Some((qrels.precision_at(query_text, before, depth)?, qrels.precision_at(query_text, after, depth)?))
+                })
+                .collect::<Vec<_>>();
+            let count = precisions.len().max(1) as f64;
+            let precision_before = precisions.iter().map(|(before, _)| before).sum::<f64>() / count;
+            let precision_after = precisions.iter().map(|(_, after)| after).sum::<f64>() / count;
+            println!("Mean P@{depth} over {} judged queries: before {precision_before:.4}, after {precision_after:.4}", precisions.len());
+
+            precision_before - precision_after
+        },
+        None => {
+            let mean_overlap = before.iter()
+                .zip(&after)
+                .map(|(before, after)| overlap(before, after, depth))
+                .sum::<f64>() / queries.len().max(1) as f64;
+            println!("Mean overlap@{depth} with unpruned results: {mean_overlap:.4}");
+
+            1.0 - mean_overlap
+        }
+    };
+
+    if effectiveness_loss > tolerance {
+        return Err(anyhow!("Effectiveness dropped by {effectiveness_loss:.4}, which is more than tolerance {tolerance}"));
+    }
+    println!("Effectiveness loss {effectiveness_loss:.4} is within tolerance {tolerance}");
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use std::str::FromStr;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use crate::engine::{IndexBuilder, Query, SearchEngine};
+use crate::normalization::ScoreNormalization;
+use crate::ranking::Ranking;
+
+/// Index over a folder of documents, built and queried from Python.
+#[pyclass(name = "Index", frozen)]
+pub struct PyIndex {
+    engine: SearchEngine
+}
+
+#[pymethods]
+impl PyIndex {
+    #[staticmethod]
+    #[pyo3(signature = (path, file_limit = None))]
+    fn build(path: &str, file_limit: Option<usize>) -> Result<Self> {
+        let mut builder = IndexBuilder::new(path);
+        if let Some(file_limit) = file_limit {
+            builder = builder.file_limit(file_limit);
+        }
+
+        Ok(PyIndex { engine: builder.build()? })
+    }
+
+    /// Ranked search, every result is a dict with document `id`, `name`, `score`, its `normalization`
+    /// and `terms`, pairs of the query terms it contains and how many times it contains them.
+    #[pyo3(signature = (text, limit = None, ranker = None, normalization = None))]
+    fn query<'py>(&self, py: Python<'py>, text: &str, limit: Option<usize>, ranker: Option<&str>, normalization: Option<&str>) -> Result<Vec<Bound<'py, PyDict>>> {
+        let mut query = Query::new(text);
+        if let Some(normalization) = normalization {
+            query = query.normalization(ScoreNormalization::from_str(normalization)?);
+        }
+        if let Some(ranker) = ranker {
+            query = query.ranking(Ranking::from_str(ranker)?);
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+
+        self.engine.search(&query)?
+            .into_iter()
+            .map(|result| {
+                let dict = PyDict::new_bound(py);
+                dict.set_item("id", result.document_id.id())?;
+                dict.set_item("name", result.name)?;
+                dict.set_item("score", result.score)?;
+                dict.set_item("normalization", result.normalization.to_string())?;
+                dict.set_item("terms", result.matched_terms.into_iter().map(|term| (term.term, term.count)).collect::<Vec<_>>())?;
+
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    #[getter]
+    fn document_count(&self) -> usize {
+        self.engine.ctx().document_ids().count()
+    }
+
+    #[getter]
+    fn term_count(&self) -> usize {
+        self.engine.index().term_count()
+    }
+}
+
+#[pymodule]
+fn ir(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyIndex>()
+}
@@ -0,0 +1,88 @@
+use anyhow::{anyhow, Result};
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use ahash::AHashMap;
+use itertools::Itertools;
+
+// NOTE: Judgments are keyed by query text and document name,
+//  one "query<TAB>document<TAB>relevance" per line
+pub struct Qrels {
+    judgments: AHashMap<String, AHashMap<String, bool>>
+}
+
+impl Qrels {
+    const SEPARATOR: char = '\t';
+
+    pub fn new() -> Self {
+        Qrels { judgments: AHashMap::new() }
+    }
+
+    pub fn load(reader: impl BufRead) -> Result<Self> {
+        let mut qrels = Qrels::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut parts = line.split(Self::SEPARATOR);
+            let (Some(query), Some(document), Some(relevance), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+                return Err(anyhow!("Expected query, document and relevance on line {}", i + 1));
+            };
+            let relevance = u32::from_str(relevance.trim())
+                .map_err(|_| anyhow!("Invalid relevance on line {}", i + 1))?;
+
+            qrels.add_judgment(query, document, relevance != 0);
+        }
+
+        Ok(qrels)
+    }
+
+    pub fn add_judgment(&mut self, query: &str, document: &str, relevant: bool) {
+        self.judgments.entry(query.trim().to_owned())
+            .or_default()
+            .insert(document.to_owned(), relevant);
+    }
+
+    pub fn write_judgment(mut writer: impl Write, query: &str, document: &str, relevant: bool) -> Result<()> {
+        writeln!(writer, "{}{}{}{}{}", query.trim(), Self::SEPARATOR, document, Self::SEPARATOR, u32::from(relevant))?;
+
+        Ok(())
+    }
+
+    pub fn precision_at(&self, query: &str, documents: &[String], depth: usize) -> Option<f64> {
+        let judgments = self.judgments.get(query.trim())?;
+        let relevant = documents.iter()
+            .unique()
+            .take(depth)
+            .filter(|document| judgments.get(*document).cloned().unwrap_or(false))
+            .count();
+
+        Some(relevant as f64 / depth as f64)
+    }
+
+    pub fn average_precision(&self, query: &str, documents: &[String]) -> Option<f64> {
+        let judgments = self.judgments.get(query.trim())?;
+        let relevant_count = judgments.values().filter(|&&relevant| relevant).count();
+        if relevant_count == 0 {
+            return Some(0.0);
+        }
+
+        let mut found = 0;
+        let mut precision_sum = 0.0;
+        for (i, document) in documents.iter().unique().enumerate() {
+            if judgments.get(document).cloned().unwrap_or(false) {
+                found += 1;
+                precision_sum += found as f64 / (i + 1) as f64;
+            }
+        }
+
+        Some(precision_sum / relevant_count as f64)
+    }
+}
+
+impl Default for Qrels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,243 @@
+use ahash::{AHashMap, AHashSet};
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::document::DocumentId;
+use crate::error::{IndexError, ParseError};
+use crate::term_index::{sorted_by_weight, InvertedIndex, Query, QueryResult};
+use crate::vector::cosine_sim;
+
+pub const DEFAULT_BM25_K1: f64 = 1.2;
+pub const DEFAULT_BM25_B: f64 = 0.75;
+// NOTE: Commonly used Dirichlet prior, roughly the length of a long document
+const LM_MU: f64 = 2000.0;
+// NOTE: Slopes around 0.2 worked best in the original pivoted normalization experiments
+pub const DEFAULT_PIVOT_SLOPE: f64 = 0.2;
+
+/// Scores candidate documents of a query, most relevant first.
+pub trait Ranker: Send + Sync {
+    fn name(&self) -> &'static str;
+    // NOTE: Candidates contain at least one query term, documents outside of them aren't scored
+    fn score(&self, index: &InvertedIndex, terms: &Query, candidates: &AHashSet<DocumentId>) -> Result<QueryResult, IndexError>;
+}
+
+impl Debug for dyn Ranker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LengthNormalization {
+    // NOTE: Document vectors are divided by their length, which favours short documents too much
+    Cosine,
+    // NOTE: Raw tf-idf divided by (1 - slope) * average length + slope * length, a document of average
+    //  length is scored as before, longer ones are penalized less than by cosine and shorter ones more
+    Pivoted(f64)
+}
+
+// NOTE: Exact vector space score against every candidate, unlike the cluster
+//  ranking, which only looks at followers of the closest leaders
+pub struct CosineRanker {
+    pub normalization: LengthNormalization
+}
+
+impl CosineRanker {
+    pub fn new() -> Self {
+        CosineRanker { normalization: LengthNormalization::Cosine }
+    }
+
+    pub fn pivoted(slope: f64) -> Self {
+        CosineRanker { normalization: LengthNormalization::Pivoted(slope) }
+    }
+}
+
+impl Ranker for CosineRanker {
+    fn name(&self) -> &'static str {
+        match self.normalization {
+            LengthNormalization::Cosine => "cosine",
+            LengthNormalization::Pivoted(_) => "pivoted"
+        }
+    }
+
+    fn score(&self, index: &InvertedIndex, terms: &Query, candidates: &AHashSet<DocumentId>) -> Result<QueryResult, IndexError> {
+        let needle = index.query_vector(terms);
+        if needle.magnitude_squared() == 0.0 {
+            return Err(IndexError::NoMatchingTerms);
+        }
+
+        let average_length = index.total_term_count() as f64 / index.document_count().max(1) as f64;
+        let score = |document_id, vector| match self.normalization {
+            LengthNormalization::Cosine => cosine_sim(&needle, vector),
+            LengthNormalization::Pivoted(slope) => {
+                let length = index.document_term_count(document_id) as f64;
+                let pivoted_length = (1.0 - slope) * average_length + slope * length;
+
+                needle.dot(vector) * length / (pivoted_length * needle.magnitude())
+            }
+        };
+
+        Ok(sorted_by_weight(candidates.iter()
+            .filter_map(|&document_id| index.document_vector(document_id).map(|vector| (document_id, score(document_id, vector))))
+            .collect()))
+    }
+}
+
+impl Default for CosineRanker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct TermFrequencyRanker;
+
+impl Ranker for TermFrequencyRanker {
+    fn name(&self) -> &'static str {
+        "tf"
+    }
+
+    fn score(&self, index: &InvertedIndex, terms: &Query, candidates: &AHashSet<DocumentId>) -> Result<QueryResult, IndexError> {
+        let mut weights = AHashMap::new();
+        for (positions, &query_weight) in terms.iter().filter_map(|(term, weight)| index.term_positions(term).map(|positions| (positions, weight))) {
+            positions.iter()
+                .filter(|(document_id, _)| candidates.contains(document_id))
+                .for_each(|(&document_id, &count)| *weights.entry(document_id).or_default() += count as f64 * query_weight);
+        }
+
+        Ok(sorted_by_weight(weights))
+    }
+}
+
+// NOTE: K1 controls how fast repeated occurrences of a term saturate, b how much document length,
+//  taken from the term counts of the index, is normalized with. With b of 0 length is ignored
+pub struct Bm25Ranker {
+    pub k1: f64,
+    pub b: f64
+}
+
+impl Bm25Ranker {
+    pub fn new(k1: f64, b: f64) -> Self {
+        Bm25Ranker { k1, b }
+    }
+}
+
+impl Ranker for Bm25Ranker {
+    fn name(&self) -> &'static str {
+        "bm25"
+    }
+
+    fn score(&self, index: &InvertedIndex, terms: &Query, candidates: &AHashSet<DocumentId>) -> Result<QueryResult, IndexError> {
+        let document_count = index.document_count() as f64;
+        let average_length = index.total_term_count() as f64 / document_count.max(1.0);
+
+        let mut weights = AHashMap::new();
+        for (positions, &query_weight) in terms.iter().filter_map(|(term, weight)| index.term_positions(term).map(|positions| (positions, weight))) {
+            let frequency = positions.document_count() as f64;
+            let idf = ((document_count - frequency + 0.5) / (frequency + 0.5) + 1.0).ln();
+            for (&document_id, &count) in positions.iter().filter(|(document_id, _)| candidates.contains(document_id)) {
+                let count = count as f64;
+                let length = index.document_term_count(document_id) as f64;
+                let norm = 1.0 - self.b + self.b * length / average_length;
+
+                *weights.entry(document_id).or_default() += query_weight * idf * count * (self.k1 + 1.0) / (count + self.k1 * norm);
+            }
+        }
+
+        Ok(sorted_by_weight(weights))
+    }
+}
+
+impl Default for Bm25Ranker {
+    fn default() -> Self {
+        Bm25Ranker::new(DEFAULT_BM25_K1, DEFAULT_BM25_B)
+    }
+}
+
+// NOTE: Query likelihood with Dirichlet smoothing, a term missing from a document
+//  still has its collection probability, so longer queries don't zero everything out
+pub struct LanguageModelRanker {
+    pub mu: f64
+}
+
+impl Ranker for LanguageModelRanker {
+    fn name(&self) -> &'static str {
+        "lm"
+    }
+
+    fn score(&self, index: &InvertedIndex, terms: &Query, candidates: &AHashSet<DocumentId>) -> Result<QueryResult, IndexError> {
+        let collection_length = index.total_term_count().max(1) as f64;
+        let collection_probabilities = terms.iter()
+            .filter_map(|(term, &query_weight)| {
+                let positions = index.term_positions(term)?;
+
+                Some((positions, query_weight, positions.total_count() as f64 / collection_length))
+            })
+            .collect::<Vec<_>>();
+        if collection_probabilities.is_empty() {
+            return Err(IndexError::NoMatchingTerms);
+        }
+
+        Ok(sorted_by_weight(candidates.iter()
+            .map(|&document_id| {
+                let length = index.document_term_count(document_id) as f64;
+                let score = collection_probabilities.iter()
+                    .map(|(positions, query_weight, probability)| {
+                        query_weight * ((positions.count(document_id) as f64 + self.mu * probability) / (length + self.mu)).ln()
+                    })
+                    .sum();
+
+                (document_id, score)
+            })
+            .collect()))
+    }
+}
+
+impl Default for LanguageModelRanker {
+    fn default() -> Self {
+        LanguageModelRanker { mu: LM_MU }
+    }
+}
+
+// NOTE: Pivoted normalization takes its slope after a colon, e.g. "pivoted:0.3",
+//  BM25 takes k1 and optionally b, e.g. "bm25:1.5" or "bm25:1.5:0.5"
+pub fn ranker_by_name(name: &str) -> Result<Arc<dyn Ranker>, ParseError> {
+    let lowercase = name.to_lowercase();
+    let (ranker, parameter) = match lowercase.split_once(':') {
+        Some((ranker, parameter)) => (ranker, Some(parameter)),
+        None => (lowercase.as_str(), None)
+    };
+
+    Ok(match (ranker, parameter) {
+        ("pivoted", slope) => {
+            let slope = slope.map_or(Ok(DEFAULT_PIVOT_SLOPE), |slope| {
+                f64::from_str(slope).ok()
+                    .filter(|slope| (0.0..=1.0).contains(slope))
+                    .ok_or_else(|| ParseError::InvalidSlope(slope.to_owned()))
+            })?;
+
+            Arc::new(CosineRanker::pivoted(slope))
+        },
+        ("vsm" | "cosine", None) => Arc::new(CosineRanker::new()),
+        ("tf", None) => Arc::new(TermFrequencyRanker),
+        ("bm25", None) => Arc::new(Bm25Ranker::default()),
+        ("bm25", Some(parameters)) => {
+            let invalid = || ParseError::InvalidBm25Parameters(parameters.to_owned());
+            let (k1, b) = match parameters.split_once(':') {
+                Some((k1, b)) => (k1, Some(b)),
+                None => (parameters, None)
+            };
+            let k1 = f64::from_str(k1).ok()
+                .filter(|k1| *k1 >= 0.0)
+                .ok_or_else(invalid)?;
+            let b = b.map_or(Ok(DEFAULT_BM25_B), |b| {
+                f64::from_str(b).ok()
+                    .filter(|b| (0.0..=1.0).contains(b))
+                    .ok_or_else(invalid)
+            })?;
+
+            Arc::new(Bm25Ranker::new(k1, b))
+        },
+        ("lm", None) => Arc::new(LanguageModelRanker::default()),
+        _ => return Err(ParseError::UnknownRanker(name.to_owned()))
+    })
+}
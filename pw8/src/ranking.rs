@@ -0,0 +1,158 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use ahash::AHashMap;
+use rayon::prelude::*;
+use crate::error::{IndexError, ParseError};
+use crate::normalization::ScoreNormalization;
+use crate::rankers::{ranker_by_name, Ranker};
+use crate::term_index::{sorted_by_weight, InvertedIndex, Query, QueryResult, TermIndex};
+
+// NOTE: Commonly used constant, dampens the difference between top ranks
+const RRF_K: f64 = 60.0;
+pub const DEFAULT_TIER_RESULT_COUNT: usize = 10;
+pub const DEFAULT_EXACT_TOP_K: usize = 10;
+
+#[derive(Clone, Default, Debug)]
+pub enum Ranking {
+    #[default]
+    Cluster,
+    // NOTE: Ranker picked by name, it scores every document containing a query term
+    Scored(Arc<dyn Ranker>),
+    // NOTE: Ranker that only scores champion lists of the query terms, the full postings are
+    //  scored only when the champions give fewer than the given number of results
+    Tiered(Arc<dyn Ranker>, usize),
+    // NOTE: Top k of the similarity the clusters approximate, without skipping any document that could be in it
+    Exact(usize),
+    // NOTE: Without a normalization results are fused by rank, otherwise normalized scores are summed
+    Fusion(Vec<Ranking>, ScoreNormalization)
+}
+
+impl Ranking {
+    const FUSION_PREFIX: &'static str = "fusion:";
+    const FUSION_SEPARATOR: &'static str = ",";
+    const TIERED_PREFIX: &'static str = "tiered:";
+    const EXACT_PREFIX: &'static str = "exact:";
+
+    // NOTE: Only fusion combines scores, other rankings are returned unchanged
+    pub fn normalized(self, normalization: ScoreNormalization) -> Self {
+        match self {
+            Ranking::Fusion(rankings, _) => Ranking::Fusion(rankings, normalization),
+            ranking => ranking
+        }
+    }
+
+    // NOTE: Applies to tiered rankings, also the ones combined by fusion
+    pub fn tiered_result_count(self, result_count: usize) -> Self {
+        match self {
+            Ranking::Tiered(ranker, _) => Ranking::Tiered(ranker, result_count),
+            Ranking::Fusion(rankings, normalization) => Ranking::Fusion(
+                rankings.into_iter()
+                    .map(|ranking| ranking.tiered_result_count(result_count))
+                    .collect(),
+                normalization
+            ),
+            ranking => ranking
+        }
+    }
+
+    // NOTE: Fusion already normalized the scores of every ranker, other rankings are normalized as a whole
+    pub fn normalize_result(&self, result: QueryResult, normalization: ScoreNormalization) -> QueryResult {
+        match (self, normalization) {
+            (Ranking::Fusion(..), _) | (_, ScoreNormalization::None) => result,
+            _ => normalization.apply(&result)
+        }
+    }
+
+    pub fn rank(&self, index: &InvertedIndex, terms: &Query, leader_count: usize) -> Result<QueryResult, IndexError> {
+        index.check_preprocessed()?;
+        Ok(match self {
+            Ranking::Cluster => index.query(terms, leader_count)?,
+            &Ranking::Exact(k) => index.query_top_k(terms, k)?,
+            Ranking::Scored(ranker) => ranker.score(index, terms, &index.candidates(terms))?,
+            Ranking::Tiered(ranker, result_count) => {
+                let result = ranker.score(index, terms, &index.champion_candidates(terms))?;
+                if result.len() >= *result_count {
+                    result
+                } else {
+                    ranker.score(index, terms, &index.candidates(terms))?
+                }
+            },
+            Ranking::Fusion(rankings, normalization) => {
+                let results = rankings.iter()
+                    .map(|ranking| ranking.rank(index, terms, leader_count))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                match normalization {
+                    ScoreNormalization::None => reciprocal_rank_fusion(&results),
+                    &normalization => normalized_score_fusion(&results, normalization)
+                }
+            }
+        })
+    }
+
+    pub fn rank_batch(&self, index: &InvertedIndex, queries: &[Query], leader_count: usize) -> Vec<Result<QueryResult, IndexError>> {
+        match self {
+            Ranking::Cluster => index.query_batch(queries, leader_count),
+            _ => queries.par_iter()
+                .map(|terms| self.rank(index, terms, leader_count))
+                .collect()
+        }
+    }
+}
+
+impl FromStr for Ranking {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        if let Some(names) = s.strip_prefix(Self::FUSION_PREFIX) {
+            let rankings = names.split(Self::FUSION_SEPARATOR)
+                .map(|name| Ranking::from_str(name.trim()))
+                .collect::<Result<Vec<_>, _>>()?;
+            if rankings.len() < 2 {
+                return Err(ParseError::FusionTooSmall);
+            }
+
+            return Ok(Ranking::Fusion(rankings, ScoreNormalization::None));
+        }
+        if let Some(k) = s.strip_prefix(Self::EXACT_PREFIX) {
+            let k = usize::from_str(k.trim()).ok()
+                .filter(|&k| k != 0)
+                .ok_or_else(|| ParseError::InvalidTopK(k.to_owned()))?;
+
+            return Ok(Ranking::Exact(k));
+        }
+        if let Some(name) = s.strip_prefix(Self::TIERED_PREFIX) {
+            return Ok(Ranking::Tiered(ranker_by_name(name.trim())?, DEFAULT_TIER_RESULT_COUNT));
+        }
+
+        Ok(match s.to_lowercase().as_str() {
+            "cluster" => Ranking::Cluster,
+            "exact" => Ranking::Exact(DEFAULT_EXACT_TOP_K),
+            _ => Ranking::Scored(ranker_by_name(s)?)
+        })
+    }
+}
+
+// NOTE: Only ranks are used, so rankers with incomparable scores can be combined
+pub fn reciprocal_rank_fusion(results: &[QueryResult]) -> QueryResult {
+    let mut scores = AHashMap::new();
+    for result in results {
+        for (rank, &(document_id, _)) in result.iter().enumerate() {
+            *scores.entry(document_id).or_default() += 1.0 / (RRF_K + rank as f64 + 1.0);
+        }
+    }
+
+    sorted_by_weight(scores)
+}
+
+// NOTE: Document missing from a result gets nothing from that ranker
+pub fn normalized_score_fusion(results: &[QueryResult], normalization: ScoreNormalization) -> QueryResult {
+    let mut scores = AHashMap::new();
+    for result in results {
+        for (document_id, score) in normalization.apply(result) {
+            *scores.entry(document_id).or_default() += score;
+        }
+    }
+
+    sorted_by_weight(scores)
+}
@@ -0,0 +1,42 @@
+use std::time::{Duration, SystemTime};
+use itertools::Itertools;
+use crate::inf_context::InfContext;
+use crate::term_index::QueryResult;
+
+const SECONDS_IN_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+pub struct RecencyScoring {
+    half_life: Duration,
+    boost: f64,
+    now: SystemTime
+}
+
+impl RecencyScoring {
+    pub fn new(half_life_days: f64, boost: f64) -> Self {
+        RecencyScoring {
+            half_life: Duration::from_secs_f64(half_life_days * SECONDS_IN_DAY),
+            boost,
+            now: SystemTime::now()
+        }
+    }
+
+    // NOTE: Freshly modified document gets (1 + boost) times its score,
+    //  which halves towards 1 every half life
+    pub fn factor(&self, modified: Option<SystemTime>) -> f64 {
+        let Some(modified) = modified else {
+            return 1.0;
+        };
+
+        let age = self.now.duration_since(modified).unwrap_or(Duration::ZERO);
+        let decay = 0.5f64.powf(age.as_secs_f64() / self.half_life.as_secs_f64());
+
+        1.0 + self.boost * decay
+    }
+
+    pub fn rescore(&self, result: QueryResult, ctx: &InfContext) -> QueryResult {
+        result.into_iter()
+            .map(|(document_id, weight)| (document_id, weight * self.factor(ctx.document_modified(document_id))))
+            .sorted_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap().reverse())
+            .collect()
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use serde_json::{Map, Value};
+use crate::corpus::{Corpus, CorpusDocument, CorpusEntry, UnreadableDocument};
+
+pub const DEFAULT_TEXT_FIELD: &str = "text";
+pub const DEFAULT_NAME_FIELD: &str = "id";
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum RecordFormat {
+    /// Comma separated values with a header row, fields may be quoted with '"'.
+    Csv,
+    /// One json object per line.
+    Jsonl
+}
+
+/// Records of a CSV or JSONL file, every record is a document.
+///
+/// The text field is indexed, the name field names the document together with the file,
+/// records without one or with an empty one are named by their position. Other fields are kept as metadata.
+pub struct RecordCorpus {
+    path: PathBuf,
+    format: RecordFormat,
+    text_field: String,
+    name_field: String
+}
+
+impl RecordCorpus {
+    pub fn new(path: impl AsRef<Path>, format: RecordFormat) -> Self {
+        RecordCorpus {
+            path: path.as_ref().to_owned(),
+            format,
+            text_field: DEFAULT_TEXT_FIELD.to_owned(),
+            name_field: DEFAULT_NAME_FIELD.to_owned()
+        }
+    }
+
+    pub fn text_field(mut self, text_field: impl Into<String>) -> Self {
+        self.text_field = text_field.into();
+        self
+    }
+
+    pub fn name_field(mut self, name_field: impl Into<String>) -> Self {
+        self.name_field = name_field.into();
+        self
+    }
+
+    fn document(&self, position: usize, fields: Vec<(String, String)>) -> CorpusEntry {
+        let mut text = None;
+        let mut name = None;
+        let mut metadata = Vec::new();
+        for (key, value) in fields {
+            if key == self.text_field {
+                text = Some(value);
+            } else if key == self.name_field {
+                name = Some(value);
+            } else {
+                metadata.push((key, value));
+            }
+        }
+
+        let name = self.path.join(name.filter(|name| !name.is_empty()).unwrap_or_else(|| position.to_string()));
+        match text {
+            Some(text) => Ok(CorpusDocument::buffer(name, text).with_metadata(metadata)),
+            None => Err(UnreadableDocument::new(name, anyhow!("Record has no \"{}\" field", self.text_field)))
+        }
+    }
+}
+
+impl Corpus for RecordCorpus {
+    fn name(&self) -> String {
+        format!("records {:?}", self.path)
+    }
+
+    // NOTE: Lines of a JSONL file are parsed one at a time, a CSV field may span lines, so the file is read whole
+    fn documents(self: Box<Self>) -> Result<Box<dyn Iterator<Item = CorpusEntry>>> {
+        let file = fs::File::open(&self.path).context(format!("Couldn't open records {:?}", self.path))?;
+
+        match self.format {
+            RecordFormat::Jsonl => {
+                let lines = BufReader::new(file).lines()
+                    .enumerate()
+                    .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()));
+
+                Ok(Box::new(lines.map(move |(i, line)| {
+                    let position = i + 1;
+                    match line.map_err(anyhow::Error::from).and_then(|line| parse_json_record(&line)) {
+                        Ok(fields) => self.document(position, fields),
+                        Err(err) => Err(UnreadableDocument::new(self.path.join(position.to_string()), err))
+                    }
+                })))
+            },
+            RecordFormat::Csv => {
+                let text = fs::read_to_string(&self.path)?;
+                let mut rows = parse_csv(&text)?.into_iter();
+                let header = rows.next().unwrap_or_default();
+
+                Ok(Box::new(rows.enumerate().map(move |(i, row)| {
+                    let position = i + 1;
+                    if row.len() != header.len() {
+                        let err = anyhow!("Record has {} fields, the header has {}", row.len(), header.len());
+                        return Err(UnreadableDocument::new(self.path.join(position.to_string()), err));
+                    }
+
+                    self.document(position, header.iter().cloned().zip(row).collect())
+                })))
+            }
+        }
+    }
+}
+
+// NOTE: Strings are kept as they are, other values are written as json
+fn parse_json_record(line: &str) -> Result<Vec<(String, String)>> {
+    let record = serde_json::from_str::<Map<String, Value>>(line).context("Record isn't a json object")?;
+
+    Ok(record.into_iter()
+        .map(|(key, value)| match value {
+            Value::String(text) => (key, text),
+            value => (key, value.to_string())
+        })
+        .collect())
+}
+
+// NOTE: Quoted fields may contain separators, line breaks and quotes written twice. Empty lines are left out
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {},
+            (false, '\n') => {
+                if !row.is_empty() || !field.is_empty() {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+            },
+            (false, c) => field.push(c)
+        }
+    }
+    if quoted {
+        return Err(anyhow!("Quoted field isn't closed at the end of the file"));
+    }
+    if !row.is_empty() || !field.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
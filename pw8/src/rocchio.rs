@@ -0,0 +1,190 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::str::FromStr;
+use ahash::{AHashMap, AHashSet};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use crate::classification::{accuracy, Labels};
+use crate::document::DocumentId;
+use crate::term_index::InvertedIndex;
+use crate::vector::{centroid, cosine_sim, SparseVector};
+use crate::sampling::Split;
+use crate::build_index;
+
+const DEFAULT_FOLD_COUNT: usize = 5;
+
+// NOTE: Centroids are stored by term, not by vector component,
+//  so a model can be applied to an index built from a different corpus
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RocchioModel {
+    centroids: BTreeMap<String, BTreeMap<String, f64>>
+}
+
+pub struct RocchioClassifier<'a> {
+    centroids: Vec<(&'a str, SparseVector)>
+}
+
+impl RocchioModel {
+    pub fn train(index: &InvertedIndex, examples: &[(DocumentId, &str)]) -> Self {
+        let terms = index.term_names();
+        let centroids = examples.iter()
+            .into_group_map_by(|(_, class)| *class)
+            .into_iter()
+            .map(|(class, examples)| {
+                let vectors = examples.iter().filter_map(|(document_id, _)| index.document_vector(*document_id));
+                let weights = centroid(vectors).iter()
+                    .map(|(term_id, weight)| (terms[term_id].to_owned(), weight))
+                    .collect();
+
+                (class.to_owned(), weights)
+            })
+            .collect();
+
+        RocchioModel { centroids }
+    }
+
+    pub fn classes(&self) -> impl Iterator<Item = &str> {
+        self.centroids.keys().map(String::as_str)
+    }
+
+    pub fn classifier(&self, index: &InvertedIndex) -> RocchioClassifier<'_> {
+        let term_ids = index.term_names()
+            .into_iter()
+            .enumerate()
+            .map(|(term_id, term)| (term, term_id))
+            .collect::<AHashMap<_, _>>();
+
+        let centroids = self.centroids.iter()
+            .map(|(class, weights)| {
+                let vector = weights.iter()
+                    .filter_map(|(term, &weight)| term_ids.get(term.as_str()).map(|&term_id| (term_id, weight)))
+                    .collect();
+
+                (class.as_str(), vector)
+            })
+            .collect();
+
+        RocchioClassifier { centroids }
+    }
+}
+
+impl<'a> RocchioClassifier<'a> {
+    pub fn classify(&self, vector: &SparseVector) -> Option<&'a str> {
+        self.closest_class(vector).map(|(class, _)| class)
+    }
+
+    // NOTE: Class of the closest centroid together with its cosine similarity to the vector
+    pub fn closest_class(&self, vector: &SparseVector) -> Option<(&'a str, f64)> {
+        self.centroids.iter()
+            .map(|(class, centroid)| (*class, cosine_sim(centroid, vector)))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+    }
+}
+
+// NOTE: Folds are assigned round-robin in document order, so runs are reproducible
+pub fn k_fold_accuracy(index: &InvertedIndex, examples: &[(DocumentId, &str)], fold_count: usize) -> Vec<(usize, usize)> {
+    (0..fold_count)
+        .map(|fold| {
+            let (test, train): (Vec<_>, Vec<_>) = examples.iter()
+                .enumerate()
+                .partition(|(i, _)| i % fold_count == fold);
+            let train = train.into_iter().map(|(_, example)| *example).collect::<Vec<_>>();
+
+            let model = RocchioModel::train(index, &train);
+            let classifier = model.classifier(index);
+
+            accuracy(test.into_iter().map(|(_, &(document_id, class))| {
+                (index.document_vector(document_id).and_then(|vector| classifier.classify(vector)), class)
+            }))
+        })
+        .collect()
+}
+
+fn load_labels(flags: &AHashMap<&str, &str>) -> Result<Labels> {
+    let path = flags.get("labels").context("Missing flag '--labels'")?;
+
+    Labels::load(BufReader::new(File::open(path)?))
+}
+
+pub fn train(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let labels = load_labels(flags)?;
+    let model_path = flags.get("model").cloned().unwrap_or("data/rocchio.json");
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+
+    let examples = labels.examples(&loaded.index, &loaded.ctx);
+    if examples.is_empty() {
+        return Err(anyhow!("None of the documents have a label"));
+    }
+    let model = RocchioModel::train(&loaded.index, &examples);
+
+    serde_json::to_writer(BufWriter::new(File::create(model_path)?), &model)?;
+    println!("Trained on {} documents, classes: {}", examples.len(), model.classes().join(", "));
+    println!("Model written to \"{model_path}\"");
+
+    Ok(())
+}
+
+// NOTE: Trained on labeled documents of the train partition, tested on labeled documents of the test one
+pub fn split_accuracy(index: &InvertedIndex, examples: &[(DocumentId, &str)], split: &Split) -> (usize, usize) {
+    let train_ids = split.train.iter().collect::<AHashSet<_>>();
+    let test_ids = split.test.iter().collect::<AHashSet<_>>();
+    let train = examples.iter()
+        .filter(|(document_id, _)| train_ids.contains(document_id))
+        .cloned()
+        .collect::<Vec<_>>();
+    let test = examples.iter().filter(|(document_id, _)| test_ids.contains(document_id));
+
+    let model = RocchioModel::train(index, &train);
+    let classifier = model.classifier(index);
+
+    accuracy(test.into_iter().map(|&(document_id, class)| {
+        (index.document_vector(document_id).and_then(|vector| classifier.classify(vector)), class)
+    }))
+}
+
+pub fn evaluate(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let labels = load_labels(flags)?;
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    let examples = labels.examples(&loaded.index, &loaded.ctx);
+
+    let results = match flags.get("model") {
+        Some(model_path) => {
+            let model: RocchioModel = serde_json::from_reader(BufReader::new(File::open(model_path)?))?;
+            let classifier = model.classifier(&loaded.index);
+
+            vec![accuracy(examples.iter().map(|&(document_id, class)| {
+                (loaded.index.document_vector(document_id).and_then(|vector| classifier.classify(vector)), class)
+            }))]
+        },
+        None if flags.contains_key("split") => {
+            let split = Split::load(flags["split"])?;
+            split.check_document_count(loaded.ctx.document_count())?;
+
+            vec![split_accuracy(&loaded.index, &examples, &split)]
+        },
+        None => {
+            let fold_count = flags.get("folds")
+                .map(|folds| usize::from_str(folds))
+                .transpose()
+                .context("Invalid fold count")?
+                .unwrap_or(DEFAULT_FOLD_COUNT);
+            if fold_count < 2 || fold_count > examples.len() {
+                return Err(anyhow!("Fold count must be between 2 and the number of labeled documents ({})", examples.len()));
+            }
+
+            k_fold_accuracy(&loaded.index, &examples, fold_count)
+        }
+    };
+
+    if results.len() > 1 {
+        for (i, (correct, total)) in results.iter().enumerate() {
+            println!("Fold {i}: accuracy {:.4} ({correct}/{total})", *correct as f64 / *total as f64);
+        }
+    }
+    let (correct, total) = results.iter().fold((0, 0), |(a, b), (correct, total)| (a + correct, b + total));
+    println!("Overall accuracy: {:.4} ({correct}/{total})", correct as f64 / total.max(1) as f64);
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufReader;
+use std::str::FromStr;
+use std::sync::Arc;
+use ahash::AHashMap;
+use itertools::Itertools;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::rocchio::RocchioModel;
+use crate::LoadedIndex;
+
+pub const CATEGORY_FIELD: &str = "category";
+const DEFAULT_ROUTE_THRESHOLD: f64 = 0.1;
+
+// NOTE: Documents are tagged with the class of a trained Rocchio model as they are indexed, so a mixed dump
+//  comes out sorted into categories. Documents that aren't similar enough to any centroid stay uncategorized
+pub struct Router {
+    model: RocchioModel,
+    threshold: f64
+}
+
+#[derive(Default)]
+pub struct RoutingReport {
+    routed: BTreeMap<String, usize>,
+    uncategorized: usize
+}
+
+impl Router {
+    pub fn from_flags(flags: &AHashMap<&str, &str>) -> Result<Option<Self>> {
+        let threshold = flags.get("route-threshold")
+            .map(|threshold| f64::from_str(threshold))
+            .transpose()
+            .context("Invalid routing threshold")?;
+        let Some(model_path) = flags.get("route-model") else {
+            return match threshold {
+                Some(_) => Err(anyhow!("Routing threshold is only used with '--route-model'")),
+                None => Ok(None)
+            };
+        };
+
+        let threshold = threshold.unwrap_or(DEFAULT_ROUTE_THRESHOLD);
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(anyhow!("Routing threshold must be between 0 and 1, got {threshold}"));
+        }
+        let file = File::open(model_path).context(anyhow!("Couldn't open model \"{model_path}\""))?;
+        let model: RocchioModel = serde_json::from_reader(BufReader::new(file))?;
+        println!("Routing documents into {} with similarity of at least {threshold}", model.classes().join(", "));
+
+        Ok(Some(Router { model, threshold }))
+    }
+
+    // NOTE: Term ids change as documents are added, so the classifier is built again for every call.
+    //  Documents without a vector, like removed or empty ones, aren't routed at all
+    pub fn route(&self, loaded: &mut LoadedIndex, documents: &[DocumentId]) -> Result<RoutingReport> {
+        let classifier = self.model.classifier(&loaded.index);
+        let categories = documents.iter()
+            .filter_map(|&document_id| loaded.index.document_vector(document_id).map(|vector| (document_id, vector)))
+            .map(|(document_id, vector)| {
+                let category = classifier.closest_class(vector)
+                    .filter(|&(_, similarity)| similarity >= self.threshold)
+                    .map(|(category, _)| category);
+
+                (document_id, category)
+            })
+            .collect::<Vec<_>>();
+
+        let ctx = Arc::get_mut(&mut loaded.ctx)
+            .ok_or_else(|| anyhow!("Documents can't be routed while the index is in use"))?;
+        let mut report = RoutingReport::default();
+        for (document_id, category) in categories {
+            match category {
+                Some(category) => {
+                    ctx.set_metadata(document_id, CATEGORY_FIELD, category);
+                    *report.routed.entry(category.to_owned()).or_default() += 1;
+                },
+                None => report.uncategorized += 1
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl Display for RoutingReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let routed = self.routed.iter()
+            .map(|(category, count)| format!("{category}: {count}"))
+            .join(", ");
+        write!(f, "Routed {} documents ({routed}), {} below the threshold", self.routed.values().sum::<usize>(), self.uncategorized)
+    }
+}
+
+pub fn category(ctx: &InfContext, document_id: DocumentId) -> Option<&str> {
+    ctx.document_metadata(document_id).iter()
+        .find(|(key, _)| key == CATEGORY_FIELD)
+        .map(|(_, value)| value.as_str())
+}
+
+pub fn categories(ctx: &InfContext) -> BTreeMap<&str, Vec<DocumentId>> {
+    ctx.document_ids()
+        .filter_map(|document_id| category(ctx, document_id).map(|category| (category, document_id)))
+        .into_group_map()
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,137 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+use ahash::{AHashMap, AHashSet};
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use crate::document::DocumentId;
+use crate::term_index::DEFAULT_SEED;
+use crate::build_index;
+
+pub const DEFAULT_TEST_FRACTION: f64 = 0.2;
+pub const DEFAULT_SPLIT_PATH: &str = "data/split.json";
+const DEFAULT_SAMPLE_PATH: &str = "data/sample";
+
+// NOTE: The same seed always picks the same documents, ids are returned sorted
+pub fn sample_ids(document_ids: &[DocumentId], count: usize, seed: u64) -> Vec<DocumentId> {
+    document_ids.choose_multiple(&mut StdRng::seed_from_u64(seed), count)
+        .cloned()
+        .sorted()
+        .collect()
+}
+
+// NOTE: Document ids only mean something for the corpus the split was made from,
+//  so the document count is kept to catch a split applied to a different corpus
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Split {
+    pub seed: u64,
+    pub document_count: usize,
+    pub train: Vec<DocumentId>,
+    pub test: Vec<DocumentId>
+}
+
+impl Split {
+    pub fn new(document_ids: &[DocumentId], test_fraction: f64, seed: u64) -> Result<Self> {
+        if !(0.0..=1.0).contains(&test_fraction) {
+            return Err(anyhow!("Test fraction must be between 0 and 1, got {test_fraction}"));
+        }
+
+        let test_count = (document_ids.len() as f64 * test_fraction).round() as usize;
+        let test = sample_ids(document_ids, test_count, seed);
+        let test_set = test.iter().collect::<AHashSet<_>>();
+        let train = document_ids.iter()
+            .filter(|document_id| !test_set.contains(document_id))
+            .cloned()
+            .sorted()
+            .collect();
+
+        Ok(Split {
+            seed,
+            document_count: document_ids.len(),
+            train,
+            test
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let file = File::open(path).context(format!("Couldn't open split \"{path}\""))?;
+
+        serde_json::from_reader(BufReader::new(file)).context(format!("Invalid split \"{path}\""))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+
+        Ok(())
+    }
+
+    pub fn check_document_count(&self, document_count: usize) -> Result<()> {
+        if self.document_count != document_count {
+            return Err(anyhow!("Split was made for {} documents, but the corpus has {document_count}", self.document_count));
+        }
+
+        Ok(())
+    }
+}
+
+fn seed(flags: &AHashMap<&str, &str>) -> Result<u64> {
+    flags.get("seed")
+        .map(|seed| u64::from_str(seed))
+        .transpose()
+        .context("Invalid seed")
+        .map(|seed| seed.unwrap_or(DEFAULT_SEED))
+}
+
+// NOTE: Sampled files are copied under their own names, so labels given by file name still apply to the sub-corpus
+pub fn sample(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let count = flags.get("count")
+        .context("Missing flag '--count'")
+        .and_then(|count| usize::from_str(count).context("Invalid sample size"))?;
+    let seed = seed(flags)?;
+    let output_path = Path::new(flags.get("output").cloned().unwrap_or(DEFAULT_SAMPLE_PATH));
+
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    let document_ids = loaded.ctx.document_ids().collect::<Vec<_>>();
+    if count > document_ids.len() {
+        return Err(anyhow!("Can't sample {count} documents out of {}", document_ids.len()));
+    }
+
+    fs::create_dir_all(output_path)?;
+    let mut file_names = AHashSet::new();
+    for document_id in sample_ids(&document_ids, count, seed) {
+        let path = loaded.ctx.document(document_id)
+            .context(anyhow!("Document with id {document_id} doesn't exist"))?
+            .path();
+        let file_name = path.file_name().context(anyhow!("Document {path:?} has no file name"))?;
+        if !file_names.insert(file_name.to_owned()) {
+            return Err(anyhow!("More than one sampled document is named {file_name:?}"));
+        }
+        fs::copy(path, output_path.join(file_name)).context(format!("Couldn't copy {path:?}"))?;
+    }
+    println!("Sampled {count} of {} documents into {output_path:?} with seed {seed}", document_ids.len());
+
+    Ok(())
+}
+
+pub fn split(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let test_fraction = flags.get("test-fraction")
+        .map(|fraction| f64::from_str(fraction))
+        .transpose()
+        .context("Invalid test fraction")?
+        .unwrap_or(DEFAULT_TEST_FRACTION);
+    let seed = seed(flags)?;
+    let output_path = flags.get("output").cloned().unwrap_or(DEFAULT_SPLIT_PATH);
+
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    let split = Split::new(&loaded.ctx.document_ids().collect::<Vec<_>>(), test_fraction, seed)?;
+    split.save(output_path)?;
+    println!("Split {} documents into {} train and {} test documents with seed {seed}", split.document_count, split.train.len(), split.test.len());
+    println!("Split written to \"{output_path}\"");
+
+    Ok(())
+}
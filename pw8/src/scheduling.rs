@@ -0,0 +1,193 @@
+use anyhow::{anyhow, Result};
+use ahash::AHashMap;
+use std::cmp::Reverse;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::sync_channel;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+use rayon::prelude::*;
+use threadpool::ThreadPool;
+use crate::merge::merge_bounded;
+use crate::metrics::{metrics, QueueDepth};
+
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub enum Pipeline {
+    // NOTE: Workers send partial indexes over a bounded channel, they are merged on the calling thread
+    #[default]
+    ThreadPool,
+    // NOTE: Partial indexes are merged by the workers themselves, without a channel
+    Rayon
+}
+
+impl FromStr for Pipeline {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "threadpool" => Ok(Pipeline::ThreadPool),
+            "rayon" => Ok(Pipeline::Rayon),
+            _ => Err(anyhow!("Unknown pipeline '{s}', expected 'threadpool' or 'rayon'"))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Scheduling {
+    pub index_threads: usize,
+    pub pipeline: Pipeline
+}
+
+impl Scheduling {
+    pub fn new() -> Self {
+        Scheduling {
+            index_threads: Self::default_thread_count(),
+            pipeline: Pipeline::default()
+        }
+    }
+
+    // NOTE: One core is left for merging partial indexes
+    pub fn default_thread_count() -> usize {
+        (num_cpus::get() - 1).max(1)
+    }
+
+    pub fn index<I, T>(&self, items: Vec<I>, merge_buffer: usize, work: impl Fn(I) -> Result<Option<T>> + Send + Sync + 'static,
+                       merge: impl Fn(&mut T, T) + Send + Sync) -> Result<Option<T>>
+        where I: Send + 'static, T: Send + 'static {
+        let start = Instant::now();
+        let usage = Arc::new(WorkerUsage::new());
+        let usage1 = usage.clone();
+        let work = move |item| {
+            let start = Instant::now();
+            let partial = work(item);
+            usage1.record(start.elapsed());
+
+            partial
+        };
+
+        let result = match self.pipeline {
+            Pipeline::ThreadPool => self.index_threadpool(items, merge_buffer, work, merge),
+            Pipeline::Rayon => self.index_rayon(items, work, merge)
+        };
+        usage.report(self.index_threads, start.elapsed());
+
+        result
+    }
+
+    fn index_threadpool<I, T>(&self, items: Vec<I>, merge_buffer: usize, work: impl Fn(I) -> Result<Option<T>> + Send + Sync + 'static,
+                              merge: impl Fn(&mut T, T)) -> Result<Option<T>>
+        where I: Send + 'static, T: Send + 'static {
+        let item_count = items.len();
+        let work = Arc::new(work);
+        let pool = ThreadPool::new(self.index_threads);
+        // NOTE: Workers wait once every one of them has a partial index queued,
+        //  so fast lexing can't run arbitrarily far ahead of merging
+        let (tx, rx) = sync_channel(self.index_threads);
+        let queue_depth = Arc::new(QueueDepth::new());
+        for item in items {
+            let tx = tx.clone();
+            let queue_depth1 = queue_depth.clone();
+            let work1 = work.clone();
+
+            pool.execute(move || {
+                let partial = work1(item);
+                queue_depth1.push();
+                // NOTE: Merging stops at the first error and drops the receiver, partials after it are discarded
+                let _ = tx.send(partial);
+            });
+        }
+
+        let partials = rx.into_iter()
+            .take(item_count)
+            .inspect(|_| queue_depth.pop());
+        let result = itertools::process_results(partials, |partials| merge_bounded(partials.flatten(), merge_buffer, merge));
+        metrics().set("max_queue_depth", queue_depth.max() as f64);
+        // NOTE: A worker sends its partial before dropping its jobs, which share the context with the caller
+        pool.join();
+
+        result
+    }
+
+    fn index_rayon<I, T>(&self, items: Vec<I>, work: impl Fn(I) -> Result<Option<T>> + Send + Sync,
+                         merge: impl Fn(&mut T, T) + Send + Sync) -> Result<Option<T>>
+        where I: Send, T: Send {
+        let merge_partials = |a: Option<T>, b: Option<T>| match (a, b) {
+            (Some(mut a), Some(b)) => {
+                merge(&mut a, b);
+                Some(a)
+            },
+            (a, None) => a,
+            (None, b) => b
+        };
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.index_threads)
+            .build()?;
+        // NOTE: Every item is a task of its own, so idle workers can steal single files
+        pool.install(|| {
+            items.into_par_iter()
+                .with_max_len(1)
+                .map(&work)
+                .try_reduce(|| None, |a, b| Ok(merge_partials(a, b)))
+        })
+    }
+}
+
+// NOTE: Workers take items in order, so the largest ones don't start last and leave every other worker idle
+pub fn largest_first<I>(mut items: Vec<I>, size: impl Fn(&I) -> usize) -> Vec<I> {
+    items.sort_by_cached_key(|item| Reverse(size(item)));
+
+    items
+}
+
+// NOTE: Time every worker thread spent on items, merging on the calling thread isn't counted
+struct WorkerUsage {
+    busy: Mutex<AHashMap<ThreadId, Duration>>
+}
+
+impl WorkerUsage {
+    fn new() -> Self {
+        WorkerUsage {
+            busy: Mutex::new(AHashMap::new())
+        }
+    }
+
+    fn record(&self, duration: Duration) {
+        *self.busy.lock().unwrap().entry(thread::current().id()).or_default() += duration;
+    }
+
+    // NOTE: Workers that never got an item are reported as idle
+    fn report(&self, worker_count: usize, elapsed: Duration) {
+        let elapsed = elapsed.as_secs_f64();
+        if elapsed == 0.0 {
+            return;
+        }
+
+        let mut utilization = self.busy.lock().unwrap()
+            .values()
+            .map(|busy| busy.as_secs_f64() / elapsed)
+            .collect::<Vec<_>>();
+        utilization.sort_by(|a, b| a.partial_cmp(b).unwrap().reverse());
+        utilization.resize(utilization.len().max(worker_count), 0.0);
+
+        for (i, utilization) in utilization.iter().enumerate() {
+            metrics().set(&format!("worker_{i}_utilization"), *utilization);
+        }
+        metrics().set("worker_utilization_min", utilization.last().cloned().unwrap_or(0.0));
+        metrics().set("worker_utilization_mean", utilization.iter().sum::<f64>() / utilization.len().max(1) as f64);
+    }
+}
+
+impl Default for Scheduling {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// NOTE: Batch queries and ranking run on the global rayon pool, it can only be configured once per process
+pub fn configure_query_threads(query_threads: usize) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(query_threads)
+        .build_global()
+        .map_err(|err| anyhow!("Couldn't configure query threads: {err}"))
+}
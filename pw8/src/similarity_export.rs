@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+use ahash::AHashMap;
+use itertools::Itertools;
+use rayon::prelude::*;
+use serde::Serialize;
+use crate::metrics::metrics;
+use crate::document::DocumentId;
+use crate::inf_context::InfContext;
+use crate::term_index::{InvertedIndex, QueryResult};
+use crate::vector::cosine_sim;
+use crate::build_index;
+
+const DEFAULT_NEIGHBOR_COUNT: usize = 10;
+
+#[derive(Serialize)]
+struct SimilarityRecord<'a> {
+    source: usize,
+    source_name: &'a str,
+    target: usize,
+    target_name: &'a str,
+    similarity: f64
+}
+
+enum Format {
+    Csv,
+    JsonLines
+}
+
+// NOTE: Exact all-pairs cosine, every document keeps only its `count` closest neighbors
+pub fn neighbors(index: &InvertedIndex, documents: &[DocumentId], count: Option<usize>) -> Vec<(DocumentId, QueryResult)> {
+    documents.par_iter()
+        .filter_map(|&source| index.document_vector(source).map(|vector| (source, vector)))
+        .map(|(source, source_vector)| {
+            let neighbors = documents.iter()
+                .filter(|&&target| target != source)
+                .filter_map(|&target| index.document_vector(target).map(|vector| (target, cosine_sim(source_vector, vector))))
+                .sorted_by(|(id_a, a), (id_b, b)| a.partial_cmp(b).unwrap().reverse().then(id_a.cmp(id_b)))
+                .take(count.unwrap_or(usize::MAX))
+                .collect();
+
+            (source, neighbors)
+        })
+        .collect()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn write_records<'a>(mut writer: impl Write, format: &Format, records: impl Iterator<Item = SimilarityRecord<'a>>) -> Result<()> {
+    if let Format::Csv = format {
+        writeln!(writer, "source,source_name,target,target_name,similarity")?;
+    }
+    for record in records {
+        match format {
+            Format::Csv => writeln!(writer, "{},{},{},{},{}", record.source, csv_field(record.source_name),
+                                    record.target, csv_field(record.target_name), record.similarity)?,
+            Format::JsonLines => {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn export(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let output_path = flags.get("output").cloned().unwrap_or("data/similarities.csv");
+    let format = match flags.get("format").cloned().or_else(|| output_path.rsplit_once('.').map(|(_, extension)| extension)) {
+        Some("csv") | None => Format::Csv,
+        Some("jsonl") => Format::JsonLines,
+        Some(format) => return Err(anyhow!("Unknown export format '{format}'"))
+    };
+    let count = match flags.get("top") {
+        Some(&"all") => None,
+        Some(count) => Some(usize::from_str(count).context("Invalid neighbor count")?),
+        None => Some(DEFAULT_NEIGHBOR_COUNT)
+    };
+
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    let ctx: &InfContext = &loaded.ctx;
+    let documents = ctx.document_ids().collect::<Vec<_>>();
+    let names = documents.iter()
+        .map(|&document_id| (document_id, ctx.document(document_id).map(|document| document.name()).unwrap_or_default()))
+        .collect::<AHashMap<_, _>>();
+
+    let (neighbors, time) = metrics().time("similarities", || neighbors(&loaded.index, &documents, count));
+    println!("Computing similarities took: {time:?}");
+
+    let records = neighbors.iter()
+        .sorted_by_key(|(source, _)| *source)
+        .flat_map(|(source, neighbors)| {
+            neighbors.iter().map(|&(target, similarity)| SimilarityRecord {
+                source: source.id(),
+                source_name: &names[source],
+                target: target.id(),
+                target_name: &names[&target],
+                similarity
+            })
+        });
+    write_records(BufWriter::new(File::create(output_path)?), &format, records)?;
+    println!("Similarities written to \"{output_path}\"");
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::document::DocumentId;
+use crate::persist;
+
+pub const SKIPPED_PATH: &str = "data/skipped.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipStage {
+    Opening,
+    Reading
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub stage: SkipStage,
+    // NOTE: Only files that failed while reading already have a document
+    pub document: Option<DocumentId>,
+    pub reason: String
+}
+
+/// Files left out of the corpus together with the reason, in the order they failed.
+pub struct SkipLedger {
+    files: Mutex<Vec<SkippedFile>>
+}
+
+impl SkipLedger {
+    pub fn new() -> Self {
+        SkipLedger { files: Mutex::new(Vec::new()) }
+    }
+
+    pub fn record(&self, path: PathBuf, stage: SkipStage, document: Option<DocumentId>, err: &anyhow::Error) {
+        println!("Ignoring file {:?}. Error: {}. Caused by: {}", path, err, err.root_cause());
+        self.files.lock().unwrap().push(SkippedFile {
+            path,
+            stage,
+            document,
+            reason: format!("{err:#}")
+        });
+    }
+
+    pub fn files(&self) -> Vec<SkippedFile> {
+        self.files.lock().unwrap().clone()
+    }
+
+    pub fn take_stage(&self, stage: SkipStage) -> Vec<SkippedFile> {
+        let mut files = self.files.lock().unwrap();
+        let (taken, kept) = files.drain(..).partition(|file| file.stage == stage);
+        *files = kept;
+
+        taken
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        persist::save_checked(path, |writer| Ok(serde_json::to_writer_pretty(writer, &*self.files.lock().unwrap())?))
+    }
+
+    pub fn load(path: &str) -> Result<Vec<SkippedFile>> {
+        if !Path::new(path).exists() {
+            return Ok(Vec::new());
+        }
+
+        Ok(serde_json::from_slice(&persist::load_checked(path)?)?)
+    }
+}
+
+impl Default for SkipLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
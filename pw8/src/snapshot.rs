@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use ahash::AHashMap;
+use human_bytes::human_bytes;
+use serde::{Deserialize, Serialize};
+use crate::boilerplate::BoilerplateFilter;
+use crate::corpus::CorpusDocument;
+use crate::document::Document;
+use crate::inf_context::InfContext;
+use crate::lexer;
+use crate::stopwords::Stopwords;
+use crate::persist;
+use crate::term_index::InvertedIndex;
+use crate::wal::{WriteAheadLog, WAL_PATH};
+use crate::{build_index, LoadedIndex};
+
+// NOTE: Version 2 stores document vectors sparsely
+const SNAPSHOT_VERSION: u32 = 2;
+pub const DEFAULT_SNAPSHOT_PATH: &str = "data/snapshot.json";
+
+#[derive(Serialize, Deserialize)]
+struct DocumentRecord {
+    // NOTE: Relative to the corpus root when the document lives under it
+    path: PathBuf,
+    #[serde(default)]
+    relative: bool,
+    size: usize,
+    modified: Option<SystemTime>,
+    // NOTE: Crc32 of the content, missing in snapshots taken before it was recorded
+    #[serde(default)]
+    hash: Option<u32>,
+    // NOTE: Inline documents have no file to be read again, so their text is stored with them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>
+}
+
+impl DocumentRecord {
+    // NOTE: Modification time alone isn't trusted, copying a corpus resets it without changing anything
+    fn change(&self, data: Option<&str>) -> Option<String> {
+        let Some(data) = data else {
+            return Some("can't be read".to_owned());
+        };
+        if data.len() != self.size {
+            return Some(format!("size changed from {} to {} bytes", self.size, data.len()));
+        }
+
+        match self.hash {
+            Some(hash) if hash != crc32fast::hash(data.as_bytes()) => Some("content changed".to_owned()),
+            _ => None
+        }
+    }
+}
+
+// NOTE: Borrowed counterpart of Snapshot, so writing doesn't clone the whole index
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    version: u32,
+    sequence: u64,
+    name: &'a str,
+    root: &'a Path,
+    documents: Vec<DocumentRecord>,
+    boilerplate: &'a BoilerplateFilter,
+    stopwords: &'a Stopwords,
+    index: &'a InvertedIndex
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    version: u32,
+    // NOTE: Last write-ahead log entry the snapshot contains, missing in snapshots taken before there were updates
+    #[serde(default)]
+    sequence: u64,
+    name: String,
+    // NOTE: Missing in snapshots taken before paths were stored relative to it
+    #[serde(default)]
+    root: Option<PathBuf>,
+    documents: Vec<DocumentRecord>,
+    boilerplate: BoilerplateFilter,
+    stopwords: Stopwords,
+    index: InvertedIndex
+}
+
+// NOTE: Documents are stored relative to the folder the index was built from,
+//  so the snapshot keeps working once the corpus is moved somewhere else
+pub fn save(loaded: &LoadedIndex, path: &str, sequence: u64) -> Result<()> {
+    let root = Path::new(&loaded.name);
+    let documents = loaded.ctx.document_ids()
+        .map(|document_id| {
+            let document = loaded.ctx.document(document_id)
+                .ok_or_else(|| anyhow!("Document with id {document_id} doesn't exist"))?;
+            let path = document.path().to_owned();
+            let data = loaded.ctx.document_data(document_id)?;
+            let text = match document {
+                Document::Inline { text, .. } => Some(text.clone()),
+                Document::File { .. } => None
+            };
+            let (path, relative) = match path.strip_prefix(root) {
+                Ok(relative_path) => (relative_path.to_owned(), true),
+                Err(_) => (path, false)
+            };
+            Ok(DocumentRecord {
+                path,
+                relative,
+                size: data.len(),
+                modified: loaded.ctx.document_modified(document_id),
+                hash: Some(crc32fast::hash(data.as_bytes())),
+                text
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let snapshot = SnapshotRef {
+        version: SNAPSHOT_VERSION,
+        sequence,
+        name: &loaded.name,
+        root,
+        documents,
+        boilerplate: loaded.ctx.boilerplate(),
+        stopwords: loaded.ctx.stopwords(),
+        index: &loaded.index
+    };
+
+    persist::save_checked(path, |writer| Ok(serde_json::to_writer(writer, &snapshot)?))
+}
+
+pub fn restore(path: &str, corpus_root: Option<&Path>) -> Result<(LoadedIndex, u64)> {
+    let snapshot: Snapshot = serde_json::from_slice(&persist::load_checked(path)?)
+        .context(anyhow!("Invalid snapshot \"{path}\""))?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(anyhow!("Snapshot version {} isn't supported, expected {SNAPSHOT_VERSION}", snapshot.version));
+    }
+
+    let root = corpus_root.map(Path::to_owned)
+        .or(snapshot.root)
+        .unwrap_or_default();
+    let paths = snapshot.documents.iter()
+        .map(|document| if document.relative { root.join(&document.path) } else { document.path.clone() })
+        .collect::<Vec<_>>();
+    let found = snapshot.documents.iter()
+        .zip(&paths)
+        .map(|(document, path)| match &document.text {
+            Some(text) => CorpusDocument::buffer(path, text.clone()),
+            None => CorpusDocument::file(path.clone())
+        })
+        .collect();
+    let ctx = InfContext::from_documents(found, snapshot.boilerplate, snapshot.stopwords);
+    // NOTE: Stopwords come with the snapshot, so a mismatch means the lexer changed since it was taken
+    let analyzer = lexer::analyzer_fingerprint(ctx.stopwords());
+    snapshot.index.check_analyzer(analyzer)
+        .context(anyhow!("Snapshot \"{path}\" can't be queried, take it again"))?;
+    if snapshot.index.analyzer().is_none() {
+        println!("Snapshot \"{path}\" doesn't record its analyzer, terms lexed differently since it was taken won't match");
+    }
+
+    let changes = ctx.document_ids()
+        .zip(&snapshot.documents)
+        .zip(&paths)
+        .filter_map(|((document_id, document), path)| {
+            let data = ctx.document_data(document_id).ok();
+            document.change(data.as_deref()).map(|change| (path, change))
+        })
+        .collect::<Vec<_>>();
+    if !changes.is_empty() {
+        println!("{} documents changed since the snapshot was taken, their results may be stale:", changes.len());
+        changes.iter().for_each(|(path, change)| println!("\t{path:?}: {change}"));
+    }
+
+    // NOTE: A moved corpus is named after its new root, so the next snapshot is relative to it
+    let name = match corpus_root {
+        Some(corpus_root) => corpus_root.to_string_lossy().to_string(),
+        None => snapshot.name
+    };
+    let loaded = LoadedIndex {
+        name,
+        ctx,
+        index: snapshot.index
+    };
+
+    Ok((loaded, snapshot.sequence))
+}
+
+pub fn snapshot(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let output_path = flags.get("output").cloned().unwrap_or(DEFAULT_SNAPSHOT_PATH);
+
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    save(&loaded, output_path, 0)?;
+    WriteAheadLog::open(WAL_PATH, 0)?.clear()?;
+    let snapshot_size = File::open(output_path)?.metadata()?.len();
+    println!("Snapshot written to \"{output_path}\", size: {}", human_bytes(snapshot_size as f64));
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::str::FromStr;
+use ahash::AHashMap;
+use crate::stopwords::{discover, Stopwords, STOPWORDS_PATH};
+use crate::build_index;
+
+const DEFAULT_MIN_DOCUMENT_FRACTION: f64 = 0.8;
+const DEFAULT_MAX_IDF: f64 = 0.35;
+
+pub fn propose(base_path: &str, file_limit: Option<usize>, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let min_document_fraction = flags.get("min-df")
+        .map(|fraction| f64::from_str(fraction))
+        .transpose()
+        .context("Invalid minimal document fraction")?
+        .unwrap_or(DEFAULT_MIN_DOCUMENT_FRACTION);
+    let max_idf = flags.get("max-idf")
+        .map(|idf| f64::from_str(idf))
+        .transpose()
+        .context("Invalid maximal idf")?
+        .unwrap_or(DEFAULT_MAX_IDF);
+    let output_path = flags.get("output").cloned().unwrap_or("data/stopwords_proposed.txt");
+
+    let loaded = build_index(base_path, file_limit, "data/index.txt")?;
+    let candidates = discover(&loaded.index, min_document_fraction, max_idf);
+
+    println!("Proposed {} stopwords:", candidates.len());
+    for candidate in &candidates {
+        println!("\t{} [DF: {:.4}][IDF: {:.4}]", candidate.term, candidate.document_fraction, candidate.idf);
+    }
+
+    let mut proposed = Stopwords::new();
+    candidates.iter().for_each(|candidate| proposed.add(&candidate.term));
+    proposed.save(BufWriter::new(File::create(output_path)?))?;
+    println!("Proposed stopwords written to \"{output_path}\"");
+
+    // NOTE: Applying merges the proposal into the list every index build reads and builds again
+    if flags.get("apply") == Some(&"true") {
+        let mut stopwords = match File::open(STOPWORDS_PATH) {
+            Ok(file) => Stopwords::load(BufReader::new(file))?,
+            Err(_) => Stopwords::new()
+        };
+        candidates.iter().for_each(|candidate| stopwords.add(&candidate.term));
+        stopwords.save(BufWriter::new(File::create(STOPWORDS_PATH)?))?;
+        println!("{} stopwords written to \"{STOPWORDS_PATH}\", reindexing...", stopwords.len());
+
+        let before = loaded.index.term_count();
+        let reindexed = build_index(base_path, file_limit, "data/index.txt")?;
+        println!("Term count: before {}, after {}", before, reindexed.index.term_count());
+    }
+
+    Ok(())
+}
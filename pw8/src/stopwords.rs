@@ -0,0 +1,95 @@
+use anyhow::Result;
+use std::io::{BufRead, Write};
+use ahash::AHashSet;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use crate::term_index::InvertedIndex;
+
+pub const STOPWORDS_PATH: &str = "data/stopwords.txt";
+
+#[derive(Serialize, Deserialize)]
+pub struct Stopwords {
+    words: AHashSet<String>
+}
+
+impl Stopwords {
+    const COMMENT: &'static str = "#";
+
+    pub fn new() -> Self {
+        Stopwords { words: AHashSet::new() }
+    }
+
+    pub fn load(reader: impl BufRead) -> Result<Self> {
+        let mut stopwords = Stopwords::new();
+        for line in reader.lines() {
+            let line = line?;
+            let word = line.trim();
+            if word.is_empty() || word.starts_with(Self::COMMENT) {
+                continue;
+            }
+
+            stopwords.add(word);
+        }
+
+        Ok(stopwords)
+    }
+
+    pub fn save(&self, mut writer: impl Write) -> Result<()> {
+        for word in self.words() {
+            writeln!(writer, "{word}")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn add(&mut self, word: &str) {
+        self.words.insert(word.to_lowercase());
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.words.iter()
+            .map(String::as_str)
+            .sorted()
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+impl Default for Stopwords {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct StopwordCandidate {
+    pub term: String,
+    pub document_fraction: f64,
+    pub idf: f64
+}
+
+// NOTE: Idf is computed the same way as for document vectors, so a term
+//  that occurs in every document has idf of exactly zero
+pub fn discover(index: &InvertedIndex, min_document_fraction: f64, max_idf: f64) -> Vec<StopwordCandidate> {
+    let document_count = index.document_count() as f64;
+
+    index.document_frequencies()
+        .map(|(term, frequency)| StopwordCandidate {
+            term: term.to_owned(),
+            document_fraction: frequency as f64 / document_count,
+            idf: ((document_count + 1.0) / (frequency as f64 + 1.0)).log2()
+        })
+        .filter(|candidate| candidate.document_fraction >= min_document_fraction && candidate.idf <= max_idf)
+        .sorted_by(|a, b| a.idf.partial_cmp(&b.idf).unwrap().then_with(|| a.term.cmp(&b.term)))
+        .collect()
+}
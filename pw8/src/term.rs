@@ -1,7 +1,27 @@
 use ahash::{AHashMap, AHashSet};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use crate::document::DocumentId;
+use crate::memory::hash_table_size;
+
+// NOTE: One document of a posting list, the index keeps occurrence counts but not positions
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+#[derive(Serialize)]
+pub struct Posting {
+    pub document_id: DocumentId,
+    pub count: usize
+}
+
+// NOTE: Query term as it was lexed, together with how many times a document contains it
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize)]
+pub struct MatchedTerm {
+    pub term: String,
+    pub count: usize
+}
 
 #[derive(Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
 pub struct TermPositions {
     positions: AHashMap<DocumentId, usize>
 }
@@ -22,6 +42,10 @@ impl TermPositions {
         self.positions.len()
     }
 
+    pub fn total_count(&self) -> usize {
+        self.positions.values().sum()
+    }
+
     pub fn count(&self, document_id: DocumentId) -> usize {
         self.positions.get(&document_id)
             .cloned()
@@ -48,4 +72,28 @@ impl TermPositions {
     pub fn iter(&self) -> impl Iterator<Item = (&DocumentId, &usize)> {
         self.positions.iter()
     }
+
+    // NOTE: Sorted by document id, the same order postings are serialized in
+    pub fn postings(&self) -> impl Iterator<Item = Posting> + '_ {
+        self.positions.iter()
+            .map(|(&document_id, &count)| Posting { document_id, count })
+            .sorted_by_key(|posting| posting.document_id)
+    }
+
+    pub fn heap_size(&self) -> usize {
+        hash_table_size::<(DocumentId, usize)>(self.positions.capacity())
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(DocumentId, usize) -> bool) -> usize {
+        let document_count = self.positions.len();
+        self.positions.retain(|&document_id, &mut count| keep(document_id, count));
+
+        document_count - self.positions.len()
+    }
+}
+
+impl Default for TermPositions {
+    fn default() -> Self {
+        Self::new()
+    }
 }
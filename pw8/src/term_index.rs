@@ -1,27 +1,78 @@
 use std::collections::BTreeMap;
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use ahash::{AHashMap, AHashSet};
 use std::io::{BufRead, Write};
 use std::str::FromStr;
 use itertools::Itertools;
 use nalgebra::DVector;
 use rand::prelude::SliceRandom;
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::binary::{self, BinaryReader};
 use crate::document::DocumentId;
-use crate::term::TermPositions;
+use crate::error::{IndexError, StorageError};
+use crate::memory::{hash_table_size, MemoryReport};
+use crate::term::{MatchedTerm, Posting, TermPositions};
+use crate::vector::{SimilarityMetric, SparseVector};
+
+// NOTE: How many times every term occurs in the query, times its boost
+pub type Query = AHashMap<String, f64>;
+pub type QueryResult = Vec<(DocumentId, f64)>;
+
+pub const DEFAULT_SEED: u64 = 0;
+pub const DEFAULT_TIER_SIZE: usize = 20;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TermStatistics {
+    pub document_frequency: usize,
+    pub collection_frequency: usize,
+    pub idf: f64
+}
+
+// NOTE: Highest weight first, ties are broken by document id so the order doesn't depend on hashing
+pub fn sorted_by_weight(weights: AHashMap<DocumentId, f64>) -> QueryResult {
+    weights.into_iter()
+        .sorted_by(|(id_a, a), (id_b, b)| a.partial_cmp(b).unwrap().reverse().then(id_a.cmp(id_b)))
+        .collect()
+}
 
 pub trait TermIndex {
     fn add_term(&mut self, term: String, document_id: DocumentId);
-    fn query(&self, terms: &AHashSet<String>, leader_count: usize) -> Result<Vec<(DocumentId, f64)>>;
+    fn query(&self, terms: &Query, leader_count: usize) -> Result<QueryResult, IndexError>;
+    fn query_batch(&self, queries: &[Query], leader_count: usize) -> Vec<Result<QueryResult, IndexError>>;
+}
+
+pub struct BinaryPostingList<'a> {
+    pub term: &'a str,
+    pub document_frequency: usize,
+    pub postings: &'a [u8]
 }
 
-#[derive(Debug)]
+#[derive(PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
 pub struct InvertedIndex {
     documents: AHashMap<DocumentId, usize>,
     index: BTreeMap<String, TermPositions>,
-    vectors: AHashMap<DocumentId, DVector<f64>>,
+    vectors: AHashMap<DocumentId, SparseVector>,
     leaders: AHashSet<DocumentId>,
-    followers: AHashMap<DocumentId, Vec<DocumentId>>
+    followers: AHashMap<DocumentId, Vec<DocumentId>>,
+    // NOTE: First tier of every term, the documents it occurs in the most
+    #[serde(default)]
+    champions: AHashMap<String, Vec<DocumentId>>,
+    // NOTE: Highest weight a term contributes to the similarity of any document, lets exact top k queries skip documents
+    #[serde(default)]
+    term_bounds: AHashMap<String, f64>,
+    #[serde(default)]
+    magnitudes: AHashMap<DocumentId, f64>,
+    #[serde(default)]
+    similarity: SimilarityMetric,
+    #[serde(default)]
+    seed: u64,
+    // NOTE: Fingerprint of the analyzer the documents were lexed with, missing in indexes saved before it was recorded
+    #[serde(default)]
+    analyzer: Option<u32>
 }
 
 impl InvertedIndex {
@@ -31,21 +82,62 @@ impl InvertedIndex {
             index: BTreeMap::new(),
             vectors: AHashMap::new(),
             leaders: AHashSet::new(),
-            followers: AHashMap::new()
+            followers: AHashMap::new(),
+            champions: AHashMap::new(),
+            term_bounds: AHashMap::new(),
+            magnitudes: AHashMap::new(),
+            similarity: SimilarityMetric::default(),
+            seed: DEFAULT_SEED,
+            analyzer: None
+        }
+    }
+
+    // NOTE: Leaders are assigned with the same metric queries are ranked with, so preprocess again after changing it
+    pub fn set_similarity(&mut self, similarity: SimilarityMetric) {
+        self.similarity = similarity;
+    }
+
+    pub fn similarity(&self) -> SimilarityMetric {
+        self.similarity
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    // NOTE: Leaders are picked with it, so the same seed and corpus always give the same clusters
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_analyzer(&mut self, analyzer: u32) {
+        self.analyzer = Some(analyzer);
+    }
+
+    pub fn analyzer(&self) -> Option<u32> {
+        self.analyzer
+    }
+
+    // NOTE: Query words analyzed differently than the documents silently stop matching,
+    //  an index that doesn't know its analyzer can't be checked and is let through
+    pub fn check_analyzer(&self, query: u32) -> Result<(), IndexError> {
+        match self.analyzer {
+            Some(index) if index != query => Err(IndexError::AnalyzerMismatch { index, query }),
+            _ => Ok(())
         }
     }
 
-    pub fn preprocess(&mut self, follower_leader_count: usize) {
+    pub fn preprocess(&mut self, follower_leader_count: usize, tier_size: usize) {
         let leader_count = (self.documents.len() as f64).sqrt() as usize;
+        // NOTE: Sorted first, otherwise the shuffle would start from hash map order
         let mut documents = self.documents.keys()
             .cloned()
+            .sorted()
             .collect::<Vec<_>>();
-        documents.shuffle(&mut thread_rng());
+        documents.shuffle(&mut StdRng::seed_from_u64(self.seed));
         let (leader_ids, follower_ids) = documents.split_at(leader_count);
 
-        self.vectors = self.documents.keys()
-            .map(|&document_id| (document_id, self.document_tf_idf(document_id)))
-            .collect();
+        self.vectors = self.document_vectors();
 
         self.leaders = leader_ids.iter().cloned().collect();
 
@@ -59,7 +151,7 @@ impl InvertedIndex {
                         .collect::<Vec<_>>()
                 )
             })
-            .collect::<AHashMap<_, _>>();
+            .collect::<Vec<_>>();
 
         self.followers = followers_to_leaders.iter()
             .flat_map(|(follower, leaders)| {
@@ -78,51 +170,227 @@ impl InvertedIndex {
                 )
             )
             .collect();
+
+        self.champions = self.index.iter()
+            .map(|(term, positions)| (term.clone(), Self::champion_list(positions, tier_size)))
+            .collect();
+        self.compute_term_bounds();
+    }
+
+    // NOTE: Derived from the document vectors, so loading them again is enough to restore the bounds
+    fn compute_term_bounds(&mut self) {
+        self.magnitudes = self.vectors.iter()
+            .map(|(&document_id, vector)| (document_id, vector.magnitude()))
+            .collect();
+        self.term_bounds = self.index.iter()
+            .enumerate()
+            .map(|(term_id, (term, positions))| {
+                let bound = positions.iter()
+                    .filter_map(|(document_id, _)| self.document_weight(*document_id, term_id))
+                    .fold(0.0, f64::max);
+
+                (term.clone(), bound)
+            })
+            .collect();
+    }
+
+    // NOTE: Component of a document vector scaled the way the similarity metric scales it
+    fn document_weight(&self, document_id: DocumentId, term_id: usize) -> Option<f64> {
+        let vector = self.vectors.get(&document_id)?;
+        let weight = vector.get(term_id);
+
+        Some(match self.similarity {
+            SimilarityMetric::Cosine => {
+                let magnitude = self.magnitudes.get(&document_id)
+                    .copied()
+                    .unwrap_or_else(|| vector.magnitude());
+                if magnitude == 0.0 { 0.0 } else { weight / magnitude }
+            },
+            SimilarityMetric::Dot | SimilarityMetric::Jaccard => weight
+        })
+    }
+
+    // NOTE: Highest term frequency first, ties are broken by document id so the tier doesn't depend on hashing
+    fn champion_list(positions: &TermPositions, tier_size: usize) -> Vec<DocumentId> {
+        positions.iter()
+            .sorted_by(|(id_a, count_a), (id_b, count_b)| count_a.cmp(count_b).reverse().then(id_a.cmp(id_b)))
+            .take(tier_size)
+            .map(|(&document_id, _)| document_id)
+            .collect()
+    }
+
+    // NOTE: Static pruning, postings with tf-idf below the given percentile of all posting weights are dropped.
+    //  Document lengths stay the same, so weights of remaining postings don't change
+    pub fn prune(&mut self, percentile: f64) -> usize {
+        let idf = self.inverse_document_frequency();
+        let documents = &self.documents;
+        let weight = |term_id: usize, document_id: DocumentId, count: usize| {
+            count as f64 / documents.get(&document_id).cloned().unwrap_or(1) as f64 * idf[term_id]
+        };
+
+        let weights = self.index.values()
+            .enumerate()
+            .flat_map(|(term_id, positions)| positions.iter().map(move |(&document_id, &count)| weight(term_id, document_id, count)))
+            .sorted_by(|a, b| a.partial_cmp(b).unwrap())
+            .collect::<Vec<_>>();
+        if weights.is_empty() {
+            return 0;
+        }
+        let threshold = weights[((weights.len() - 1) as f64 * percentile.clamp(0.0, 100.0) / 100.0).round() as usize];
+
+        let pruned = self.index.values_mut()
+            .enumerate()
+            .map(|(term_id, positions)| positions.retain(|document_id, count| weight(term_id, document_id, count) >= threshold))
+            .sum();
+        self.index.retain(|_, positions| positions.document_count() != 0);
+        self.clear_preprocessed();
+
+        pruned
+    }
+
+    // NOTE: Same as adding the term `count` times, used to restore partial indexes that were cached
+    pub fn add_term_with_count(&mut self, term: String, document_id: DocumentId, count: usize) {
+        self.index.entry(term)
+            .or_default()
+            .add_position_with_count(document_id, count);
+        *self.documents.entry(document_id).or_default() += count;
+    }
+
+    pub fn document_count(&self) -> usize {
+        self.documents.len()
+    }
+
+    // NOTE: Call `preprocess` to rebuild vectors, clusters and champion lists, the index can't be queried until then
+    pub fn remove_document(&mut self, document_id: DocumentId) -> bool {
+        if self.documents.remove(&document_id).is_none() {
+            return false;
+        }
+
+        self.index.values_mut()
+            .for_each(|positions| {
+                positions.retain(|id, _| id != document_id);
+            });
+        self.index.retain(|_, positions| positions.document_count() != 0);
+        self.clear_preprocessed();
+
+        true
+    }
+
+    // NOTE: Term ids are positions in term order, so dropping a term shifts the ids of every term after it,
+    //  and removed postings change the idf of the rest. Nothing computed from the old ids is kept
+    fn clear_preprocessed(&mut self) {
+        self.vectors.clear();
+        self.magnitudes.clear();
+        self.term_bounds.clear();
+        self.champions.clear();
+        self.leaders.clear();
+        self.followers.clear();
+    }
+
+    // NOTE: Preprocessing gives every document a vector, documents without one were added or changed since
+    pub fn check_preprocessed(&self) -> Result<(), IndexError> {
+        if self.vectors.len() != self.documents.len() {
+            return Err(IndexError::NotPreprocessed);
+        }
+
+        Ok(())
+    }
+
+    pub fn document_frequencies(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.index.iter()
+            .map(|(term, positions)| (term.as_str(), positions.document_count()))
+    }
+
+    // NOTE: Idf is the same one document vectors are weighted with
+    pub fn term_statistics(&self, term: &str) -> TermStatistics {
+        let (document_frequency, collection_frequency) = self.index.get(term)
+            .map(|positions| (positions.document_count(), positions.total_count()))
+            .unwrap_or((0, 0));
+        let idf = ((self.documents.len() as f64 + 1.0) / (document_frequency as f64 + 1.0)).log2();
+
+        TermStatistics {
+            document_frequency,
+            collection_frequency,
+            idf
+        }
+    }
+
+    pub fn posting_count(&self) -> usize {
+        self.index.values()
+            .map(TermPositions::document_count)
+            .sum()
     }
 
     pub fn shrink_to_fit(&mut self) {
         self.documents.shrink_to_fit();
     }
 
+    pub fn estimate_memory(&self, report: &mut MemoryReport) {
+        report.dictionary += self.index.keys()
+            .map(|term| size_of::<(String, TermPositions)>() + term.capacity())
+            .sum::<usize>();
+        report.postings += self.index.values()
+            .map(TermPositions::heap_size)
+            .sum::<usize>();
+        report.vectors += hash_table_size::<(DocumentId, SparseVector)>(self.vectors.capacity())
+            + self.vectors.values().map(SparseVector::heap_size).sum::<usize>();
+        report.clusters += hash_table_size::<DocumentId>(self.leaders.capacity())
+            + hash_table_size::<(DocumentId, Vec<DocumentId>)>(self.followers.capacity())
+            + self.followers.values().map(|followers| followers.capacity() * size_of::<DocumentId>()).sum::<usize>();
+        report.postings += hash_table_size::<(String, Vec<DocumentId>)>(self.champions.capacity())
+            + self.champions.iter().map(|(term, champions)| term.capacity() + champions.capacity() * size_of::<DocumentId>()).sum::<usize>();
+        report.documents += hash_table_size::<(DocumentId, usize)>(self.documents.capacity());
+        report.vectors += hash_table_size::<(DocumentId, f64)>(self.magnitudes.capacity());
+        report.dictionary += hash_table_size::<(String, f64)>(self.term_bounds.capacity())
+            + self.term_bounds.keys().map(String::capacity).sum::<usize>();
+    }
+
     pub fn term_count(&self) -> usize {
         self.index.len()
     }
 
-    fn closest_documents<'a>(&self, count: usize, needle: &DVector<f64>, haystack: impl Iterator<Item = &'a DocumentId>)
+    // NOTE: Most similar first, ties are broken by document id so the order doesn't depend on hashing
+    pub fn closest_documents<'a>(&self, count: usize, needle: &SparseVector, haystack: impl Iterator<Item = &'a DocumentId>)
         -> Vec<(DocumentId, f64)> {
         haystack
-            .map(|&document_id| (document_id, Self::cosine_sim(&self.vectors[&document_id], needle)))
-            .sorted_by(|(_, sim_a), (_, sim_b)| sim_a.partial_cmp(sim_b).unwrap())
+            .map(|&document_id| (document_id, self.similarity.similarity(&self.vectors[&document_id], needle)))
+            .sorted_by(|(id_a, sim_a), (id_b, sim_b)| sim_a.partial_cmp(sim_b).unwrap().reverse().then(id_a.cmp(id_b)))
             .take(count)
             .collect()
     }
 
-    fn cosine_sim(a: &DVector<f64>, b: &DVector<f64>) -> f64 {
-        let a_mag = a.magnitude();
-        let b_mag = b.magnitude();
-        if a_mag == 0.0 || b_mag == 0.0 {
-            return 0.0;
+    // NOTE: One pass over the postings instead of one over the vocabulary for every document.
+    //  Term ids only grow, so entries of every vector come out sorted
+    fn document_vectors(&self) -> AHashMap<DocumentId, SparseVector> {
+        let idf = self.inverse_document_frequency();
+        let mut entries = AHashMap::<DocumentId, Vec<(usize, f64)>>::new();
+        for (term_id, positions) in self.index.values().enumerate() {
+            for (document_id, &count) in positions.iter() {
+                let document_term_count = self.document_term_count(*document_id).max(1) as f64;
+                entries.entry(*document_id)
+                    .or_default()
+                    .push((term_id, count as f64 / document_term_count * idf[term_id]));
+            }
         }
 
-        a.dot(b) / (a_mag * b_mag)
-    }
-
-    fn document_tf_idf(&self, document_id: DocumentId) -> DVector<f64> {
-        self.terms_frequency(document_id).component_mul(&self.inverse_document_frequency())
+        self.documents.keys()
+            .map(|&document_id| (document_id, entries.remove(&document_id).unwrap_or_default().into_iter().collect()))
+            .collect()
     }
 
-    fn terms_frequency(&self, document_id: DocumentId) -> DVector<f64> {
-        let document_term_count = self.documents.get(&document_id).cloned().unwrap_or(0) as f64;
+    // NOTE: Vector of a document from another index in the term space of this one
+    pub fn foreign_tf_idf(&self, other: &InvertedIndex, document_id: DocumentId) -> SparseVector {
+        let document_term_count = other.document_term_count(document_id).max(1) as f64;
+        let idf = self.inverse_document_frequency();
 
-        self.terms_count(document_id) / document_term_count
-    }
+        self.index.keys()
+            .enumerate()
+            .filter_map(|(term_id, term)| {
+                let count = other.index.get(term)?.count(document_id);
 
-    fn terms_count(&self, document_id: DocumentId) -> DVector<f64> {
-        DVector::from_iterator(
-            self.term_count(),
-            self.index.values()
-                .map(|positions| positions.count(document_id) as f64)
-        )
+                Some((term_id, count as f64 / document_term_count * idf[term_id]))
+            })
+            .collect()
     }
 
     fn inverse_document_frequency(&self) -> DVector<f64> {
@@ -141,12 +409,247 @@ impl InvertedIndex {
         vector
     }
 
-    fn query_vector(&self, terms: &AHashSet<String>) -> DVector<f64> {
-        DVector::from_iterator(
-            self.term_count(),
-            self.index.keys()
-                .map(|term| terms.contains(term).then_some(1.0).unwrap_or(0.0))
-        )
+    // NOTE: Weighted the same way document vectors are, so repeated and rare query terms count for more
+    pub fn query_vector(&self, terms: &Query) -> SparseVector {
+        self.query_vector_from_ids(terms, &self.term_ids(terms.keys()), &self.inverse_document_frequency())
+    }
+
+    fn term_ids<'a>(&self, terms: impl Iterator<Item = &'a String>) -> AHashMap<&'a str, usize> {
+        let terms = terms.map(String::as_str).collect::<AHashSet<_>>();
+
+        self.index.keys()
+            .enumerate()
+            .filter_map(|(term_id, term)| terms.get(term.as_str()).map(|&term| (term, term_id)))
+            .collect()
+    }
+
+    fn query_vector_from_ids(&self, terms: &Query, term_ids: &AHashMap<&str, usize>, idf: &DVector<f64>) -> SparseVector {
+        let weights = terms.iter()
+            .filter_map(|(term, &weight)| term_ids.get(term.as_str()).map(|&term_id| (term_id, weight)));
+        let query_term_count = terms.values().sum::<f64>();
+        if query_term_count == 0.0 {
+            return weights.collect();
+        }
+
+        weights
+            .map(|(term_id, weight)| (term_id, weight / query_term_count * idf[term_id]))
+            .collect()
+    }
+
+    pub fn query_by_vector(&self, needle: &SparseVector, leader_count: usize) -> Result<QueryResult, IndexError> {
+        if needle.magnitude_squared() == 0.0 {
+            return Err(IndexError::NoMatchingTerms);
+        }
+
+        // NOTE: A follower can belong to several of the closest leaders, collecting into a map keeps it once
+        let leaders = self.closest_documents(leader_count, needle, self.leaders.iter());
+        let followers = leaders.iter()
+            .filter_map(|(leader, _)| self.followers.get(leader))
+            .flatten()
+            .map(|&follower| (follower, self.similarity.similarity(needle, &self.vectors[&follower])));
+
+        Ok(sorted_by_weight(leaders.iter().cloned().chain(followers).collect()))
+    }
+
+    // NOTE: Exact top k of the similarity the clusters approximate, found with term at a time max-score.
+    //  Terms are added highest bound first, once the bounds of the remaining terms can't lift a document
+    //  that wasn't seen yet above the k-th score, only documents that were seen are scored any further.
+    //  Jaccard doesn't add up over terms, so every candidate is compared instead
+    pub fn query_top_k(&self, terms: &Query, k: usize) -> Result<QueryResult, IndexError> {
+        let needle = self.query_vector(terms);
+        if needle.magnitude_squared() == 0.0 {
+            return Err(IndexError::NoMatchingTerms);
+        }
+        let query_scale = match self.similarity {
+            SimilarityMetric::Cosine => 1.0 / needle.magnitude(),
+            SimilarityMetric::Dot => 1.0,
+            SimilarityMetric::Jaccard => return Ok(self.closest_documents(k, &needle, self.candidates(terms).iter()))
+        };
+
+        // NOTE: Terms missing from the bounds were added after preprocessing, they are never skipped
+        let query_terms = self.term_ids(terms.keys())
+            .into_iter()
+            .filter(|&(_, term_id)| needle.get(term_id) != 0.0)
+            .map(|(term, term_id)| {
+                let weight = needle.get(term_id) * query_scale;
+                let bound = self.term_bounds.get(term).map_or(f64::INFINITY, |bound| bound * weight);
+
+                (term, term_id, weight, bound)
+            })
+            .sorted_by(|(term_a, _, _, a), (term_b, _, _, b)| a.partial_cmp(b).unwrap().reverse().then(term_a.cmp(term_b)))
+            .collect::<Vec<_>>();
+
+        let mut remaining = query_terms.iter().map(|&(_, _, _, bound)| bound).sum::<f64>();
+        let mut scores = AHashMap::<DocumentId, f64>::new();
+        for (term, term_id, weight, bound) in query_terms {
+            // NOTE: Ties are broken by document id, so a new document has to stay strictly below the k-th score to be skipped
+            if remaining < Self::kth_score(&scores, k) {
+                for (&document_id, score) in scores.iter_mut() {
+                    *score += self.document_weight(document_id, term_id).unwrap_or(0.0) * weight;
+                }
+            } else {
+                for (&document_id, _) in self.index[term].iter() {
+                    if let Some(document_weight) = self.document_weight(document_id, term_id) {
+                        *scores.entry(document_id).or_default() += document_weight * weight;
+                    }
+                }
+            }
+            remaining -= bound;
+        }
+
+        let mut result = sorted_by_weight(scores);
+        result.truncate(k);
+
+        Ok(result)
+    }
+
+    fn kth_score(scores: &AHashMap<DocumentId, f64>, k: usize) -> f64 {
+        if k == 0 || scores.len() < k {
+            return f64::NEG_INFINITY;
+        }
+
+        let mut values = scores.values().copied().collect::<Vec<_>>();
+        let (_, kth, _) = values.select_nth_unstable_by(k - 1, |a, b| a.partial_cmp(b).unwrap().reverse());
+
+        *kth
+    }
+
+    pub fn candidates(&self, terms: &Query) -> AHashSet<DocumentId> {
+        terms.keys()
+            .filter_map(|term| self.index.get(term))
+            .flat_map(TermPositions::iter)
+            .map(|(&document_id, _)| document_id)
+            .collect()
+    }
+
+    // NOTE: Empty when the index wasn't preprocessed, callers fall back to `candidates`
+    pub fn champion_candidates(&self, terms: &Query) -> AHashSet<DocumentId> {
+        terms.keys()
+            .filter_map(|term| self.champions.get(term))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    pub fn champions(&self, term: &str) -> &[DocumentId] {
+        self.champions.get(term)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    // NOTE: Sorted by term, query terms the document doesn't contain are left out
+    pub fn matched_terms(&self, terms: &Query, document_id: DocumentId) -> Vec<MatchedTerm> {
+        terms.keys()
+            .sorted()
+            .filter_map(|term| {
+                let count = self.index.get(term)?.count(document_id);
+
+                (count != 0).then(|| MatchedTerm { term: term.clone(), count })
+            })
+            .collect()
+    }
+
+    pub fn term_positions(&self, term: &str) -> Option<&TermPositions> {
+        self.index.get(term)
+    }
+
+    // NOTE: Empty for terms the index doesn't hold
+    pub fn postings(&self, term: &str) -> impl Iterator<Item = Posting> + '_ {
+        self.index.get(term)
+            .into_iter()
+            .flat_map(TermPositions::postings)
+    }
+
+    pub fn document_vector(&self, document_id: DocumentId) -> Option<&SparseVector> {
+        self.vectors.get(&document_id)
+    }
+
+    // NOTE: Keeps only the highest weighted terms, the rest of a document is mostly noise for similarity
+    pub fn top_terms_vector(&self, document_id: DocumentId, term_count: usize) -> Option<SparseVector> {
+        let vector = self.vectors.get(&document_id)?;
+
+        Some(vector.iter()
+            .filter(|&(_, weight)| weight > 0.0)
+            .sorted_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap().reverse())
+            .take(term_count)
+            .collect())
+    }
+
+    // NOTE: Highest tf-idf weight first, ties are broken by term
+    pub fn top_terms(&self, document_id: DocumentId, count: usize) -> Vec<(&str, f64)> {
+        let Some(vector) = self.vectors.get(&document_id) else {
+            return Vec::new();
+        };
+
+        self.index.keys()
+            .enumerate()
+            .map(|(term_id, term)| (term, vector.get(term_id)))
+            .filter(|&(_, weight)| weight > 0.0)
+            .sorted_by(|(term_a, a), (term_b, b)| a.partial_cmp(b).unwrap().reverse().then(term_a.cmp(term_b)))
+            .take(count)
+            .map(|(term, weight)| (term.as_str(), weight))
+            .collect()
+    }
+
+    // NOTE: Terms whose occurrence counts over documents point the same way as those of the given term,
+    //  highest cosine first. Only documents of the given term contribute, others are zero in its vector
+    pub fn related_terms(&self, term: &str, count: usize) -> Vec<(&str, f64)> {
+        let Some(positions) = self.index.get(term) else {
+            return Vec::new();
+        };
+        let magnitude = |positions: &TermPositions| positions.iter()
+            .map(|(_, &count)| (count * count) as f64)
+            .sum::<f64>()
+            .sqrt();
+        let term_magnitude = magnitude(positions);
+
+        self.index.iter()
+            .filter(|(other, _)| other.as_str() != term)
+            .filter_map(|(other, other_positions)| {
+                let dot = positions.iter()
+                    .map(|(&document_id, &count)| (count * other_positions.count(document_id)) as f64)
+                    .sum::<f64>();
+                if dot == 0.0 {
+                    return None;
+                }
+
+                Some((other.as_str(), dot / (term_magnitude * magnitude(other_positions))))
+            })
+            .sorted_by(|(term_a, a), (term_b, b)| a.partial_cmp(b).unwrap().reverse().then(term_a.cmp(term_b)))
+            .take(count)
+            .collect()
+    }
+
+    // NOTE: For every term, how many times it occurs in the given documents and in the whole collection
+    pub fn term_counts_in(&self, documents: &AHashSet<DocumentId>) -> Vec<(&str, usize, usize)> {
+        self.index.iter()
+            .map(|(term, positions)| {
+                let (inside, total) = positions.iter()
+                    .fold((0, 0), |(inside, total), (document_id, &count)| {
+                        (inside + if documents.contains(document_id) { count } else { 0 }, total + count)
+                    });
+
+                (term.as_str(), inside, total)
+            })
+            .collect()
+    }
+
+    pub fn total_term_count(&self) -> usize {
+        self.documents.values().sum()
+    }
+
+    pub fn leader_clusters(&self) -> Vec<(DocumentId, Vec<DocumentId>)> {
+        self.leaders.iter()
+            .sorted()
+            .map(|&leader| (leader, self.followers.get(&leader).cloned().unwrap_or_default()))
+            .collect()
+    }
+
+    // NOTE: Position of a term in this list is its component in document vectors
+    pub fn term_names(&self) -> Vec<&str> {
+        self.index.keys()
+            .map(String::as_str)
+            .collect()
     }
 
     pub fn term_documents(&self, term: &str) -> AHashSet<DocumentId> {
@@ -167,6 +670,12 @@ impl InvertedIndex {
             .collect()
     }
 
+    pub fn document_terms(&self, document_id: DocumentId) -> impl Iterator<Item = (&str, usize)> {
+        self.index.iter()
+            .map(move |(term, positions)| (term.as_str(), positions.count(document_id)))
+            .filter(|&(_, count)| count != 0)
+    }
+
     pub fn terms(&self) -> AHashSet<String> {
         self.index.keys()
             .cloned()
@@ -184,16 +693,22 @@ impl InvertedIndex {
         other.index.into_iter()
             .for_each(|(term, other_positions)| {
                 self.index.entry(term)
-                    .or_insert_with(TermPositions::new)
+                    .or_default()
                     .merge(other_positions);
             });
     }
 }
 
+impl Default for InvertedIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TermIndex for InvertedIndex {
     fn add_term(&mut self, term: String, document_id: DocumentId) {
         self.index.entry(term)
-            .or_insert_with(TermPositions::new)
+            .or_default()
             .add_position(document_id);
 
         self.documents.entry(document_id)
@@ -201,28 +716,21 @@ impl TermIndex for InvertedIndex {
             .or_insert(1);
     }
 
-    fn query(&self, terms: &AHashSet<String>, leader_count: usize) -> Result<Vec<(DocumentId, f64)>> {
-        let needle = self.query_vector(terms);
-        if needle.magnitude_squared() == 0.0 {
-            return Err(anyhow!("Index doesn't contain any word from the query"));
+    fn query(&self, terms: &Query, leader_count: usize) -> Result<QueryResult, IndexError> {
+        self.check_preprocessed()?;
+        self.query_by_vector(&self.query_vector(terms), leader_count)
+    }
+
+    fn query_batch(&self, queries: &[Query], leader_count: usize) -> Vec<Result<QueryResult, IndexError>> {
+        if self.check_preprocessed().is_err() {
+            return queries.iter().map(|_| Err(IndexError::NotPreprocessed)).collect();
         }
+        let term_ids = self.term_ids(queries.iter().flat_map(|terms| terms.keys()));
+        let idf = self.inverse_document_frequency();
 
-        let leaders = self.closest_documents(leader_count, &needle, self.leaders.iter());
-        let followers = leaders.iter()
-            .flat_map(|(leader, _)|
-                self.followers.get(leader).iter()
-                    .flat_map(|followers| {
-                        followers.iter()
-                            .map(|&follower| (follower, Self::cosine_sim(&needle, &self.vectors[&follower])))
-                    })
-                    .collect::<Vec<_>>()
-            );
-
-        Ok(leaders.iter()
-            .cloned()
-            .chain(followers)
-            .sorted_by(|(_, sim_a), (_, sim_b)| sim_a.partial_cmp(sim_b).unwrap().reverse())
-            .collect())
+        queries.par_iter()
+            .map(|terms| self.query_by_vector(&self.query_vector_from_ids(terms, &term_ids, &idf), leader_count))
+            .collect()
     }
 }
 
@@ -231,6 +739,9 @@ impl InvertedIndex {
     const KEY_VALUE_SEPARATOR: &'static str = ":";
     const VALUE_SEPARATOR: &'static str = ",";
     const DOCUMENT_POSITIONS_SEPARATOR: &'static str = "#";
+    const BINARY_MAGIC: &'static [u8] = b"PW8INDEX6";
+    // NOTE: Written in place of the analyzer fingerprint when it isn't known, fingerprints fit in 32 bits
+    const UNKNOWN_ANALYZER: u64 = u64::MAX;
 
     pub fn save(&self, mut writer: impl Write) -> Result<()> {
         for (document, count) in self.documents.iter().sorted_by_key(|(&document_id, _)| document_id) {
@@ -254,7 +765,174 @@ impl InvertedIndex {
         Ok(())
     }
 
-    pub fn load(reader: impl BufRead) -> Result<Self> {
+    // NOTE: Canonical form for golden files and diffing builds, everything is sorted
+    //  and floats have a fixed precision, unlike `save` which is meant to be read back
+    pub fn dump(&self, mut writer: impl Write) -> Result<()> {
+        writeln!(writer, "documents {}", self.documents.len())?;
+        for (document, count) in self.documents.iter().sorted_by_key(|(&document_id, _)| document_id) {
+            writeln!(writer, "document {} {}", document.id(), count)?;
+        }
+
+        writeln!(writer, "terms {}", self.index.len())?;
+        for (term, positions) in &self.index {
+            let statistics = self.term_statistics(term);
+            writeln!(writer, "term {} {} {} {:.6}", term, statistics.document_frequency, statistics.collection_frequency, statistics.idf)?;
+            let postings = positions.iter()
+                .sorted_by_key(|(&document_id, _)| document_id)
+                .map(|(document, count)| format!("{}:{}", document.id(), count))
+                .join(" ");
+            writeln!(writer, "\t{postings}")?;
+        }
+
+        Ok(())
+    }
+
+    // NOTE: Unlike `save`, keeps everything `preprocess` computed, so a loaded index can be queried right away.
+    //  Maps are written sorted, so the same index always produces the same bytes
+    pub fn save_binary(&self, mut writer: impl Write) -> Result<(), StorageError> {
+        writer.write_all(Self::BINARY_MAGIC)?;
+        binary::write_str(&mut writer, &self.similarity.to_string())?;
+        binary::write_u64(&mut writer, self.seed)?;
+        binary::write_u64(&mut writer, self.analyzer.map_or(Self::UNKNOWN_ANALYZER, u64::from))?;
+
+        binary::write_usize(&mut writer, self.documents.len())?;
+        for (document, &count) in self.documents.iter().sorted_by_key(|(&document_id, _)| document_id) {
+            binary::write_usize(&mut writer, document.id())?;
+            binary::write_usize(&mut writer, count)?;
+        }
+
+        binary::write_usize(&mut writer, self.index.len())?;
+        for (term, positions) in &self.index {
+            binary::write_str(&mut writer, term)?;
+            binary::write_usize(&mut writer, positions.document_count())?;
+            for (document, &count) in positions.iter().sorted_by_key(|(&document_id, _)| document_id) {
+                binary::write_usize(&mut writer, document.id())?;
+                binary::write_usize(&mut writer, count)?;
+            }
+        }
+
+        binary::write_usize(&mut writer, self.vectors.len())?;
+        for (document, vector) in self.vectors.iter().sorted_by_key(|(&document_id, _)| document_id) {
+            binary::write_usize(&mut writer, document.id())?;
+            binary::write_usize(&mut writer, vector.len())?;
+            vector.iter().try_for_each(|(term_id, weight)| {
+                binary::write_usize(&mut writer, term_id)?;
+                binary::write_f64(&mut writer, weight)
+            })?;
+        }
+
+        binary::write_usize(&mut writer, self.leaders.len())?;
+        self.leaders.iter()
+            .sorted()
+            .try_for_each(|leader| binary::write_usize(&mut writer, leader.id()))?;
+
+        binary::write_usize(&mut writer, self.followers.len())?;
+        for (leader, followers) in self.followers.iter().sorted_by_key(|(&leader, _)| leader) {
+            binary::write_usize(&mut writer, leader.id())?;
+            binary::write_usize(&mut writer, followers.len())?;
+            followers.iter().try_for_each(|follower| binary::write_usize(&mut writer, follower.id()))?;
+        }
+
+        binary::write_usize(&mut writer, self.champions.len())?;
+        for (term, champions) in self.champions.iter().sorted_by_key(|(term, _)| term.as_str()) {
+            binary::write_str(&mut writer, term)?;
+            binary::write_usize(&mut writer, champions.len())?;
+            champions.iter().try_for_each(|champion| binary::write_usize(&mut writer, champion.id()))?;
+        }
+
+        Ok(())
+    }
+
+    // NOTE: Walks the dictionary of a binary index without reading any postings,
+    //  values are fixed width, so skipping a posting list doesn't touch its pages
+    pub fn binary_posting_lists(data: &[u8]) -> Result<Vec<BinaryPostingList<'_>>, StorageError> {
+        let mut reader = BinaryReader::new(data);
+        if reader.take(Self::BINARY_MAGIC.len())? != Self::BINARY_MAGIC {
+            return Err(StorageError::NotBinaryIndex);
+        }
+
+        reader.read_str()?;
+        reader.read_u64()?;
+        reader.read_u64()?;
+        let document_count = reader.read_usize()?;
+        reader.take(document_count * 2 * size_of::<u64>())?;
+
+        (0..reader.read_usize()?)
+            .map(|_| {
+                let term = reader.read_str()?;
+                let document_frequency = reader.read_usize()?;
+                let postings = reader.take(document_frequency * 2 * size_of::<u64>())?;
+
+                Ok(BinaryPostingList { term, document_frequency, postings })
+            })
+            .collect()
+    }
+
+    pub fn load_binary(data: &[u8]) -> Result<Self, StorageError> {
+        let mut reader = BinaryReader::new(data);
+        if reader.take(Self::BINARY_MAGIC.len())? != Self::BINARY_MAGIC {
+            return Err(StorageError::NotBinaryIndex);
+        }
+
+        let mut index = InvertedIndex::new();
+        index.similarity = SimilarityMetric::from_str(reader.read_str()?).map_err(|err| StorageError::Malformed(err.to_string()))?;
+        index.seed = reader.read_u64()?;
+        index.analyzer = match reader.read_u64()? {
+            Self::UNKNOWN_ANALYZER => None,
+            analyzer => Some(u32::try_from(analyzer).map_err(|_| StorageError::Malformed(format!("Invalid analyzer fingerprint {analyzer}")))?)
+        };
+        for _ in 0..reader.read_usize()? {
+            let document = DocumentId(reader.read_usize()?);
+            index.documents.insert(document, reader.read_usize()?);
+        }
+
+        for _ in 0..reader.read_usize()? {
+            let term = reader.read_str()?.to_owned();
+            let mut positions = TermPositions::new();
+            for _ in 0..reader.read_usize()? {
+                let document = DocumentId(reader.read_usize()?);
+                positions.add_position_with_count(document, reader.read_usize()?);
+            }
+            index.index.insert(term, positions);
+        }
+
+        for _ in 0..reader.read_usize()? {
+            let document = DocumentId(reader.read_usize()?);
+            let vector = (0..reader.read_usize()?)
+                .map(|_| Ok((reader.read_usize()?, reader.read_f64()?)))
+                .collect::<Result<SparseVector, StorageError>>()?;
+            index.vectors.insert(document, vector);
+        }
+
+        for _ in 0..reader.read_usize()? {
+            index.leaders.insert(DocumentId(reader.read_usize()?));
+        }
+
+        for _ in 0..reader.read_usize()? {
+            let leader = DocumentId(reader.read_usize()?);
+            let followers = (0..reader.read_usize()?)
+                .map(|_| reader.read_usize().map(DocumentId))
+                .collect::<Result<Vec<_>, _>>()?;
+            index.followers.insert(leader, followers);
+        }
+
+        for _ in 0..reader.read_usize()? {
+            let term = reader.read_str()?.to_owned();
+            let champions = (0..reader.read_usize()?)
+                .map(|_| reader.read_usize().map(DocumentId))
+                .collect::<Result<Vec<_>, _>>()?;
+            index.champions.insert(term, champions);
+        }
+
+        if !reader.is_empty() {
+            return Err(StorageError::TrailingData);
+        }
+        index.compute_term_bounds();
+
+        Ok(index)
+    }
+
+    pub fn load(reader: impl BufRead) -> Result<Self, StorageError> {
         let mut index = InvertedIndex::new();
 
         let mut iter = reader.lines();
@@ -264,7 +942,7 @@ impl InvertedIndex {
         Ok(index)
     }
 
-    fn read_documents(index: &mut Self, iter: &mut impl Iterator<Item = Result<String, std::io::Error>>) -> Result<()> {
+    fn read_documents(index: &mut Self, iter: &mut impl Iterator<Item = Result<String, std::io::Error>>) -> Result<(), StorageError> {
         for line in iter {
             let line = line?;
             if line == Self::DOCUMENT_POSITIONS_SEPARATOR {
@@ -277,7 +955,7 @@ impl InvertedIndex {
         Ok(())
     }
 
-    fn read_positions(index: &mut Self, iter: &mut impl Iterator<Item = Result<String, std::io::Error>>) -> Result<()> {
+    fn read_positions(index: &mut Self, iter: &mut impl Iterator<Item = Result<String, std::io::Error>>) -> Result<(), StorageError> {
         for line in iter {
             let line = line?;
 
@@ -287,28 +965,28 @@ impl InvertedIndex {
         Ok(())
     }
 
-    fn read_documents_line(index: &mut Self, line: &str) -> Result<()> {
+    fn read_documents_line(index: &mut Self, line: &str) -> Result<(), StorageError> {
         let (document_str, count_str) = line.split(Self::KEY_VALUE_SEPARATOR).collect_tuple()
-            .ok_or_else(|| anyhow!("Expected document id and term count"))?;
+            .ok_or_else(|| StorageError::Malformed("Expected document id and term count".to_owned()))?;
 
-        let document = usize::from_str(document_str)?;
-        let count = usize::from_str(count_str)?;
+        let document = Self::read_count(document_str)?;
+        let count = Self::read_count(count_str)?;
 
         index.documents.insert(DocumentId(document), count);
 
         Ok(())
     }
 
-    fn read_positions_line(index: &mut Self, line: &str) -> Result<()> {
+    fn read_positions_line(index: &mut Self, line: &str) -> Result<(), StorageError> {
         let (term, positions_str) = line.split(Self::TERM_POSITIONS_SEPARATOR).collect_tuple()
-            .ok_or_else(|| anyhow!("Expected term and document ids"))?;
+            .ok_or_else(|| StorageError::Malformed("Expected term and document ids".to_owned()))?;
         let mut positions = TermPositions::new();
         for position_str in positions_str.split(Self::VALUE_SEPARATOR) {
             let (document_str, count_str) = position_str.split(Self::KEY_VALUE_SEPARATOR).collect_tuple()
-                .ok_or_else(|| anyhow!("Expected document and count"))?;
+                .ok_or_else(|| StorageError::Malformed("Expected document and count".to_owned()))?;
 
-            let document = usize::from_str(document_str)?;
-            let count = usize::from_str(count_str)?;
+            let document = Self::read_count(document_str)?;
+            let count = Self::read_count(count_str)?;
 
             positions.add_position_with_count(DocumentId(document), count);
         }
@@ -317,4 +995,8 @@ impl InvertedIndex {
 
         Ok(())
     }
+
+    fn read_count(value: &str) -> Result<usize, StorageError> {
+        usize::from_str(value).map_err(|_| StorageError::Malformed(format!("Expected a number, got '{value}'")))
+    }
 }
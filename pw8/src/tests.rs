@@ -0,0 +1,711 @@
+use anyhow::Result;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use ahash::AHashSet;
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::archive::ArchiveCorpus;
+use crate::boilerplate::BoilerplateFilter;
+use crate::corpus::{Corpus, CorpusDocument, DocumentText};
+use crate::crawler::{CrawlerCorpus, MAX_RESPONSE_SIZE};
+use crate::document::{Document, DocumentId};
+use crate::engine::{IndexBuilder, Query as SearchQuery};
+use crate::records::{RecordCorpus, RecordFormat};
+use crate::error::{Error, IndexError, ParseError, StorageError};
+use crate::lexer;
+use crate::normalization::ScoreNormalization;
+use crate::ranking::Ranking;
+use crate::rankers::ranker_by_name;
+use crate::stopwords::Stopwords;
+use crate::term::MatchedTerm;
+use crate::term_index::{InvertedIndex, Query, DEFAULT_TIER_SIZE};
+use crate::vector::{SimilarityMetric, SparseVector};
+
+fn build_index() -> Result<InvertedIndex> {
+    build_index_with(SimilarityMetric::default())
+}
+
+fn build_index_with(similarity: SimilarityMetric) -> Result<InvertedIndex> {
+    let engine = IndexBuilder::default()
+        .similarity(similarity)
+        .document("hamlet.txt", "To be or not to be, that is the question.")
+        .document("lear.txt", "King Lear and his daughters.")
+        .document("macbeth.txt", "The king is dead. Long live the king!")
+        .document("romeo.txt", "Romeo and Juliet. What is love?")
+        .document("tempest.txt", "The tempest, an island and a storm.")
+        .build()?;
+
+    Ok(engine.into_parts().1)
+}
+
+#[test]
+fn binary_round_trip() -> Result<()> {
+    let index = build_index()?;
+
+    let mut data = Vec::new();
+    index.save_binary(&mut data)?;
+    let index_read = InvertedIndex::load_binary(&data)?;
+    assert_eq!(index, index_read);
+
+    Ok(())
+}
+
+#[test]
+fn binary_is_deterministic() -> Result<()> {
+    let mut first = Vec::new();
+    build_index()?.save_binary(&mut first)?;
+    let mut second = Vec::new();
+    InvertedIndex::load_binary(&first)?.save_binary(&mut second)?;
+    assert_eq!(first, second);
+
+    Ok(())
+}
+
+#[test]
+fn binary_truncated() -> Result<()> {
+    let mut data = Vec::new();
+    build_index()?.save_binary(&mut data)?;
+    data.truncate(data.len() - 1);
+    assert!(InvertedIndex::load_binary(&data).is_err());
+
+    Ok(())
+}
+
+fn query(words: &[&str]) -> Query {
+    words.iter()
+        .map(|&word| (word.to_owned(), 1.0))
+        .collect()
+}
+
+fn assert_descending(result: &[(DocumentId, f64)]) {
+    assert!(result.windows(2).all(|pair| pair[0].1 >= pair[1].1), "{result:?} isn't sorted by similarity");
+}
+
+#[test]
+fn closest_documents_most_similar_first() -> Result<()> {
+    for similarity in [SimilarityMetric::Cosine, SimilarityMetric::Dot, SimilarityMetric::Jaccard] {
+        let index = build_index_with(similarity)?;
+        let documents = (0..5).map(DocumentId).collect::<Vec<_>>();
+        for document in &documents {
+            let needle = index.document_vector(*document).unwrap();
+            let closest = index.closest_documents(documents.len(), needle, documents.iter());
+
+            assert_eq!(closest.len(), documents.len());
+            assert_descending(&closest);
+            if similarity != SimilarityMetric::Dot {
+                assert_eq!(closest[0].0, *document, "{similarity}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn closest_documents_takes_top() -> Result<()> {
+    let index = build_index()?;
+    let documents = (0..5).map(DocumentId).collect::<Vec<_>>();
+    let needle = index.document_vector(DocumentId(2)).unwrap();
+
+    let all = index.closest_documents(documents.len(), needle, documents.iter());
+    let top = index.closest_documents(2, needle, documents.iter());
+    assert_eq!(top, all[..2]);
+
+    Ok(())
+}
+
+#[test]
+fn similarity_metrics() {
+    let a = SparseVector::from_dense(&[1.0, 2.0, 0.0, 0.0]);
+    let b = SparseVector::from_dense(&[2.0, 0.0, 3.0, 0.0]);
+
+    assert_eq!(SimilarityMetric::Dot.similarity(&a, &b), 2.0);
+    assert!((SimilarityMetric::Cosine.similarity(&a, &a) - 1.0).abs() < 1e-9);
+    assert!((SimilarityMetric::Cosine.similarity(&a, &b) - 2.0 / (5.0f64.sqrt() * 13.0f64.sqrt())).abs() < 1e-9);
+    assert_eq!(SimilarityMetric::Jaccard.similarity(&a, &b), 1.0 / 3.0);
+    assert_eq!(SimilarityMetric::Jaccard.similarity(&a, &SparseVector::new()), 0.0);
+}
+
+#[test]
+fn sparse_vectors_skip_zero_weights() {
+    let a = SparseVector::from_dense(&[0.0, 2.0, 0.0, 1.0]);
+    let b: SparseVector = [(3, 1.0), (0, 4.0), (3, -2.0), (1, 0.0)].into_iter().collect();
+
+    assert_eq!(a.len(), 2);
+    assert_eq!(b.iter().collect::<Vec<_>>(), vec![(0, 4.0), (3, -1.0)]);
+    assert_eq!(a.get(2), 0.0);
+    assert_eq!(a.dot(&b), -1.0);
+    assert_eq!((&a + &b).iter().collect::<Vec<_>>(), vec![(0, 4.0), (1, 2.0)]);
+}
+
+#[test]
+fn cluster_query_sorted_without_duplicates() -> Result<()> {
+    for similarity in [SimilarityMetric::Cosine, SimilarityMetric::Dot, SimilarityMetric::Jaccard] {
+        let index = build_index_with(similarity)?;
+        let result = Ranking::Cluster.rank(&index, &query(&["king", "lear"]), 5)?;
+
+        assert_descending(&result);
+        let unique = result.iter().map(|(document, _)| *document).collect::<AHashSet<_>>();
+        assert_eq!(unique.len(), result.len());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn rankings_prefer_matching_documents() -> Result<()> {
+    let index = build_index()?;
+    for name in ["cluster", "cosine", "pivoted", "pivoted:0.5", "tf", "bm25", "bm25:2:0.5", "lm"] {
+        let ranking = Ranking::from_str(name)?;
+        let result = ranking.rank(&index, &query(&["king"]), 5)?;
+
+        assert_descending(&result);
+        // NOTE: Macbeth mentions the king twice in fewer words than Lear
+        assert_eq!(result[0].0, DocumentId(2), "{ranking:?}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn similarity_survives_binary() -> Result<()> {
+    let index = build_index_with(SimilarityMetric::Jaccard)?;
+
+    let mut data = Vec::new();
+    index.save_binary(&mut data)?;
+    assert_eq!(InvertedIndex::load_binary(&data)?.similarity(), SimilarityMetric::Jaccard);
+
+    Ok(())
+}
+
+#[test]
+fn normalizations() {
+    let result = vec![(DocumentId(0), 4.0), (DocumentId(1), 2.0), (DocumentId(2), 0.0)];
+
+    let min_max = ScoreNormalization::MinMax.apply(&result);
+    assert_eq!(min_max, vec![(DocumentId(0), 1.0), (DocumentId(1), 0.5), (DocumentId(2), 0.0)]);
+
+    let z_scores = ScoreNormalization::ZScore.apply(&result);
+    assert!(z_scores.iter().map(|(_, score)| score).sum::<f64>().abs() < 1e-9);
+    assert!(z_scores[0].1 > 0.0 && z_scores[2].1 < 0.0);
+
+    assert_eq!(ScoreNormalization::None.apply(&result), result);
+    assert_eq!(ScoreNormalization::MinMax.apply(&vec![(DocumentId(0), 3.0)]), vec![(DocumentId(0), 1.0)]);
+}
+
+#[test]
+fn normalized_fusion() -> Result<()> {
+    let index = build_index()?;
+    let terms = query(&["king", "lear"]);
+    for normalization in [ScoreNormalization::MinMax, ScoreNormalization::ZScore] {
+        let ranking = Ranking::from_str("fusion:bm25,tf")?.normalized(normalization);
+        let result = ranking.rank(&index, &terms, 5)?;
+        assert_descending(&result);
+
+        let expected = ["bm25", "tf"].iter()
+            .flat_map(|name| normalization.apply(&Ranking::from_str(name).unwrap().rank(&index, &terms, 5).unwrap()))
+            .filter(|(document, _)| *document == result[0].0)
+            .map(|(_, score)| score)
+            .sum::<f64>();
+        assert!((result[0].1 - expected).abs() < 1e-9);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn binary_posting_lists() -> Result<()> {
+    let index = build_index()?;
+    let mut data = Vec::new();
+    index.save_binary(&mut data)?;
+
+    let posting_lists = InvertedIndex::binary_posting_lists(&data)?;
+    assert_eq!(posting_lists.len(), index.term_count());
+    for list in posting_lists {
+        assert_eq!(list.document_frequency, index.term_statistics(list.term).document_frequency);
+        assert_eq!(list.postings.len(), list.document_frequency * 16);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn rankers_only_score_candidates() -> Result<()> {
+    let index = build_index()?;
+    let terms = query(&["king"]);
+    let candidates = [DocumentId(2)].into_iter().collect::<AHashSet<_>>();
+    for name in ["cosine", "pivoted", "tf", "bm25", "lm"] {
+        let result = ranker_by_name(name)?.score(&index, &terms, &candidates)?;
+        assert_eq!(result.iter().map(|(document, _)| *document).collect::<Vec<_>>(), vec![DocumentId(2)], "{name}");
+    }
+    assert!(ranker_by_name("pagerank").is_err());
+    assert!(matches!(ranker_by_name("pivoted:1.5"), Err(ParseError::InvalidSlope(_))));
+    assert!(matches!(ranker_by_name("cosine:0.5"), Err(ParseError::UnknownRanker(_))));
+    assert!(matches!(ranker_by_name("bm25:-1"), Err(ParseError::InvalidBm25Parameters(_))));
+    assert!(matches!(ranker_by_name("bm25:1.2:2"), Err(ParseError::InvalidBm25Parameters(_))));
+
+    Ok(())
+}
+
+#[test]
+fn typed_errors() -> Result<()> {
+    let engine = IndexBuilder::default()
+        .document("lear.txt", "King Lear and his daughters.")
+        .build()?;
+
+    assert!(matches!(engine.search(&SearchQuery::new("  ")), Err(Error::Parse(ParseError::EmptyQuery))));
+    assert!(matches!(engine.search(&SearchQuery::new("king^x")), Err(Error::Parse(ParseError::InvalidBoost { .. }))));
+    assert!(matches!(engine.search(&SearchQuery::new("storm")), Err(Error::Index(IndexError::NoMatchingTerms))));
+    assert!(matches!(Ranking::from_str("pagerank"), Err(ParseError::UnknownRanker(_))));
+    assert!(matches!(InvertedIndex::load_binary(b"PW7INDEX0"), Err(StorageError::NotBinaryIndex)));
+    assert!(matches!(InvertedIndex::load_binary(b"PW8"), Err(StorageError::UnexpectedEnd { expected: 9, found: 3 })));
+
+    Ok(())
+}
+
+#[test]
+fn postings() -> Result<()> {
+    let engine = IndexBuilder::default()
+        .document("lear.txt", "King Lear and his daughters.")
+        .document("macbeth.txt", "The king is dead. Long live the king!")
+        .build()?;
+
+    let postings = engine.postings("king")
+        .map(|posting| (posting.path.unwrap().file_name().unwrap().to_string_lossy().to_string(), posting.count))
+        .collect::<Vec<_>>();
+    assert_eq!(postings, vec![("lear.txt".to_owned(), 1), ("macbeth.txt".to_owned(), 2)]);
+    assert_eq!(engine.postings("storm").count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn seeded_leaders() -> Result<()> {
+    let build = || IndexBuilder::default()
+        .seed(7)
+        .document("hamlet.txt", "To be or not to be, that is the question.")
+        .document("lear.txt", "King Lear and his daughters.")
+        .document("macbeth.txt", "The king is dead. Long live the king!")
+        .document("romeo.txt", "Romeo and Juliet. What is love?")
+        .document("tempest.txt", "The tempest, an island and a storm.")
+        .build()
+        .map(|engine| engine.into_parts().1);
+
+    let (first, second) = (build()?, build()?);
+    assert_eq!(first.leader_clusters(), second.leader_clusters());
+
+    let mut data = Vec::new();
+    first.save_binary(&mut data)?;
+    assert_eq!(InvertedIndex::load_binary(&data)?.seed(), 7);
+
+    Ok(())
+}
+
+#[test]
+fn analyzer_fingerprint() -> Result<()> {
+    let index = build_index()?;
+    let analyzer = lexer::analyzer_fingerprint(&Stopwords::new());
+    assert_eq!(index.analyzer(), Some(analyzer));
+    index.check_analyzer(analyzer)?;
+
+    let mut stopwords = Stopwords::new();
+    stopwords.add("the");
+    let other = lexer::analyzer_fingerprint(&stopwords);
+    assert!(matches!(index.check_analyzer(other), Err(IndexError::AnalyzerMismatch { index, query }) if index == analyzer && query == other));
+    assert!(InvertedIndex::new().check_analyzer(other).is_ok());
+
+    let mut data = Vec::new();
+    index.save_binary(&mut data)?;
+    assert_eq!(InvertedIndex::load_binary(&data)?.analyzer(), Some(analyzer));
+
+    Ok(())
+}
+
+#[test]
+fn related_terms() -> Result<()> {
+    let index = build_index()?;
+
+    // NOTE: "king" is once in Lear and twice in Macbeth, terms only found in Macbeth point the same way the most
+    let related = index.related_terms("king", 3);
+    assert_eq!(related.iter().map(|(term, _)| *term).collect::<Vec<_>>(), vec!["dead", "live", "long"]);
+    assert!((related[0].1 - 2.0 / 5f64.sqrt()).abs() < 1e-9);
+    assert!(index.related_terms("storm", 3).iter().all(|(term, _)| *term != "storm"));
+    assert!(index.related_terms("pagerank", 3).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn random_document() -> Result<()> {
+    let engine = IndexBuilder::default()
+        .document("lear.txt", "King Lear and his daughters.")
+        .document("macbeth.txt", "The king is dead. Long live the king!")
+        .document("tempest.txt", "The tempest, an island and a storm.")
+        .build()?;
+    let mut rng = StdRng::seed_from_u64(0);
+
+    for _ in 0..10 {
+        let summary = engine.random_document(Some("King dead"), 3, &mut rng)?.unwrap();
+        assert_eq!(summary.name, "macbeth.txt");
+        assert_eq!(summary.top_terms.len(), 3);
+    }
+    assert!(engine.random_document(Some("storm king"), 3, &mut rng)?.is_none());
+    assert!(engine.random_document(None, 3, &mut rng)?.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn build_cache_reuses_partials() -> Result<()> {
+    let folder = std::env::temp_dir().join(format!("pw8_build_cache_{}", std::process::id()));
+    let build = |lear: &str| IndexBuilder::default()
+        .build_cache(&folder)
+        .document("lear.txt", lear)
+        .document("macbeth.txt", "The king is dead. Long live the king!")
+        .build();
+
+    let uncached = build("King Lear and his daughters.")?;
+    let cached = build("King Lear and his daughters.")?;
+    assert_eq!((cached.report().cache_hits, cached.report().cache_misses), (2, 0));
+    assert_eq!(cached.index(), uncached.index());
+
+    let changed = build("King Lear and his three daughters.")?;
+    assert_eq!((changed.report().cache_hits, changed.report().cache_misses), (1, 1));
+    std::fs::remove_dir_all(&folder)?;
+
+    Ok(())
+}
+
+#[test]
+fn record_corpora() -> Result<()> {
+    let folder = std::env::temp_dir().join(format!("pw8_records_{}", std::process::id()));
+    std::fs::create_dir_all(&folder)?;
+    let csv = folder.join("plays.csv");
+    std::fs::write(&csv, "id,text,author\nlear,\"King Lear, and his \"\"daughters\"\"\",Shakespeare\n,\"The king is dead.\nLong live the king!\",\nbroken\n")?;
+    let jsonl = folder.join("plays.jsonl");
+    std::fs::write(&jsonl, "{\"id\": \"tempest\", \"text\": \"The tempest, an island and a storm.\", \"year\": 1611}\n\nnot json\n")?;
+
+    let engine = IndexBuilder::default()
+        .corpus(Box::new(RecordCorpus::new(&csv, RecordFormat::Csv)))
+        .corpus(Box::new(RecordCorpus::new(&jsonl, RecordFormat::Jsonl)))
+        .build()?;
+    std::fs::remove_dir_all(&folder)?;
+
+    let ctx = engine.ctx();
+    let names = ctx.document_ids()
+        .map(|document_id| ctx.document(document_id).unwrap().path().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, [csv.join("lear"), csv.join("2"), jsonl.join("tempest")]);
+    assert_eq!(ctx.document_metadata(DocumentId(0)), [("author".to_owned(), "Shakespeare".to_owned())]);
+    assert_eq!(ctx.document_metadata(DocumentId(2)), [("year".to_owned(), "1611".to_owned())]);
+    assert_eq!(ctx.skipped().len(), 2);
+
+    let results = engine.search(&SearchQuery::new("daughters").limit(1))?;
+    assert_eq!(results[0].document_id, DocumentId(0));
+
+    Ok(())
+}
+
+#[test]
+fn inline_documents() -> Result<()> {
+    let engine = IndexBuilder::default()
+        .document("lear.txt", "King Lear and his daughters.")
+        .document("macbeth.txt", "The king is dead. Long live the king!")
+        .build()?;
+    let ctx = engine.ctx();
+
+    assert!(matches!(ctx.document(DocumentId(1)), Some(Document::Inline { name, .. }) if name == "macbeth.txt"));
+    assert_eq!(&*ctx.document_data(DocumentId(1))?, "The king is dead. Long live the king!");
+    assert_eq!(ctx.document_size(DocumentId(0)), "King Lear and his daughters.".len());
+    assert!(ctx.document_modified(DocumentId(0)).is_none());
+
+    let results = engine.search(&SearchQuery::new("daughters").limit(1))?;
+    assert_eq!(results[0].document_id, DocumentId(0));
+
+    Ok(())
+}
+
+#[test]
+fn metadata_replaced() -> Result<()> {
+    let engine = IndexBuilder::default()
+        .document("lear.txt", "King Lear and his daughters.")
+        .build()?;
+    let (mut ctx, _) = engine.into_parts();
+    let ctx = Arc::get_mut(&mut ctx).unwrap();
+
+    ctx.set_metadata(DocumentId(0), "category", "comedy");
+    ctx.set_metadata(DocumentId(0), "category", "tragedy");
+    assert_eq!(ctx.document_metadata(DocumentId(0)), [("category".to_owned(), "tragedy".to_owned())]);
+
+    Ok(())
+}
+
+#[test]
+fn tiered_ranking_falls_back_to_full_postings() -> Result<()> {
+    let engine = IndexBuilder::default()
+        .tier_size(1)
+        .document("lear.txt", "King Lear and his daughters.")
+        .document("macbeth.txt", "The king is dead. Long live the king!")
+        .build()?;
+    let (_, index) = engine.into_parts();
+    assert_eq!(index.champions("king"), [DocumentId(1)]);
+
+    let champions = Ranking::from_str("tiered:bm25")?.tiered_result_count(1).rank(&index, &query(&["king"]), 5)?;
+    assert_eq!(champions.iter().map(|(document, _)| *document).collect::<Vec<_>>(), [DocumentId(1)]);
+
+    let full = Ranking::from_str("tiered:bm25")?.tiered_result_count(2).rank(&index, &query(&["king"]), 5)?;
+    assert_eq!(full, Ranking::from_str("bm25")?.rank(&index, &query(&["king"]), 5)?);
+
+    Ok(())
+}
+
+#[test]
+fn bm25_length_normalization() -> Result<()> {
+    let engine = IndexBuilder::default()
+        .document("short.txt", "The king.")
+        .document("long.txt", "The king and his daughters and their husbands.")
+        .build()?;
+    let (_, index) = engine.into_parts();
+    let terms = query(&["king"]);
+    let candidates = [DocumentId(0), DocumentId(1)].into_iter().collect::<AHashSet<_>>();
+
+    let normalized = ranker_by_name("bm25")?.score(&index, &terms, &candidates)?;
+    assert_eq!(normalized[0].0, DocumentId(0));
+    assert!(normalized[0].1 > normalized[1].1);
+
+    let unnormalized = ranker_by_name("bm25:1.2:0")?.score(&index, &terms, &candidates)?;
+    assert!((unnormalized[0].1 - unnormalized[1].1).abs() < 1e-12);
+
+    Ok(())
+}
+
+#[test]
+fn search_results_list_matched_terms() -> Result<()> {
+    let engine = IndexBuilder::default()
+        .document("lear.txt", "King Lear and his daughters.")
+        .document("macbeth.txt", "The king is dead. Long live the king!")
+        .build()?;
+    let matched = |term: &str, count| MatchedTerm { term: term.to_owned(), count };
+
+    let results = engine.search(&SearchQuery::new("KING Lear").ranking(Ranking::from_str("bm25")?))?;
+    let lear = results.iter().find(|result| result.document_id == DocumentId(0)).unwrap();
+    let macbeth = results.iter().find(|result| result.document_id == DocumentId(1)).unwrap();
+    assert_eq!(lear.matched_terms, [matched("king", 1), matched("lear", 1)]);
+    assert_eq!(macbeth.matched_terms, [matched("king", 2)]);
+
+    Ok(())
+}
+
+#[test]
+fn exact_top_k_matches_exhaustive_scoring() -> Result<()> {
+    let words = ["king", "lear", "storm", "island", "love", "crown", "ghost", "sword"];
+    for similarity in [SimilarityMetric::Cosine, SimilarityMetric::Dot] {
+        let engine = (0..60)
+            .fold(IndexBuilder::default().similarity(similarity), |builder, i| {
+                let text = (0..1 + i % 7)
+                    .map(|j| words[(i * 3 + j * j) % words.len()])
+                    .join(" ");
+                builder.document(format!("{i}.txt"), text)
+            })
+            .build()?;
+        let (_, index) = engine.into_parts();
+
+        for terms in [query(&["king"]), query(&["king", "storm"]), query(&["lear", "ghost", "sword", "love"])] {
+            let exact = Ranking::Exact(5).rank(&index, &terms, 5)?;
+            let exhaustive = index.closest_documents(5, &index.query_vector(&terms), index.candidates(&terms).iter());
+
+            assert_eq!(exact.len(), exhaustive.len());
+            for ((_, a), (_, b)) in exact.iter().zip(&exhaustive) {
+                assert!((a - b).abs() < 1e-9, "{similarity:?} {a} {b}");
+            }
+        }
+    }
+    assert!(matches!(Ranking::from_str("exact:0"), Err(ParseError::InvalidTopK(_))));
+
+    Ok(())
+}
+
+#[test]
+fn boilerplate_rejects_empty_markers() -> Result<()> {
+    assert!(BoilerplateFilter::load("end:\n".as_bytes()).is_err());
+    assert!(BoilerplateFilter::load("start:   \n".as_bytes()).is_err());
+
+    let filter = BoilerplateFilter::load("start: *** start\nend: *** end\n".as_bytes())?;
+    assert_eq!(filter.strip("header\n*** start\nbody\n*** end\nfooter").trim(), "body");
+
+    Ok(())
+}
+
+#[test]
+fn pruned_index_must_be_preprocessed_again() -> Result<()> {
+    let mut index = build_index()?;
+    let terms = query(&["king", "storm"]);
+
+    index.prune(50.0);
+    assert!(matches!(Ranking::Cluster.rank(&index, &terms, 2), Err(IndexError::NotPreprocessed)));
+    assert!(matches!(Ranking::Exact(3).rank(&index, &terms, 2), Err(IndexError::NotPreprocessed)));
+
+    index.preprocess(2, DEFAULT_TIER_SIZE);
+    let exact = Ranking::Exact(3).rank(&index, &terms, 2)?;
+    let exhaustive = index.closest_documents(3, &index.query_vector(&terms), index.candidates(&terms).iter());
+    assert_eq!(exact.len(), exhaustive.len());
+    for ((_, a), (_, b)) in exact.iter().zip(&exhaustive) {
+        assert!((a - b).abs() < 1e-9);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn index_with_removed_document_must_be_preprocessed_again() -> Result<()> {
+    let mut index = build_index()?;
+    let terms = query(&["king", "storm"]);
+    let &tempest = index.candidates(&query(&["tempest"])).iter().next().unwrap();
+
+    assert!(index.remove_document(tempest));
+    assert!(matches!(Ranking::Cluster.rank(&index, &terms, 2), Err(IndexError::NotPreprocessed)));
+
+    index.preprocess(2, DEFAULT_TIER_SIZE);
+    let result = Ranking::Exact(3).rank(&index, &terms, 2)?;
+    assert!(!result.is_empty());
+    assert!(result.iter().all(|(document_id, _)| *document_id != tempest));
+
+    Ok(())
+}
+
+fn tar_entry(name: &str, type_flag: u8, data: &[u8]) -> Vec<u8> {
+    let mut entry = vec![0; 512];
+    entry[..name.len()].copy_from_slice(name.as_bytes());
+    entry[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+    entry[156] = type_flag;
+    entry[257..263].copy_from_slice(b"ustar\0");
+    entry.extend(data);
+    entry.resize(entry.len().next_multiple_of(512), 0);
+
+    entry
+}
+
+fn corpus_entries(corpus: Box<dyn Corpus>) -> Result<Vec<std::result::Result<(PathBuf, String), String>>> {
+    Ok(corpus.documents()?
+        .map(|entry| match entry {
+            Ok(CorpusDocument { name, text: DocumentText::Buffer(text), .. }) => Ok((name, text)),
+            Ok(document) => Err(format!("{:?} isn't in memory", document.name)),
+            Err(unreadable) => Err(unreadable.name.to_string_lossy().into_owned())
+        })
+        .collect())
+}
+
+#[test]
+fn archive_round_trip() -> Result<()> {
+    let long_name = format!("plays/{}.txt", "lear".repeat(30));
+    let mut archive = tar_entry("hamlet.txt", b'0', b"To be or not to be");
+    archive.extend(tar_entry("plays/", b'5', b""));
+    archive.extend(tar_entry("././@LongLink", b'L', format!("{long_name}\0").as_bytes()));
+    archive.extend(tar_entry(&long_name[..100], b'0', b"King Lear"));
+    archive.extend(tar_entry("cover.png", b'0', &[0xff, 0xfe, 0]));
+    archive.extend([0; 1024]);
+
+    let path = std::env::temp_dir().join(format!("pw8_archive_{}.tar", std::process::id()));
+    std::fs::write(&path, &archive)?;
+    let entries = corpus_entries(Box::new(ArchiveCorpus::new(&path)));
+    std::fs::remove_file(&path)?;
+
+    assert_eq!(entries?, [
+        Ok((path.join("hamlet.txt"), "To be or not to be".to_owned())),
+        Ok((path.join(&long_name), "King Lear".to_owned())),
+        Err(path.join("cover.png").to_string_lossy().into_owned())
+    ]);
+
+    Ok(())
+}
+
+#[test]
+fn archive_malformed_header_ends_it() -> Result<()> {
+    let mut malformed = tar_entry("hamlet.txt", b'0', b"To be or not to be");
+    malformed.extend(tar_entry("lear.txt", b'0', b"King Lear"));
+    let size_start = 512 + 512 + 124;
+    malformed[size_start..size_start + 12].copy_from_slice(b"zzzzzzzzzzz\0");
+    let mut truncated = tar_entry("hamlet.txt", b'0', b"To be or not to be");
+    truncated.truncate(512 + 5);
+
+    let path = std::env::temp_dir().join(format!("pw8_malformed_{}.tar", std::process::id()));
+    for (archive, expected_files) in [(malformed, 1), (truncated, 0)] {
+        std::fs::write(&path, &archive)?;
+        let entries = corpus_entries(Box::new(ArchiveCorpus::new(&path)))?;
+
+        assert_eq!(entries.len(), expected_files + 1);
+        assert_eq!(entries.last(), Some(&Err(path.to_string_lossy().into_owned())));
+    }
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
+
+// NOTE: Answers every connection with the response for the requested path, 404 for any other
+fn serve(responses: Vec<(&'static str, Vec<u8>)>) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().map_while(std::result::Result::ok) {
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(count) => request.extend(&buffer[..count])
+                }
+            }
+            let request = String::from_utf8_lossy(&request);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let response = responses.iter()
+                .find(|(response_path, _)| *response_path == path)
+                .map_or(b"HTTP/1.0 404 Not Found\r\n\r\n".as_slice(), |(_, response)| response);
+            let _ = stream.write_all(response);
+        }
+    });
+
+    Ok(format!("http://{address}"))
+}
+
+#[test]
+fn crawler_follows_links_on_the_same_host() -> Result<()> {
+    let start = serve(vec![
+        ("/", b"HTTP/1.0 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\r\n<html><a href=\"/plays/lear.txt\">Lear</a> <a href='http://elsewhere/'>x</a> \
+            <a href='missing#top'>m</a><script>ignored()</script>King &amp; queen</html>".to_vec()),
+        ("/plays/lear.txt", b"HTTP/1.0 200 OK\r\ncontent-type: text/plain\r\n\r\nKing Lear and his daughters.".to_vec())
+    ])?;
+
+    let entries = corpus_entries(Box::new(CrawlerCorpus::new(format!("{start}/"), 10)))?;
+    assert_eq!(entries.len(), 3);
+    let Ok((name, text)) = &entries[0] else { panic!("{:?}", entries[0]) };
+    assert_eq!(name, &PathBuf::from(format!("{start}/")));
+    assert!(text.contains("King & queen") && !text.contains("ignored") && !text.contains('<'), "{text}");
+    assert_eq!(entries[1], Ok((PathBuf::from(format!("{start}/plays/lear.txt")), "King Lear and his daughters.".to_owned())));
+    assert_eq!(entries[2], Err(format!("{start}/missing")));
+
+    Ok(())
+}
+
+#[test]
+fn crawler_rejects_malformed_responses() -> Result<()> {
+    let mut oversized = b"HTTP/1.0 200 OK\r\n\r\n".to_vec();
+    oversized.resize(MAX_RESPONSE_SIZE + 1, b'a');
+    let start = serve(vec![
+        ("/headers", b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n<html>".to_vec()),
+        ("/binary", b"HTTP/1.0 200 OK\r\nContent-Type: image/png\r\n\r\n\x89PNG".to_vec()),
+        ("/oversized", oversized)
+    ])?;
+
+    for path in ["/headers", "/binary", "/oversized", "/missing"] {
+        let entries = corpus_entries(Box::new(CrawlerCorpus::new(format!("{start}{path}"), 1)))?;
+        assert_eq!(entries, [Err(format!("{start}{path}"))], "{path}");
+    }
+    assert!(Box::new(CrawlerCorpus::new("https://example.com/", 1)).documents().is_err());
+    assert!(Box::new(CrawlerCorpus::new("http:///", 1)).documents().is_err());
+
+    Ok(())
+}
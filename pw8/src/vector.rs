@@ -0,0 +1,200 @@
+use anyhow::Result;
+use std::fmt::{Display, Formatter};
+use std::ops::Add;
+use std::str::FromStr;
+use itertools::{EitherOrBoth, Itertools};
+use serde::{Deserialize, Serialize};
+use crate::error::ParseError;
+
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+pub enum SimilarityMetric {
+    #[default]
+    Cosine,
+    Dot,
+    Jaccard
+}
+
+impl SimilarityMetric {
+    pub fn similarity(self, a: &SparseVector, b: &SparseVector) -> f64 {
+        match self {
+            SimilarityMetric::Cosine => cosine_sim(a, b),
+            SimilarityMetric::Dot => a.dot(b),
+            SimilarityMetric::Jaccard => jaccard_sim(a, b)
+        }
+    }
+}
+
+impl FromStr for SimilarityMetric {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        Ok(match s.to_lowercase().as_str() {
+            "cosine" => SimilarityMetric::Cosine,
+            "dot" => SimilarityMetric::Dot,
+            "jaccard" => SimilarityMetric::Jaccard,
+            _ => return Err(ParseError::UnknownSimilarity(s.to_owned()))
+        })
+    }
+}
+
+impl Display for SimilarityMetric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SimilarityMetric::Cosine => "cosine",
+            SimilarityMetric::Dot => "dot",
+            SimilarityMetric::Jaccard => "jaccard"
+        })
+    }
+}
+
+pub fn cosine_sim(a: &SparseVector, b: &SparseVector) -> f64 {
+    let a_mag = a.magnitude();
+    let b_mag = b.magnitude();
+    if a_mag == 0.0 || b_mag == 0.0 {
+        return 0.0;
+    }
+
+    a.dot(b) / (a_mag * b_mag)
+}
+
+// NOTE: Only compares which terms occur, weights are ignored
+pub fn jaccard_sim(a: &SparseVector, b: &SparseVector) -> f64 {
+    let intersection = a.iter()
+        .merge_join_by(b.iter(), |(term_a, _), (term_b, _)| term_a.cmp(term_b))
+        .filter(|entry| matches!(entry, EitherOrBoth::Both(..)))
+        .count();
+    let union = a.len() + b.len() - intersection;
+    if union == 0 {
+        return 0.0;
+    }
+
+    intersection as f64 / union as f64
+}
+
+pub fn unit(vector: &SparseVector) -> SparseVector {
+    let magnitude = vector.magnitude();
+    if magnitude == 0.0 {
+        return vector.clone();
+    }
+
+    vector.scaled(1.0 / magnitude)
+}
+
+// NOTE: Mean of normalized vectors, so long documents don't dominate
+pub fn centroid<'a>(vectors: impl Iterator<Item = &'a SparseVector>) -> SparseVector {
+    let mut count = 0;
+    let sum = vectors.fold(SparseVector::new(), |sum, vector| {
+        count += 1;
+
+        &sum + &unit(vector)
+    });
+
+    if count == 0 { sum } else { sum.scaled(1.0 / count as f64) }
+}
+
+// NOTE: Weights of the terms a vector has, sorted by term id. A dense vector would have
+//  a component for every term of the vocabulary, while a document only has a few of them
+#[derive(Clone, Default, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+pub struct SparseVector {
+    entries: Vec<(usize, f64)>
+}
+
+impl SparseVector {
+    pub fn new() -> Self {
+        SparseVector { entries: Vec::new() }
+    }
+
+    pub fn from_dense(components: &[f64]) -> Self {
+        components.iter()
+            .enumerate()
+            .map(|(term_id, &weight)| (term_id, weight))
+            .collect()
+    }
+
+    // NOTE: Zero for terms the vector doesn't have
+    pub fn get(&self, term_id: usize) -> f64 {
+        self.entries.binary_search_by_key(&term_id, |&(id, _)| id)
+            .map_or(0.0, |i| self.entries[i].1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.entries.iter().copied()
+    }
+
+    // NOTE: Number of terms with a weight, not the size of the vocabulary
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn dot(&self, other: &SparseVector) -> f64 {
+        self.iter()
+            .merge_join_by(other.iter(), |(term_a, _), (term_b, _)| term_a.cmp(term_b))
+            .map(|entry| match entry {
+                EitherOrBoth::Both((_, a), (_, b)) => a * b,
+                _ => 0.0
+            })
+            .sum()
+    }
+
+    pub fn magnitude_squared(&self) -> f64 {
+        self.entries.iter()
+            .map(|(_, weight)| weight * weight)
+            .sum()
+    }
+
+    pub fn magnitude(&self) -> f64 {
+        self.magnitude_squared().sqrt()
+    }
+
+    pub fn scaled(&self, factor: f64) -> SparseVector {
+        self.iter()
+            .map(|(term_id, weight)| (term_id, weight * factor))
+            .collect()
+    }
+
+    pub fn heap_size(&self) -> usize {
+        self.entries.capacity() * size_of::<(usize, f64)>()
+    }
+}
+
+impl Add for &SparseVector {
+    type Output = SparseVector;
+
+    fn add(self, other: &SparseVector) -> SparseVector {
+        SparseVector {
+            entries: self.iter()
+                .merge_join_by(other.iter(), |(term_a, _), (term_b, _)| term_a.cmp(term_b))
+                .map(|entry| match entry {
+                    EitherOrBoth::Both((term_id, a), (_, b)) => (term_id, a + b),
+                    EitherOrBoth::Left(entry) | EitherOrBoth::Right(entry) => entry
+                })
+                .filter(|&(_, weight)| weight != 0.0)
+                .collect()
+        }
+    }
+}
+
+// NOTE: Entries can come in any order, weights of a repeated term are added up and zero weights are dropped
+impl FromIterator<(usize, f64)> for SparseVector {
+    fn from_iter<T: IntoIterator<Item = (usize, f64)>>(iter: T) -> Self {
+        let mut entries = iter.into_iter().collect::<Vec<_>>();
+        entries.sort_by_key(|&(term_id, _)| term_id);
+        entries.dedup_by(|(term_id, weight), (previous_id, previous_weight)| {
+            if term_id != previous_id {
+                return false;
+            }
+            *previous_weight += *weight;
+
+            true
+        });
+        entries.retain(|&(_, weight)| weight != 0.0);
+
+        SparseVector { entries }
+    }
+}
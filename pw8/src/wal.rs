@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::document::DocumentId;
+use crate::lexer::Lexer;
+use crate::term_index::InvertedIndex;
+use crate::LoadedIndex;
+
+pub const WAL_PATH: &str = "data/wal.log";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum Operation {
+    // NOTE: Id the document was added as, later operations refer to documents by it
+    Add { path: PathBuf, document: usize },
+    Remove { document: usize }
+}
+
+// NOTE: Sequence numbers keep growing across snapshots, so operations
+//  that are already part of a snapshot are recognized and not applied twice
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entry {
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub operation: Operation
+}
+
+// NOTE: Every operation since the last snapshot, one JSON object per line
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: File,
+    last_sequence: u64,
+    entry_count: usize
+}
+
+impl WriteAheadLog {
+    pub fn open(path: impl AsRef<Path>, snapshot_sequence: u64) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let entries = Self::read(&path)?;
+
+        Ok(WriteAheadLog {
+            file: Self::open_append(&path)?,
+            path,
+            last_sequence: entries.last().map_or(0, |entry| entry.sequence).max(snapshot_sequence),
+            entry_count: entries.len()
+        })
+    }
+
+    fn open_append(path: &Path) -> Result<File> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    // NOTE: Only operations that were applied are appended, so a failed one never reaches the log.
+    //  A crash in between loses the operation along with the rest of the in-memory index
+    pub fn append(&mut self, operation: Operation) -> Result<Entry> {
+        let entry = Entry { sequence: self.last_sequence + 1, operation };
+        writeln!(self.file, "{}", serde_json::to_string(&entry)?)?;
+        self.file.sync_data()?;
+        self.last_sequence = entry.sequence;
+        self.entry_count += 1;
+
+        Ok(entry)
+    }
+
+    // NOTE: Last line may be torn by a crash during append, its operation was lost with the crash so it is skipped
+    pub fn read(path: impl AsRef<Path>) -> Result<Vec<Entry>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Ok(Vec::new())
+        };
+
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(entry) => entries.push(entry),
+                Err(_) => break
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.entry_count = 0;
+
+        Ok(())
+    }
+
+    // NOTE: Log is rewritten next to the old one and renamed over it,
+    //  a crash in between leaves entries the snapshot already skips
+    pub fn retain_after(&mut self, sequence: u64) -> Result<()> {
+        let entries = Self::read(&self.path)?
+            .into_iter()
+            .filter(|entry| entry.sequence > sequence)
+            .collect::<Vec<_>>();
+
+        let temp_path = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        for entry in &entries {
+            writeln!(writer, "{}", serde_json::to_string(entry)?)?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temp_path, &self.path)?;
+
+        self.file = Self::open_append(&self.path)?;
+        self.entry_count = entries.len();
+
+        Ok(())
+    }
+}
+
+pub fn apply(loaded: &mut LoadedIndex, operation: &Operation) -> Result<DocumentId> {
+    match operation {
+        Operation::Add { path, document } => {
+            let ctx = Arc::get_mut(&mut loaded.ctx)
+                .ok_or_else(|| anyhow!("Documents can't be added while the index is in use"))?;
+            if ctx.document_count() != *document {
+                return Err(anyhow!("File {path:?} was added as {}, but would be added as {} now", DocumentId(*document), DocumentId(ctx.document_count())));
+            }
+            let document_id = ctx.add_file(path.clone())?;
+
+            let mut document_index = InvertedIndex::new();
+            let data = loaded.ctx.document_data(document_id)?;
+            Lexer::new(document_id, loaded.ctx.strip_boilerplate(&data), &loaded.ctx).lex(&mut document_index);
+            loaded.index.merge(document_index);
+
+            Ok(document_id)
+        },
+        Operation::Remove { document } => {
+            let document_id = DocumentId(*document);
+            if !loaded.index.remove_document(document_id) {
+                return Err(anyhow!("Document {document_id} isn't indexed"));
+            }
+
+            Ok(document_id)
+        }
+    }
+}
+
+// NOTE: Logged operations succeeded once, one failing now means the corpus changed since, like a removed file.
+//  It is skipped and counted, the rest of the log still applies. A skipped add still takes its id,
+//  so later documents keep the ids they were logged with and removals hit the right ones
+pub fn replay(loaded: &mut LoadedIndex, entries: &[Entry]) -> usize {
+    entries.iter()
+        .filter(|entry| {
+            let failed = apply(loaded, &entry.operation).is_err();
+            if let (true, Operation::Add { path, document }, Some(ctx)) = (failed, &entry.operation, Arc::get_mut(&mut loaded.ctx)) {
+                if ctx.document_count() == *document {
+                    ctx.add_inline(path.to_string_lossy(), "");
+                }
+            }
+
+            failed
+        })
+        .count()
+}
@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use ahash::AHashMap;
+use std::fs::File;
+use std::hint::black_box;
+use std::str::FromStr;
+use human_bytes::human_bytes;
+use itertools::Itertools;
+use memmap::Mmap;
+use crate::metrics::metrics;
+use crate::term_index::InvertedIndex;
+
+pub const DEFAULT_WARMUP_TERMS: usize = 1000;
+const PAGE_SIZE: usize = 4096;
+
+// NOTE: Reads a byte of every page, which is enough for the kernel to fault it in
+fn touch(data: &[u8]) -> usize {
+    data.iter()
+        .step_by(PAGE_SIZE)
+        .map(|&byte| black_box(byte) as usize)
+        .sum()
+}
+
+// NOTE: Pages stay in the page cache after this process exits,
+//  so the next process mapping or reading the index starts warm
+pub fn warmup(index_path: &str, flags: &AHashMap<&str, &str>) -> Result<()> {
+    let term_count = flags.get("warmup-terms")
+        .map(|term_count| usize::from_str(term_count))
+        .transpose()
+        .context("Invalid warmup term count")?
+        .unwrap_or(DEFAULT_WARMUP_TERMS);
+
+    let file = File::open(index_path).context(format!("Couldn't open index \"{index_path}\""))?;
+    let mmap = unsafe { Mmap::map(&file)? };
+
+    let (result, time) = metrics().time("warmup", || -> Result<(usize, usize, usize)> {
+        // NOTE: Walking the dictionary reads every term, so its pages are faulted in along the way
+        let posting_lists = InvertedIndex::binary_posting_lists(&mmap)?;
+        let hottest = posting_lists.iter()
+            .sorted_by_key(|list| (usize::MAX - list.document_frequency, list.term))
+            .take(term_count)
+            .collect::<Vec<_>>();
+        black_box(hottest.iter().map(|list| touch(list.postings)).sum::<usize>());
+
+        Ok((posting_lists.len(), hottest.len(), hottest.iter().map(|list| list.postings.len()).sum()))
+    });
+    let (total, warmed, warmed_size) = result?;
+
+    println!("Warmed up the dictionary of {total} terms and {warmed} hottest posting lists ({}) of \"{index_path}\" in {time:?}",
+             human_bytes(warmed_size as f64));
+
+    Ok(())
+}